@@ -0,0 +1,39 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum DarkError
+{
+    #[error("No dark frames to work from")]
+    NoFrames,
+    #[error("Frame is {found_width}x{found_height}, expected {width}x{height}")]
+    SizeMismatch
+    {
+        width: u32,
+        height: u32,
+        found_width: u32,
+        found_height: u32,
+    },
+    #[error("Raw buffer is {found} bytes, expected {expected}")]
+    BufferLength
+    {
+        expected: usize,
+        found: usize,
+    },
+    #[error("Not a pixel map")]
+    BadPixelMap,
+    #[error("Pixel map IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl DarkError
+{
+    // Check a frame's size against what's expected
+    pub(crate) fn check_size(width: u32, height: u32, found_width: u32, found_height: u32) -> Result<(), DarkError>
+    {
+        if width != found_width || height != found_height
+        {
+            return Err(DarkError::SizeMismatch { width, height, found_width, found_height });
+        }
+        Ok(())
+    }
+}
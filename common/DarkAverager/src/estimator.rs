@@ -0,0 +1,149 @@
+use crate::error::DarkError;
+use crate::pixel::{DarkPixel, MonoImage};
+
+// Converts median absolute deviation into a standard deviation for normally distributed noise
+pub(crate) const MAD_TO_SIGMA: f64 = 1.4826;
+
+// How the stack of dark frames gets boiled down to one value per pixel
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DarkEstimator
+{
+    // Plain per-pixel mean, cheapest, but a cosmic ray in one frame bleeds into the dark
+    #[default]
+    Mean,
+    // Per-pixel median, ignores anything that hits fewer than half the frames
+    Median,
+    // Mean after repeatedly throwing out values more than `sigma` deviations from the median.
+    // Closer to the mean's noise than the median, still drops one-off hits.
+    SigmaClipped
+    {
+        sigma: f32,
+        iterations: u32,
+    },
+}
+
+impl DarkEstimator
+{
+    // Sigma clipping with the usual settings
+    pub fn sigma_clipped() -> DarkEstimator
+    {
+        DarkEstimator::SigmaClipped { sigma: 3.0, iterations: 3 }
+    }
+
+    // Combine a stack of frames into one dark frame
+    pub fn combine<P: DarkPixel>(&self, frames: &[MonoImage<P>]) -> Result<MonoImage<P>, DarkError>
+    {
+        let first = frames.first().ok_or(DarkError::NoFrames)?;
+        let (width, height) = first.dimensions();
+        for frame in frames
+        {
+            DarkError::check_size(width, height, frame.width(), frame.height())?;
+        }
+
+        let mut output = MonoImage::<P>::new(width, height);
+        if let DarkEstimator::Mean = self
+        {
+            // No need to gather every pixel's stack for the mean, and u64 can't overflow
+            let mut sum = vec![0u64; first.as_raw().len()];
+            for frame in frames
+            {
+                for (total, pixel) in sum.iter_mut().zip(frame.as_raw())
+                {
+                    *total += pixel.as_u32() as u64;
+                }
+            }
+            let count = frames.len() as u64;
+            for (out, total) in output.iter_mut().zip(sum)
+            {
+                *out = P::from_u32_clamped(((total + count / 2) / count) as u32);
+            }
+            return Ok(output);
+        }
+
+        let mut stack = vec![0u32; frames.len()];
+        let mut scratch = vec![0u32; frames.len()];
+        for (i, out) in output.iter_mut().enumerate()
+        {
+            for (value, frame) in stack.iter_mut().zip(frames)
+            {
+                *value = frame.as_raw()[i].as_u32();
+            }
+            *out = P::from_u32_clamped(self.estimate_with(&mut stack, &mut scratch));
+        }
+        Ok(output)
+    }
+
+    // One pixel's worth of values, order isn't preserved
+    pub fn estimate(&self, values: &mut [u32]) -> u32
+    {
+        let mut scratch = vec![0u32; values.len()];
+        self.estimate_with(values, &mut scratch)
+    }
+
+    // Saves an allocation per pixel when going through a whole frame
+    fn estimate_with(&self, values: &mut [u32], scratch: &mut [u32]) -> u32
+    {
+        if values.is_empty()
+        {
+            return 0;
+        }
+
+        match *self
+        {
+            DarkEstimator::Mean => mean(values).round() as u32,
+            DarkEstimator::Median => median(values).round() as u32,
+            DarkEstimator::SigmaClipped { sigma, iterations } => sigma_clipped_mean(values, scratch, sigma, iterations),
+        }
+    }
+}
+
+fn mean(values: &[u32]) -> f64
+{
+    values.iter().map(|v| *v as f64).sum::<f64>() / values.len() as f64
+}
+
+fn median(values: &mut [u32]) -> f64
+{
+    values.sort_unstable();
+    let middle = values.len() / 2;
+    if values.len().is_multiple_of(2)
+    {
+        return (values[middle - 1] as f64 + values[middle] as f64) / 2.0;
+    }
+    values[middle] as f64
+}
+
+// Clips around the median with a spread from the median absolute deviation. A plain mean and
+// standard deviation can't reject a lone hit in a small stack, the hit drags both along with it.
+fn sigma_clipped_mean(values: &mut [u32], scratch: &mut [u32], sigma: f32, iterations: u32) -> u32
+{
+    // Kept values get moved to the front, `kept` marks the end of them
+    let mut kept = values.len();
+    for _ in 0..iterations
+    {
+        let center = median(&mut values[..kept]);
+        for (deviation, value) in scratch.iter_mut().zip(&values[..kept])
+        {
+            *deviation = (*value as f64 - center).abs().round() as u32;
+        }
+        // Pixel values are whole counts, don't let the spread drop under one of them
+        let spread = (median(&mut scratch[..kept]) * MAD_TO_SIGMA).max(1.0);
+
+        let limit = spread * sigma as f64;
+        let mut next = 0;
+        for i in 0..kept
+        {
+            if (values[i] as f64 - center).abs() <= limit
+            {
+                values.swap(next, i);
+                next += 1;
+            }
+        }
+        if next == kept || next == 0
+        {
+            break;
+        }
+        kept = next;
+    }
+    mean(&values[..kept]).round() as u32
+}
@@ -1,171 +1,294 @@
-use std::process::Output;
+// Crate name is what odin and jupiter already depend on
+#![allow(non_snake_case)]
 
-use image::{GrayImage, ImageBuffer, Luma};
+use image::ImageBuffer;
 
-// Stores the computed average grayscale image
-pub struct ImageAveragerFromBuffer
+pub mod error;
+pub mod estimator;
+pub mod pixel;
+pub mod pixel_map;
+
+pub use error::DarkError;
+pub use estimator::DarkEstimator;
+pub use pixel::{DarkPixel, MonoImage, mono8_from_bytes, mono12p_from_bytes, mono16_from_bytes};
+pub use pixel_map::{BadPixelThresholds, PixelMap, PixelStats};
+
+// Stores the computed dark frame, and which pixels can't be trusted. Works on 8-bit frames by
+// default, u16 covers the 12 and 16-bit Basler formats.
+pub struct ImageAveragerFromBuffer<P: DarkPixel = u8>
 {
-    average_img: GrayImage,
+    average_img: MonoImage<P>,
+    bad_pixels: PixelMap,
 }
 
-// This should use error but I'm too time pressed to impl an error so do that later
-#[allow(dead_code)]
-impl ImageAveragerFromBuffer
+impl<P: DarkPixel> Default for ImageAveragerFromBuffer<P>
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl<P: DarkPixel> ImageAveragerFromBuffer<P>
 {
     // Create an empty averager (0x0 image)
-    pub fn new() -> ImageAveragerFromBuffer
+    pub fn new() -> ImageAveragerFromBuffer<P>
     {
-        return ImageAveragerFromBuffer
+        ImageAveragerFromBuffer
         {
-            average_img: ImageBuffer::new(0, 0)
+            average_img: ImageBuffer::new(0, 0),
+            bad_pixels: PixelMap::new(0, 0),
         }
     }
 
     // Create averager and compute average from input images
-    pub fn new_with_source(source: Vec<ImageBuffer<Luma<u8>, Vec<u8>>>) -> Option<ImageAveragerFromBuffer>
+    pub fn new_with_source(source: Vec<MonoImage<P>>) -> Result<ImageAveragerFromBuffer<P>, DarkError>
     {
-        if let Some(image) = Self::find_average(source) {
-            return Some(ImageAveragerFromBuffer{
-                    average_img: image
-                });
-        }
-        return None
+        Self::new_with_options(source, DarkEstimator::default(), &BadPixelThresholds::default())
+    }
+
+    // Same as new_with_source, with control over how the darks are combined and what gets
+    // flagged as a bad pixel
+    pub fn new_with_options(
+        source: Vec<MonoImage<P>>,
+        estimator: DarkEstimator,
+        thresholds: &BadPixelThresholds,
+    ) -> Result<ImageAveragerFromBuffer<P>, DarkError>
+    {
+        // Also checks every frame is the same size
+        let average_img = estimator.combine(&source)?;
+        let bad_pixels = PixelStats::from_frames(&source)?.find_bad_pixels(thresholds);
+
+        Ok(ImageAveragerFromBuffer { average_img, bad_pixels })
     }
 
     // Compute per-pixel average across all images
-    pub fn find_average(source: Vec<ImageBuffer<Luma<u8>, Vec<u8>>>) -> Option<GrayImage>
-    {
-        if !source.is_empty() {
-            // Accumulator uses u32 to prevent overflow during summation
-            let mut avg: ImageBuffer<Luma<u32>, Vec<u32>> =
-                ImageBuffer::new(source[0].width(), source[0].height());
-
-            // Final output image (u8 grayscale)
-            let mut output: GrayImage =
-                ImageBuffer::new(avg.width(), avg.height());
-
-            // Number of input images
-            let sample_size = source.iter().count() as u32;
-            if sample_size > 0 {
-
-                
-            }
-            // Sum pixel values across all images
-            for buf in source
-            {
-                for x in 0..buf.width()
-                {
-                    for y in 0..buf.height()
-                    {
-                        // Safe accumulation (no early clipping like u8)
-                        avg[(x, y)][0] =
-                            avg[(x, y)][0].saturating_add(buf[(x, y)][0] as u32);
-                    }
-                }
-            }
+    pub fn find_average(source: &[MonoImage<P>]) -> Result<MonoImage<P>, DarkError>
+    {
+        DarkEstimator::Mean.combine(source)
+    }
+
+    // Return a copy of the average image
+    pub fn get_average(&self) -> MonoImage<P>
+    {
+        self.average_img.clone()
+    }
+
+    // Bad pixels found in the dark frames, plus anything merged in from a saved map
+    pub fn pixel_map(&self) -> &PixelMap
+    {
+        &self.bad_pixels
+    }
+
+    // Fold in a map saved on a previous boot, errors if it's for a different sized sensor
+    pub fn merge_pixel_map(&mut self, map: &PixelMap) -> Result<(), DarkError>
+    {
+        self.bad_pixels.merge(map)
+    }
 
-            // Normalize sum to get average
-            for x in 0..avg.width()
-            {
-                for y in 0..avg.height()
+    // Subtract average image from input image (in-place), then patch over bad pixels so they
+    // don't turn up as stars
+    pub fn apply_average(&self, img: &mut MonoImage<P>) -> Result<(), DarkError>
+    {
+        DarkError::check_size(self.average_img.width(), self.average_img.height(), img.width(), img.height())?;
+
+        for (pixel, dark) in img.iter_mut().zip(self.average_img.iter())
+        {
+            // Clamp negatives to 0
+            *pixel = P::from_u32_clamped(pixel.as_u32().saturating_sub(dark.as_u32()));
+        }
+        self.bad_pixels.correct(img)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fs;
+    use image::{ImageBuffer, ImageReader, Luma};
+    use crate::pixel_map::HOT;
+    use crate::{DarkError, DarkEstimator, ImageAveragerFromBuffer, MonoImage, BadPixelThresholds, mono12p_from_bytes, mono16_from_bytes};
+
+    fn flat<P: crate::DarkPixel>(width: u32, height: u32, value: P) -> MonoImage<P>
+    {
+        ImageBuffer::from_pixel(width, height, Luma([value]))
+    }
+
+    // Ten darks at a level of 10 with a little structure, one of which took a cosmic ray
+    fn darks_with_cosmic_ray() -> Vec<MonoImage<u8>>
+    {
+        (0..10u8)
+            .map(|n| {
+                let mut frame = ImageBuffer::from_fn(8, 8, |x, y| Luma([9 + ((x + y + n as u32) % 3) as u8]));
+                if n == 4
                 {
-                    if sample_size > 0
-                    {
-                        // Divide once, then cast back to u8
-                        output[(x, y)][0] =
-                            (avg[(x, y)][0] / sample_size) as u8;
-                    }
-                    else
-                    {
-                        // Fallback for empty input (should not normally occur)
-                        output[(x, y)][0] = 0;
-                    }
+                    frame[(5, 5)][0] = 250;
                 }
-            }
-            return Some(output);
+                frame
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_averaging_by_folder()
+    {
+        // Same flow as the bench test against real frames, with synthetic ones written out as TIFFs
+        let dir = std::env::temp_dir().join(format!("dark_averager_{}", std::process::id()));
+        let source_dir = dir.join("source_images");
+        let apply_dir = dir.join("images_to_apply");
+        let output_dir = dir.join("output_images");
+        for path in [&source_dir, &apply_dir, &output_dir]
+        {
+            fs::create_dir_all(path).unwrap();
+        }
+
+        for i in 0..5u8
+        {
+            flat(32, 24, 20 + i).save(source_dir.join(format!("{i}.tiff"))).unwrap();
         }
-        return None;
+        let mut star = flat(32, 24, 22u8);
+        star[(16, 12)][0] = 200;
+        star.save(apply_dir.join("0.tiff")).unwrap();
+
+        let read_dir = |path: &std::path::Path| -> Vec<MonoImage<u8>> {
+            let mut paths: Vec<_> = fs::read_dir(path).unwrap().map(|entry| entry.unwrap().path()).collect();
+            paths.sort();
+            paths
+                .into_iter()
+                .map(|path| ImageReader::open(path).unwrap().with_guessed_format().unwrap().decode().unwrap().into_luma8())
+                .collect()
+        };
+
+        let avger = ImageAveragerFromBuffer::new_with_source(read_dir(&source_dir)).unwrap();
+        assert_eq!(avger.get_average()[(0, 0)][0], 22);
+
+        for (i, ref mut buf) in read_dir(&apply_dir).into_iter().enumerate()
+        {
+            avger.apply_average(buf).unwrap();
+            assert_eq!(buf[(0, 0)][0], 0);
+            assert_eq!(buf[(16, 12)][0], 178);
+            buf.save(output_dir.join(format!("{i}.tiff"))).unwrap();
+        }
+
+        fs::remove_dir_all(&dir).ok();
     }
 
-    // Return a copy of the average image
-    #[allow(dead_code)]
-    pub fn get_average(&self) -> GrayImage
+    #[test]
+    fn test_empty_and_mismatched_sources()
+    {
+        assert!(matches!(ImageAveragerFromBuffer::<u8>::new_with_source(vec![]), Err(DarkError::NoFrames)));
+
+        let source = vec![flat(8, 8, 1u8), flat(8, 4, 1u8)];
+        assert!(matches!(
+            ImageAveragerFromBuffer::new_with_source(source),
+            Err(DarkError::SizeMismatch { width: 8, height: 8, found_width: 8, found_height: 4 })
+        ));
+    }
+
+    #[test]
+    fn test_apply_wrong_size()
+    {
+        let avger = ImageAveragerFromBuffer::new_with_source(vec![flat(8, 8, 1u8)]).unwrap();
+        let mut img = flat(4, 4, 1u8);
+        assert!(avger.apply_average(&mut img).is_err());
+        // Left untouched
+        assert_eq!(img, flat(4, 4, 1u8));
+    }
+
+    #[test]
+    fn test_apply_clamps_at_zero()
+    {
+        let avger = ImageAveragerFromBuffer::new_with_source(vec![flat(4, 4, 30u8)]).unwrap();
+        let mut img = flat(4, 4, 10u8);
+        img[(1, 1)][0] = 100;
+        avger.apply_average(&mut img).unwrap();
+        assert_eq!(img[(0, 0)][0], 0);
+        assert_eq!(img[(1, 1)][0], 70);
+    }
+
+    #[test]
+    fn test_sixteen_bit()
     {
-        return self.average_img.clone();
+        // Dark levels and signals well past what u8 can hold
+        let source = vec![flat(8, 8, 1000u16), flat(8, 8, 1200u16), flat(8, 8, 1100u16)];
+        let avger = ImageAveragerFromBuffer::new_with_source(source).unwrap();
+        assert_eq!(avger.get_average()[(3, 3)][0], 1100);
+
+        let mut img = flat(8, 8, 1150u16);
+        img[(2, 2)][0] = 60_000;
+        avger.apply_average(&mut img).unwrap();
+        assert_eq!(img[(0, 0)][0], 50);
+        assert_eq!(img[(2, 2)][0], 58_900);
+    }
+
+    #[test]
+    fn test_mean_keeps_cosmic_ray()
+    {
+        let avger = ImageAveragerFromBuffer::new_with_options(darks_with_cosmic_ray(), DarkEstimator::Mean, &BadPixelThresholds::default()).unwrap();
+        assert!(avger.get_average()[(5, 5)][0] > 30);
     }
 
-    // Subtract average image from input image (in-place)
-    #[allow(dead_code)]
-    pub fn apply_average(&self, img: &mut ImageBuffer<Luma<u8>, Vec<u8>>)
+    #[test]
+    fn test_robust_estimators_reject_cosmic_ray()
     {
-        for x in 0..img.width()
+        for estimator in [DarkEstimator::Median, DarkEstimator::sigma_clipped()]
         {
-            for y in 0..img.height()
-            {
-                // Compute pixel difference using wider type to avoid underflow
-                let mut brightness_value =
-                    (img[(x, y)][0] as i16) - (self.average_img[(x, y)][0] as i16);
-
-                // Clamp negatives to 0 (valid u8 range)
-                if brightness_value < 0
-                {
-                    brightness_value = 0;
-                }
+            let avger = ImageAveragerFromBuffer::new_with_options(darks_with_cosmic_ray(), estimator, &BadPixelThresholds::default()).unwrap();
+            let dark = avger.get_average();
+            assert!((9..=11).contains(&dark[(5, 5)][0]), "{estimator:?} gave {}", dark[(5, 5)][0]);
+            assert!((9..=11).contains(&dark[(0, 0)][0]));
+        }
+    }
+
+    #[test]
+    fn test_estimators_on_a_stack()
+    {
+        let mut values = [10, 12, 11, 10, 900, 11];
+        assert_eq!(DarkEstimator::Median.estimate(&mut values), 11);
+        let mut values = [10, 12, 11, 10, 900, 11];
+        assert_eq!(DarkEstimator::sigma_clipped().estimate(&mut values), 11);
+        let mut values = [10, 12, 11, 10, 900, 11];
+        assert_eq!(DarkEstimator::Mean.estimate(&mut values), 159);
+        // Nothing to clip when every value is the same
+        let mut values = [7, 7, 7];
+        assert_eq!(DarkEstimator::sigma_clipped().estimate(&mut values), 7);
+        assert_eq!(DarkEstimator::Median.estimate(&mut []), 0);
+    }
 
-                // Write adjusted pixel back
-                img[(x, y)][0] = brightness_value as u8;
-            }
+    #[test]
+    fn test_raw_buffers()
+    {
+        // Two pixels, 0x123 and 0xABC, packed into three bytes
+        let img = mono12p_from_bytes(2, 1, &[0x23, 0xC1, 0xAB]).unwrap();
+        assert_eq!(img.as_raw(), &vec![0x123, 0xABC]);
+
+        // Odd pixel counts still take whole triples
+        let img = mono12p_from_bytes(3, 1, &[0x23, 0xC1, 0xAB, 0xFF, 0x0F, 0x00]).unwrap();
+        assert_eq!(img.as_raw(), &vec![0x123, 0xABC, 0xFFF]);
+
+        let img = mono16_from_bytes(2, 1, &[0x34, 0x12, 0xFF, 0xFF]).unwrap();
+        assert_eq!(img.as_raw(), &vec![0x1234, 0xFFFF]);
+
+        assert!(matches!(mono16_from_bytes(2, 2, &[0; 7]), Err(DarkError::BufferLength { expected: 8, found: 7 })));
+    }
+
+    #[test]
+    fn test_hot_pixel_corrected_on_apply()
+    {
+        let mut source = vec![];
+        for n in 0..6u16
+        {
+            let mut frame = ImageBuffer::from_fn(8, 8, |x, y| Luma([400 + ((x * 7 + y * 3 + n as u32) % 5) as u16]));
+            frame[(4, 4)][0] = 3000;
+            source.push(frame);
         }
+        let avger = ImageAveragerFromBuffer::new_with_source(source).unwrap();
+        assert!(avger.pixel_map().flags(4, 4) & HOT != 0);
+
+        let mut img: MonoImage<u16> = flat(8, 8, 410);
+        img[(4, 4)][0] = 3500;
+        avger.apply_average(&mut img).unwrap();
+        // Patched from the neighbors rather than left as a fake star
+        assert!(img[(4, 4)][0] < 20);
     }
 }
-
-// #[cfg(test)]
-// mod tests
-// {
-//     use std::fs;
-//     use image::{ImageBuffer, ImageReader, Luma};
-//     use crate::ImageAveragerFromBuffer;
-
-//     #[test]
-//     fn test_averaging_by_folder()
-//     {
-//         let source_dir = fs::read_dir("./unit_test/source_images/").unwrap();
-//         let apply_dir = fs::read_dir("./unit_test/images_to_apply/").unwrap();
-
-//         let mut source_buf_vec: Vec<ImageBuffer<Luma<u8>, Vec<u8>>> = vec![];
-//         let mut apply_buf_vec: Vec<ImageBuffer<Luma<u8>, Vec<u8>>> = vec![];
-
-//         for path in source_dir
-//         {
-//             let img = ImageReader::open(path.unwrap().path().display().to_string()).unwrap();
-//             let img = img.with_guessed_format().unwrap();
-//             let img = img.decode().unwrap();
-//             let img = img.as_luma8().unwrap();
-
-//             source_buf_vec.push(img.clone());
-//         }
-
-//         for path in apply_dir
-//         {
-//             let img = ImageReader::open(path.unwrap().path().display().to_string()).unwrap();
-//             let img = img.with_guessed_format().unwrap();
-//             let img = img.decode().unwrap();
-//             let img = img.as_luma8().unwrap();
-
-//             apply_buf_vec.push(img.clone());
-//         }
-
-//         let avger = ImageAveragerFromBuffer::new_with_source(source_buf_vec);
-
-//         let mut i = 0;
-//         for ref mut buf in apply_buf_vec
-//         {
-//             let output_path = String::from("./unit_test/output_images/") + i.to_string().as_str() + ".tiff";
-
-//             avger.apply_average(buf);
-//             buf.clone().save(output_path).unwrap();
-
-//             i = i + 1;
-//         }
-//     }
-// }
\ No newline at end of file
@@ -0,0 +1,88 @@
+use image::{ImageBuffer, Luma, Primitive};
+
+use crate::error::DarkError;
+
+// A single mono frame at whatever depth the camera hands over
+pub type MonoImage<P> = ImageBuffer<Luma<P>, Vec<P>>;
+
+// Pixel depths the averager works with. Mono8 is u8, Basler's Mono12 and Mono16 both come
+// through as u16.
+pub trait DarkPixel: Primitive + Send + Sync + 'static
+{
+    fn as_u32(self) -> u32;
+
+    // Clamps anything over the type's max
+    fn from_u32_clamped(value: u32) -> Self;
+}
+
+impl DarkPixel for u8
+{
+    fn as_u32(self) -> u32
+    {
+        self as u32
+    }
+
+    fn from_u32_clamped(value: u32) -> Self
+    {
+        value.min(u8::MAX as u32) as u8
+    }
+}
+
+impl DarkPixel for u16
+{
+    fn as_u32(self) -> u32
+    {
+        self as u32
+    }
+
+    fn from_u32_clamped(value: u32) -> Self
+    {
+        value.min(u16::MAX as u32) as u16
+    }
+}
+
+// Mono8 straight from a camera buffer
+pub fn mono8_from_bytes(width: u32, height: u32, raw: &[u8]) -> Result<MonoImage<u8>, DarkError>
+{
+    let expected = width as usize * height as usize;
+    check_length(expected, raw.len())?;
+    ImageBuffer::from_raw(width, height, raw.to_vec()).ok_or(DarkError::BufferLength { expected, found: raw.len() })
+}
+
+// Mono12 and Mono16, two little endian bytes per pixel
+pub fn mono16_from_bytes(width: u32, height: u32, raw: &[u8]) -> Result<MonoImage<u16>, DarkError>
+{
+    let expected = width as usize * height as usize * 2;
+    check_length(expected, raw.len())?;
+
+    let pixels = raw.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+    ImageBuffer::from_raw(width, height, pixels).ok_or(DarkError::BufferLength { expected, found: raw.len() })
+}
+
+// Mono12p, two pixels packed into three bytes, low bits first
+pub fn mono12p_from_bytes(width: u32, height: u32, raw: &[u8]) -> Result<MonoImage<u16>, DarkError>
+{
+    let count = width as usize * height as usize;
+    let expected = count.div_ceil(2) * 3;
+    check_length(expected, raw.len())?;
+
+    let mut pixels = Vec::with_capacity(count + 1);
+    for packed in raw.chunks_exact(3)
+    {
+        let (b0, b1, b2) = (packed[0] as u16, packed[1] as u16, packed[2] as u16);
+        pixels.push(b0 | ((b1 & 0x0F) << 8));
+        pixels.push((b1 >> 4) | (b2 << 4));
+    }
+    // An odd pixel count leaves half a pair at the end
+    pixels.truncate(count);
+    ImageBuffer::from_raw(width, height, pixels).ok_or(DarkError::BufferLength { expected, found: raw.len() })
+}
+
+fn check_length(expected: usize, found: usize) -> Result<(), DarkError>
+{
+    if expected != found
+    {
+        return Err(DarkError::BufferLength { expected, found });
+    }
+    Ok(())
+}
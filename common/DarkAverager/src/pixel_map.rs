@@ -0,0 +1,488 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::error::DarkError;
+use crate::estimator::MAD_TO_SIGMA;
+use crate::pixel::{DarkPixel, MonoImage};
+
+// Marks the start of a saved pixel map, followed by a format version
+const MAP_MAGIC: &[u8; 5] = b"PXMAP";
+const MAP_VERSION: u8 = 1;
+// Magic, version, width and height
+const MAP_HEADER_LEN: u64 = 14;
+
+// Flag bits, a pixel can be more than one
+pub const HOT: u8 = 1 << 0;
+pub const NOISY: u8 = 1 << 1;
+pub const DEAD: u8 = 1 << 2;
+
+// Per-pixel running sums over a set of dark frames. Sums are exact so the order frames come in
+// doesn't matter.
+pub struct PixelStats
+{
+    width: u32,
+    height: u32,
+    frames: u32,
+    sum: Vec<u64>,
+    sum_sq: Vec<u64>,
+}
+
+impl PixelStats
+{
+    pub fn new(width: u32, height: u32) -> PixelStats
+    {
+        let len = width as usize * height as usize;
+        PixelStats
+        {
+            width,
+            height,
+            frames: 0,
+            sum: vec![0; len],
+            sum_sq: vec![0; len],
+        }
+    }
+
+    // Build from a whole set of frames at once
+    pub fn from_frames<P: DarkPixel>(frames: &[MonoImage<P>]) -> Result<PixelStats, DarkError>
+    {
+        let first = frames.first().ok_or(DarkError::NoFrames)?;
+        let mut stats = PixelStats::new(first.width(), first.height());
+        for frame in frames
+        {
+            stats.add(frame)?;
+        }
+        Ok(stats)
+    }
+
+    // Frames that don't match the stats dimensions are rejected
+    pub fn add<P: DarkPixel>(&mut self, frame: &MonoImage<P>) -> Result<(), DarkError>
+    {
+        DarkError::check_size(self.width, self.height, frame.width(), frame.height())?;
+
+        for (i, pixel) in frame.as_raw().iter().enumerate()
+        {
+            let value = pixel.as_u32() as u64;
+            self.sum[i] += value;
+            self.sum_sq[i] += value * value;
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    pub fn frames(&self) -> u32
+    {
+        self.frames
+    }
+
+    pub fn mean(&self, index: usize) -> f32
+    {
+        if self.frames == 0
+        {
+            return 0.0;
+        }
+        (self.sum[index] as f64 / self.frames as f64) as f32
+    }
+
+    // Sample variance, zero with fewer than two frames
+    pub fn variance(&self, index: usize) -> f32
+    {
+        if self.frames < 2
+        {
+            return 0.0;
+        }
+        let n = self.frames as f64;
+        let sum = self.sum[index] as f64;
+        let spread = self.sum_sq[index] as f64 - sum * sum / n;
+        (spread.max(0.0) / (n - 1.0)) as f32
+    }
+
+    // Classify every pixel against the rest of the frame
+    pub fn find_bad_pixels(&self, thresholds: &BadPixelThresholds) -> PixelMap
+    {
+        let len = self.sum.len();
+        let mut map = PixelMap::new(self.width, self.height);
+        if self.frames == 0 || len == 0
+        {
+            return map;
+        }
+
+        let means: Vec<f32> = (0..len).map(|i| self.mean(i)).collect();
+        let variances: Vec<f32> = (0..len).map(|i| self.variance(i)).collect();
+
+        // Median and MAD rather than mean and std so the bad pixels don't drag the baseline
+        let typical_level = median(&means);
+        let deviations: Vec<f32> = means.iter().map(|m| (m - typical_level).abs()).collect();
+        let level_sigma = median(&deviations) * MAD_TO_SIGMA as f32;
+        let typical_variance = median(&variances);
+
+        let hot_level =
+            typical_level + (thresholds.hot_sigma * level_sigma).max(thresholds.min_excess);
+        let dead_level =
+            typical_level - (thresholds.dead_sigma * level_sigma).max(thresholds.min_excess);
+
+        for i in 0..len
+        {
+            let mut flags = 0;
+            if means[i] > hot_level
+            {
+                flags |= HOT;
+            }
+            if typical_variance > 0.0 && variances[i] > typical_variance * thresholds.noisy_factor
+            {
+                flags |= NOISY;
+            }
+            // Reading well under everyone else, or stuck at one value while everyone else moves.
+            // A pixel stuck at zero in a dark frame looks like a clipped good pixel, so zero is
+            // left alone.
+            let stuck = self.frames >= 2 && typical_variance > 0.0 && variances[i] == 0.0 && means[i] > 0.0;
+            if means[i] < dead_level || stuck
+            {
+                flags |= DEAD;
+            }
+            map.flags[i] = flags;
+        }
+        map
+    }
+}
+
+// What counts as a bad pixel
+#[derive(Debug, Clone, Copy)]
+pub struct BadPixelThresholds
+{
+    // Hot when the mean dark level sits this many sigmas over the typical pixel
+    pub hot_sigma: f32,
+    // Dead when the mean dark level sits this many sigmas under the typical pixel
+    pub dead_sigma: f32,
+    // Noisy when the variance is this many times the typical pixel's
+    pub noisy_factor: f32,
+    // Smallest distance from the typical level, in counts, that can be hot or dead. Stops a
+    // very clean sensor with a tiny sigma flagging ordinary pixels.
+    pub min_excess: f32,
+}
+
+impl Default for BadPixelThresholds
+{
+    fn default() -> BadPixelThresholds
+    {
+        BadPixelThresholds
+        {
+            hot_sigma: 6.0,
+            dead_sigma: 6.0,
+            noisy_factor: 10.0,
+            min_excess: 8.0,
+        }
+    }
+}
+
+// Which pixels can't be trusted, one byte of flags per pixel
+#[derive(Debug, Clone, PartialEq)]
+pub struct PixelMap
+{
+    width: u32,
+    height: u32,
+    flags: Vec<u8>,
+}
+
+impl PixelMap
+{
+    // All good pixels
+    pub fn new(width: u32, height: u32) -> PixelMap
+    {
+        PixelMap
+        {
+            width,
+            height,
+            flags: vec![0; width as usize * height as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32
+    {
+        self.width
+    }
+
+    pub fn height(&self) -> u32
+    {
+        self.height
+    }
+
+    pub fn flags(&self, x: u32, y: u32) -> u8
+    {
+        self.flags[self.index(x, y)]
+    }
+
+    pub fn mark(&mut self, x: u32, y: u32, flags: u8)
+    {
+        let index = self.index(x, y);
+        self.flags[index] |= flags;
+    }
+
+    pub fn is_bad(&self, x: u32, y: u32) -> bool
+    {
+        self.flags(x, y) != 0
+    }
+
+    // Number of pixels with any of the given flags set
+    pub fn count(&self, flags: u8) -> usize
+    {
+        self.flags.iter().filter(|f| **f & flags != 0).count()
+    }
+
+    // Bad pixels don't heal, so a map from a previous boot is folded into the new one rather
+    // than replaced. Leaves the map alone if the sizes don't match.
+    pub fn merge(&mut self, other: &PixelMap) -> Result<(), DarkError>
+    {
+        DarkError::check_size(self.width, self.height, other.width, other.height)?;
+        for (flags, other_flags) in self.flags.iter_mut().zip(&other.flags)
+        {
+            *flags |= other_flags;
+        }
+        Ok(())
+    }
+
+    // Replace each bad pixel with the average of the good pixels around it. Looks one pixel out
+    // first and two if that's all bad, and leaves the pixel alone if there's nothing good nearby.
+    pub fn correct<P: DarkPixel>(&self, img: &mut MonoImage<P>) -> Result<(), DarkError>
+    {
+        DarkError::check_size(self.width, self.height, img.width(), img.height())?;
+
+        // Neighbors are read from the uncorrected image so clusters don't smear into each other
+        let source = img.clone();
+        for y in 0..self.height
+        {
+            for x in 0..self.width
+            {
+                if !self.is_bad(x, y)
+                {
+                    continue;
+                }
+                for radius in 1..=2
+                {
+                    if let Some(value) = self.neighbor_average(&source, x, y, radius)
+                    {
+                        img[(x, y)][0] = value;
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), DarkError>
+    {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAP_MAGIC)?;
+        writer.write_all(&[MAP_VERSION])?;
+        writer.write_all(&self.width.to_le_bytes())?;
+        writer.write_all(&self.height.to_le_bytes())?;
+        writer.write_all(&self.flags)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<PixelMap, DarkError>
+    {
+        let file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0u8; 6];
+        reader.read_exact(&mut magic)?;
+        if &magic[..5] != MAP_MAGIC || magic[5] != MAP_VERSION
+        {
+            return Err(DarkError::BadPixelMap);
+        }
+
+        let mut size = [0u8; 4];
+        reader.read_exact(&mut size)?;
+        let width = u32::from_le_bytes(size);
+        reader.read_exact(&mut size)?;
+        let height = u32::from_le_bytes(size);
+
+        // One byte of flags per pixel and nothing after them. The size comes from the file, so
+        // it's checked against what the file holds before anything is allocated for it.
+        let expected = (width as usize)
+            .checked_mul(height as usize)
+            .ok_or(DarkError::BadPixelMap)?;
+        let found = file_len.saturating_sub(MAP_HEADER_LEN) as usize;
+        if found != expected
+        {
+            return Err(DarkError::BufferLength { expected, found });
+        }
+        let mut flags = vec![0; expected];
+        reader.read_exact(&mut flags)?;
+        Ok(PixelMap { width, height, flags })
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize
+    {
+        y as usize * self.width as usize + x as usize
+    }
+
+    fn neighbor_average<P: DarkPixel>(&self, img: &MonoImage<P>, x: u32, y: u32, radius: u32) -> Option<P>
+    {
+        let mut sum = 0u64;
+        let mut count = 0u64;
+        for ny in y.saturating_sub(radius)..=(y + radius).min(self.height - 1)
+        {
+            for nx in x.saturating_sub(radius)..=(x + radius).min(self.width - 1)
+            {
+                if !self.is_bad(nx, ny)
+                {
+                    sum += img[(nx, ny)][0].as_u32() as u64;
+                    count += 1;
+                }
+            }
+        }
+        if count == 0
+        {
+            return None;
+        }
+        Some(P::from_u32_clamped(((sum + count / 2) / count) as u32))
+    }
+}
+
+fn median(values: &[f32]) -> f32
+{
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable_by(|a, b| a.total_cmp(b));
+    sorted[sorted.len() / 2]
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use image::{GrayImage, ImageBuffer};
+
+    // Small deterministic generator so the frames are noisy but repeatable
+    struct Noise(u32);
+
+    impl Noise
+    {
+        fn next(&mut self, spread: u8) -> u8
+        {
+            self.0 = self.0.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            ((self.0 >> 24) % (spread as u32 + 1)) as u8
+        }
+    }
+
+    // Dark frames around a level of 20 with a few counts of noise, plus a hot pixel at (3, 4),
+    // a stuck pixel at (10, 2), a dead pixel at (7, 7) and a flickering one at (12, 12)
+    fn dark_frames(count: usize) -> Vec<GrayImage>
+    {
+        let mut noise = Noise(7);
+        let mut frames = vec![];
+        for n in 0..count
+        {
+            let mut frame: GrayImage = ImageBuffer::from_fn(16, 16, |_, _| image::Luma([18 + noise.next(4)]));
+            frame[(3, 4)][0] = 200 + noise.next(4);
+            frame[(10, 2)][0] = 90;
+            frame[(7, 7)][0] = 0;
+            frame[(12, 12)][0] = if n % 2 == 0 { 0 } else { 80 };
+            frames.push(frame);
+        }
+        frames
+    }
+
+    #[test]
+    fn test_finds_bad_pixels()
+    {
+        let stats = PixelStats::from_frames(&dark_frames(20)).unwrap();
+        assert_eq!(stats.frames(), 20);
+
+        let map = stats.find_bad_pixels(&BadPixelThresholds::default());
+        assert!(map.flags(3, 4) & HOT != 0);
+        assert!(map.flags(10, 2) & DEAD != 0);
+        assert!(map.flags(7, 7) & DEAD != 0);
+        assert!(map.flags(12, 12) & NOISY != 0);
+        assert_eq!(map.count(HOT | NOISY | DEAD), 4);
+    }
+
+    #[test]
+    fn test_mismatched_frame_skipped()
+    {
+        let mut stats = PixelStats::new(16, 16);
+        let small: GrayImage = ImageBuffer::new(8, 8);
+        assert!(matches!(stats.add(&small), Err(DarkError::SizeMismatch { .. })));
+        assert_eq!(stats.frames(), 0);
+        assert!(matches!(PixelStats::from_frames::<u8>(&[]), Err(DarkError::NoFrames)));
+    }
+
+    #[test]
+    fn test_correct_interpolates_neighbors()
+    {
+        let mut map = PixelMap::new(5, 5);
+        map.mark(2, 2, HOT);
+        // A bad neighbor shouldn't be part of the average
+        map.mark(1, 2, HOT);
+
+        let mut img: GrayImage = ImageBuffer::from_pixel(5, 5, image::Luma([10]));
+        img[(2, 2)][0] = 255;
+        img[(1, 2)][0] = 255;
+        img[(3, 2)][0] = 18;
+
+        map.correct(&mut img).unwrap();
+        // Seven good neighbors, one of them 18
+        assert_eq!(img[(2, 2)][0], 11);
+        assert!(img[(1, 2)][0] < 20);
+    }
+
+    #[test]
+    fn test_correct_widens_search()
+    {
+        let mut map = PixelMap::new(5, 5);
+        for y in 1..4
+        {
+            for x in 1..4
+            {
+                map.mark(x, y, DEAD);
+            }
+        }
+
+        let mut img: GrayImage = ImageBuffer::from_pixel(5, 5, image::Luma([40]));
+        img[(2, 2)][0] = 0;
+        map.correct(&mut img).unwrap();
+        assert_eq!(img[(2, 2)][0], 40);
+    }
+
+    #[test]
+    fn test_save_load_merge()
+    {
+        let mut map = PixelMap::new(16, 16);
+        map.mark(3, 4, HOT);
+        map.mark(0, 15, DEAD);
+
+        let path = std::env::temp_dir().join(format!("pixel_map_{}.bin", std::process::id()));
+        map.save(&path).unwrap();
+        let loaded = PixelMap::load(&path).unwrap();
+        assert_eq!(loaded, map);
+
+        // Last boot's map carries into this boot's
+        let mut fresh = PixelMap::new(16, 16);
+        fresh.mark(8, 8, NOISY);
+        fresh.merge(&loaded).unwrap();
+        assert_eq!(fresh.count(HOT | NOISY | DEAD), 3);
+        assert!(fresh.merge(&PixelMap::new(8, 8)).is_err());
+
+        std::fs::write(&path, b"garbage").unwrap();
+        assert!(matches!(PixelMap::load(&path), Err(DarkError::BadPixelMap)));
+
+        // Header promising more pixels than follow it, or fewer
+        map.save(&path).unwrap();
+        let mut bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(matches!(PixelMap::load(&path), Err(DarkError::BufferLength { expected: 256, found: 255 })));
+        bytes.push(0);
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(PixelMap::load(&path), Err(DarkError::BufferLength { expected: 256, found: 257 })));
+
+        // A size that can't be allocated is turned away before anything is
+        bytes[6..14].copy_from_slice(&[0xff; 8]);
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(PixelMap::load(&path).is_err());
+        std::fs::remove_file(&path).ok();
+    }
+}
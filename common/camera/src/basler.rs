@@ -0,0 +1,154 @@
+//! Basler cameras through the Pylon SDK
+
+use std::time::{Duration, SystemTime};
+
+use log::info;
+use pylon_cxx::{GrabOptions, GrabResult, GrabStrategy, InstantCamera, Pylon, TimeoutHandling, TlFactory};
+
+use crate::{Camera, CameraError, Frame, GrayFrame, TriggerMode};
+
+/// The first Basler camera Pylon finds, opened and set up for 8-bit mono. It borrows the
+/// [`Pylon`] instance, which has to outlive it.
+pub struct BaslerCamera<'a> {
+    camera: InstantCamera<'a>,
+    grab_result: GrabResult,
+    mode: TriggerMode,
+    sequence: u64,
+}
+
+impl<'a> BaslerCamera<'a> {
+    /// Open the first camera found
+    pub fn first(pylon: &'a Pylon) -> Result<Self, CameraError> {
+        let camera = TlFactory::instance(pylon).create_first_device()?;
+        camera.open()?;
+
+        let node_map = camera.node_map()?;
+        let formats = node_map.enum_node("PixelFormat")?.settable_values()?;
+        info!("Basler camera opened, pixel formats: {}", formats.join(", "));
+
+        Ok(Self {
+            camera,
+            grab_result: GrabResult::new()?,
+            mode: TriggerMode::FreeRun,
+            sequence: 0,
+        })
+    }
+
+    /// The Pylon camera, for nodes the trait doesn't cover
+    pub fn inner(&mut self) -> &mut InstantCamera<'a> {
+        &mut self.camera
+    }
+
+    fn set_enum(&self, name: &str, value: &str) -> Result<(), CameraError> {
+        self.camera.node_map()?.enum_node(name)?.set_value(value)?;
+        Ok(())
+    }
+}
+
+impl Camera for BaslerCamera<'_> {
+    fn start(&mut self) -> Result<(), CameraError> {
+        if !self.camera.is_grabbing() {
+            self.sequence = 0;
+            // Star trackers want the newest sky, never a backlog
+            self.camera
+                .start_grabbing(&GrabOptions::default().strategy(GrabStrategy::LatestImageOnly))?;
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), CameraError> {
+        if self.camera.is_grabbing() {
+            self.camera.stop_grabbing()?;
+        }
+        Ok(())
+    }
+
+    fn is_streaming(&self) -> bool {
+        self.camera.is_grabbing()
+    }
+
+    fn set_exposure(&mut self, exposure: Duration) -> Result<(), CameraError> {
+        self.camera
+            .node_map()?
+            .float_node("ExposureTime")?
+            .set_value(exposure.as_secs_f64() * 1e6)?;
+        Ok(())
+    }
+
+    fn exposure(&self) -> Result<Duration, CameraError> {
+        let us = self.camera.node_map()?.float_node("ExposureTime")?.value()?;
+        Ok(Duration::from_secs_f64(us.max(0.0) / 1e6))
+    }
+
+    fn set_gain(&mut self, gain_db: f32) -> Result<(), CameraError> {
+        self.camera.node_map()?.float_node("Gain")?.set_value(gain_db as f64)?;
+        Ok(())
+    }
+
+    fn gain(&self) -> Result<f32, CameraError> {
+        Ok(self.camera.node_map()?.float_node("Gain")?.value()? as f32)
+    }
+
+    fn set_trigger_mode(&mut self, mode: TriggerMode) -> Result<(), CameraError> {
+        self.set_enum("TriggerSelector", "FrameStart")?;
+        match mode {
+            TriggerMode::FreeRun => self.set_enum("TriggerMode", "Off")?,
+            TriggerMode::Software => {
+                self.set_enum("TriggerSource", "Software")?;
+                self.set_enum("TriggerMode", "On")?;
+            }
+        }
+        self.mode = mode;
+        Ok(())
+    }
+
+    fn trigger(&mut self) -> Result<(), CameraError> {
+        if self.mode != TriggerMode::Software {
+            return Err(CameraError::Unsupported("software trigger while free running"));
+        }
+        if !self.camera.is_grabbing() {
+            return Err(CameraError::NotStreaming);
+        }
+        self.camera.node_map()?.command_node("TriggerSoftware")?.execute(true)?;
+        Ok(())
+    }
+
+    fn grab(&mut self, timeout: Duration) -> Result<Frame, CameraError> {
+        if !self.camera.is_grabbing() {
+            return Err(CameraError::NotStreaming);
+        }
+
+        let timeout_ms = timeout.as_millis().min(u32::MAX as u128) as u32;
+        if !self
+            .camera
+            .retrieve_result(timeout_ms, &mut self.grab_result, TimeoutHandling::Return)?
+        {
+            return Err(CameraError::Timeout);
+        }
+        if !self.grab_result.grab_succeeded()? {
+            return Err(CameraError::GrabFailed("Pylon flagged the grab failed".to_string()));
+        }
+        let captured = SystemTime::now();
+
+        let (width, height) = (self.grab_result.width()?, self.grab_result.height()?);
+        let buffer = self.grab_result.buffer()?;
+        let expected = (width * height) as usize;
+        if buffer.len() < expected {
+            return Err(CameraError::BufferLength {
+                width,
+                height,
+                expected,
+                found: buffer.len(),
+            });
+        }
+        let image = GrayFrame::from_raw(width, height, buffer[..expected].to_vec()).expect("length checked");
+
+        let frame = Frame {
+            image,
+            sequence: self.sequence,
+            captured,
+        };
+        self.sequence += 1;
+        Ok(frame)
+    }
+}
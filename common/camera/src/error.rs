@@ -0,0 +1,50 @@
+//! What can go wrong talking to a camera
+
+use thiserror::Error;
+
+/// Why a camera call failed
+#[derive(Error, Debug)]
+pub enum CameraError {
+    /// Frames were asked for before [`Camera::start`](crate::Camera::start)
+    #[error("Camera isn't streaming")]
+    NotStreaming,
+    /// No frame came in time
+    #[error("No frame within the timeout")]
+    Timeout,
+    /// The camera delivered a frame but flagged it bad
+    #[error("Grab failed: {0}")]
+    GrabFailed(String),
+    /// The backend can't do what was asked
+    #[error("Not supported by this camera: {0}")]
+    Unsupported(&'static str),
+    /// A setting was out of range
+    #[error("Invalid setting: {0}")]
+    InvalidSetting(String),
+    /// The frame wasn't the size or format expected
+    #[error("Frame is {found} bytes, expected {expected} for {width}x{height}")]
+    BufferLength {
+        /// Frame width
+        width: u32,
+        /// Frame height
+        height: u32,
+        /// Bytes expected
+        expected: usize,
+        /// Bytes delivered
+        found: usize,
+    },
+    /// A star catalog line didn't parse
+    #[error("Catalog line {line}: {reason}")]
+    Catalog {
+        /// Line number, from one
+        line: usize,
+        /// What was wrong with it
+        reason: String,
+    },
+    /// Device or file IO failed
+    #[error("Camera IO error: {0}")]
+    Io(#[from] std::io::Error),
+    /// The Pylon SDK reported an error
+    #[cfg(feature = "pylon")]
+    #[error("Pylon error: {0}")]
+    Pylon(#[from] pylon_cxx::PylonError),
+}
@@ -0,0 +1,139 @@
+#![warn(missing_docs)]
+
+//! Cameras for the star trackers, behind one trait so the tracking loops don't care whether
+//! frames come from a Basler over Pylon, a V4L2 device, or a star field rendered on the spot.
+//!
+//! The hardware backends are behind the `pylon` and `v4l` features. The synthetic backend is
+//! always built, it's what lets the tracking loops run on a laptop.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use image::{ImageBuffer, Luma};
+
+pub mod error;
+pub mod synthetic;
+
+#[cfg(feature = "pylon")]
+pub mod basler;
+#[cfg(feature = "v4l")]
+pub mod v4l_capture;
+
+pub use error::CameraError;
+pub use synthetic::{AttitudeHandle, CatalogStar, Optics, SensorModel, StarCatalog, SyntheticCamera};
+
+#[cfg(feature = "pylon")]
+pub use basler::BaslerCamera;
+#[cfg(feature = "v4l")]
+pub use v4l_capture::V4lCamera;
+
+/// An 8-bit mono frame, what every backend delivers
+pub type GrayFrame = ImageBuffer<Luma<u8>, Vec<u8>>;
+
+/// What starts an exposure
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TriggerMode {
+    /// The camera exposes back to back as fast as the exposure allows
+    #[default]
+    FreeRun,
+    /// One exposure per call to [`Camera::trigger`]
+    Software,
+}
+
+/// A grabbed frame
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// The pixels
+    pub image: GrayFrame,
+    /// Counts up from zero each time the camera starts. A gap means frames were dropped.
+    pub sequence: u64,
+    /// Wall clock time the frame came off the camera
+    pub captured: SystemTime,
+}
+
+impl Frame {
+    /// When the frame came off the camera, in unix ms, the way packets and file names carry it
+    pub fn unix_ms(&self) -> u64 {
+        self.captured
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0)
+    }
+}
+
+/// A camera that can be set up and streamed from
+///
+/// Settings can be changed while streaming, and take effect from the next exposure where the
+/// hardware allows. Gain is in dB over the sensor's base gain.
+pub trait Camera {
+    /// Start delivering frames
+    fn start(&mut self) -> Result<(), CameraError>;
+
+    /// Stop delivering frames. Stopping a stopped camera does nothing.
+    fn stop(&mut self) -> Result<(), CameraError>;
+
+    /// Whether frames are being delivered
+    fn is_streaming(&self) -> bool;
+
+    /// Set the exposure time
+    fn set_exposure(&mut self, exposure: Duration) -> Result<(), CameraError>;
+
+    /// The exposure time in use
+    fn exposure(&self) -> Result<Duration, CameraError>;
+
+    /// Set the gain, in dB
+    fn set_gain(&mut self, gain_db: f32) -> Result<(), CameraError>;
+
+    /// The gain in use, in dB
+    fn gain(&self) -> Result<f32, CameraError>;
+
+    /// Choose what starts an exposure
+    fn set_trigger_mode(&mut self, mode: TriggerMode) -> Result<(), CameraError>;
+
+    /// Start an exposure, in [`TriggerMode::Software`]
+    fn trigger(&mut self) -> Result<(), CameraError>;
+
+    /// Wait up to `timeout` for the next frame
+    fn grab(&mut self, timeout: Duration) -> Result<Frame, CameraError>;
+}
+
+impl<C: Camera + ?Sized> Camera for Box<C> {
+    fn start(&mut self) -> Result<(), CameraError> {
+        (**self).start()
+    }
+
+    fn stop(&mut self) -> Result<(), CameraError> {
+        (**self).stop()
+    }
+
+    fn is_streaming(&self) -> bool {
+        (**self).is_streaming()
+    }
+
+    fn set_exposure(&mut self, exposure: Duration) -> Result<(), CameraError> {
+        (**self).set_exposure(exposure)
+    }
+
+    fn exposure(&self) -> Result<Duration, CameraError> {
+        (**self).exposure()
+    }
+
+    fn set_gain(&mut self, gain_db: f32) -> Result<(), CameraError> {
+        (**self).set_gain(gain_db)
+    }
+
+    fn gain(&self) -> Result<f32, CameraError> {
+        (**self).gain()
+    }
+
+    fn set_trigger_mode(&mut self, mode: TriggerMode) -> Result<(), CameraError> {
+        (**self).set_trigger_mode(mode)
+    }
+
+    fn trigger(&mut self) -> Result<(), CameraError> {
+        (**self).trigger()
+    }
+
+    fn grab(&mut self, timeout: Duration) -> Result<Frame, CameraError> {
+        (**self).grab(timeout)
+    }
+}
@@ -0,0 +1,116 @@
+//! Stars to draw, as unit vectors in the reference (ICRF) frame
+
+use std::fs;
+use std::path::Path;
+
+use crate::CameraError;
+
+/// A catalog star
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CatalogStar {
+    /// Unit vector to the star in the reference frame
+    pub direction: [f32; 3],
+    /// Visual magnitude, brighter is smaller
+    pub magnitude: f32,
+}
+
+impl CatalogStar {
+    /// A star at right ascension and declination, both in degrees
+    pub fn from_ra_dec(ra_deg: f32, dec_deg: f32, magnitude: f32) -> Self {
+        let (ra, dec) = (ra_deg.to_radians(), dec_deg.to_radians());
+        Self {
+            direction: [dec.cos() * ra.cos(), dec.cos() * ra.sin(), dec.sin()],
+            magnitude,
+        }
+    }
+}
+
+/// The stars a synthetic camera can see
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StarCatalog {
+    stars: Vec<CatalogStar>,
+}
+
+impl StarCatalog {
+    /// A catalog of these stars
+    pub fn new(stars: Vec<CatalogStar>) -> Self {
+        Self { stars }
+    }
+
+    /// Parse `ra_deg,dec_deg,magnitude` lines. Blank lines, lines starting with `#` and a header
+    /// line are skipped.
+    pub fn parse_csv(text: &str) -> Result<Self, CameraError> {
+        let mut stars = Vec::new();
+
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<_> = line.split(',').map(str::trim).collect();
+            let parsed: Result<Vec<f32>, _> = fields.iter().map(|f| f.parse()).collect();
+            match parsed {
+                Ok(values) if values.len() == 3 => {
+                    stars.push(CatalogStar::from_ra_dec(values[0], values[1], values[2]));
+                }
+                Ok(values) => {
+                    return Err(CameraError::Catalog {
+                        line: n + 1,
+                        reason: format!("{} fields, expected ra,dec,magnitude", values.len()),
+                    });
+                }
+                // Column names
+                Err(_) if stars.is_empty() && n == 0 => continue,
+                Err(e) => {
+                    return Err(CameraError::Catalog {
+                        line: n + 1,
+                        reason: e.to_string(),
+                    });
+                }
+            }
+        }
+
+        Ok(Self { stars })
+    }
+
+    /// Load a catalog saved as [`StarCatalog::parse_csv`] reads it
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, CameraError> {
+        Self::parse_csv(&fs::read_to_string(path)?)
+    }
+
+    /// Every star
+    pub fn stars(&self) -> &[CatalogStar] {
+        &self.stars
+    }
+
+    /// Stars no fainter than `magnitude`, what a real sensor would pick up
+    pub fn brighter_than(&self, magnitude: f32) -> Self {
+        Self {
+            stars: self.stars.iter().filter(|s| s.magnitude <= magnitude).copied().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv() {
+        let text = "ra,dec,mag\n# Polaris\n37.95,89.26,1.98\n\n0,0,3.5\n";
+        let catalog = StarCatalog::parse_csv(text).unwrap();
+        assert_eq!(catalog.stars().len(), 2);
+
+        let equator = catalog.stars()[1];
+        assert!((equator.direction[0] - 1.0).abs() < 1e-6);
+        assert_eq!(equator.magnitude, 3.5);
+        assert_eq!(catalog.brighter_than(2.0).stars().len(), 1);
+
+        assert!(matches!(
+            StarCatalog::parse_csv("0,0,1\n0,zero,1\n"),
+            Err(CameraError::Catalog { line: 2, .. })
+        ));
+        assert!(matches!(StarCatalog::parse_csv("0,0\n"), Err(CameraError::Catalog { line: 1, .. })));
+    }
+}
@@ -0,0 +1,615 @@
+//! A camera that renders the star field it would see at a given attitude
+//!
+//! Stars from a [`StarCatalog`] are rotated into the body frame, projected through a pinhole
+//! looking down body +z, and spread over a Gaussian PSF. Dark current, hot pixels, shot noise and
+//! read noise go on top, then the electrons are scaled by the gain into 8-bit counts. Image x
+//! follows body x and image y follows body y, pixel centers are at whole coordinates.
+
+pub mod catalog;
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use rand::rngs::SmallRng;
+use rand::seq::index;
+use rand::{Rng, SeedableRng};
+use rand_distr::StandardNormal;
+
+use crate::{Camera, CameraError, Frame, GrayFrame, TriggerMode};
+
+pub use catalog::{CatalogStar, StarCatalog};
+
+/// Rotation taking reference frame vectors into the body frame for a `[w, i, j, k]` quaternion,
+/// the same convention QUEST solves in (body = A * reference)
+pub fn attitude_matrix(q: [f32; 4]) -> [[f32; 3]; 3] {
+    let [w, x, y, z] = q;
+    [
+        [w * w + x * x - y * y - z * z, 2.0 * (x * y + w * z), 2.0 * (x * z - w * y)],
+        [2.0 * (x * y - w * z), w * w - x * x + y * y - z * z, 2.0 * (y * z + w * x)],
+        [2.0 * (x * z + w * y), 2.0 * (y * z - w * x), w * w - x * x - y * y + z * z],
+    ]
+}
+
+/// The lens and sensor geometry, as an ideal pinhole
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Optics {
+    /// Sensor width in pixels
+    pub width: u32,
+    /// Sensor height in pixels
+    pub height: u32,
+    /// Focal length in pixels
+    pub focal_px: f32,
+    /// Where the boresight lands, in pixels
+    pub principal_point: [f32; 2],
+}
+
+impl Optics {
+    /// A sensor with the boresight in the middle
+    pub fn new(width: u32, height: u32, focal_px: f32) -> Self {
+        Self {
+            width,
+            height,
+            focal_px,
+            principal_point: [(width as f32 - 1.0) / 2.0, (height as f32 - 1.0) / 2.0],
+        }
+    }
+
+    /// A sensor with the boresight in the middle and this horizontal field of view, in degrees
+    pub fn with_fov(width: u32, height: u32, horizontal_fov_deg: f32) -> Self {
+        let focal_px = width as f32 / 2.0 / (horizontal_fov_deg.to_radians() / 2.0).tan();
+        Self::new(width, height, focal_px)
+    }
+
+    /// Where a body frame direction lands on the sensor, if it's in front of the lens. The point
+    /// can be off the edge of the sensor.
+    pub fn project(&self, body: [f32; 3]) -> Option<[f32; 2]> {
+        if body[2] <= 0.0 {
+            return None;
+        }
+        Some([
+            self.principal_point[0] + self.focal_px * body[0] / body[2],
+            self.principal_point[1] + self.focal_px * body[1] / body[2],
+        ])
+    }
+}
+
+/// How the sensor turns light into counts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SensorModel {
+    /// Gaussian PSF standard deviation, in pixels
+    pub psf_sigma: f32,
+    /// Electrons per second collected from a magnitude 0 star
+    pub zero_mag_flux: f32,
+    /// Dark current, in electrons per pixel per second
+    pub dark_current: f32,
+    /// Number of hot pixels
+    pub hot_pixels: usize,
+    /// Extra dark current in a hot pixel, in electrons per second
+    pub hot_pixel_current: f32,
+    /// Read noise, in electrons RMS
+    pub read_noise: f32,
+    /// Add Poisson noise on the collected electrons
+    pub shot_noise: bool,
+    /// Counts per electron at 0 dB
+    pub counts_per_electron: f32,
+    /// Counts added to every pixel
+    pub bias: f32,
+    /// Seeds the hot pixel layout and the noise. The same seed gives the same sensor.
+    pub seed: u64,
+}
+
+impl Default for SensorModel {
+    fn default() -> Self {
+        Self {
+            psf_sigma: 1.2,
+            zero_mag_flux: 5.0e7,
+            dark_current: 20.0,
+            hot_pixels: 50,
+            hot_pixel_current: 2.0e4,
+            read_noise: 3.0,
+            shot_noise: true,
+            counts_per_electron: 0.1,
+            bias: 8.0,
+            seed: 0,
+        }
+    }
+}
+
+impl SensorModel {
+    /// No noise, no dark current and no hot pixels, only the stars
+    pub fn ideal() -> Self {
+        Self {
+            dark_current: 0.0,
+            hot_pixels: 0,
+            read_noise: 0.0,
+            shot_noise: false,
+            bias: 0.0,
+            ..Default::default()
+        }
+    }
+}
+
+/// Shared attitude of a [`SyntheticCamera`], so a test can slew it while a tracking loop owns the
+/// camera
+#[derive(Debug, Clone)]
+pub struct AttitudeHandle(Arc<Mutex<[f32; 4]>>);
+
+impl AttitudeHandle {
+    /// Point the camera, as a `[w, i, j, k]` quaternion of the body relative to the reference
+    /// frame. It's normalized here.
+    pub fn set(&self, q: [f32; 4]) {
+        let norm = q.iter().map(|c| c * c).sum::<f32>().sqrt();
+        let q = if norm > 0.0 { q.map(|c| c / norm) } else { [1.0, 0.0, 0.0, 0.0] };
+        *self.0.lock().unwrap_or_else(|e| e.into_inner()) = q;
+    }
+
+    /// Where the camera is pointed
+    pub fn get(&self) -> [f32; 4] {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A camera with no hardware behind it, rendering each frame from a catalog at the current
+/// attitude
+pub struct SyntheticCamera {
+    catalog: StarCatalog,
+    optics: Optics,
+    sensor: SensorModel,
+    attitude: AttitudeHandle,
+    hot_pixels: Vec<usize>,
+    rng: SmallRng,
+
+    exposure: Duration,
+    gain_db: f32,
+    mode: TriggerMode,
+    streaming: bool,
+    sequence: u64,
+    // Free run: when the exposure in progress finishes. Software: when the triggered one does.
+    next_frame: Option<Instant>,
+}
+
+impl SyntheticCamera {
+    /// A camera over `catalog`, pointed along the reference frame's +z, with a 10ms exposure and
+    /// no gain
+    pub fn new(catalog: StarCatalog, optics: Optics, sensor: SensorModel) -> Self {
+        let mut rng = SmallRng::seed_from_u64(sensor.seed);
+        let pixels = (optics.width * optics.height) as usize;
+        let hot_pixels = index::sample(&mut rng, pixels, sensor.hot_pixels.min(pixels)).into_vec();
+
+        Self {
+            catalog,
+            optics,
+            sensor,
+            attitude: AttitudeHandle(Arc::new(Mutex::new([1.0, 0.0, 0.0, 0.0]))),
+            hot_pixels,
+            rng,
+            exposure: Duration::from_millis(10),
+            gain_db: 0.0,
+            mode: TriggerMode::FreeRun,
+            streaming: false,
+            sequence: 0,
+            next_frame: None,
+        }
+    }
+
+    /// Handle to point the camera with, from this or any other thread
+    pub fn attitude(&self) -> AttitudeHandle {
+        self.attitude.clone()
+    }
+
+    /// The sensor geometry
+    pub fn optics(&self) -> &Optics {
+        &self.optics
+    }
+
+    /// Where the hot pixels are, as `(x, y)`
+    pub fn hot_pixels(&self) -> Vec<(u32, u32)> {
+        let width = self.optics.width as usize;
+        self.hot_pixels
+            .iter()
+            .map(|&i| ((i % width) as u32, (i / width) as u32))
+            .collect()
+    }
+
+    /// Where each catalog star in view lands at the current attitude, with its magnitude
+    pub fn visible_stars(&self) -> Vec<([f32; 2], f32)> {
+        let a = attitude_matrix(self.attitude.get());
+        let (width, height) = (self.optics.width as f32, self.optics.height as f32);
+
+        self.catalog
+            .stars()
+            .iter()
+            .filter_map(|star| {
+                let r = star.direction;
+                let body = [
+                    a[0][0] * r[0] + a[0][1] * r[1] + a[0][2] * r[2],
+                    a[1][0] * r[0] + a[1][1] * r[1] + a[1][2] * r[2],
+                    a[2][0] * r[0] + a[2][1] * r[1] + a[2][2] * r[2],
+                ];
+                let [x, y] = self.optics.project(body)?;
+                let on_sensor = (-0.5..width - 0.5).contains(&x) && (-0.5..height - 0.5).contains(&y);
+                on_sensor.then_some(([x, y], star.magnitude))
+            })
+            .collect()
+    }
+
+    /// Render a frame at the current attitude and settings, without the trigger and timing a grab
+    /// goes through
+    pub fn render(&mut self) -> GrayFrame {
+        let (width, height) = (self.optics.width as usize, self.optics.height as usize);
+        let t = self.exposure.as_secs_f32();
+        let sensor = self.sensor;
+
+        let mut electrons = vec![sensor.dark_current * t; width * height];
+        for &i in &self.hot_pixels {
+            electrons[i] += sensor.hot_pixel_current * t;
+        }
+
+        for ([x, y], magnitude) in self.visible_stars() {
+            let total = sensor.zero_mag_flux * 10f32.powf(-0.4 * magnitude) * t;
+            deposit(&mut electrons, width, height, x, y, total, sensor.psf_sigma);
+        }
+
+        let scale = sensor.counts_per_electron * 10f32.powf(self.gain_db / 20.0);
+        let pixels = electrons
+            .into_iter()
+            .map(|mut e| {
+                if sensor.shot_noise && e > 0.0 {
+                    let n: f32 = self.rng.sample(StandardNormal);
+                    e += e.sqrt() * n;
+                }
+                if sensor.read_noise > 0.0 {
+                    let n: f32 = self.rng.sample(StandardNormal);
+                    e += sensor.read_noise * n;
+                }
+                (sensor.bias + e * scale).round().clamp(0.0, 255.0) as u8
+            })
+            .collect();
+
+        GrayFrame::from_raw(width as u32, height as u32, pixels).expect("sized from the optics")
+    }
+
+    fn frame(&mut self) -> Frame {
+        let frame = Frame {
+            image: self.render(),
+            sequence: self.sequence,
+            captured: SystemTime::now(),
+        };
+        self.sequence += 1;
+        frame
+    }
+}
+
+// Spread `total` electrons over a Gaussian centered on (x, y), out to three sigma. Light falling
+// off the sensor is lost.
+fn deposit(electrons: &mut [f32], width: usize, height: usize, x: f32, y: f32, total: f32, sigma: f32) {
+    let sigma = sigma.max(0.1);
+    let reach = (3.0 * sigma).ceil() as i64;
+    let (cx, cy) = (x.round() as i64, y.round() as i64);
+
+    let weight = |px: i64, py: i64| {
+        let (dx, dy) = (px as f32 - x, py as f32 - y);
+        (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp()
+    };
+
+    let mut sum = 0.0;
+    for py in cy - reach..=cy + reach {
+        for px in cx - reach..=cx + reach {
+            sum += weight(px, py);
+        }
+    }
+
+    for py in (cy - reach).max(0)..=(cy + reach).min(height as i64 - 1) {
+        for px in (cx - reach).max(0)..=(cx + reach).min(width as i64 - 1) {
+            electrons[py as usize * width + px as usize] += total * weight(px, py) / sum;
+        }
+    }
+}
+
+// Sleep until `deadline` if it's within `timeout`, otherwise sleep out the timeout and give up
+fn wait_until(deadline: Instant, timeout: Duration) -> Result<(), CameraError> {
+    let now = Instant::now();
+    if deadline <= now {
+        return Ok(());
+    }
+    if deadline - now > timeout {
+        thread::sleep(timeout);
+        return Err(CameraError::Timeout);
+    }
+    thread::sleep(deadline - now);
+    Ok(())
+}
+
+impl Camera for SyntheticCamera {
+    fn start(&mut self) -> Result<(), CameraError> {
+        if !self.streaming {
+            self.streaming = true;
+            self.sequence = 0;
+            self.next_frame = match self.mode {
+                TriggerMode::FreeRun => Some(Instant::now() + self.exposure),
+                TriggerMode::Software => None,
+            };
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), CameraError> {
+        self.streaming = false;
+        self.next_frame = None;
+        Ok(())
+    }
+
+    fn is_streaming(&self) -> bool {
+        self.streaming
+    }
+
+    fn set_exposure(&mut self, exposure: Duration) -> Result<(), CameraError> {
+        if exposure.is_zero() {
+            return Err(CameraError::InvalidSetting("exposure must be more than zero".to_string()));
+        }
+        self.exposure = exposure;
+        Ok(())
+    }
+
+    fn exposure(&self) -> Result<Duration, CameraError> {
+        Ok(self.exposure)
+    }
+
+    fn set_gain(&mut self, gain_db: f32) -> Result<(), CameraError> {
+        if !(0.0..=48.0).contains(&gain_db) {
+            return Err(CameraError::InvalidSetting(format!("gain {gain_db}dB outside 0-48dB")));
+        }
+        self.gain_db = gain_db;
+        Ok(())
+    }
+
+    fn gain(&self) -> Result<f32, CameraError> {
+        Ok(self.gain_db)
+    }
+
+    fn set_trigger_mode(&mut self, mode: TriggerMode) -> Result<(), CameraError> {
+        if self.streaming {
+            return Err(CameraError::InvalidSetting("trigger mode can't change while streaming".to_string()));
+        }
+        self.mode = mode;
+        Ok(())
+    }
+
+    fn trigger(&mut self) -> Result<(), CameraError> {
+        if !self.streaming {
+            return Err(CameraError::NotStreaming);
+        }
+        if self.mode != TriggerMode::Software {
+            return Err(CameraError::Unsupported("software trigger while free running"));
+        }
+        self.next_frame = Some(Instant::now() + self.exposure);
+        Ok(())
+    }
+
+    fn grab(&mut self, timeout: Duration) -> Result<Frame, CameraError> {
+        if !self.streaming {
+            return Err(CameraError::NotStreaming);
+        }
+
+        match self.mode {
+            TriggerMode::FreeRun => {
+                let ready = self.next_frame.unwrap_or_else(Instant::now);
+                wait_until(ready, timeout)?;
+                // A late grab gets the exposure that just finished, the way the hardware keeps
+                // only the latest image
+                self.next_frame = Some(ready.max(Instant::now()) + self.exposure);
+            }
+            TriggerMode::Software => {
+                let Some(ready) = self.next_frame else {
+                    thread::sleep(timeout);
+                    return Err(CameraError::Timeout);
+                };
+                wait_until(ready, timeout)?;
+                self.next_frame = None;
+            }
+        }
+
+        Ok(self.frame())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: u32 = 64;
+    const HEIGHT: u32 = 48;
+
+    fn camera(catalog: StarCatalog, sensor: SensorModel) -> SyntheticCamera {
+        SyntheticCamera::new(catalog, Optics::with_fov(WIDTH, HEIGHT, 10.0), sensor)
+    }
+
+    fn one_star(ra_deg: f32, dec_deg: f32) -> StarCatalog {
+        StarCatalog::new(vec![CatalogStar::from_ra_dec(ra_deg, dec_deg, 4.0)])
+    }
+
+    fn centroid(image: &GrayFrame) -> [f32; 2] {
+        let (mut sx, mut sy, mut total) = (0.0, 0.0, 0.0);
+        for (x, y, p) in image.enumerate_pixels() {
+            let v = p.0[0] as f32;
+            sx += x as f32 * v;
+            sy += y as f32 * v;
+            total += v;
+        }
+        [sx / total, sy / total]
+    }
+
+    fn mean(image: &GrayFrame) -> f32 {
+        image.pixels().map(|p| p.0[0] as f32).sum::<f32>() / image.len() as f32
+    }
+
+    #[test]
+    fn test_star_on_boresight() {
+        // The pole is along reference +z, which is where an identity attitude looks
+        let mut cam = camera(one_star(0.0, 90.0), SensorModel::ideal());
+        let image = cam.render();
+
+        let [x, y] = centroid(&image);
+        let [px, py] = cam.optics().principal_point;
+        assert!((x - px).abs() < 0.05 && (y - py).abs() < 0.05, "({x}, {y})");
+        assert!(image.pixels().any(|p| p.0[0] > 0));
+    }
+
+    #[test]
+    fn test_star_follows_attitude() {
+        let mut cam = camera(one_star(0.0, 90.0), SensorModel::ideal());
+        let optics = *cam.optics();
+
+        // Two degrees about body x moves a boresight star along image +y
+        let angle = 2f32.to_radians();
+        cam.attitude().set([(angle / 2.0).cos(), (angle / 2.0).sin(), 0.0, 0.0]);
+        let [x, y] = centroid(&cam.render());
+
+        let expected = optics.principal_point[1] + optics.focal_px * angle.tan();
+        assert!((x - optics.principal_point[0]).abs() < 0.05, "x {x}");
+        assert!((y - expected).abs() < 0.1, "y {y}, expected {expected}");
+
+        // Turned away, nothing to see
+        cam.attitude().set([0.0, 1.0, 0.0, 0.0]);
+        assert!(cam.visible_stars().is_empty());
+        assert!(cam.render().pixels().all(|p| p.0[0] == 0));
+    }
+
+    #[test]
+    fn test_hot_pixels_are_stable() {
+        let sensor = SensorModel {
+            hot_pixels: 5,
+            seed: 7,
+            ..SensorModel::ideal()
+        };
+        let mut a = camera(StarCatalog::default(), sensor);
+        let mut b = camera(StarCatalog::default(), sensor);
+        assert_eq!(a.hot_pixels().len(), 5);
+        assert_eq!(a.hot_pixels(), b.hot_pixels());
+
+        let frame = a.render();
+        for (x, y) in a.hot_pixels() {
+            assert!(frame.get_pixel(x, y).0[0] > 0);
+        }
+        assert_eq!(frame.pixels().filter(|p| p.0[0] > 0).count(), 5);
+        assert_eq!(frame, b.render());
+    }
+
+    #[test]
+    fn test_dark_current_scales_with_exposure() {
+        let sensor = SensorModel {
+            dark_current: 1000.0,
+            ..SensorModel::ideal()
+        };
+        let mut cam = camera(StarCatalog::default(), sensor);
+
+        cam.set_exposure(Duration::from_millis(100)).unwrap();
+        let short = mean(&cam.render());
+        cam.set_exposure(Duration::from_millis(500)).unwrap();
+        let long = mean(&cam.render());
+        assert_eq!(short, 10.0);
+        assert_eq!(long, 50.0);
+
+        // 6dB is twice the counts
+        cam.set_gain(6.0206).unwrap();
+        assert_eq!(mean(&cam.render()), 100.0);
+        assert!(cam.set_gain(-1.0).is_err());
+        assert!(cam.set_exposure(Duration::ZERO).is_err());
+    }
+
+    #[test]
+    fn test_noise() {
+        let sensor = SensorModel {
+            hot_pixels: 0,
+            dark_current: 0.0,
+            bias: 100.0,
+            counts_per_electron: 1.0,
+            read_noise: 4.0,
+            ..Default::default()
+        };
+        let mut cam = camera(StarCatalog::default(), sensor);
+        let (a, b) = (cam.render(), cam.render());
+        assert_ne!(a, b);
+
+        let m = mean(&a);
+        let var = a.pixels().map(|p| (p.0[0] as f32 - m).powi(2)).sum::<f32>() / a.len() as f32;
+        assert!((m - 100.0).abs() < 0.5, "mean {m}");
+        assert!((var.sqrt() - 4.0).abs() < 0.5, "sd {}", var.sqrt());
+    }
+
+    #[test]
+    fn test_free_run() {
+        let mut cam = camera(one_star(0.0, 90.0), SensorModel::default());
+        assert!(matches!(cam.grab(Duration::from_millis(50)), Err(CameraError::NotStreaming)));
+
+        cam.set_exposure(Duration::from_millis(5)).unwrap();
+        cam.start().unwrap();
+        assert!(matches!(cam.trigger(), Err(CameraError::Unsupported(_))));
+        assert!(cam.set_trigger_mode(TriggerMode::Software).is_err());
+
+        let start = Instant::now();
+        let first = cam.grab(Duration::from_millis(50)).unwrap();
+        let second = cam.grab(Duration::from_millis(50)).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(10));
+        assert_eq!((first.sequence, second.sequence), (0, 1));
+        assert_eq!(first.image.dimensions(), (WIDTH, HEIGHT));
+
+        // Exposure longer than the timeout
+        cam.set_exposure(Duration::from_millis(200)).unwrap();
+        // The exposure already under way still comes in
+        cam.grab(Duration::from_millis(50)).unwrap();
+        assert!(matches!(cam.grab(Duration::from_millis(10)), Err(CameraError::Timeout)));
+
+        cam.stop().unwrap();
+        assert!(!cam.is_streaming());
+    }
+
+    #[test]
+    fn test_software_trigger() {
+        let mut cam = camera(one_star(0.0, 90.0), SensorModel::default());
+        cam.set_trigger_mode(TriggerMode::Software).unwrap();
+        assert!(matches!(cam.trigger(), Err(CameraError::NotStreaming)));
+        cam.start().unwrap();
+
+        assert!(matches!(cam.grab(Duration::from_millis(5)), Err(CameraError::Timeout)));
+        cam.trigger().unwrap();
+        assert_eq!(cam.grab(Duration::from_millis(50)).unwrap().sequence, 0);
+        // One trigger, one frame
+        assert!(matches!(cam.grab(Duration::from_millis(5)), Err(CameraError::Timeout)));
+
+        // Restarting starts the count over
+        cam.stop().unwrap();
+        cam.start().unwrap();
+        cam.trigger().unwrap();
+        assert_eq!(cam.grab(Duration::from_millis(50)).unwrap().sequence, 0);
+    }
+
+    // What a tracking loop holds: some camera, it doesn't know which
+    fn grab_two(camera: &mut dyn Camera) -> Result<(Frame, Frame), CameraError> {
+        camera.start()?;
+        let frames = (camera.grab(Duration::from_millis(100))?, camera.grab(Duration::from_millis(100))?);
+        camera.stop()?;
+        Ok(frames)
+    }
+
+    #[test]
+    fn test_slew_between_grabs() {
+        let cam = camera(one_star(0.0, 90.0), SensorModel::ideal());
+        let attitude = cam.attitude();
+
+        let mut boxed: Box<dyn Camera + Send> = Box::new(cam);
+        let (before, _) = grab_two(&mut boxed).unwrap();
+
+        let angle = 1f32.to_radians();
+        thread::spawn(move || attitude.set([(angle / 2.0).cos(), 0.0, (angle / 2.0).sin(), 0.0]))
+            .join()
+            .unwrap();
+        let (after, _) = grab_two(&mut boxed).unwrap();
+
+        // About body y the star moves along image -x
+        let (x0, x1) = (centroid(&before.image)[0], centroid(&after.image)[0]);
+        assert!(x1 < x0 - 5.0, "{x0} -> {x1}");
+        assert!(after.unix_ms() >= before.unix_ms());
+    }
+}
@@ -0,0 +1,206 @@
+//! Any camera with a V4L2 driver, streamed over mmap buffers
+
+use std::io;
+use std::time::{Duration, SystemTime};
+
+use log::{info, warn};
+use v4l::buffer::Type;
+use v4l::control::{Control, Value};
+use v4l::io::mmap::Stream as MmapStream;
+use v4l::io::traits::CaptureStream;
+use v4l::video::Capture;
+use v4l::{Device, FourCC};
+
+use crate::{Camera, CameraError, Frame, GrayFrame, TriggerMode};
+
+// From linux/v4l2-controls.h
+const V4L2_CID_GAIN: u32 = 0x0098_0913;
+const V4L2_CID_EXPOSURE_AUTO: u32 = 0x009a_0901;
+const V4L2_CID_EXPOSURE_ABSOLUTE: u32 = 0x009a_0902;
+const V4L2_EXPOSURE_MANUAL: i64 = 1;
+
+// V4L2 absolute exposure is in units of 100us
+const EXPOSURE_UNIT_US: u64 = 100;
+
+const BUFFERS: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Layout {
+    Grey,
+    // Luma is every other byte
+    Yuyv,
+}
+
+/// A V4L2 capture device. V4L2 has no standard gain unit, so gain is passed to the driver as is
+/// rather than in dB. Only free running is supported.
+pub struct V4lCamera {
+    device: Device,
+    stream: Option<MmapStream<'static>>,
+    width: u32,
+    height: u32,
+    stride: usize,
+    layout: Layout,
+    sequence_base: Option<u32>,
+}
+
+impl V4lCamera {
+    /// Open `/dev/video<index>` in its current format, which must be 8-bit grey or YUYV
+    pub fn new(index: usize) -> Result<Self, CameraError> {
+        Self::from_device(Device::new(index)?)
+    }
+
+    /// Use an already opened device
+    pub fn from_device(device: Device) -> Result<Self, CameraError> {
+        let format = device.format()?;
+        let layout = if format.fourcc == FourCC::new(b"GREY") {
+            Layout::Grey
+        } else if format.fourcc == FourCC::new(b"YUYV") {
+            Layout::Yuyv
+        } else {
+            return Err(CameraError::Unsupported("pixel format other than GREY or YUYV"));
+        };
+        info!("V4L2 camera {}x{} {}", format.width, format.height, format.fourcc);
+
+        Ok(Self {
+            device,
+            stream: None,
+            width: format.width,
+            height: format.height,
+            stride: format.stride as usize,
+            layout,
+            sequence_base: None,
+        })
+    }
+
+    /// The device, for settings the trait doesn't cover
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    fn integer_control(&self, id: u32) -> Result<i64, CameraError> {
+        match self.device.control(id)?.value {
+            Value::Integer(v) => Ok(v),
+            _ => Err(CameraError::Unsupported("non-integer control")),
+        }
+    }
+
+    fn to_gray(&self, buf: &[u8]) -> Result<GrayFrame, CameraError> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let bytes_per_pixel = match self.layout {
+            Layout::Grey => 1,
+            Layout::Yuyv => 2,
+        };
+        let stride = self.stride.max(width * bytes_per_pixel);
+        let expected = stride * (height - 1) + width * bytes_per_pixel;
+        if buf.len() < expected {
+            return Err(CameraError::BufferLength {
+                width: self.width,
+                height: self.height,
+                expected,
+                found: buf.len(),
+            });
+        }
+
+        let mut pixels = Vec::with_capacity(width * height);
+        for row in buf.chunks(stride).take(height) {
+            let row = &row[..width * bytes_per_pixel];
+            match self.layout {
+                Layout::Grey => pixels.extend_from_slice(row),
+                Layout::Yuyv => pixels.extend(row.iter().step_by(2)),
+            }
+        }
+        Ok(GrayFrame::from_raw(self.width, self.height, pixels).expect("sized from the format"))
+    }
+}
+
+impl Camera for V4lCamera {
+    fn start(&mut self) -> Result<(), CameraError> {
+        if self.stream.is_none() {
+            self.stream = Some(MmapStream::with_buffers(&self.device, Type::VideoCapture, BUFFERS)?);
+            self.sequence_base = None;
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), CameraError> {
+        // Dropping the stream stops it and gives the buffers back
+        self.stream = None;
+        Ok(())
+    }
+
+    fn is_streaming(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    fn set_exposure(&mut self, exposure: Duration) -> Result<(), CameraError> {
+        let units = (exposure.as_micros() as u64 / EXPOSURE_UNIT_US).max(1);
+        if let Err(e) = self.device.set_control(Control {
+            id: V4L2_CID_EXPOSURE_AUTO,
+            value: Value::Integer(V4L2_EXPOSURE_MANUAL),
+        }) {
+            warn!("Auto exposure not turned off: {e}");
+        }
+        self.device.set_control(Control {
+            id: V4L2_CID_EXPOSURE_ABSOLUTE,
+            value: Value::Integer(units as i64),
+        })?;
+        Ok(())
+    }
+
+    fn exposure(&self) -> Result<Duration, CameraError> {
+        let units = self.integer_control(V4L2_CID_EXPOSURE_ABSOLUTE)?;
+        Ok(Duration::from_micros(units.max(0) as u64 * EXPOSURE_UNIT_US))
+    }
+
+    fn set_gain(&mut self, gain: f32) -> Result<(), CameraError> {
+        self.device.set_control(Control {
+            id: V4L2_CID_GAIN,
+            value: Value::Integer(gain.round() as i64),
+        })?;
+        Ok(())
+    }
+
+    fn gain(&self) -> Result<f32, CameraError> {
+        Ok(self.integer_control(V4L2_CID_GAIN)? as f32)
+    }
+
+    fn set_trigger_mode(&mut self, mode: TriggerMode) -> Result<(), CameraError> {
+        match mode {
+            TriggerMode::FreeRun => Ok(()),
+            TriggerMode::Software => Err(CameraError::Unsupported("software trigger over V4L2")),
+        }
+    }
+
+    fn trigger(&mut self) -> Result<(), CameraError> {
+        Err(CameraError::Unsupported("software trigger over V4L2"))
+    }
+
+    fn grab(&mut self, timeout: Duration) -> Result<Frame, CameraError> {
+        let Some(stream) = self.stream.as_mut() else {
+            return Err(CameraError::NotStreaming);
+        };
+        stream.set_timeout(timeout);
+
+        let (buf, meta) = match stream.next() {
+            Ok(next) => next,
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => return Err(CameraError::Timeout),
+            Err(e) => return Err(e.into()),
+        };
+        let captured = SystemTime::now();
+        // Some drivers leave bytesused at zero
+        let used = match meta.bytesused as usize {
+            0 => buf.len(),
+            n => n.min(buf.len()),
+        };
+        let buf = buf[..used].to_vec();
+        let sequence = meta.sequence;
+
+        // Driver sequence numbers don't restart with the stream
+        let base = *self.sequence_base.get_or_insert(sequence);
+        Ok(Frame {
+            image: self.to_gray(&buf)?,
+            sequence: sequence.wrapping_sub(base) as u64,
+            captured,
+        })
+    }
+}
@@ -5,13 +5,44 @@ use defmt::Format;
 
 use crate::phases::EjectorPhase;
 use crate::rgbstatus::RGBOptions;
+use crate::sequence::{SequenceId, Step};
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, Encode, Decode, Format, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Encode, Decode, Format, Serialize, Deserialize)]
 pub enum CommandPacket {
     SyncTime(u32),
     Ping,
     EjectorPhaseSet(EjectorPhase),
     ColorSet(RGBOptions),
+    /// Step `index` of a replacement `len` step table for `sequence`. The table takes effect
+    /// once every step is in and it validates.
+    SequenceStep {
+        sequence: SequenceId,
+        index: u8,
+        len: u8,
+        step: Step,
+    },
+}
+
+/// The receiver's answer to a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, Format, Serialize, Deserialize)]
+pub enum CommandReply {
+    /// Carried out, or already had been
+    Ack,
+    /// Refused
+    Nack(NackReason),
+}
+
+/// Why a command was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, Format, Serialize, Deserialize)]
+pub enum NackReason {
+    /// The receiver doesn't handle this command
+    Unsupported,
+    /// The receiver can't get to the requested phase from the one it's in
+    InvalidPhase,
+    /// The sequence it completed is outside safe bounds, so the old one stays
+    InvalidSequence,
+    /// The sequence has already started running and can't be replaced
+    Busy,
 }
@@ -1,7 +1,8 @@
 use crate::time::Timestamp;
 
 use bincode::{Decode, Encode};
-// use serde::{Serialize, Deserialize};
+use defmt::Format;
+use serde::{Deserialize, Serialize};
 
 // Currently quaternions and reference frames pull STD, so switching to tranposrting a raw vector
 // #[cfg(feature = "aether")]
@@ -21,4 +22,49 @@ pub struct AttitudeMetrics {
     /// Quaternion encoded as [w, i, j, k].
     pub quaternion: [f32; 4],
     pub signal_match: f32,
+    /// Losses in the SDR stream since boot
+    pub sdr_health: SdrHealth,
+}
+
+/// Running totals of what the SDR stream has lost, so holes in the recording show up on the
+/// ground without having to pull the recording first
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Encode, Decode)]
+pub struct SdrHealth {
+    /// Packets produced by the SDR
+    pub packets: u32,
+    /// Overflows reported by the device, each one is lost samples
+    pub overflows: u32,
+    /// Packets dropped because the ring buffer to the recorder was full
+    pub ring_drops: u32,
+    /// Reads that returned less than a full chunk
+    pub short_reads: u32,
+    /// Packets whose timestamp didn't follow on from the one before
+    pub discontinuities: u32,
+    /// Samples that didn't fit in a packet and were discarded
+    pub truncated_samples: u32,
+}
+
+impl SdrHealth {
+    /// Whether the stream has lost any samples
+    pub fn is_healthy(&self) -> bool {
+        self.overflows == 0
+            && self.ring_drops == 0
+            && self.discontinuities == 0
+            && self.truncated_samples == 0
+    }
+}
+
+/// How the photodiode sun fix came out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, Format, Serialize, Deserialize)]
+pub enum SunFixStatus {
+    /// A direction to use
+    Valid,
+    /// Too few diodes lit to fit
+    TooFewLit,
+    /// Lit, but too dim to be the sun
+    Dark,
+    /// The diodes disagree on a direction
+    Residual,
+    /// Too much light on the side facing away from the sun
+    Albedo,
 }
@@ -3,8 +3,6 @@
 pub mod adcs;
 pub mod scientific_data;
 pub mod status;
-pub mod telemetry;
 
 pub use scientific_data::*;
 pub use status::*;
-pub use telemetry::*;
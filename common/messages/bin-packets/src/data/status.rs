@@ -5,41 +5,306 @@ use defmt::Format;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    phases::EjectorPhase,
-    time::{DurationMillis, Timestamp},
+    phases::{EjectorPhase, IcarusPhase, JupiterPhase},
+    sequence::Target,
 };
 
-use super::JupiterTelemetry;
+/// Health every board reports with its heartbeat
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Encode, Decode, Format, Serialize, Deserialize,
+)]
+pub struct BoardHealth {
+    /// Time since power on, in ms
+    pub uptime_ms: u64,
+    /// Time in the current phase, in ms
+    pub time_in_phase_ms: u64,
+    /// Counts up with each status packet, wrapping
+    pub packet_number: u16,
+    /// Whether the RBF pin is in, for boards that read one
+    pub rbf_inserted: Option<bool>,
+    /// Battery bus voltage in mV, for boards that measure it. Only Icarus does; Jupiter can latch
+    /// the battery but has nothing to read it with.
+    pub battery_mv: Option<u16>,
+    /// Bit per active fault, see each board's status for what they mean
+    pub faults: u16,
+    /// Packets waiting to go out
+    pub queue_depth: u16,
+    /// Most packets that have been waiting at once
+    pub queue_max: u16,
+    /// Number of the last command sent or handled, if there's been one
+    pub last_command: Option<u16>,
+}
+
+impl BoardHealth {
+    /// Whether any of the bits in `fault` are set
+    pub fn has_fault(&self, fault: u16) -> bool {
+        self.faults & fault != 0
+    }
+
+    /// Note the queue's depth, keeping the high water mark
+    pub fn record_queue(&mut self, depth: usize) {
+        let depth = depth.min(u16::MAX as usize) as u16;
+        self.queue_depth = depth;
+        self.queue_max = self.queue_max.max(depth);
+    }
+
+    /// Names of the faults set, from `names` indexed by bit
+    pub fn fault_names<'a>(&self, names: &'a [&'a str]) -> impl Iterator<Item = &'a str> + 'a {
+        let faults = self.faults;
+        names
+            .iter()
+            .enumerate()
+            .filter(move |(bit, _)| faults & (1 << bit) != 0)
+            .map(|(_, name)| *name)
+    }
+}
 
 /// Status information for Ejector
 #[derive(Debug, Clone, Copy, Encode, Decode, Format, Serialize, Deserialize)]
 pub struct EjectorStatus {
+    /// Phase the board is in
     pub phase: EjectorPhase,
-    pub time_in_phase: u64,
-    pub timestamp: u64,
-    pub packet_number: u16,
+    /// Health common to every board
+    pub health: BoardHealth,
+    /// Latest checked move of the servo holding the deployable, if it has position feedback
+    pub ejector_servo: Option<ActuationResult>,
+    /// Latest checked move of the power servo, if it has position feedback
+    pub power_servo: Option<ActuationResult>,
+    /// Latest checked switch of the electromagnet, if it has current sense
+    pub magnet: Option<ActuationResult>,
+    /// Whether the limit switch saw the deployable leave, if it's fitted
+    pub deployed: Option<bool>,
+}
+
+impl EjectorStatus {
+    /// A servo or the magnet didn't do what it was told
+    pub const FAULT_ACTUATION: u16 = 1 << 0;
+    /// Nothing heard from JUPITER for a while
+    pub const FAULT_LINK: u16 = 1 << 1;
+    /// The BME280 isn't reading
+    pub const FAULT_SENSORS: u16 = 1 << 2;
+    /// Packets were dropped for want of queue space
+    pub const FAULT_QUEUE_FULL: u16 = 1 << 3;
+    /// Fault names, by bit
+    pub const FAULT_NAMES: [&'static str; 4] = ["actuation", "link", "sensors", "queue full"];
+
+    /// Nothing checked yet
+    pub fn new(phase: EjectorPhase) -> Self {
+        Self {
+            phase,
+            health: BoardHealth::default(),
+            ejector_servo: None,
+            power_servo: None,
+            magnet: None,
+            deployed: None,
+        }
+    }
+
+    /// Every actuation result there is
+    pub fn actuations(&self) -> impl Iterator<Item = ActuationResult> {
+        [self.ejector_servo, self.power_servo, self.magnet]
+            .into_iter()
+            .flatten()
+    }
+}
+
+/// How a commanded actuation turned out, going by the actuator's feedback
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, Format, Serialize, Deserialize)]
+pub enum ActuationOutcome {
+    /// Got to the target
+    Reached,
+    /// Never started moving
+    NoMotion,
+    /// Started moving, then stopped short of the target
+    Stalled,
+    /// Drew less current than it should once energized: open coil, or the bridge didn't switch
+    NoCurrent,
+    /// Drew more current than it should once energized
+    OverCurrent,
+    /// Still drawing current after being switched off
+    StuckOn,
+    /// The feedback couldn't be read
+    SenseFault,
+}
+
+/// The verdict on one commanded actuation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, Format, Serialize, Deserialize)]
+pub struct ActuationResult {
+    /// What it was commanded to
+    pub target: Target,
+    /// How it turned out
+    pub outcome: ActuationOutcome,
+    /// Last feedback reading, in degrees for a servo or mA for the magnet
+    pub reading: u16,
+    /// From the command to the verdict, in ms
+    pub elapsed_ms: u32,
+}
+
+impl ActuationResult {
+    /// Whether the actuator did what it was told
+    pub fn ok(&self) -> bool {
+        self.outcome == ActuationOutcome::Reached
+    }
 }
 
 /// Status information for ICARUS
 #[derive(Debug, Clone, Copy, Encode, Decode, Format, Serialize, Deserialize)]
 pub struct IcarusStatus {
-    pub time_in_phase: DurationMillis,
-    pub timestamp: Timestamp,
-    pub packet_number: u16,
+    /// Phase the board is in
+    pub phase: IcarusPhase,
+    /// Health common to every board
+    pub health: BoardHealth,
+}
+
+impl IcarusStatus {
+    /// A power monitor tripped, switching its load off if it has a switch
+    pub const FAULT_POWER: u16 = 1 << 0;
+    /// A power monitor isn't answering
+    pub const FAULT_POWER_MONITOR: u16 = 1 << 1;
+    /// The IMU, magnetometer or BME280 isn't reading
+    pub const FAULT_SENSORS: u16 = 1 << 2;
+    /// Packets were dropped for want of queue space
+    pub const FAULT_QUEUE_FULL: u16 = 1 << 3;
+    /// Fault names, by bit
+    pub const FAULT_NAMES: [&'static str; 4] =
+        ["power", "power monitor", "sensors", "queue full"];
+
+    /// Just powered on
+    pub fn new(phase: IcarusPhase) -> Self {
+        Self {
+            phase,
+            health: BoardHealth::default(),
+        }
+    }
 }
 
 /// Status information for JUPITER
 #[derive(Debug, Clone, Copy, Encode, Decode, Format, Serialize, Deserialize)]
 pub struct JupiterStatus {
-    pub time_in_phase: DurationMillis,
-    pub timestamp: Timestamp,
-    pub packet_number: u16,
-    pub telemetry: JupiterTelemetry,
+    /// Phase the board is in
+    pub phase: JupiterPhase,
+    /// Health common to every board
+    pub health: BoardHealth,
+    /// Which software subsystems are up
+    pub subsystems: SubsystemHealth,
+}
+
+impl JupiterStatus {
+    /// A subsystem has failed
+    pub const FAULT_SUBSYSTEM: u16 = 1 << 0;
+    /// A reliable command to the Ejector went unanswered
+    pub const FAULT_COMMAND: u16 = 1 << 1;
+    /// Packets were dropped for want of queue space
+    pub const FAULT_QUEUE_FULL: u16 = 1 << 2;
+    /// Fault names, by bit
+    pub const FAULT_NAMES: [&'static str; 3] = ["subsystem", "command", "queue full"];
+}
+
+/// Health of the JUPITER software subsystems, one bit per subsystem in each mask
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Encode, Decode, Format, Serialize, Deserialize)]
+pub struct SubsystemHealth {
+    /// Subsystems running and checking in on time
+    pub healthy: u8,
+    /// Subsystems that failed and have been given up on, or are still failing
+    pub degraded: u8,
+    /// Restarts of supervised threads since boot
+    pub restarts: u16,
+}
+
+impl SubsystemHealth {
+    /// IMU reads in the main loop
+    pub const IMU: u8 = 1 << 0;
+    /// GUARD radiation logging
+    pub const GUARD: u8 = 1 << 1;
+    /// Infratracker capture and solve thread
+    pub const INFRATRACKER: u8 = 1 << 2;
+    /// Main camera ffmpeg recording
+    pub const MAIN_CAM: u8 = 1 << 3;
+    /// Serial link to the Ejector
+    pub const SERIAL: u8 = 1 << 4;
+
+    /// Whether `subsystem` is up
+    pub fn is_healthy(&self, subsystem: u8) -> bool {
+        self.healthy & subsystem != 0
+    }
+
+    /// Whether `subsystem` has failed
+    pub fn is_degraded(&self, subsystem: u8) -> bool {
+        self.degraded & subsystem != 0
+    }
+}
+
+/// What a power monitor tripped on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, Format, Serialize, Deserialize)]
+pub enum PowerFaultKind {
+    /// Current above its limit
+    OverCurrent,
+    /// Current below its limit
+    UnderCurrent,
+    /// Bus voltage above its limit
+    OverVoltage,
+    /// Bus voltage below its limit
+    UnderVoltage,
+    /// Power above its limit
+    OverPower,
 }
 
 /// Status packet for Relay
-#[derive(Debug, Clone, Copy, Encode, Decode, Format)]
+#[derive(Debug, Clone, Copy, Encode, Decode, Format, Serialize, Deserialize)]
 pub struct RelayStatus {
-    pub timestamp: Timestamp,
-    pub packet_number: u16,
+    /// Health common to every board
+    pub health: BoardHealth,
+}
+
+impl RelayStatus {
+    /// Packets were dropped for want of queue space
+    pub const FAULT_QUEUE_FULL: u16 = 1 << 0;
+    /// Fault names, by bit
+    pub const FAULT_NAMES: [&'static str; 1] = ["queue full"];
+}
+
+#[cfg(test)]
+mod tests {
+    use bincode::{config::standard, decode_from_slice, encode_into_slice};
+
+    use super::*;
+    use crate::packets::ApplicationPacket;
+
+    #[test]
+    fn test_health_faults_and_queue() {
+        let mut health = BoardHealth {
+            faults: EjectorStatus::FAULT_LINK | EjectorStatus::FAULT_QUEUE_FULL,
+            ..Default::default()
+        };
+        assert!(health.has_fault(EjectorStatus::FAULT_LINK));
+        assert!(!health.has_fault(EjectorStatus::FAULT_ACTUATION));
+        let names: Vec<_> = health.fault_names(&EjectorStatus::FAULT_NAMES).collect();
+        assert_eq!(names, ["link", "queue full"]);
+
+        health.record_queue(40);
+        health.record_queue(3);
+        assert_eq!((health.queue_depth, health.queue_max), (3, 40));
+        health.record_queue(100_000);
+        assert_eq!(health.queue_max, u16::MAX);
+    }
+
+    #[test]
+    fn test_status_round_trip() {
+        let mut status = IcarusStatus::new(IcarusPhase::FlapDeploy);
+        status.health.rbf_inserted = Some(false);
+        status.health.battery_mv = Some(7400);
+        status.health.last_command = Some(12);
+
+        let mut bytes = [0u8; 64];
+        let written =
+            encode_into_slice(ApplicationPacket::IcarusStatus(status), &mut bytes, standard())
+                .unwrap();
+        let (packet, _): (ApplicationPacket, _) =
+            decode_from_slice(&bytes[..written], standard()).unwrap();
+        let ApplicationPacket::IcarusStatus(decoded) = packet else {
+            panic!("decoded as {}", packet.name());
+        };
+        assert_eq!(decoded.phase, IcarusPhase::FlapDeploy);
+        assert_eq!(decoded.health, status.health);
+    }
 }
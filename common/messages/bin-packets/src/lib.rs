@@ -9,4 +9,7 @@ pub mod i2c;
 pub mod packets;
 pub mod phases;
 pub mod rgbstatus;
+pub mod sequence;
+#[cfg(feature = "std")]
+pub mod storage;
 pub mod time;
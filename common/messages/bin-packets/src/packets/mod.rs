@@ -8,13 +8,29 @@ use defmt::Format;
 use serde::{Deserialize, Serialize};
 use status::Status;
 
-use crate::commands::CommandPacket;
+use crate::commands::{CommandPacket, CommandReply};
+use crate::data::adcs::SunFixStatus;
+use crate::data::{EjectorStatus, IcarusStatus, JupiterStatus, PowerFaultKind, RelayStatus};
 use crate::i2c::I2CPacket;
+use crate::phases::EjectorPhase;
 // use crate::data::adcs::AttitudeMetrics;
 
 #[derive(Debug, Clone, Copy, Encode, Decode, Format, Serialize, Deserialize)]
 pub enum ApplicationPacket {
-    Command(CommandPacket),
+    /// A command, numbered so the reply can be matched to it. Retries reuse the number.
+    Command {
+        seq: u16,
+        command: CommandPacket,
+    },
+    /// Reply to the command numbered `seq`, sent for every copy received. Echoes the command as
+    /// it was understood, since a damaged packet can decode as a different one.
+    CommandAck {
+        seq: u16,
+        command: CommandPacket,
+        reply: CommandReply,
+        /// The Ejector's phase once the command was handled
+        phase: EjectorPhase,
+    },
     Status(Status),
     I2C(I2CPacket),
     // ADCS(AttitudeMetrics),
@@ -87,4 +103,79 @@ pub enum ApplicationPacket {
         channel: u8,
         hot_junction_temp: f32,
     },
+    /// A power monitor alerted, and its load was switched off if it has a switch
+    PowerFault {
+        timestamp_ms: u64,
+        /// Which monitor, from 0
+        channel: u8,
+        kind: PowerFaultKind,
+        /// Limit that was crossed, in mA, mV or mW to match `kind`
+        limit: f32,
+        /// Reading after the alert, same units
+        measured: f32,
+    },
+    /// Sun direction from the photodiodes
+    SunVector {
+        timestamp_ms: u64,
+        /// Unit vector toward the sun, body frame. Zero unless `status` is valid.
+        direction: [f32; 3],
+        /// Fraction of full sun
+        irradiance: f32,
+        /// RMS fit residual relative to irradiance
+        residual: f32,
+        /// Signal on the diodes facing away from the sun, relative to irradiance
+        albedo: f32,
+        /// Bit per diode used in the fit
+        used: u32,
+        status: SunFixStatus,
+    },
+    /// The Ejector's health and how its last actuations went
+    EjectorStatus(EjectorStatus),
+    /// ICARUS's health
+    IcarusStatus(IcarusStatus),
+    /// JUPITER's health
+    JupiterStatus(JupiterStatus),
+    /// The relay's health
+    RelayStatus(RelayStatus),
+}
+
+impl ApplicationPacket {
+    /// The variant's name, for indexing and logs
+    pub fn name(&self) -> &'static str {
+        match self {
+            ApplicationPacket::Command { .. } => "Command",
+            ApplicationPacket::CommandAck { .. } => "CommandAck",
+            ApplicationPacket::Status { .. } => "Status",
+            ApplicationPacket::I2C { .. } => "I2C",
+            ApplicationPacket::VoltageData { .. } => "VoltageData",
+            ApplicationPacket::PowerData { .. } => "PowerData",
+            ApplicationPacket::CurrentData { .. } => "CurrentData",
+            ApplicationPacket::GeigerData { .. } => "GeigerData",
+            ApplicationPacket::JupiterAccelerometer { .. } => "JupiterAccelerometer",
+            ApplicationPacket::AccelerometerData { .. } => "AccelerometerData",
+            ApplicationPacket::MagnetometerData { .. } => "MagnetometerData",
+            ApplicationPacket::GyroscopeData { .. } => "GyroscopeData",
+            ApplicationPacket::EnvironmentData { .. } => "EnvironmentData",
+            ApplicationPacket::BMPData { .. } => "BMPData",
+            ApplicationPacket::BMEData { .. } => "BMEData",
+            ApplicationPacket::PhotoresistorData { .. } => "PhotoresistorData",
+            ApplicationPacket::InfratrackerData { .. } => "InfratrackerData",
+            ApplicationPacket::ThermocoupleData { .. } => "ThermocoupleData",
+            ApplicationPacket::PowerFault { .. } => "PowerFault",
+            ApplicationPacket::SunVector { .. } => "SunVector",
+            ApplicationPacket::EjectorStatus { .. } => "EjectorStatus",
+            ApplicationPacket::IcarusStatus { .. } => "IcarusStatus",
+            ApplicationPacket::JupiterStatus { .. } => "JupiterStatus",
+            ApplicationPacket::RelayStatus { .. } => "RelayStatus",
+        }
+    }
+
+    /// Bytes the packet takes on the wire, for link budgeting
+    pub fn encoded_len(&self) -> usize {
+        let mut size = bincode::enc::write::SizeWriter::default();
+        match bincode::encode_into_writer(*self, &mut size, bincode::config::standard()) {
+            Ok(()) => size.bytes_written,
+            Err(_) => 0,
+        }
+    }
 }
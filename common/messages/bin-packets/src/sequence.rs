@@ -0,0 +1,97 @@
+#![warn(missing_docs)]
+
+//! Actuation sequences as tables of steps, so a sequence can change without a reflash. Each step
+//! waits on its guard, drives one actuator to a target, then holds for its duration before the
+//! next step starts.
+
+use bincode::{Decode, Encode};
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+/// Which of the Ejector's sequences a table is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, Format, Serialize, Deserialize)]
+pub enum SequenceId {
+    /// Latching, ejecting and retracting
+    Ejector,
+    /// Powering the cameras
+    Cameras,
+}
+
+/// Something a step can drive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, Format, Serialize, Deserialize)]
+pub enum Actuator {
+    /// The servo holding the deployable
+    EjectorServo,
+    /// The servo switching the deployable's power
+    PowerServo,
+    /// The electromagnet pushing the deployable out
+    Magnet,
+    /// The camera supply
+    Cameras,
+}
+
+/// Electromagnet polarity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, Format, Serialize, Deserialize)]
+pub enum Polarity {
+    /// Pulling the deployable in
+    Attract,
+    /// Pushing it away
+    Repel,
+}
+
+/// What an actuator is driven to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, Format, Serialize, Deserialize)]
+pub enum Target {
+    /// Servo powered and at this angle, in degrees
+    Angle(u16),
+    /// Magnet energized with this polarity
+    Magnet(Polarity),
+    /// Switched on
+    On,
+    /// Switched off, or for a servo, unpowered
+    Off,
+}
+
+/// What a step waits for before it runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, Format, Serialize, Deserialize)]
+pub enum Guard {
+    /// Nothing, runs as soon as the previous step is done
+    None,
+    /// The RBF pin is out
+    RbfRemoved,
+    /// Jupiter has commanded ejection or raised the ejection line
+    EjectSignal,
+    /// This many ms since power on
+    AfterBoot(u32),
+}
+
+/// One row of a sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, Format, Serialize, Deserialize)]
+pub struct Step {
+    /// What's driven
+    pub actuator: Actuator,
+    /// What it's driven to
+    pub target: Target,
+    /// How long to hold before the next step, in ms
+    pub duration_ms: u32,
+    /// What the step waits on
+    pub guard: Guard,
+}
+
+impl Step {
+    /// A step with no guard
+    pub const fn new(actuator: Actuator, target: Target, duration_ms: u32) -> Self {
+        Self {
+            actuator,
+            target,
+            duration_ms,
+            guard: Guard::None,
+        }
+    }
+
+    /// Wait on `guard` first
+    pub const fn when(mut self, guard: Guard) -> Self {
+        self.guard = guard;
+        self
+    }
+}
@@ -0,0 +1,280 @@
+#![warn(missing_docs)]
+
+//! On-disk framing for logged packets, shared by the flight software writing segments and the
+//! ground tools reading them back.
+//!
+//! Segment layout:
+//!
+//!  | magic (8) | version (u16) | record | record | ... |
+//!
+//! Every record is framed as
+//!
+//!  | payload len (u32) | logged at, unix ms (u64) | payload (bincode) | crc32 of len + time + payload (u32) |
+//!
+//! Records are only ever appended, so a power cut can at worst leave a partial record at the end.
+//! Readers stop at the first record that's short or fails its checksum and report how much of the
+//! segment was good, which is where a writer picking the segment back up truncates to.
+
+use std::fs::File;
+use std::io::{self, BufReader, ErrorKind, Read, Write};
+use std::path::Path;
+
+use bincode::{config::standard, decode_from_slice, encode_to_vec};
+
+pub use bincode::error::EncodeError;
+
+use crate::packets::ApplicationPacket;
+
+/// First bytes of every segment
+pub const SEGMENT_MAGIC: [u8; 8] = *b"JUPPKTLG";
+
+/// Bumped whenever the framing changes
+pub const SEGMENT_VERSION: u16 = 1;
+
+/// Bytes before the first record
+pub const SEGMENT_HEADER_LEN: u64 = 8 + 2;
+
+/// Framing bytes around each payload
+pub const RECORD_OVERHEAD: u64 = 4 + 8 + 4;
+
+// Packets are tens of bytes, anything this big is a corrupt length field
+const MAX_PAYLOAD_LEN: u32 = 64 * 1024;
+
+/// A packet as it was logged
+#[derive(Debug, Clone, Copy)]
+pub struct Record {
+    /// Wall-clock time the packet was logged, in ms since the unix epoch
+    pub logged_unix_ms: u64,
+    /// The packet itself
+    pub packet: ApplicationPacket,
+}
+
+/// How a read through a segment finished
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentEnd {
+    /// Not finished yet
+    Reading,
+    /// Every byte belonged to a good record
+    Clean,
+    /// The last record was cut short, as a power cut mid-write would leave it
+    Torn,
+    /// A complete record failed its checksum or didn't decode
+    Corrupt,
+}
+
+/// Write the segment header
+pub fn write_segment_header<W: Write>(writer: &mut W) -> io::Result<()> {
+    writer.write_all(&SEGMENT_MAGIC)?;
+    writer.write_all(&SEGMENT_VERSION.to_le_bytes())
+}
+
+/// Frame a packet as a record, ready to append
+pub fn encode_record(logged_unix_ms: u64, packet: &ApplicationPacket) -> Result<Vec<u8>, EncodeError> {
+    let payload = encode_to_vec(packet, standard())?;
+
+    let mut record = Vec::with_capacity(payload.len() + RECORD_OVERHEAD as usize);
+    record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    record.extend_from_slice(&logged_unix_ms.to_le_bytes());
+    record.extend_from_slice(&payload);
+    let crc = crc32fast::hash(&record);
+    record.extend_from_slice(&crc.to_le_bytes());
+    Ok(record)
+}
+
+/// Reads records back out of a segment, stopping at the first bad one
+pub struct SegmentReader<R> {
+    reader: R,
+    valid_len: u64,
+    end: SegmentEnd,
+}
+
+impl SegmentReader<BufReader<File>> {
+    /// Open a segment file
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> SegmentReader<R> {
+    /// Check the header and get ready to read records
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = [0u8; SEGMENT_HEADER_LEN as usize];
+        reader.read_exact(&mut header)?;
+
+        if header[..8] != SEGMENT_MAGIC {
+            return Err(io::Error::new(ErrorKind::InvalidData, "not a packet segment"));
+        }
+        let version = u16::from_le_bytes([header[8], header[9]]);
+        if version != SEGMENT_VERSION {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("segment version {version}, expected {SEGMENT_VERSION}"),
+            ));
+        }
+
+        Ok(Self {
+            reader,
+            valid_len: SEGMENT_HEADER_LEN,
+            end: SegmentEnd::Reading,
+        })
+    }
+
+    /// Bytes from the start of the segment up to the end of the last good record
+    pub fn valid_len(&self) -> u64 {
+        self.valid_len
+    }
+
+    /// How reading finished, once the iterator has run out
+    pub fn end(&self) -> SegmentEnd {
+        self.end
+    }
+
+    // Fill `buf`, telling a clean end of file apart from one partway through
+    fn fill(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.reader.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(filled)
+    }
+
+    fn next_record(&mut self) -> Result<Record, SegmentEnd> {
+        let mut head = [0u8; 12];
+        match self.fill(&mut head) {
+            Ok(0) => return Err(SegmentEnd::Clean),
+            Ok(12) => {}
+            _ => return Err(SegmentEnd::Torn),
+        }
+
+        let len = u32::from_le_bytes(head[..4].try_into().unwrap()); // -Unwrap- fixed size slice
+        if len > MAX_PAYLOAD_LEN {
+            return Err(SegmentEnd::Corrupt);
+        }
+
+        let mut rest = vec![0u8; len as usize + 4];
+        match self.fill(&mut rest) {
+            Ok(n) if n == rest.len() => {}
+            _ => return Err(SegmentEnd::Torn),
+        }
+        let (payload, crc) = rest.split_at(len as usize);
+
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&head);
+        hasher.update(payload);
+        if hasher.finalize() != u32::from_le_bytes(crc.try_into().unwrap()) {
+            return Err(SegmentEnd::Corrupt);
+        }
+
+        let packet = match decode_from_slice::<ApplicationPacket, _>(payload, standard()) {
+            Ok((packet, read)) if read == payload.len() => packet,
+            _ => return Err(SegmentEnd::Corrupt),
+        };
+
+        self.valid_len += RECORD_OVERHEAD + len as u64;
+        Ok(Record {
+            logged_unix_ms: u64::from_le_bytes(head[4..].try_into().unwrap()),
+            packet,
+        })
+    }
+}
+
+impl<R: Read> Iterator for SegmentReader<R> {
+    type Item = Record;
+
+    fn next(&mut self) -> Option<Record> {
+        if self.end != SegmentEnd::Reading {
+            return None;
+        }
+        match self.next_record() {
+            Ok(record) => Some(record),
+            Err(end) => {
+                self.end = end;
+                None
+            }
+        }
+    }
+}
+
+/// Whether a file starts like a segment, as opposed to the older raw bincode logs
+pub fn is_segment<P: AsRef<Path>>(path: P) -> io::Result<bool> {
+    let mut magic = [0u8; 8];
+    match File::open(path)?.read_exact(&mut magic) {
+        Ok(()) => Ok(magic == SEGMENT_MAGIC),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn geiger(n: u16) -> ApplicationPacket {
+        ApplicationPacket::GeigerData {
+            timestamp_ms: n as u64 * 100,
+            recorded_pulses: n,
+        }
+    }
+
+    fn segment(count: u16) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_segment_header(&mut bytes).unwrap();
+        for n in 0..count {
+            bytes.extend(encode_record(1_000 + n as u64, &geiger(n)).unwrap());
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bytes = segment(3);
+        let mut reader = SegmentReader::new(bytes.as_slice()).unwrap();
+        let records: Vec<_> = reader.by_ref().collect();
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[2].logged_unix_ms, 1_002);
+        assert!(matches!(records[1].packet, ApplicationPacket::GeigerData { recorded_pulses: 1, .. }));
+        assert_eq!(reader.end(), SegmentEnd::Clean);
+        assert_eq!(reader.valid_len(), bytes.len() as u64);
+    }
+
+    #[test]
+    fn test_torn_tail() {
+        let whole = segment(3);
+        let two = segment(2).len();
+
+        // Cut anywhere in the last record
+        for cut in two + 1..whole.len() {
+            let mut reader = SegmentReader::new(&whole[..cut]).unwrap();
+            assert_eq!(reader.by_ref().count(), 2);
+            assert_eq!(reader.end(), SegmentEnd::Torn);
+            assert_eq!(reader.valid_len(), two as u64);
+        }
+    }
+
+    #[test]
+    fn test_bad_checksum() {
+        let mut bytes = segment(3);
+        let two = segment(2).len();
+        bytes[two + 14] ^= 0xff;
+
+        let mut reader = SegmentReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.by_ref().count(), 2);
+        assert_eq!(reader.end(), SegmentEnd::Corrupt);
+        assert_eq!(reader.valid_len(), two as u64);
+    }
+
+    #[test]
+    fn test_rejects_other_files() {
+        assert!(SegmentReader::new(&b"not a segment at all"[..]).is_err());
+
+        let mut bytes = segment(0);
+        bytes[8] = 0xee;
+        assert!(SegmentReader::new(bytes.as_slice()).is_err());
+    }
+}
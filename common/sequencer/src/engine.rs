@@ -0,0 +1,309 @@
+use bin_packets::sequence::{Actuator, Guard, Step, Target};
+
+use crate::{Limits, Table, TableError};
+
+/// The hardware a sequence drives
+pub trait Actuators {
+    /// Drive `actuator` to `target`. Steps are only ever for actuators the table's [`Limits`]
+    /// allow, with targets that make sense for them.
+    fn apply(&mut self, actuator: Actuator, target: Target);
+}
+
+/// What guards are checked against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Signals {
+    /// Time since power on, in ms
+    pub uptime_ms: u64,
+    /// The RBF pin is out
+    pub rbf_removed: bool,
+    /// Ejection has been commanded or the ejection line is up
+    pub eject: bool,
+}
+
+impl Signals {
+    fn allow(&self, guard: Guard) -> bool {
+        match guard {
+            Guard::None => true,
+            Guard::RbfRemoved => self.rbf_removed,
+            Guard::EjectSignal => self.eject,
+            Guard::AfterBoot(ms) => self.uptime_ms >= ms as u64,
+        }
+    }
+}
+
+/// Where a sequence is after a poll
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Progress {
+    /// Step `step` is waiting on its guard. Poll again once the signals might have changed.
+    Waiting {
+        /// Index of the step
+        step: usize,
+        /// What it's waiting on
+        guard: Guard,
+    },
+    /// Step `step` has been applied and is held until `until_ms`
+    Holding {
+        /// Index of the step
+        step: usize,
+        /// Uptime the hold ends at, in ms
+        until_ms: u64,
+    },
+    /// Every step has run
+    Done,
+}
+
+/// Why a table couldn't be swapped in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ReplaceError {
+    /// The current table has already started
+    Started,
+    /// The new table is outside the limits
+    Invalid(TableError),
+}
+
+/// Works through a table as it's polled
+#[derive(Debug)]
+pub struct Sequencer {
+    limits: Limits,
+    table: Table,
+    next: usize,
+    hold_until: Option<u64>,
+}
+
+impl Sequencer {
+    /// A sequencer for `steps`, if they're within `limits`
+    pub fn new(steps: &[Step], limits: Limits) -> Result<Self, TableError> {
+        limits.check(steps)?;
+        Ok(Self {
+            limits,
+            table: Table::from_slice(steps).map_err(|_| TableError::TooLong)?,
+            next: 0,
+            hold_until: None,
+        })
+    }
+
+    /// Swap in a new table, which is only allowed before the first step has been applied
+    pub fn replace(&mut self, steps: &[Step]) -> Result<(), ReplaceError> {
+        if self.started() {
+            return Err(ReplaceError::Started);
+        }
+        self.limits.check(steps).map_err(ReplaceError::Invalid)?;
+        self.table =
+            Table::from_slice(steps).map_err(|_| ReplaceError::Invalid(TableError::TooLong))?;
+        Ok(())
+    }
+
+    /// The table being run
+    pub fn steps(&self) -> &[Step] {
+        &self.table
+    }
+
+    /// Whether any step has been applied yet
+    pub fn started(&self) -> bool {
+        self.next > 0
+    }
+
+    /// Run every step that's due. Holds are timed back to back from when the previous one ended,
+    /// so a late poll doesn't stretch the sequence.
+    pub fn poll(&mut self, signals: Signals, actuators: &mut impl Actuators) -> Progress {
+        let now = signals.uptime_ms;
+        let mut at = now;
+
+        loop {
+            if let Some(until_ms) = self.hold_until {
+                if now < until_ms {
+                    return Progress::Holding {
+                        step: self.next - 1,
+                        until_ms,
+                    };
+                }
+                self.hold_until = None;
+                at = until_ms;
+            }
+
+            let Some(step) = self.table.get(self.next) else {
+                return Progress::Done;
+            };
+            if !signals.allow(step.guard) {
+                return Progress::Waiting {
+                    step: self.next,
+                    guard: step.guard,
+                };
+            }
+            if step.guard != Guard::None {
+                // Whenever it opened, this is when it was seen
+                at = now;
+            }
+
+            actuators.apply(step.actuator, step.target);
+            self.next += 1;
+            if step.duration_ms > 0 {
+                self.hold_until = Some(at + step.duration_ms as u64);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bin_packets::sequence::Polarity;
+
+    use super::*;
+    use crate::AngleRange;
+
+    const LIMITS: Limits = Limits {
+        actuators: &[
+            Actuator::EjectorServo,
+            Actuator::PowerServo,
+            Actuator::Magnet,
+        ],
+        ejector_servo: AngleRange::new(140, 240),
+        power_servo: AngleRange::new(30, 140),
+        max_hold_ms: 60_000,
+        max_magnet_ms: 10_000,
+        holding_angle: None,
+    };
+
+    // Records what was driven, and when
+    #[derive(Default)]
+    struct Log {
+        now: u64,
+        applied: Vec<(u64, Actuator, Target)>,
+    }
+
+    impl Actuators for Log {
+        fn apply(&mut self, actuator: Actuator, target: Target) {
+            self.applied.push((self.now, actuator, target));
+        }
+    }
+
+    fn signals(uptime_ms: u64) -> Signals {
+        Signals {
+            uptime_ms,
+            ..Signals::default()
+        }
+    }
+
+    const STEPS: [Step; 6] = [
+        Step::new(Actuator::EjectorServo, Target::Angle(150), 0).when(Guard::RbfRemoved),
+        Step::new(Actuator::PowerServo, Target::Angle(40), 2000).when(Guard::EjectSignal),
+        Step::new(Actuator::EjectorServo, Target::Angle(180), 50),
+        Step::new(Actuator::EjectorServo, Target::Angle(220), 50),
+        Step::new(Actuator::Magnet, Target::Magnet(Polarity::Repel), 7000),
+        Step::new(Actuator::Magnet, Target::Off, 0),
+    ];
+
+    #[test]
+    fn test_runs_table_in_order() {
+        let mut sequencer = Sequencer::new(&STEPS, LIMITS).unwrap();
+        let mut log = Log::default();
+
+        // Nothing until the RBF is out
+        assert_eq!(
+            sequencer.poll(signals(0), &mut log),
+            Progress::Waiting {
+                step: 0,
+                guard: Guard::RbfRemoved
+            }
+        );
+        assert!(!sequencer.started());
+
+        let mut armed = Signals {
+            rbf_removed: true,
+            ..signals(100)
+        };
+        log.now = 100;
+        assert_eq!(
+            sequencer.poll(armed, &mut log),
+            Progress::Waiting {
+                step: 1,
+                guard: Guard::EjectSignal
+            }
+        );
+        assert!(sequencer.started());
+
+        armed.eject = true;
+        armed.uptime_ms = 1000;
+        log.now = 1000;
+        assert_eq!(
+            sequencer.poll(armed, &mut log),
+            Progress::Holding {
+                step: 1,
+                until_ms: 3000
+            }
+        );
+
+        // Polled late, the sweep still runs to the original schedule
+        for now in [2999, 3020, 3060, 3110, 10_000, 10_100] {
+            armed.uptime_ms = now;
+            log.now = now;
+            sequencer.poll(armed, &mut log);
+        }
+        assert_eq!(sequencer.poll(armed, &mut log), Progress::Done);
+
+        let applied: Vec<_> = log
+            .applied
+            .iter()
+            .map(|&(at, _, target)| (at, target))
+            .collect();
+        assert_eq!(
+            applied,
+            [
+                (100, Target::Angle(150)),
+                (1000, Target::Angle(40)),
+                (3020, Target::Angle(180)),
+                (3060, Target::Angle(220)),
+                (3110, Target::Magnet(Polarity::Repel)),
+                (10_100, Target::Off),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_zero_length_steps_run_together() {
+        let steps = [
+            Step::new(Actuator::EjectorServo, Target::Angle(150), 0),
+            Step::new(Actuator::PowerServo, Target::Angle(130), 0),
+            Step::new(Actuator::PowerServo, Target::Off, 500),
+        ];
+        let mut sequencer = Sequencer::new(&steps, LIMITS).unwrap();
+        let mut log = Log::default();
+
+        assert_eq!(
+            sequencer.poll(signals(0), &mut log),
+            Progress::Holding {
+                step: 2,
+                until_ms: 500
+            }
+        );
+        assert_eq!(log.applied.len(), 3);
+        assert_eq!(sequencer.poll(signals(500), &mut log), Progress::Done);
+    }
+
+    #[test]
+    fn test_replace_only_before_start() {
+        let mut sequencer = Sequencer::new(&STEPS, LIMITS).unwrap();
+        let mut log = Log::default();
+
+        let bad = [Step::new(Actuator::EjectorServo, Target::Angle(300), 0)];
+        assert_eq!(
+            sequencer.replace(&bad),
+            Err(ReplaceError::Invalid(TableError::AngleOutOfRange {
+                index: 0,
+                angle: 300
+            }))
+        );
+        assert_eq!(sequencer.steps(), STEPS);
+
+        let shorter = [Step::new(Actuator::PowerServo, Target::Angle(130), 100)];
+        sequencer.replace(&shorter).unwrap();
+        assert_eq!(sequencer.steps(), shorter);
+
+        sequencer.poll(signals(0), &mut log);
+        assert_eq!(sequencer.replace(&STEPS), Err(ReplaceError::Started));
+        assert_eq!(sequencer.steps(), shorter);
+    }
+}
@@ -0,0 +1,424 @@
+use bin_packets::data::{ActuationOutcome, ActuationResult};
+use bin_packets::sequence::Target;
+use embedded_hal::digital::InputPin;
+
+/// Converts potentiometer ADC counts to degrees, linear through two calibration points
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PotCalibration {
+    /// Counts at `deg_low`
+    pub raw_low: u16,
+    /// Angle read at `raw_low`
+    pub deg_low: u16,
+    /// Counts at `deg_high`
+    pub raw_high: u16,
+    /// Angle read at `raw_high`
+    pub deg_high: u16,
+}
+
+impl PotCalibration {
+    /// The angle `raw` counts read as, clamped at zero
+    pub fn degrees(&self, raw: u16) -> u16 {
+        let span = self.raw_high as i32 - self.raw_low as i32;
+        if span == 0 {
+            return self.deg_low;
+        }
+        let deg = self.deg_low as i32
+            + (raw as i32 - self.raw_low as i32) * (self.deg_high as i32 - self.deg_low as i32)
+                / span;
+        deg.clamp(0, u16::MAX as i32) as u16
+    }
+}
+
+/// How a servo move is judged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MotionLimits {
+    /// Close enough to the target, in degrees
+    pub tolerance_deg: u16,
+    /// Smallest change that counts as moving, in degrees. Keep it above the pot's noise.
+    pub min_step_deg: u16,
+    /// How long a move may take to get going, in ms
+    pub start_ms: u32,
+    /// How long a move may go without progress once it's going, in ms
+    pub stall_ms: u32,
+}
+
+// A move being watched
+#[derive(Debug, Clone, Copy)]
+struct Move {
+    target: u16,
+    commanded_at: u64,
+    start_deg: u16,
+    moving: bool,
+    // Position and time of the last progress
+    progress_deg: u16,
+    progress_at: u64,
+}
+
+/// Watches a servo's potentiometer for it to reach each commanded angle
+#[derive(Debug, Clone, Copy)]
+pub struct ServoMonitor {
+    calibration: PotCalibration,
+    limits: MotionLimits,
+    current: Option<Move>,
+}
+
+impl ServoMonitor {
+    /// Nothing commanded yet
+    pub const fn new(calibration: PotCalibration, limits: MotionLimits) -> Self {
+        Self {
+            calibration,
+            limits,
+            current: None,
+        }
+    }
+
+    /// The servo was commanded to `target` degrees, with the pot at `raw`. Commanding again
+    /// before the last move has a verdict retargets it, so a sweep is judged as one move.
+    pub fn command(&mut self, target: u16, now_ms: u64, raw: u16) {
+        let deg = self.calibration.degrees(raw);
+        self.current = Some(match self.current {
+            Some(current) => Move { target, ..current },
+            None => Move {
+                target,
+                commanded_at: now_ms,
+                start_deg: deg,
+                moving: false,
+                progress_deg: deg,
+                progress_at: now_ms,
+            },
+        });
+    }
+
+    /// Whether a move is waiting on a verdict
+    pub fn busy(&self) -> bool {
+        self.current.is_some()
+    }
+
+    /// Check the latest reading. Returns the verdict once there is one, and stops watching until
+    /// the next command.
+    pub fn update(&mut self, now_ms: u64, raw: u16) -> Option<ActuationResult> {
+        let current = self.current.as_mut()?;
+        let deg = self.calibration.degrees(raw);
+
+        let outcome = if deg.abs_diff(current.target) <= self.limits.tolerance_deg {
+            Some(ActuationOutcome::Reached)
+        } else {
+            if deg.abs_diff(current.progress_deg) >= self.limits.min_step_deg {
+                current.progress_deg = deg;
+                current.progress_at = now_ms;
+            }
+            if deg.abs_diff(current.start_deg) >= self.limits.min_step_deg {
+                current.moving = true;
+            }
+
+            let since_progress = now_ms.saturating_sub(current.progress_at);
+            if !current.moving && since_progress >= self.limits.start_ms as u64 {
+                Some(ActuationOutcome::NoMotion)
+            } else if current.moving && since_progress >= self.limits.stall_ms as u64 {
+                Some(ActuationOutcome::Stalled)
+            } else {
+                None
+            }
+        }?;
+
+        let result = ActuationResult {
+            target: Target::Angle(current.target),
+            outcome,
+            reading: deg,
+            elapsed_ms: now_ms.saturating_sub(current.commanded_at) as u32,
+        };
+        self.current = None;
+        Some(result)
+    }
+
+    /// The pot couldn't be read, so the move in progress can't be judged
+    pub fn fault(&mut self, now_ms: u64) -> Option<ActuationResult> {
+        let current = self.current.take()?;
+        Some(ActuationResult {
+            target: Target::Angle(current.target),
+            outcome: ActuationOutcome::SenseFault,
+            reading: current.progress_deg,
+            elapsed_ms: now_ms.saturating_sub(current.commanded_at) as u32,
+        })
+    }
+}
+
+/// What the magnet should draw, with the sense amplifier's scale
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CurrentLimits {
+    /// Sense scale, in uA per ADC count
+    pub ua_per_count: u32,
+    /// Least current once energized, in mA
+    pub min_on_ma: u16,
+    /// Most current once energized, in mA
+    pub max_on_ma: u16,
+    /// Most current once switched off, in mA
+    pub max_off_ma: u16,
+    /// How long the coil current takes to settle after a switch, in ms
+    pub settle_ms: u32,
+}
+
+impl CurrentLimits {
+    /// Current `raw` counts read as, in mA
+    pub fn milliamps(&self, raw: u16) -> u16 {
+        (raw as u32 * self.ua_per_count / 1000).min(u16::MAX as u32) as u16
+    }
+}
+
+/// Watches the magnet's current sense for it to switch as commanded
+#[derive(Debug, Clone, Copy)]
+pub struct MagnetMonitor {
+    limits: CurrentLimits,
+    // What it was switched to, and when
+    current: Option<(Target, u64)>,
+}
+
+impl MagnetMonitor {
+    /// Nothing commanded yet
+    pub const fn new(limits: CurrentLimits) -> Self {
+        Self {
+            limits,
+            current: None,
+        }
+    }
+
+    /// The magnet was switched to `target`
+    pub fn command(&mut self, target: Target, now_ms: u64) {
+        self.current = Some((target, now_ms));
+    }
+
+    /// Whether a switch is waiting on a verdict
+    pub fn busy(&self) -> bool {
+        self.current.is_some()
+    }
+
+    /// Check the latest reading once the current has settled. Returns the verdict once there is
+    /// one, and stops watching until the next command.
+    pub fn update(&mut self, now_ms: u64, raw: u16) -> Option<ActuationResult> {
+        let (target, commanded_at) = self.current?;
+        let elapsed_ms = now_ms.saturating_sub(commanded_at);
+        if elapsed_ms < self.limits.settle_ms as u64 {
+            return None;
+        }
+
+        let ma = self.limits.milliamps(raw);
+        let outcome = match target {
+            Target::Off if ma > self.limits.max_off_ma => ActuationOutcome::StuckOn,
+            Target::Off => ActuationOutcome::Reached,
+            _ if ma < self.limits.min_on_ma => ActuationOutcome::NoCurrent,
+            _ if ma > self.limits.max_on_ma => ActuationOutcome::OverCurrent,
+            _ => ActuationOutcome::Reached,
+        };
+        self.current = None;
+        Some(ActuationResult {
+            target,
+            outcome,
+            reading: ma,
+            elapsed_ms: elapsed_ms as u32,
+        })
+    }
+
+    /// The current sense couldn't be read, so the switch in progress can't be judged
+    pub fn fault(&mut self, now_ms: u64) -> Option<ActuationResult> {
+        let (target, commanded_at) = self.current.take()?;
+        Some(ActuationResult {
+            target,
+            outcome: ActuationOutcome::SenseFault,
+            reading: 0,
+            elapsed_ms: now_ms.saturating_sub(commanded_at) as u32,
+        })
+    }
+}
+
+/// A limit switch held closed by the deployable, that opens once it has left
+#[derive(Debug)]
+pub struct DeploymentSwitch<P> {
+    pin: P,
+    closed_low: bool,
+}
+
+impl<P: InputPin> DeploymentSwitch<P> {
+    /// A switch on `pin`, which reads low while closed if `closed_low`
+    pub fn new(pin: P, closed_low: bool) -> Self {
+        Self { pin, closed_low }
+    }
+
+    /// Whether the deployable is gone
+    pub fn deployed(&mut self) -> Result<bool, P::Error> {
+        let low = self.pin.is_low()?;
+        Ok(low != self.closed_low)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_hal_mock::eh1::digital::{Mock as PinMock, State, Transaction};
+
+    use super::*;
+
+    const POT: PotCalibration = PotCalibration {
+        raw_low: 500,
+        deg_low: 0,
+        raw_high: 3500,
+        deg_high: 300,
+    };
+
+    const MOTION: MotionLimits = MotionLimits {
+        tolerance_deg: 5,
+        min_step_deg: 2,
+        start_ms: 300,
+        stall_ms: 300,
+    };
+
+    // A servo that slews toward its command at 1 deg/ms, unless it jams at `jam`
+    struct Plant {
+        deg: u16,
+        jam: Option<u16>,
+    }
+
+    impl Plant {
+        fn step(&mut self, target: u16) -> u16 {
+            let stop = match self.jam {
+                Some(jam) if target > self.deg => target.min(jam),
+                _ => target,
+            };
+            if stop > self.deg {
+                self.deg += 1;
+            } else if stop < self.deg {
+                self.deg -= 1;
+            }
+            // Back to counts, as the pot reads it
+            500 + self.deg * 10
+        }
+    }
+
+    fn run(plant: &mut Plant, monitor: &mut ServoMonitor, target: u16) -> ActuationResult {
+        monitor.command(target, 0, 500 + plant.deg * 10);
+        (1..2000)
+            .find_map(|now| {
+                let raw = plant.step(target);
+                monitor.update(now, raw)
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_pot_calibration() {
+        assert_eq!(POT.degrees(500), 0);
+        assert_eq!(POT.degrees(2000), 150);
+        assert_eq!(POT.degrees(3500), 300);
+        // Below the low point clamps rather than wrapping
+        assert_eq!(POT.degrees(0), 0);
+    }
+
+    #[test]
+    fn test_servo_reaches_target() {
+        let mut plant = Plant {
+            deg: 150,
+            jam: None,
+        };
+        let mut monitor = ServoMonitor::new(POT, MOTION);
+
+        let result = run(&mut plant, &mut monitor, 220);
+        assert_eq!(result.outcome, ActuationOutcome::Reached);
+        assert_eq!(result.target, Target::Angle(220));
+        assert!(result.reading.abs_diff(220) <= 5);
+        assert!(!monitor.busy());
+    }
+
+    #[test]
+    fn test_servo_stall_and_no_motion() {
+        // Jams partway through the sweep
+        let mut plant = Plant {
+            deg: 150,
+            jam: Some(180),
+        };
+        let mut monitor = ServoMonitor::new(POT, MOTION);
+        let result = run(&mut plant, &mut monitor, 220);
+        assert_eq!(result.outcome, ActuationOutcome::Stalled);
+        assert_eq!(result.reading, 180);
+
+        // Never leaves the start
+        let mut plant = Plant {
+            deg: 150,
+            jam: Some(150),
+        };
+        let result = run(&mut plant, &mut monitor, 220);
+        assert_eq!(result.outcome, ActuationOutcome::NoMotion);
+        assert_eq!(result.elapsed_ms, 300);
+    }
+
+    #[test]
+    fn test_servo_retarget_is_one_move() {
+        let mut monitor = ServoMonitor::new(POT, MOTION);
+        monitor.command(140, 0, 2000);
+        assert_eq!(monitor.update(50, 2010), None);
+        // Next notch of the sweep before the first got a verdict
+        monitor.command(150, 50, 2010);
+        let result = monitor.update(100, 1950).unwrap();
+        assert_eq!(result.outcome, ActuationOutcome::Reached);
+        assert_eq!(result.target, Target::Angle(150));
+        assert_eq!(result.elapsed_ms, 100);
+
+        monitor.command(220, 200, 2500);
+        let fault = monitor.fault(250).unwrap();
+        assert_eq!(fault.outcome, ActuationOutcome::SenseFault);
+        assert_eq!(monitor.update(300, 2500), None);
+    }
+
+    #[test]
+    fn test_magnet_current() {
+        let limits = CurrentLimits {
+            ua_per_count: 1000,
+            min_on_ma: 400,
+            max_on_ma: 1500,
+            max_off_ma: 50,
+            settle_ms: 20,
+        };
+        let repel = Target::Magnet(bin_packets::sequence::Polarity::Repel);
+        let mut monitor = MagnetMonitor::new(limits);
+
+        monitor.command(repel, 0);
+        // Still settling
+        assert_eq!(monitor.update(10, 0), None);
+        let result = monitor.update(20, 900).unwrap();
+        assert_eq!(result.outcome, ActuationOutcome::Reached);
+        assert_eq!(result.reading, 900);
+
+        monitor.command(repel, 100);
+        assert_eq!(
+            monitor.update(120, 100).unwrap().outcome,
+            ActuationOutcome::NoCurrent
+        );
+        monitor.command(repel, 200);
+        assert_eq!(
+            monitor.update(220, 2000).unwrap().outcome,
+            ActuationOutcome::OverCurrent
+        );
+        monitor.command(Target::Off, 300);
+        assert_eq!(
+            monitor.update(320, 300).unwrap().outcome,
+            ActuationOutcome::StuckOn
+        );
+        monitor.command(Target::Off, 400);
+        assert_eq!(
+            monitor.update(420, 10).unwrap().outcome,
+            ActuationOutcome::Reached
+        );
+        assert_eq!(monitor.update(500, 2000), None);
+    }
+
+    #[test]
+    fn test_deployment_switch() {
+        let expectations = [Transaction::get(State::Low), Transaction::get(State::High)];
+        let mut pin = PinMock::new(&expectations);
+        let mut switch = DeploymentSwitch::new(pin.clone(), true);
+
+        assert!(!switch.deployed().unwrap());
+        assert!(switch.deployed().unwrap());
+        pin.done();
+    }
+}
@@ -0,0 +1,26 @@
+#![warn(missing_docs)]
+#![cfg_attr(not(test), no_std)]
+
+//! Running actuation sequences from step tables
+//!
+//! A table is checked against the [`Limits`] of the hardware it drives before it's accepted, and
+//! a [`Sequencer`] then works through it as it's polled. Tables can be compiled in, or sent a
+//! step at a time as [`CommandPacket::SequenceStep`](bin_packets::commands::CommandPacket) and
+//! put back together with an [`Upload`]. Where an actuator has feedback, a [`ServoMonitor`] or
+//! [`MagnetMonitor`] judges whether each move actually happened.
+
+mod engine;
+mod feedback;
+mod limits;
+mod upload;
+
+pub use engine::*;
+pub use feedback::*;
+pub use limits::*;
+pub use upload::*;
+
+/// Most steps a table can hold
+pub const MAX_STEPS: usize = 32;
+
+/// A sequence's steps
+pub type Table = heapless::Vec<bin_packets::sequence::Step, MAX_STEPS>;
@@ -0,0 +1,342 @@
+use bin_packets::sequence::{Actuator, Guard, Step, Target};
+
+use crate::MAX_STEPS;
+
+/// Servo travel a table may command, in degrees
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AngleRange {
+    /// Smallest angle allowed
+    pub min: u16,
+    /// Largest angle allowed
+    pub max: u16,
+}
+
+impl AngleRange {
+    /// Angles from `min` to `max` inclusive
+    pub const fn new(min: u16, max: u16) -> Self {
+        Self { min, max }
+    }
+
+    fn contains(&self, angle: u16) -> bool {
+        (self.min..=self.max).contains(&angle)
+    }
+}
+
+/// What a table is allowed to do to the hardware it runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Limits {
+    /// The actuators this sequence owns
+    pub actuators: &'static [Actuator],
+    /// Ejector servo travel
+    pub ejector_servo: AngleRange,
+    /// Power servo travel
+    pub power_servo: AngleRange,
+    /// Longest a single step may hold, in ms
+    pub max_hold_ms: u32,
+    /// Longest the magnet may stay energized, in ms. The coil heats up fast.
+    pub max_magnet_ms: u32,
+    /// Ejector servo angle that holds the deployable in, for sequences that eject it. The table
+    /// then has to start on [`Guard::RbfRemoved`], and can't open the servo past this or energize
+    /// the magnet before a [`Guard::EjectSignal`] step.
+    pub holding_angle: Option<u16>,
+}
+
+/// Why a table was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TableError {
+    /// No steps at all
+    Empty,
+    /// More than [`MAX_STEPS`]
+    TooLong,
+    /// Drives an actuator this sequence doesn't own
+    NotAllowed {
+        /// Offending step
+        index: usize,
+    },
+    /// The target doesn't make sense for the actuator, like an angle for the magnet
+    WrongTarget {
+        /// Offending step
+        index: usize,
+    },
+    /// A servo angle outside its travel
+    AngleOutOfRange {
+        /// Offending step
+        index: usize,
+        /// Angle asked for
+        angle: u16,
+    },
+    /// A step holds longer than `max_hold_ms`
+    HoldTooLong {
+        /// Offending step
+        index: usize,
+    },
+    /// The magnet is energized longer than `max_magnet_ms`, or across a guard that could keep
+    /// it on indefinitely
+    MagnetTooLong {
+        /// Step that energized it
+        index: usize,
+    },
+    /// The table finishes with the magnet still energized
+    MagnetLeftOn,
+    /// An ejecting table whose first step doesn't wait for the RBF pin
+    NoRbfGuard,
+    /// Releases the deployable before any step waits for the ejection signal
+    BeforeEjectSignal {
+        /// Offending step
+        index: usize,
+    },
+}
+
+impl Limits {
+    /// Check every step of `steps`
+    pub fn check(&self, steps: &[Step]) -> Result<(), TableError> {
+        if steps.is_empty() {
+            return Err(TableError::Empty);
+        }
+        if steps.len() > MAX_STEPS {
+            return Err(TableError::TooLong);
+        }
+
+        if self.holding_angle.is_some() && steps[0].guard != Guard::RbfRemoved {
+            return Err(TableError::NoRbfGuard);
+        }
+
+        // Step that energized the magnet, and how long it's been on by the end of each step
+        let mut magnet: Option<(usize, u32)> = None;
+        let mut eject_signalled = false;
+
+        for (index, step) in steps.iter().enumerate() {
+            if !self.actuators.contains(&step.actuator) {
+                return Err(TableError::NotAllowed { index });
+            }
+            self.check_target(index, step)?;
+            if step.duration_ms > self.max_hold_ms {
+                return Err(TableError::HoldTooLong { index });
+            }
+            eject_signalled |= step.guard == Guard::EjectSignal;
+            if !eject_signalled && self.releases(step) {
+                return Err(TableError::BeforeEjectSignal { index });
+            }
+
+            if let Some((on_at, _)) = magnet
+                && step.guard != Guard::None
+            {
+                return Err(TableError::MagnetTooLong { index: on_at });
+            }
+            magnet = match (step.actuator, step.target) {
+                (Actuator::Magnet, Target::Off) => None,
+                (Actuator::Magnet, Target::Magnet(_)) => magnet.or(Some((index, 0))),
+                _ => magnet,
+            };
+            if let Some((on_at, on_for)) = magnet {
+                let on_for = on_for.saturating_add(step.duration_ms);
+                if on_for > self.max_magnet_ms {
+                    return Err(TableError::MagnetTooLong { index: on_at });
+                }
+                magnet = Some((on_at, on_for));
+            }
+        }
+
+        match magnet {
+            Some(_) => Err(TableError::MagnetLeftOn),
+            None => Ok(()),
+        }
+    }
+
+    // Whether the step lets go of the deployable, on a sequence that holds one
+    fn releases(&self, step: &Step) -> bool {
+        let Some(holding_angle) = self.holding_angle else {
+            return false;
+        };
+        match (step.actuator, step.target) {
+            (Actuator::EjectorServo, Target::Angle(angle)) => angle > holding_angle,
+            (Actuator::Magnet, Target::Magnet(_)) => true,
+            _ => false,
+        }
+    }
+
+    fn check_target(&self, index: usize, step: &Step) -> Result<(), TableError> {
+        let travel = match step.actuator {
+            Actuator::EjectorServo => self.ejector_servo,
+            Actuator::PowerServo => self.power_servo,
+            Actuator::Magnet => {
+                return match step.target {
+                    Target::Magnet(_) | Target::Off => Ok(()),
+                    _ => Err(TableError::WrongTarget { index }),
+                };
+            }
+            Actuator::Cameras => {
+                return match step.target {
+                    Target::On | Target::Off => Ok(()),
+                    _ => Err(TableError::WrongTarget { index }),
+                };
+            }
+        };
+
+        match step.target {
+            Target::Angle(angle) if !travel.contains(angle) => {
+                Err(TableError::AngleOutOfRange { index, angle })
+            }
+            Target::Angle(_) | Target::On | Target::Off => Ok(()),
+            Target::Magnet(_) => Err(TableError::WrongTarget { index }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bin_packets::sequence::Polarity;
+
+    use super::*;
+
+    const LIMITS: Limits = Limits {
+        actuators: &[
+            Actuator::EjectorServo,
+            Actuator::PowerServo,
+            Actuator::Magnet,
+        ],
+        ejector_servo: AngleRange::new(140, 240),
+        power_servo: AngleRange::new(30, 140),
+        max_hold_ms: 60_000,
+        max_magnet_ms: 10_000,
+        holding_angle: None,
+    };
+
+    const EJECTING: Limits = Limits {
+        holding_angle: Some(150),
+        ..LIMITS
+    };
+
+    fn servo(angle: u16, duration_ms: u32) -> Step {
+        Step::new(Actuator::EjectorServo, Target::Angle(angle), duration_ms)
+    }
+
+    fn magnet(target: Target, duration_ms: u32) -> Step {
+        Step::new(Actuator::Magnet, target, duration_ms)
+    }
+
+    const REPEL: Target = Target::Magnet(Polarity::Repel);
+
+    #[test]
+    fn test_accepts_in_bounds() {
+        let steps = [
+            servo(150, 0).when(Guard::RbfRemoved),
+            servo(220, 50).when(Guard::EjectSignal),
+            magnet(REPEL, 7000),
+            magnet(Target::Off, 0),
+            servo(150, 0),
+        ];
+        assert_eq!(LIMITS.check(&steps), Ok(()));
+        assert_eq!(EJECTING.check(&steps), Ok(()));
+    }
+
+    #[test]
+    fn test_ejection_waits_for_rbf_and_signal() {
+        // Fine anywhere but on the ejector
+        let unguarded = [servo(220, 50)];
+        assert_eq!(LIMITS.check(&unguarded), Ok(()));
+        assert_eq!(EJECTING.check(&unguarded), Err(TableError::NoRbfGuard));
+
+        let early_release = [servo(150, 0).when(Guard::RbfRemoved), servo(220, 50)];
+        assert_eq!(
+            EJECTING.check(&early_release),
+            Err(TableError::BeforeEjectSignal { index: 1 })
+        );
+
+        let early_magnet = [
+            servo(150, 0).when(Guard::RbfRemoved),
+            magnet(REPEL, 1000),
+            magnet(Target::Off, 0),
+            servo(220, 50).when(Guard::EjectSignal),
+        ];
+        assert_eq!(
+            EJECTING.check(&early_magnet),
+            Err(TableError::BeforeEjectSignal { index: 1 })
+        );
+
+        // Moving within the latch, or anything else, is fine before the signal
+        let settle = [
+            servo(150, 0).when(Guard::RbfRemoved),
+            servo(140, 50),
+            Step::new(Actuator::PowerServo, Target::Angle(130), 0),
+            servo(220, 50).when(Guard::EjectSignal),
+        ];
+        assert_eq!(EJECTING.check(&settle), Ok(()));
+    }
+
+    #[test]
+    fn test_rejects_out_of_bounds_steps() {
+        assert_eq!(LIMITS.check(&[]), Err(TableError::Empty));
+        assert_eq!(
+            LIMITS.check(&[servo(150, 0); MAX_STEPS + 1]),
+            Err(TableError::TooLong)
+        );
+        assert_eq!(
+            LIMITS.check(&[servo(150, 0), servo(260, 0)]),
+            Err(TableError::AngleOutOfRange {
+                index: 1,
+                angle: 260
+            })
+        );
+        assert_eq!(
+            LIMITS.check(&[Step::new(Actuator::Cameras, Target::On, 0)]),
+            Err(TableError::NotAllowed { index: 0 })
+        );
+        assert_eq!(
+            LIMITS.check(&[magnet(Target::Angle(90), 0)]),
+            Err(TableError::WrongTarget { index: 0 })
+        );
+        assert_eq!(
+            LIMITS.check(&[Step::new(Actuator::PowerServo, REPEL, 0)]),
+            Err(TableError::WrongTarget { index: 0 })
+        );
+        assert_eq!(
+            LIMITS.check(&[servo(150, 60_001)]),
+            Err(TableError::HoldTooLong { index: 0 })
+        );
+    }
+
+    #[test]
+    fn test_magnet_on_time_is_bounded() {
+        // Over the limit across several steps, counting a polarity change as still on
+        let long = [
+            servo(150, 0),
+            magnet(REPEL, 6000),
+            magnet(Target::Magnet(Polarity::Attract), 3000),
+            servo(220, 1500),
+            magnet(Target::Off, 0),
+        ];
+        assert_eq!(
+            LIMITS.check(&long),
+            Err(TableError::MagnetTooLong { index: 1 })
+        );
+
+        // A guard while it's on could wait forever
+        let guarded = [
+            magnet(REPEL, 0),
+            servo(220, 0).when(Guard::EjectSignal),
+            magnet(Target::Off, 0),
+        ];
+        assert_eq!(
+            LIMITS.check(&guarded),
+            Err(TableError::MagnetTooLong { index: 0 })
+        );
+
+        assert_eq!(
+            LIMITS.check(&[magnet(REPEL, 1000)]),
+            Err(TableError::MagnetLeftOn)
+        );
+
+        // Switched off in between resets the count
+        let pulsed = [
+            magnet(REPEL, 8000),
+            magnet(Target::Off, 1000),
+            magnet(REPEL, 8000),
+            magnet(Target::Off, 0),
+        ];
+        assert_eq!(LIMITS.check(&pulsed), Ok(()));
+    }
+}
@@ -0,0 +1,158 @@
+use bin_packets::commands::CommandPacket;
+use bin_packets::sequence::{SequenceId, Step};
+
+use crate::{MAX_STEPS, Table};
+
+/// Why a step couldn't be staged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum UploadError {
+    /// The table is empty or longer than [`MAX_STEPS`]
+    BadLength,
+    /// The index is past the end of the table
+    BadIndex,
+}
+
+/// Puts a table back together from its [`CommandPacket::SequenceStep`]s, which can arrive in any
+/// order and more than once
+#[derive(Debug)]
+pub struct Upload {
+    sequence: Option<SequenceId>,
+    len: u8,
+    steps: [Option<Step>; MAX_STEPS],
+}
+
+impl Default for Upload {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Upload {
+    /// Nothing staged
+    pub const fn new() -> Self {
+        Self {
+            sequence: None,
+            len: 0,
+            steps: [None; MAX_STEPS],
+        }
+    }
+
+    /// Stage one step. A step for a different sequence or length starts over. Returns the table
+    /// once every step is in, which still has to pass the sequence's limits.
+    pub fn receive(
+        &mut self,
+        sequence: SequenceId,
+        index: u8,
+        len: u8,
+        step: Step,
+    ) -> Result<Option<(SequenceId, Table)>, UploadError> {
+        if len == 0 || len as usize > MAX_STEPS {
+            return Err(UploadError::BadLength);
+        }
+        if index >= len {
+            return Err(UploadError::BadIndex);
+        }
+
+        if self.sequence != Some(sequence) || self.len != len {
+            *self = Self::new();
+            self.sequence = Some(sequence);
+            self.len = len;
+        }
+        self.steps[index as usize] = Some(step);
+
+        let staged = &self.steps[..len as usize];
+        if staged.iter().any(Option::is_none) {
+            return Ok(None);
+        }
+        let table = staged.iter().flatten().copied().collect();
+        *self = Self::new();
+        Ok(Some((sequence, table)))
+    }
+}
+
+/// The commands that send `steps` as the new table for `sequence`
+pub fn upload(sequence: SequenceId, steps: &[Step]) -> impl Iterator<Item = CommandPacket> + '_ {
+    let len = steps.len() as u8;
+    steps
+        .iter()
+        .enumerate()
+        .map(move |(index, &step)| CommandPacket::SequenceStep {
+            sequence,
+            index: index as u8,
+            len,
+            step,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use bin_packets::sequence::{Actuator, Target};
+
+    use super::*;
+
+    fn angle(angle: u16) -> Step {
+        Step::new(Actuator::EjectorServo, Target::Angle(angle), 50)
+    }
+
+    fn stage(upload: &mut Upload, packet: CommandPacket) -> Option<(SequenceId, Table)> {
+        let CommandPacket::SequenceStep {
+            sequence,
+            index,
+            len,
+            step,
+        } = packet
+        else {
+            panic!("not a sequence step");
+        };
+        upload.receive(sequence, index, len, step).unwrap()
+    }
+
+    #[test]
+    fn test_reassembles_out_of_order_with_repeats() {
+        let steps = [angle(140), angle(160), angle(180)];
+        let packets: Vec<_> = upload(SequenceId::Ejector, &steps).collect();
+        let mut upload = Upload::new();
+
+        assert_eq!(stage(&mut upload, packets[2]), None);
+        assert_eq!(stage(&mut upload, packets[0]), None);
+        // A retry of one already in
+        assert_eq!(stage(&mut upload, packets[2]), None);
+
+        let (sequence, table) = stage(&mut upload, packets[1]).unwrap();
+        assert_eq!(sequence, SequenceId::Ejector);
+        assert_eq!(table, steps);
+    }
+
+    #[test]
+    fn test_new_upload_starts_over() {
+        let mut upload = Upload::new();
+        let old: Vec<_> = super::upload(SequenceId::Ejector, &[angle(140), angle(150)]).collect();
+        assert_eq!(stage(&mut upload, old[0]), None);
+
+        // Another sequence, then a different length, each throw away what was staged
+        let cameras = Step::new(Actuator::Cameras, Target::On, 0);
+        assert_eq!(upload.receive(SequenceId::Cameras, 1, 2, cameras), Ok(None));
+        assert_eq!(
+            upload.receive(SequenceId::Cameras, 0, 1, cameras),
+            Ok(Some((
+                SequenceId::Cameras,
+                Table::from_slice(&[cameras]).unwrap()
+            )))
+        );
+
+        assert_eq!(stage(&mut upload, old[1]), None);
+        assert_eq!(
+            upload.receive(SequenceId::Ejector, 2, 2, angle(140)),
+            Err(UploadError::BadIndex)
+        );
+        assert_eq!(
+            upload.receive(SequenceId::Ejector, 0, 0, angle(140)),
+            Err(UploadError::BadLength)
+        );
+        assert_eq!(
+            upload.receive(SequenceId::Ejector, 0, MAX_STEPS as u8 + 1, angle(140)),
+            Err(UploadError::BadLength)
+        );
+    }
+}
@@ -7,3 +7,23 @@ pub enum SignalError {
     #[error("Error Reading SDR Stream, sample length: {0} ")]
     StreamReadError(usize),
 }
+
+#[derive(Error, Debug)]
+pub enum RecordingError {
+    #[error("Recording IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Error encoding recording data: {0}")]
+    Encode(#[from] bincode::error::EncodeError),
+    #[error("Error decoding recording data: {0}")]
+    Decode(#[from] bincode::error::DecodeError),
+    #[error("Error writing SigMF metadata: {0}")]
+    Metadata(#[from] serde_json::Error),
+    #[error("Not a signet recording")]
+    BadMagic,
+    #[error("Unsupported recording version {0}")]
+    UnsupportedVersion(u16),
+    #[error("Recording header failed its checksum")]
+    HeaderChecksum,
+    #[error("Packet {0} is not in the recording")]
+    PacketNotFound(u64),
+}
@@ -19,6 +19,10 @@ use signet::{
 fn main() {
     let mut expected_average: Vec<f32> = Vec::new();
 
+    if Cli::run_recording_tools() {
+        return;
+    }
+
     let (radio_config, signal_config) = Cli::get_configs();
     let (record_baseline, psd_path) = Cli::run_commands();
 
@@ -7,10 +7,38 @@ use std::borrow::Cow;
 // May need to be paced to guarantee zero padding.
 // packed
 // #[repr(C)]
+/// Whatever was lost between the previous packet in the stream and this one. Most packets carry
+/// an empty marker, anything else means the recording has a hole right before this packet.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GapMarker {
+    /// Packets thrown away before reaching the recorder because the ring buffer was full
+    pub dropped_packets: u32,
+    /// Times the device reported an overflow while this packet was being filled
+    pub overflows: u32,
+    /// Reads that came back with less than a full chunk
+    pub short_reads: u32,
+    /// Downsampled samples that didn't fit in the packet and were discarded
+    pub truncated_samples: u32,
+    /// How far this packet's timestamp is from where the previous packet said it should be,
+    /// in nanoseconds. Zero when it's within tolerance.
+    pub discontinuity_ns: i64,
+}
+
+impl GapMarker {
+    /// Whether any samples were lost before this packet. Short reads alone don't lose data.
+    pub fn is_gap(&self) -> bool {
+        self.dropped_packets > 0
+            || self.overflows > 0
+            || self.truncated_samples > 0
+            || self.discontinuity_ns != 0
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 pub struct SdrPacketLog {
     pub timestamp: u128,
     pub sample_count: usize,
+    pub gap: GapMarker,
     #[serde(with = "serde_arrays")]
     pub samples: [Complex<f32>; BUFF_SIZE],
 }
@@ -20,6 +48,7 @@ impl SdrPacketLog {
         Self {
             timestamp,
             sample_count,
+            gap: GapMarker::default(),
             samples,
         }
     }
@@ -30,6 +59,7 @@ impl Default for SdrPacketLog {
         Self {
             timestamp: 0,
             sample_count: 0,
+            gap: GapMarker::default(),
             samples: [Complex::new(0.0, 0.0); BUFF_SIZE],
         }
     }
@@ -39,5 +69,6 @@ impl Default for SdrPacketLog {
 pub struct SdrPacketOwned {
     pub timestamp: u128,
     pub sample_count: usize,
+    pub gap: GapMarker,
     pub data: Vec<Complex<f32>>,
 }
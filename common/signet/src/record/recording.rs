@@ -0,0 +1,908 @@
+use crate::error::RecordingError;
+use crate::record::packet::SdrPacketLog;
+use crate::sdr::radio_config::RadioConfig;
+use bincode::{
+    config::standard,
+    serde::{decode_from_slice, encode_into_std_write},
+};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Recording layout:
+//
+//  | magic (8) | version (u16) | header len (u32) | header (bincode) | header crc (u32) |
+//  | record | record | ... | footer record |
+//
+// Every record is framed as
+//
+//  | tag (u8) | payload len (u32) | payload | crc32 of tag + len + payload (u32) |
+//
+// Chunks hold one bincode SdrPacketLog each. Every INDEX_INTERVAL chunks an index record is
+// written listing the offset and timestamp of those chunks along with the offset of the previous
+// index record, so a reader can walk the index backwards from the footer without touching samples.
+// The footer is only written on a clean close - if it's missing, the file was cut short and the
+// reader falls back to scanning.
+
+pub const RECORDING_MAGIC: [u8; 8] = *b"SIGNETRC";
+pub const RECORDING_VERSION: u16 = 2;
+
+/// Number of chunks between index records
+pub const INDEX_INTERVAL: usize = 64;
+
+// Anything larger than this is a corrupt length field, not a packet
+const MAX_RECORD_LEN: u32 = 16 * 1024 * 1024;
+
+const TAG_CHUNK: u8 = 0x01;
+const TAG_INDEX: u8 = 0x02;
+const TAG_FOOTER: u8 = 0x03;
+
+const RECORD_OVERHEAD: u64 = 1 + 4 + 4;
+const FOOTER_PAYLOAD_LEN: usize = 16;
+const FOOTER_LEN: u64 = RECORD_OVERHEAD + FOOTER_PAYLOAD_LEN as u64;
+
+/// Describes the capture a recording came from. Written once at the start of the file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecordingHeader {
+    /// Center frequency in Hz
+    pub center_frequency: f64,
+    /// Rate of the recorded (post-decimation) samples in Hz
+    pub sample_rate: f64,
+    /// Manual gain in dB, None if AGC was on
+    pub gain: Option<f64>,
+    /// Version string of the software that made the recording
+    pub firmware_version: String,
+    /// Unix time in nanoseconds when the recording was opened
+    pub start_time_ns: u128,
+}
+
+impl RecordingHeader {
+    /// Build a header from the radio configuration, stamped with the current time
+    pub fn new(config: &RadioConfig, sample_rate: f64, firmware_version: &str) -> Self {
+        Self {
+            center_frequency: config.frequency,
+            sample_rate,
+            gain: config.gain,
+            firmware_version: firmware_version.to_string(),
+            start_time_ns: now_ns(),
+        }
+    }
+
+    /// Same header with the start time reset to now, for when a new file is opened
+    pub fn restamped(&self) -> Self {
+        Self {
+            start_time_ns: now_ns(),
+            ..self.clone()
+        }
+    }
+}
+
+/// Location of a single chunk in the file
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct IndexEntry {
+    pub packet_number: u64,
+    pub timestamp: u128,
+    pub offset: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct IndexRecord {
+    previous: Option<u64>,
+    entries: Vec<IndexEntry>,
+}
+
+fn now_ns() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0)
+}
+
+/// Writes SdrPacketLogs into an indexed recording
+pub struct RecordingWriter {
+    writer: BufWriter<File>,
+    // Offset of the next byte to be written, tracked by hand so BufWriter doesn't need to flush
+    position: u64,
+    packet_count: u64,
+    pending_index: Vec<IndexEntry>,
+    last_index_offset: Option<u64>,
+    scratch: Vec<u8>,
+    finished: bool,
+}
+
+impl RecordingWriter {
+    /// Create (or truncate) a recording at the path and write its header
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        header: &RecordingHeader,
+    ) -> Result<Self, RecordingError> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+
+        let mut recording = Self {
+            writer: BufWriter::with_capacity(1024 * 1024, file),
+            position: 0,
+            packet_count: 0,
+            pending_index: Vec::with_capacity(INDEX_INTERVAL),
+            last_index_offset: None,
+            scratch: Vec::new(),
+            finished: false,
+        };
+        recording.write_header(header)?;
+        Ok(recording)
+    }
+
+    fn write_header(&mut self, header: &RecordingHeader) -> Result<(), RecordingError> {
+        let mut payload = Vec::new();
+        encode_into_std_write(header, &mut payload, standard())?;
+
+        self.writer.write_all(&RECORDING_MAGIC)?;
+        self.writer.write_all(&RECORDING_VERSION.to_le_bytes())?;
+        self.writer
+            .write_all(&(payload.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&payload)?;
+        self.writer
+            .write_all(&crc32fast::hash(&payload).to_le_bytes())?;
+        self.position = (RECORDING_MAGIC.len() + 2 + 4 + payload.len() + 4) as u64;
+        Ok(())
+    }
+
+    fn write_record(&mut self, tag: u8, payload: &[u8]) -> Result<u64, RecordingError> {
+        let offset = self.position;
+        let len = (payload.len() as u32).to_le_bytes();
+
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&[tag]);
+        hasher.update(&len);
+        hasher.update(payload);
+
+        self.writer.write_all(&[tag])?;
+        self.writer.write_all(&len)?;
+        self.writer.write_all(payload)?;
+        self.writer.write_all(&hasher.finalize().to_le_bytes())?;
+        self.position += RECORD_OVERHEAD + payload.len() as u64;
+        Ok(offset)
+    }
+
+    /// Append one packet to the recording
+    pub fn write_packet(&mut self, packet: &SdrPacketLog) -> Result<(), RecordingError> {
+        // Reuse the scratch buffer, these are ~600KB a piece
+        let mut scratch = std::mem::take(&mut self.scratch);
+        scratch.clear();
+        let encoded = encode_into_std_write(packet, &mut scratch, standard());
+        let written = encoded
+            .map_err(RecordingError::from)
+            .and_then(|_| self.write_record(TAG_CHUNK, &scratch));
+        self.scratch = scratch;
+        let offset = written?;
+
+        self.pending_index.push(IndexEntry {
+            packet_number: self.packet_count,
+            timestamp: packet.timestamp,
+            offset,
+        });
+        self.packet_count += 1;
+
+        if self.pending_index.len() >= INDEX_INTERVAL {
+            self.write_index()?;
+        }
+        Ok(())
+    }
+
+    fn write_index(&mut self) -> Result<(), RecordingError> {
+        if self.pending_index.is_empty() {
+            return Ok(());
+        }
+        let record = IndexRecord {
+            previous: self.last_index_offset,
+            entries: std::mem::take(&mut self.pending_index),
+        };
+        let mut payload = Vec::new();
+        encode_into_std_write(&record, &mut payload, standard())?;
+        self.last_index_offset = Some(self.write_record(TAG_INDEX, &payload)?);
+        Ok(())
+    }
+
+    /// Number of packets written so far
+    pub fn packet_count(&self) -> u64 {
+        self.packet_count
+    }
+
+    /// Flush the outstanding index, write the footer and sync to disk. Safe to call more than once.
+    pub fn finish(&mut self) -> Result<(), RecordingError> {
+        if self.finished {
+            return Ok(());
+        }
+        self.write_index()?;
+
+        let mut footer = [0u8; FOOTER_PAYLOAD_LEN];
+        footer[..8].copy_from_slice(&self.last_index_offset.unwrap_or(0).to_le_bytes());
+        footer[8..].copy_from_slice(&self.packet_count.to_le_bytes());
+        self.write_record(TAG_FOOTER, &footer)?;
+
+        self.writer.flush()?;
+        self.writer.get_ref().sync_all()?;
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl Drop for RecordingWriter {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+fn read_header<R: Read>(reader: &mut R) -> Result<(RecordingHeader, u64), RecordingError> {
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if magic != RECORDING_MAGIC {
+        return Err(RecordingError::BadMagic);
+    }
+
+    let mut word = [0u8; 2];
+    reader.read_exact(&mut word)?;
+    let version = u16::from_le_bytes(word);
+    if version != RECORDING_VERSION {
+        return Err(RecordingError::UnsupportedVersion(version));
+    }
+
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len);
+    if len > MAX_RECORD_LEN {
+        return Err(RecordingError::HeaderChecksum);
+    }
+
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload)?;
+    let mut crc = [0u8; 4];
+    reader.read_exact(&mut crc)?;
+    if crc32fast::hash(&payload) != u32::from_le_bytes(crc) {
+        return Err(RecordingError::HeaderChecksum);
+    }
+
+    let (header, _) = decode_from_slice(&payload, standard())?;
+    Ok((header, (8 + 2 + 4 + len + 4) as u64))
+}
+
+// Outcome of trying to pull one framed record off the reader
+enum RawRecord {
+    Valid { tag: u8, payload: Vec<u8>, len: u64 },
+    // Framing was intact but the payload didn't match its checksum, scanning can continue
+    Corrupt { len: u64 },
+    // Clean end of file on a record boundary
+    End,
+    // The file stops (or turns to garbage) part way through a record
+    Torn,
+}
+
+fn read_raw_record<R: Read>(reader: &mut R) -> Result<RawRecord, RecordingError> {
+    let mut frame = [0u8; 5];
+    let mut filled = 0;
+    while filled < frame.len() {
+        match reader.read(&mut frame[filled..])? {
+            0 if filled == 0 => return Ok(RawRecord::End),
+            0 => return Ok(RawRecord::Torn),
+            n => filled += n,
+        }
+    }
+
+    let tag = frame[0];
+    let len = u32::from_le_bytes([frame[1], frame[2], frame[3], frame[4]]);
+    if !matches!(tag, TAG_CHUNK | TAG_INDEX | TAG_FOOTER) || len > MAX_RECORD_LEN {
+        return Ok(RawRecord::Torn);
+    }
+
+    let mut payload = vec![0u8; len as usize];
+    let mut crc = [0u8; 4];
+    if let Err(e) = reader
+        .read_exact(&mut payload)
+        .and_then(|_| reader.read_exact(&mut crc))
+    {
+        return match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => Ok(RawRecord::Torn),
+            _ => Err(e.into()),
+        };
+    }
+
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&frame);
+    hasher.update(&payload);
+    let total = RECORD_OVERHEAD + len as u64;
+
+    if hasher.finalize() == u32::from_le_bytes(crc) {
+        Ok(RawRecord::Valid {
+            tag,
+            payload,
+            len: total,
+        })
+    } else {
+        Ok(RawRecord::Corrupt { len: total })
+    }
+}
+
+fn decode_footer(payload: &[u8]) -> Option<(u64, u64)> {
+    if payload.len() != FOOTER_PAYLOAD_LEN {
+        return None;
+    }
+    let last_index = u64::from_le_bytes(payload[..8].try_into().ok()?);
+    let packet_count = u64::from_le_bytes(payload[8..].try_into().ok()?);
+    Some((last_index, packet_count))
+}
+
+/// Reads packets back out of an indexed recording
+pub struct RecordingReader {
+    reader: BufReader<File>,
+    header: RecordingHeader,
+    index: Vec<IndexEntry>,
+    data_start: u64,
+}
+
+impl RecordingReader {
+    /// Open a recording and load its index. Uses the footer when present, otherwise scans the file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, RecordingError> {
+        let file = File::open(path)?;
+        let mut reader = BufReader::with_capacity(1024 * 1024, file);
+        let (header, data_start) = read_header(&mut reader)?;
+
+        let mut recording = Self {
+            reader,
+            header,
+            index: Vec::new(),
+            data_start,
+        };
+
+        recording.index = match recording.index_from_footer()? {
+            Some(index) => index,
+            None => recording.index_from_scan()?,
+        };
+        recording.rewind()?;
+        Ok(recording)
+    }
+
+    fn index_from_footer(&mut self) -> Result<Option<Vec<IndexEntry>>, RecordingError> {
+        let file_len = self.reader.get_ref().metadata()?.len();
+        if file_len < self.data_start + FOOTER_LEN {
+            return Ok(None);
+        }
+
+        self.reader.seek(SeekFrom::Start(file_len - FOOTER_LEN))?;
+        let (last_index, packet_count) = match read_raw_record(&mut self.reader)? {
+            RawRecord::Valid {
+                tag: TAG_FOOTER,
+                payload,
+                ..
+            } => match decode_footer(&payload) {
+                Some(footer) => footer,
+                None => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+
+        if packet_count == 0 {
+            return Ok(Some(Vec::new()));
+        }
+
+        // Walk the index records backwards from the newest
+        let mut index = Vec::with_capacity(packet_count as usize);
+        let mut next = Some(last_index);
+        while let Some(offset) = next {
+            self.reader.seek(SeekFrom::Start(offset))?;
+            let record: IndexRecord = match read_raw_record(&mut self.reader)? {
+                RawRecord::Valid {
+                    tag: TAG_INDEX,
+                    payload,
+                    ..
+                } => decode_from_slice(&payload, standard())?.0,
+                _ => return Ok(None),
+            };
+
+            // A previous pointer that doesn't move backwards means the chain is broken
+            if record.previous.is_some_and(|previous| previous >= offset) {
+                return Ok(None);
+            }
+            index.extend(record.entries);
+            next = record.previous;
+        }
+
+        index.sort_by_key(|entry| entry.packet_number);
+        if index.len() as u64 != packet_count {
+            return Ok(None);
+        }
+        Ok(Some(index))
+    }
+
+    fn index_from_scan(&mut self) -> Result<Vec<IndexEntry>, RecordingError> {
+        self.reader.seek(SeekFrom::Start(self.data_start))?;
+        let mut index = Vec::new();
+        let mut offset = self.data_start;
+
+        loop {
+            match read_raw_record(&mut self.reader)? {
+                RawRecord::Valid { tag, payload, len } => {
+                    if tag == TAG_CHUNK {
+                        let (packet, _): (SdrPacketLog, usize) =
+                            decode_from_slice(&payload, standard())?;
+                        index.push(IndexEntry {
+                            packet_number: index.len() as u64,
+                            timestamp: packet.timestamp,
+                            offset,
+                        });
+                    }
+                    offset += len;
+                }
+                RawRecord::Corrupt { len } => offset += len,
+                RawRecord::End | RawRecord::Torn => break,
+            }
+        }
+        Ok(index)
+    }
+
+    /// The recording's header
+    pub fn header(&self) -> &RecordingHeader {
+        &self.header
+    }
+
+    /// Offset and timestamp of every readable chunk, in packet order
+    pub fn index(&self) -> &[IndexEntry] {
+        &self.index
+    }
+
+    /// Go back to the first packet
+    pub fn rewind(&mut self) -> Result<(), RecordingError> {
+        self.reader.seek(SeekFrom::Start(self.data_start))?;
+        Ok(())
+    }
+
+    /// Position the reader so the next packet is the given packet number
+    pub fn seek_to_packet(&mut self, packet_number: u64) -> Result<(), RecordingError> {
+        let entry = self
+            .index
+            .get(packet_number as usize)
+            .ok_or(RecordingError::PacketNotFound(packet_number))?;
+        self.reader.seek(SeekFrom::Start(entry.offset))?;
+        Ok(())
+    }
+
+    /// Position the reader at the first packet with a timestamp at or after `timestamp`
+    pub fn seek_to_timestamp(&mut self, timestamp: u128) -> Result<(), RecordingError> {
+        let position = self
+            .index
+            .partition_point(|entry| entry.timestamp < timestamp);
+        self.seek_to_packet(position as u64)
+    }
+
+    /// Read the next packet, skipping index records and chunks that fail their checksum.
+    /// Returns None at the end of the recording.
+    pub fn next_packet(&mut self) -> Option<Result<SdrPacketLog, RecordingError>> {
+        loop {
+            match read_raw_record(&mut self.reader) {
+                Ok(RawRecord::Valid {
+                    tag: TAG_CHUNK,
+                    payload,
+                    ..
+                }) => {
+                    return Some(
+                        decode_from_slice(&payload, standard())
+                            .map(|(packet, _)| packet)
+                            .map_err(RecordingError::from),
+                    );
+                }
+                Ok(RawRecord::Valid {
+                    tag: TAG_FOOTER, ..
+                }) => return None,
+                Ok(RawRecord::Valid { .. }) | Ok(RawRecord::Corrupt { .. }) => continue,
+                Ok(RawRecord::End) | Ok(RawRecord::Torn) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Result of checking a recording end to end
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VerifyReport {
+    /// Chunks that passed their checksum and decoded
+    pub packets: u64,
+    /// Chunks that failed their checksum or didn't decode
+    pub corrupt_chunks: u64,
+    pub index_records: u64,
+    /// Packets whose timestamp went backwards relative to the one before
+    pub out_of_order: u64,
+    /// Packets with a gap marker, i.e. samples were lost right before them
+    pub gaps: u64,
+    /// Bytes after the last intact record, left by a write that was cut off
+    pub torn_bytes: u64,
+    /// Whether a valid footer was found, i.e. the recorder shut down cleanly
+    pub footer: bool,
+}
+
+impl VerifyReport {
+    /// Nothing to repair
+    pub fn is_clean(&self) -> bool {
+        self.corrupt_chunks == 0 && self.torn_bytes == 0 && self.footer
+    }
+}
+
+/// Check the header, every record checksum, packet decoding and timestamp order
+pub fn verify_recording<P: AsRef<Path>>(path: P) -> Result<VerifyReport, RecordingError> {
+    let file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let mut reader = BufReader::with_capacity(1024 * 1024, file);
+    let (_, mut offset) = read_header(&mut reader)?;
+
+    let mut report = VerifyReport::default();
+    let mut last_timestamp = None;
+
+    loop {
+        match read_raw_record(&mut reader)? {
+            RawRecord::Valid { tag, payload, len } => {
+                offset += len;
+                match tag {
+                    TAG_CHUNK => match decode_from_slice::<SdrPacketLog, _>(&payload, standard()) {
+                        Ok((packet, _)) => {
+                            if last_timestamp.is_some_and(|last| packet.timestamp < last) {
+                                report.out_of_order += 1;
+                            }
+                            if packet.gap.is_gap() {
+                                report.gaps += 1;
+                            }
+                            last_timestamp = Some(packet.timestamp);
+                            report.packets += 1;
+                        }
+                        Err(_) => report.corrupt_chunks += 1,
+                    },
+                    TAG_INDEX => report.index_records += 1,
+                    _ => {
+                        // Anything after the footer is junk
+                        report.footer = decode_footer(&payload)
+                            .is_some_and(|(_, count)| count == report.packets);
+                        report.torn_bytes = file_len - offset;
+                        break;
+                    }
+                }
+            }
+            RawRecord::Corrupt { len } => {
+                offset += len;
+                report.corrupt_chunks += 1;
+            }
+            RawRecord::End => break,
+            RawRecord::Torn => {
+                report.torn_bytes = file_len - offset;
+                break;
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Rewrite a recording keeping only intact packets, regenerating the index and footer.
+/// The original is replaced atomically. Returns the report for the file as it was found.
+pub fn repair_recording<P: AsRef<Path>>(path: P) -> Result<VerifyReport, RecordingError> {
+    let path = path.as_ref();
+    let report = verify_recording(path)?;
+    if report.is_clean() {
+        return Ok(report);
+    }
+
+    let mut repaired_path = PathBuf::from(path);
+    repaired_path.set_extension("repair");
+
+    {
+        let mut reader = BufReader::with_capacity(1024 * 1024, File::open(path)?);
+        let (header, _) = read_header(&mut reader)?;
+        let mut writer = RecordingWriter::create(&repaired_path, &header)?;
+
+        loop {
+            match read_raw_record(&mut reader)? {
+                RawRecord::Valid {
+                    tag: TAG_CHUNK,
+                    payload,
+                    ..
+                } => {
+                    if let Ok((packet, _)) =
+                        decode_from_slice::<SdrPacketLog, _>(&payload, standard())
+                    {
+                        writer.write_packet(&packet)?;
+                    }
+                }
+                RawRecord::Valid {
+                    tag: TAG_FOOTER, ..
+                }
+                | RawRecord::End
+                | RawRecord::Torn => {
+                    break;
+                }
+                RawRecord::Valid { .. } | RawRecord::Corrupt { .. } => {}
+            }
+        }
+        writer.finish()?;
+    }
+
+    fs::rename(&repaired_path, path)?;
+    Ok(report)
+}
+
+/// Export a recording as a SigMF pair: `<base>.sigmf-data` holding the raw cf32_le samples and
+/// `<base>.sigmf-meta` describing them. The radio settings are fixed for a whole recording, so a
+/// new capture segment starts only after a gap, where the sample clock jumps.
+pub fn export_sigmf<P: AsRef<Path>, Q: AsRef<Path>>(
+    recording: P,
+    base: Q,
+) -> Result<(), RecordingError> {
+    let mut reader = RecordingReader::open(recording)?;
+    let header = reader.header().clone();
+
+    let data_path = base.as_ref().with_extension("sigmf-data");
+    let meta_path = base.as_ref().with_extension("sigmf-meta");
+    let mut data = BufWriter::with_capacity(1024 * 1024, File::create(&data_path)?);
+
+    let mut captures = Vec::new();
+    let mut annotations = Vec::new();
+    let mut sample_start: u64 = 0;
+    while let Some(packet) = reader.next_packet() {
+        let packet = packet?;
+        let count = packet.sample_count.min(packet.samples.len());
+
+        for sample in &packet.samples[..count] {
+            data.write_all(&sample.re.to_le_bytes())?;
+            data.write_all(&sample.im.to_le_bytes())?;
+        }
+
+        if captures.is_empty() || packet.gap.is_gap() {
+            captures.push(serde_json::json!({
+                "core:sample_start": sample_start,
+                "core:frequency": header.center_frequency,
+                "core:datetime": sigmf_datetime(packet.timestamp),
+            }));
+        }
+        // Point the ground tools at the first sample after each hole
+        if packet.gap.is_gap() {
+            annotations.push(serde_json::json!({
+                "core:sample_start": sample_start,
+                "core:sample_count": 1,
+                "core:label": "gap",
+                "core:comment": format!("{:?}", packet.gap),
+            }));
+        }
+        sample_start += count as u64;
+    }
+    data.flush()?;
+
+    let gain = match header.gain {
+        Some(gain) => format!("{gain} dB"),
+        None => "AGC".to_string(),
+    };
+    let meta = serde_json::json!({
+        "global": {
+            "core:datatype": "cf32_le",
+            "core:version": "1.0.0",
+            "core:sample_rate": header.sample_rate,
+            "core:recorder": format!("signet {}", header.firmware_version),
+            "core:description": format!("SDR recording, gain {gain}"),
+            "core:num_channels": 1,
+        },
+        "captures": captures,
+        "annotations": annotations,
+    });
+
+    let meta_file = File::create(&meta_path)?;
+    serde_json::to_writer_pretty(meta_file, &meta)?;
+    Ok(())
+}
+
+// SigMF wants ISO-8601 UTC
+fn sigmf_datetime(timestamp_ns: u128) -> String {
+    chrono::DateTime::from_timestamp_nanos(timestamp_ns as i64)
+        .to_rfc3339_opts(chrono::SecondsFormat::Nanos, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustfft::num_complex::Complex;
+
+    // SdrPacketLog is ~600KB and decoding makes a few copies of it on the stack, more than the
+    // default test thread gets
+    fn with_stack<F: FnOnce() + Send + 'static>(test: F) {
+        std::thread::Builder::new()
+            .stack_size(32 * 1024 * 1024)
+            .spawn(test)
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        let mut path = std::env::temp_dir();
+        path.push(format!("signet_{}_{name}.bin", std::process::id()));
+        path
+    }
+
+    fn header() -> RecordingHeader {
+        RecordingHeader {
+            center_frequency: 1420.405e6,
+            sample_rate: 100_000.0,
+            gain: Some(30.0),
+            firmware_version: "test".to_string(),
+            start_time_ns: 1_700_000_000_000_000_000,
+        }
+    }
+
+    fn packet(n: u64) -> Box<SdrPacketLog> {
+        let mut packet = Box::<SdrPacketLog>::default();
+        packet.timestamp = 1_700_000_000_000_000_000 + n as u128 * 1_000_000;
+        packet.sample_count = 16;
+        for (i, sample) in packet.samples.iter_mut().take(16).enumerate() {
+            *sample = Complex::new(n as f32, i as f32);
+        }
+        packet
+    }
+
+    fn write_recording(path: &Path, count: u64) {
+        let mut writer = RecordingWriter::create(path, &header()).unwrap();
+        for n in 0..count {
+            writer.write_packet(&packet(n)).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_roundtrip_with_index() {
+        with_stack(|| {
+            let path = temp_path("roundtrip");
+            let count = INDEX_INTERVAL as u64 * 2 + 5;
+            write_recording(&path, count);
+
+            let report = verify_recording(&path).unwrap();
+            assert!(report.is_clean(), "{report:?}");
+            assert_eq!(report.packets, count);
+            assert_eq!(report.index_records, 3);
+
+            let mut reader = RecordingReader::open(&path).unwrap();
+            assert_eq!(reader.header(), &header());
+            assert_eq!(reader.index().len() as u64, count);
+
+            reader.seek_to_packet(70).unwrap();
+            let found = reader.next_packet().unwrap().unwrap();
+            assert_eq!(found.timestamp, packet(70).timestamp);
+
+            reader.seek_to_timestamp(packet(100).timestamp - 1).unwrap();
+            let found = reader.next_packet().unwrap().unwrap();
+            assert_eq!(found.samples[3], Complex::new(100.0, 3.0));
+
+            fs::remove_file(&path).ok();
+        });
+    }
+
+    #[test]
+    fn test_repair_torn_tail() {
+        with_stack(|| {
+            let path = temp_path("torn");
+            write_recording(&path, 10);
+
+            // Cut the file part way through the last chunk, like a power cut would
+            let last = RecordingReader::open(&path).unwrap().index()[9].offset;
+            let file = OpenOptions::new().write(true).open(&path).unwrap();
+            file.set_len(last + 100).unwrap();
+            drop(file);
+
+            let report = verify_recording(&path).unwrap();
+            assert!(!report.footer);
+            assert!(report.torn_bytes > 0);
+            assert_eq!(report.packets, 9);
+
+            // Scanning still finds the intact packets
+            assert_eq!(RecordingReader::open(&path).unwrap().index().len(), 9);
+
+            repair_recording(&path).unwrap();
+            let report = verify_recording(&path).unwrap();
+            assert!(report.is_clean(), "{report:?}");
+            assert_eq!(report.packets, 9);
+
+            fs::remove_file(&path).ok();
+        });
+    }
+
+    #[test]
+    fn test_repair_drops_corrupt_chunk() {
+        with_stack(|| {
+            let path = temp_path("corrupt");
+            write_recording(&path, 10);
+
+            let offset = RecordingReader::open(&path).unwrap().index()[4].offset;
+            let mut bytes = fs::read(&path).unwrap();
+            bytes[offset as usize + 40] ^= 0xFF;
+            fs::write(&path, &bytes).unwrap();
+
+            let report = verify_recording(&path).unwrap();
+            assert_eq!(report.corrupt_chunks, 1);
+            assert_eq!(report.packets, 9);
+
+            repair_recording(&path).unwrap();
+            let report = verify_recording(&path).unwrap();
+            assert!(report.is_clean(), "{report:?}");
+            assert_eq!(report.packets, 9);
+
+            fs::remove_file(&path).ok();
+        });
+    }
+
+    #[test]
+    fn test_sigmf_export() {
+        with_stack(|| {
+            let path = temp_path("sigmf");
+            write_recording(&path, 3);
+
+            let base = temp_path("sigmf_out");
+            export_sigmf(&path, &base).unwrap();
+
+            let data = fs::read(base.with_extension("sigmf-data")).unwrap();
+            assert_eq!(data.len(), 3 * 16 * 8);
+
+            let meta: serde_json::Value =
+                serde_json::from_reader(File::open(base.with_extension("sigmf-meta")).unwrap())
+                    .unwrap();
+            assert_eq!(meta["global"]["core:datatype"], "cf32_le");
+            assert_eq!(meta["captures"].as_array().unwrap().len(), 1);
+            assert_eq!(meta["captures"][0]["core:sample_start"], 0);
+
+            fs::remove_file(&path).ok();
+            fs::remove_file(base.with_extension("sigmf-data")).ok();
+            fs::remove_file(base.with_extension("sigmf-meta")).ok();
+        });
+    }
+
+    #[test]
+    fn test_gap_markers_reported() {
+        with_stack(|| {
+            let path = temp_path("gaps");
+            let mut writer = RecordingWriter::create(&path, &header()).unwrap();
+            for n in 0..4 {
+                let mut packet = packet(n);
+                if n == 2 {
+                    packet.gap.dropped_packets = 3;
+                }
+                writer.write_packet(&packet).unwrap();
+            }
+            writer.finish().unwrap();
+
+            let mut reader = RecordingReader::open(&path).unwrap();
+            reader.seek_to_packet(2).unwrap();
+            let packet = reader.next_packet().unwrap().unwrap();
+            assert_eq!(packet.gap.dropped_packets, 3);
+
+            let report = verify_recording(&path).unwrap();
+            assert_eq!(report.gaps, 1);
+            assert!(report.is_clean());
+
+            let base = temp_path("gaps_out");
+            export_sigmf(&path, &base).unwrap();
+            let meta: serde_json::Value =
+                serde_json::from_reader(File::open(base.with_extension("sigmf-meta")).unwrap())
+                    .unwrap();
+            let captures = meta["captures"].as_array().unwrap();
+            assert_eq!(captures.len(), 2);
+            assert_eq!(captures[1]["core:sample_start"], 32);
+            assert_eq!(
+                captures[1]["core:datetime"],
+                sigmf_datetime(1_700_000_000_000_000_000 + 2_000_000)
+            );
+
+            let annotations = meta["annotations"].as_array().unwrap();
+            assert_eq!(annotations.len(), 1);
+            assert_eq!(annotations[0]["core:sample_start"], 32);
+            assert_eq!(annotations[0]["core:label"], "gap");
+
+            fs::remove_file(&path).ok();
+            fs::remove_file(base.with_extension("sigmf-data")).ok();
+            fs::remove_file(base.with_extension("sigmf-meta")).ok();
+        });
+    }
+}
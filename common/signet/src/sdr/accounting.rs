@@ -0,0 +1,235 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use log::warn;
+
+use crate::record::packet::GapMarker;
+
+/// How far a packet's timestamp can wander from where the previous packet predicted, as a
+/// fraction of the previous packet's length, before it's called a discontinuity. Timestamps are
+/// taken from the host clock after the first read returns so there's always some jitter.
+pub const DISCONTINUITY_TOLERANCE: f64 = 0.1;
+
+/// Running totals since the stream was opened. Shared so other threads can report on the stream
+/// without touching the SDR.
+#[derive(Debug, Default)]
+pub struct StreamCounters {
+    packets: AtomicU64,
+    device_overflows: AtomicU64,
+    short_reads: AtomicU64,
+    truncated_samples: AtomicU64,
+    discontinuities: AtomicU64,
+    ring_drops: AtomicU64,
+}
+
+/// A point in time copy of [`StreamCounters`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StreamStats {
+    pub packets: u64,
+    pub device_overflows: u64,
+    pub short_reads: u64,
+    pub truncated_samples: u64,
+    pub discontinuities: u64,
+    pub ring_drops: u64,
+}
+
+impl StreamCounters {
+    pub fn snapshot(&self) -> StreamStats {
+        StreamStats {
+            packets: self.packets.load(Ordering::Relaxed),
+            device_overflows: self.device_overflows.load(Ordering::Relaxed),
+            short_reads: self.short_reads.load(Ordering::Relaxed),
+            truncated_samples: self.truncated_samples.load(Ordering::Relaxed),
+            discontinuities: self.discontinuities.load(Ordering::Relaxed),
+            ring_drops: self.ring_drops.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Collects everything that goes wrong while a packet is being filled, and turns it into the
+/// [`GapMarker`] that packet carries. Doesn't touch hardware, so it can be driven from tests.
+pub struct GapTracker {
+    counters: Arc<StreamCounters>,
+    sample_rate: f64,
+    pending: GapMarker,
+    // Timestamp and sample count of the last finished packet
+    last: Option<(u128, usize)>,
+}
+
+impl GapTracker {
+    /// `sample_rate` is the rate of the samples that end up in packets, after downsampling
+    pub fn new(sample_rate: f64) -> Self {
+        Self {
+            counters: Arc::new(StreamCounters::default()),
+            sample_rate,
+            pending: GapMarker::default(),
+            last: None,
+        }
+    }
+
+    pub fn counters(&self) -> Arc<StreamCounters> {
+        self.counters.clone()
+    }
+
+    pub fn overflow(&mut self) {
+        self.pending.overflows = self.pending.overflows.saturating_add(1);
+        self.counters
+            .device_overflows
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn short_read(&mut self) {
+        self.pending.short_reads = self.pending.short_reads.saturating_add(1);
+        self.counters.short_reads.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn truncated(&mut self, samples: usize) {
+        if samples == 0 {
+            return;
+        }
+        self.pending.truncated_samples = self
+            .pending
+            .truncated_samples
+            .saturating_add(samples.try_into().unwrap_or(u32::MAX));
+        self.counters
+            .truncated_samples
+            .fetch_add(samples as u64, Ordering::Relaxed);
+    }
+
+    /// A finished packet never made it into the stream. Whatever it lost, plus the packet itself,
+    /// gets pinned to the next packet instead.
+    pub fn dropped(&mut self, lost: &GapMarker) {
+        let pending = &mut self.pending;
+        pending.dropped_packets = pending
+            .dropped_packets
+            .saturating_add(lost.dropped_packets)
+            .saturating_add(1);
+        pending.overflows = pending.overflows.saturating_add(lost.overflows);
+        pending.short_reads = pending.short_reads.saturating_add(lost.short_reads);
+        pending.truncated_samples = pending
+            .truncated_samples
+            .saturating_add(lost.truncated_samples);
+        pending.discontinuity_ns = pending
+            .discontinuity_ns
+            .saturating_add(lost.discontinuity_ns);
+        self.counters.ring_drops.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Close out a packet and hand back its marker
+    pub fn finish(&mut self, timestamp: u128, sample_count: usize) -> GapMarker {
+        if let Some((last_timestamp, last_count)) = self.last {
+            let offset = self.timestamp_offset(last_timestamp, last_count, timestamp);
+            if offset != 0 {
+                self.pending.discontinuity_ns =
+                    self.pending.discontinuity_ns.saturating_add(offset);
+                self.counters
+                    .discontinuities
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.last = Some((timestamp, sample_count));
+
+        let marker = std::mem::take(&mut self.pending);
+        let packet = self.counters.packets.fetch_add(1, Ordering::Relaxed);
+        if marker.is_gap() {
+            warn!("SDR stream gap before packet {packet}: {marker:?}");
+        }
+        marker
+    }
+
+    // Zero if `timestamp` is about where the previous packet said the next one would start,
+    // otherwise how far off it is
+    fn timestamp_offset(&self, last_timestamp: u128, last_count: usize, timestamp: u128) -> i64 {
+        let expected_ns = last_count as f64 / self.sample_rate * 1e9;
+        let actual_ns = timestamp as f64 - last_timestamp as f64;
+        let offset = actual_ns - expected_ns;
+
+        if offset.abs() > expected_ns * DISCONTINUITY_TOLERANCE {
+            offset as i64
+        } else {
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 1000 samples at 1kHz, one packet per second
+    const SECOND: u128 = 1_000_000_000;
+
+    #[test]
+    fn test_clean_stream_has_no_gaps() {
+        let mut tracker = GapTracker::new(1000.0);
+        for n in 0..10 {
+            // A little jitter is expected
+            let jitter = if n % 2 == 0 { 0 } else { 20_000_000 };
+            assert_eq!(
+                tracker.finish(n * SECOND + jitter, 1000),
+                GapMarker::default()
+            );
+        }
+        assert_eq!(tracker.counters().snapshot().packets, 10);
+        assert_eq!(tracker.counters().snapshot().discontinuities, 0);
+    }
+
+    #[test]
+    fn test_timestamp_discontinuity() {
+        let mut tracker = GapTracker::new(1000.0);
+        tracker.finish(0, 1000);
+
+        // Half a second late
+        let marker = tracker.finish(SECOND + SECOND / 2, 1000);
+        assert!(marker.is_gap());
+        assert_eq!(marker.discontinuity_ns, 500_000_000);
+
+        // Back on schedule relative to the late packet
+        assert!(!tracker.finish(2 * SECOND + SECOND / 2, 1000).is_gap());
+
+        // Clock stepped backwards
+        let marker = tracker.finish(SECOND, 1000);
+        assert_eq!(marker.discontinuity_ns, -2_500_000_000);
+        assert_eq!(tracker.counters().snapshot().discontinuities, 2);
+    }
+
+    #[test]
+    fn test_overflow_and_short_reads_land_on_packet() {
+        let mut tracker = GapTracker::new(1000.0);
+        tracker.short_read();
+        assert!(!tracker.finish(0, 1000).is_gap());
+
+        tracker.overflow();
+        tracker.overflow();
+        tracker.truncated(12);
+        let marker = tracker.finish(SECOND, 1000);
+        assert_eq!(marker.overflows, 2);
+        assert_eq!(marker.truncated_samples, 12);
+        assert!(marker.is_gap());
+
+        // Counted once, not carried into the next packet
+        assert_eq!(tracker.finish(2 * SECOND, 1000), GapMarker::default());
+        let stats = tracker.counters().snapshot();
+        assert_eq!(stats.device_overflows, 2);
+        assert_eq!(stats.short_reads, 1);
+        assert_eq!(stats.truncated_samples, 12);
+    }
+
+    #[test]
+    fn test_drops_carry_to_next_packet() {
+        let mut tracker = GapTracker::new(1000.0);
+        tracker.finish(0, 1000);
+
+        // Two packets read but never pushed, the first of which had an overflow
+        tracker.overflow();
+        let lost = tracker.finish(SECOND, 1000);
+        tracker.dropped(&lost);
+        let lost = tracker.finish(2 * SECOND, 1000);
+        tracker.dropped(&lost);
+
+        let marker = tracker.finish(3 * SECOND, 1000);
+        assert_eq!(marker.dropped_packets, 2);
+        assert_eq!(marker.overflows, 1);
+        assert_eq!(tracker.counters().snapshot().ring_drops, 2);
+    }
+}
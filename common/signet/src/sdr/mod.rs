@@ -1,2 +1,3 @@
+pub mod accounting;
 pub mod radio_config;
 pub mod sdr;
@@ -2,6 +2,8 @@ pub const TARGET_PACKET_SIZE: usize = 65_536;
 pub const READ_CHUNK_SIZE: usize = 8192;
 // pub const READ_CHUNK_SIZE: usize = 16384;
 pub const BUFF_SIZE: usize = TARGET_PACKET_SIZE + READ_CHUNK_SIZE;
+// How much the default downsampler decimates the raw SDR stream by
+pub const DECIMATION_FACTOR: usize = 30;
 
 #[derive(Clone, Copy)]
 pub struct RadioConfig {
@@ -28,6 +30,11 @@ impl RadioConfig {
         self
     }
 
+    /// Rate of the samples that come out of the default downsampler
+    pub fn output_sample_rate(&self) -> f64 {
+        self.sample_rate / DECIMATION_FACTOR as f64
+    }
+
     // Configure how large a sequence of I/Q data will be, and the size of chunks read from the SDR
     // pub fn with_sizes(mut self, packet_size: usize, chunk_size: usize) -> Self {
     //     self.target_packet_size = packet_size;
@@ -1,10 +1,14 @@
 use core::time;
 
-use crate::sdr::radio_config::{BUFF_SIZE, READ_CHUNK_SIZE, RadioConfig, TARGET_PACKET_SIZE};
+use crate::record::packet::{GapMarker, SdrPacketLog};
+use crate::sdr::accounting::{GapTracker, StreamCounters};
+use crate::sdr::radio_config::{
+    BUFF_SIZE, DECIMATION_FACTOR, READ_CHUNK_SIZE, RadioConfig, TARGET_PACKET_SIZE,
+};
 use bincode::de::read;
 use rustfft::num_complex::Complex;
-use soapysdr::{Device, Direction, RxStream};
-use std::{any::Any, time::{SystemTime, UNIX_EPOCH}};
+use soapysdr::{Device, Direction, ErrorCode, RxStream};
+use std::{any::Any, sync::Arc, time::{SystemTime, UNIX_EPOCH}};
 use crate::error::SignalError;
 use scirs2_signal::{
     filter::firwin,
@@ -115,7 +119,7 @@ impl Default for Downsampler {
             history: vec![Complex::new(0.0, 0.0); n_taps],
             taps,
             head: 0,
-            decimation_factor: 30,
+            decimation_factor: DECIMATION_FACTOR,
             skip_count: 0, // Start computing immediately on the first sample
         }
     }
@@ -127,6 +131,7 @@ pub struct SDR {
     pub stream: RxStream<Complex<f32>>,
     pub read_buffer: [Complex<f32>; READ_CHUNK_SIZE],
     downsampler: Downsampler,
+    gaps: GapTracker,
 }
 
 use log::{LevelFilter, error, info};
@@ -173,22 +178,58 @@ impl SDR {
             stream,
             read_buffer: [Complex::new(0.0, 0.0); READ_CHUNK_SIZE],
             downsampler: Downsampler::default(),
+            gaps: GapTracker::new(config.output_sample_rate()),
         })
     }
 
+    /// Overflow, drop and discontinuity totals for this stream
+    pub fn counters(&self) -> Arc<StreamCounters> {
+        self.gaps.counters()
+    }
+
+    /// Fill a packet from the stream, including the gap marker for anything lost since the last one
+    pub fn read_packet(&mut self, packet: &mut SdrPacketLog) -> Result<(), SignalError> {
+        let (timestamp, sample_count) = self.fill(&mut packet.samples)?;
+        packet.timestamp = timestamp;
+        packet.sample_count = sample_count;
+        packet.gap = self.gaps.finish(timestamp, sample_count);
+        Ok(())
+    }
+
+    /// A packet from [`SDR::read_packet`] was thrown away instead of recorded, so the next packet
+    /// has to own up to the hole
+    pub fn packet_dropped(&mut self, lost: &GapMarker) {
+        self.gaps.dropped(lost);
+    }
+
 
     pub fn read_and_timestamp(
         &mut self,
         slice: &mut [Complex<f32>; BUFF_SIZE],
     ) -> Result<(u128, usize), SignalError> {
+        let (time_stamp, head) = self.fill(slice)?;
+        // Still counted, the marker just has nowhere to go
+        self.gaps.finish(time_stamp, head);
+        Ok((time_stamp, head))
+    }
+
+    fn fill(&mut self, slice: &mut [Complex<f32>; BUFF_SIZE]) -> Result<(u128, usize), SignalError> {
         let mut time_stamp = None;
         let mut head: usize = 0;
 
         while head < TARGET_PACKET_SIZE {
-            let read_len = self
-                .stream
-                .read(&mut [&mut self.read_buffer], 100_000)
-                .map_err(|e| SignalError::StreamReadError(head))?;
+            let read_len = match self.stream.read(&mut [&mut self.read_buffer], 100_000) {
+                Ok(read_len) => read_len,
+                // The device dropped samples on its side, note it and keep going
+                Err(e) if matches!(e.code, ErrorCode::Overflow) => {
+                    self.gaps.overflow();
+                    continue;
+                }
+                Err(_) => return Err(SignalError::StreamReadError(head)),
+            };
+            if read_len < READ_CHUNK_SIZE {
+                self.gaps.short_read();
+            }
 
             let downsampled_signal = self.downsampler.downsample(&self.read_buffer[..read_len]);
             let down_chunk_size = downsampled_signal.len();
@@ -215,6 +256,7 @@ impl SDR {
                 // Handle buffer overrun if a chunk pushes head past BUFF_SIZE
                 let space_left = max - head;
                 slice[head..max].copy_from_slice(&downsampled_signal[..space_left]);
+                self.gaps.truncated(down_chunk_size - space_left);
                 head = max;
                 break;
             }
@@ -1,7 +1,11 @@
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
-use crate::{sdr::radio_config::RadioConfig, signal::signal_config::SignalConfig};
+use crate::{
+    record::recording::{export_sigmf, repair_recording, verify_recording},
+    sdr::radio_config::RadioConfig,
+    signal::signal_config::SignalConfig,
+};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -33,6 +37,36 @@ impl Cli {
         (radio_config, signal_config)
     }
 
+    /// Runs the offline recording commands, which don't need the SDR. Returns true if one ran.
+    pub fn run_recording_tools() -> bool {
+        let cli = Cli::parse();
+        match &cli.command {
+            Commands::Verify { input, repair } => {
+                match verify_recording(input) {
+                    Ok(report) => {
+                        println!("{:#?}", report);
+                        if *repair && !report.is_clean() {
+                            match repair_recording(input) {
+                                Ok(_) => println!("Repaired {:?}", input),
+                                Err(e) => println!("Repair failed: {}", e),
+                            }
+                        }
+                    }
+                    Err(e) => println!("Could not verify {:?}: {}", input, e),
+                }
+                true
+            }
+            Commands::Export { input, output } => {
+                match export_sigmf(input, output) {
+                    Ok(_) => println!("Exported SigMF to {:?}", output),
+                    Err(e) => println!("Export failed: {}", e),
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
     pub fn run_commands() -> (bool, PathBuf) {
         let cli = Cli::parse();
         match &cli.command {
@@ -48,6 +82,9 @@ impl Cli {
                 let psd_path = input.clone();
                 (record_baseline, psd_path)
             }
+            Commands::Verify { .. } | Commands::Export { .. } => {
+                unreachable!("recording commands are handled by run_recording_tools")
+            }
         }
     }
 }
@@ -66,4 +103,22 @@ pub enum Commands {
         #[arg(short, long, default_value = "comp.psd")]
         input: PathBuf,
     },
+    /// Checks a recording's checksums and index, optionally repairing it
+    Verify {
+        /// The recording to check
+        #[arg(short, long, default_value = "sdr_recording.bin")]
+        input: PathBuf,
+        /// Rewrite the recording without its damaged chunks
+        #[arg(short, long)]
+        repair: bool,
+    },
+    /// Exports a recording as a SigMF data + metadata pair
+    Export {
+        /// The recording to export
+        #[arg(short, long, default_value = "sdr_recording.bin")]
+        input: PathBuf,
+        /// Base path for the .sigmf-data and .sigmf-meta files
+        #[arg(short, long, default_value = "sdr_recording")]
+        output: PathBuf,
+    },
 }
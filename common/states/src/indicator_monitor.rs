@@ -0,0 +1,586 @@
+#![warn(missing_docs)]
+
+//! Timestamps and checks the indicator lines. Every line is debounced on its own, so a change is
+//! only an edge once it has held, and one that goes back before then is logged as a glitch. The
+//! timer events are expected to rise in a set order, and one that comes early is flagged.
+//!
+//! The monitor only watches: it doesn't change what the states read, so whatever acts on the
+//! lines sees them as soon as they move. Like the RBF monitor, feed it raw samples and the time
+//! they were taken.
+
+use bincode::{
+    BorrowDecode, Decode, Encode,
+    config::standard,
+    de::{BorrowDecoder, Decoder},
+    enc::Encoder,
+    error::{DecodeError, EncodeError},
+};
+use embedded_hal::digital::PinState;
+
+use crate::indicators::{IndicatorBuilder, IndicatorStates};
+
+/// One of the lines in [`IndicatorStates`]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub enum IndicatorLine {
+    /// GSE-1
+    Gse1,
+    /// GSE-2
+    Gse2,
+    /// TE-RA
+    TeRa,
+    /// TE-RB
+    TeRb,
+    /// TE-1
+    Te1,
+    /// TE-2
+    Te2,
+    /// TE-3
+    Te3,
+}
+
+impl IndicatorLine {
+    /// Every line, in the order they're packed into a byte
+    pub const ALL: [IndicatorLine; 7] = [
+        IndicatorLine::Gse1,
+        IndicatorLine::Gse2,
+        IndicatorLine::TeRa,
+        IndicatorLine::TeRb,
+        IndicatorLine::Te1,
+        IndicatorLine::Te2,
+        IndicatorLine::Te3,
+    ];
+
+    /// The line's level in `states`
+    pub fn level(self, states: &IndicatorStates) -> PinState {
+        match self {
+            IndicatorLine::Gse1 => states.gse1(),
+            IndicatorLine::Gse2 => states.gse2(),
+            IndicatorLine::TeRa => states.te_ra(),
+            IndicatorLine::TeRb => states.te_rb(),
+            IndicatorLine::Te1 => states.te1(),
+            IndicatorLine::Te2 => states.te2(),
+            IndicatorLine::Te3 => states.te3(),
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// How long a line has to hold, and the order the timer events should come in
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndicatorConfig {
+    /// A new level counts once it has held this long, in ms
+    pub debounce_ms: u64,
+    /// Lines expected to rise once each, in this order
+    pub sequence: &'static [IndicatorLine],
+}
+
+impl IndicatorConfig {
+    /// 50ms to settle, and TE-1, TE-2 then TE-3
+    pub const DEFAULT: IndicatorConfig = IndicatorConfig {
+        debounce_ms: 50,
+        sequence: &[IndicatorLine::Te1, IndicatorLine::Te2, IndicatorLine::Te3],
+    };
+}
+
+impl Default for IndicatorConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// What was wrong with an edge
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub enum IndicatorAnomaly {
+    /// Went back before the debounce time was up, so never counted as an edge
+    Glitch,
+    /// Rose before the line that was due
+    OutOfOrder {
+        /// The line that should have come next
+        expected: IndicatorLine,
+    },
+    /// Rose after a later line in the sequence already had, or rose a second time
+    Late,
+}
+
+/// A line changing level, or trying to
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub struct IndicatorEvent {
+    /// Which line
+    pub line: IndicatorLine,
+    /// The level it went to
+    pub high: bool,
+    /// When it was first read at that level, in ms
+    pub edge_ms: u64,
+    /// When it held long enough, or went back for a glitch, in ms
+    pub confirmed_ms: u64,
+    /// Anything wrong with it
+    pub anomaly: Option<IndicatorAnomaly>,
+}
+
+/// At most one event per line from each sample
+pub type IndicatorEvents = [Option<IndicatorEvent>; 7];
+
+/// The last `N` events, oldest first, with a count of the older ones that didn't fit
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone)]
+pub struct IndicatorLog<const N: usize> {
+    events: [Option<IndicatorEvent>; N],
+    // Where the oldest is
+    start: usize,
+    len: usize,
+    dropped: u32,
+}
+
+impl<const N: usize> Default for IndicatorLog<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> IndicatorLog<N> {
+    /// Nothing logged
+    pub const fn new() -> Self {
+        Self {
+            events: [None; N],
+            start: 0,
+            len: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Add an event, pushing out the oldest if it's full
+    pub fn push(&mut self, event: IndicatorEvent) {
+        if N == 0 {
+            self.dropped = self.dropped.saturating_add(1);
+            return;
+        }
+        if self.len == N {
+            self.events[self.start] = Some(event);
+            self.start = (self.start + 1) % N;
+            self.dropped = self.dropped.saturating_add(1);
+        } else {
+            self.events[(self.start + self.len) % N] = Some(event);
+            self.len += 1;
+        }
+    }
+
+    /// The events held, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &IndicatorEvent> {
+        (0..self.len).filter_map(move |i| self.events[(self.start + i) % N].as_ref())
+    }
+
+    /// How many are held
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether nothing is held
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// How many were pushed out to make room
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    /// Encode into `buffer` with bincode's standard config, returning the length written
+    pub fn to_bytes(&self, buffer: &mut [u8]) -> Result<usize, EncodeError> {
+        bincode::encode_into_slice(self, buffer, standard())
+    }
+
+    /// Decode a log written by [`IndicatorLog::to_bytes`]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        bincode::decode_from_slice(bytes, standard()).map(|(log, _)| log)
+    }
+}
+
+// Equal when they hold the same events, wherever they sit in the ring
+impl<const N: usize> PartialEq for IndicatorLog<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.dropped == other.dropped && self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<const N: usize> Eq for IndicatorLog<N> {}
+
+/// Encode with bincode, as the dropped count then the events oldest first
+impl<const N: usize> Encode for IndicatorLog<N> {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        self.dropped.encode(encoder)?;
+        (self.len as u32).encode(encoder)?;
+        for event in self.iter() {
+            event.encode(encoder)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decode with bincode
+impl<const N: usize, Context> Decode<Context> for IndicatorLog<N> {
+    fn decode<D: Decoder<Context = Context>>(decoder: &mut D) -> Result<Self, DecodeError> {
+        let dropped = u32::decode(decoder)?;
+        let len = u32::decode(decoder)? as usize;
+        if len > N {
+            return Err(DecodeError::ArrayLengthMismatch {
+                required: N,
+                found: len,
+            });
+        }
+
+        let mut log = Self::new();
+        for _ in 0..len {
+            log.push(IndicatorEvent::decode(decoder)?);
+        }
+        log.dropped = dropped;
+        Ok(log)
+    }
+}
+
+/// Borrow decode with bincode
+impl<'de, const N: usize, Context> BorrowDecode<'de, Context> for IndicatorLog<N> {
+    fn borrow_decode<D: BorrowDecoder<'de, Context = Context>>(
+        decoder: &mut D,
+    ) -> Result<Self, DecodeError> {
+        Self::decode(decoder)
+    }
+}
+
+// One line's debounced level and edge times
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, Default)]
+struct LineState {
+    high: bool,
+    // Level being timed, and when it was first read
+    candidate: Option<(bool, u64)>,
+    rose_at_ms: Option<u64>,
+    fell_at_ms: Option<u64>,
+}
+
+/// Debounces indicator samples, timestamps each line's edges and checks the timer events come in
+/// order, keeping the last `N` events
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone)]
+pub struct IndicatorMonitor<const N: usize> {
+    config: IndicatorConfig,
+    lines: [LineState; 7],
+    // How far through the sequence the rising edges have got
+    next_in_sequence: usize,
+    anomalies: u32,
+    log: IndicatorLog<N>,
+}
+
+impl<const N: usize> IndicatorMonitor<N> {
+    /// Every line taken as low to start with
+    pub const fn new(config: IndicatorConfig) -> Self {
+        Self {
+            config,
+            lines: [LineState {
+                high: false,
+                candidate: None,
+                rose_at_ms: None,
+                fell_at_ms: None,
+            }; 7],
+            next_in_sequence: 0,
+            anomalies: 0,
+            log: IndicatorLog::new(),
+        }
+    }
+
+    /// Take a raw sample, returning what came of it. Lines in the sequence that settle in the
+    /// same sample are taken in sequence order, so coming up with several timer events already
+    /// high isn't called out of order.
+    pub fn update(&mut self, now_ms: u64, sample: IndicatorStates) -> IndicatorEvents {
+        let unsequenced = IndicatorLine::ALL
+            .into_iter()
+            .filter(|line| !self.config.sequence.contains(line));
+        let order = unsequenced.chain(self.config.sequence.iter().copied());
+
+        let mut events = [None; 7];
+        for (slot, line) in events.iter_mut().zip(order) {
+            let high = line.level(&sample) == PinState::High;
+            *slot = self.sample_line(now_ms, line, high);
+            if let Some(event) = *slot {
+                if event.anomaly.is_some() {
+                    self.anomalies = self.anomalies.saturating_add(1);
+                }
+                self.log.push(event);
+            }
+        }
+        events
+    }
+
+    /// The levels that have held
+    pub fn states(&self) -> IndicatorStates {
+        let high = |line: IndicatorLine| self.lines[line.index()].high;
+        IndicatorBuilder::new()
+            .gse1(high(IndicatorLine::Gse1))
+            .gse2(high(IndicatorLine::Gse2))
+            .te_ra(high(IndicatorLine::TeRa))
+            .te_rb(high(IndicatorLine::TeRb))
+            .te1(high(IndicatorLine::Te1))
+            .te2(high(IndicatorLine::Te2))
+            .te3(high(IndicatorLine::Te3))
+            .build()
+    }
+
+    /// When `line` last went high, by the first sample of the level that held, in ms
+    pub fn rose_at_ms(&self, line: IndicatorLine) -> Option<u64> {
+        self.lines[line.index()].rose_at_ms
+    }
+
+    /// When `line` last went low, by the first sample of the level that held, in ms
+    pub fn fell_at_ms(&self, line: IndicatorLine) -> Option<u64> {
+        self.lines[line.index()].fell_at_ms
+    }
+
+    /// The line due to rise next, `None` once the whole sequence has
+    pub fn next_expected(&self) -> Option<IndicatorLine> {
+        self.config.sequence.get(self.next_in_sequence).copied()
+    }
+
+    /// Glitches and sequence problems seen, including any pushed out of the log
+    pub fn anomalies(&self) -> u32 {
+        self.anomalies
+    }
+
+    /// The recent events
+    pub fn log(&self) -> &IndicatorLog<N> {
+        &self.log
+    }
+
+    fn sample_line(
+        &mut self,
+        now_ms: u64,
+        line: IndicatorLine,
+        high: bool,
+    ) -> Option<IndicatorEvent> {
+        let debounce_ms = self.config.debounce_ms;
+        let state = &mut self.lines[line.index()];
+
+        if high == state.high {
+            // Back where it was before the new level held
+            return state.candidate.take().map(|(level, since)| IndicatorEvent {
+                line,
+                high: level,
+                edge_ms: since,
+                confirmed_ms: now_ms,
+                anomaly: Some(IndicatorAnomaly::Glitch),
+            });
+        }
+
+        let since = match state.candidate {
+            Some((level, since)) if level == high => since,
+            _ => {
+                state.candidate = Some((high, now_ms));
+                now_ms
+            }
+        };
+        if now_ms.saturating_sub(since) < debounce_ms {
+            return None;
+        }
+
+        state.high = high;
+        state.candidate = None;
+        if high {
+            state.rose_at_ms = Some(since);
+        } else {
+            state.fell_at_ms = Some(since);
+        }
+
+        let anomaly = if high {
+            self.check_sequence(line)
+        } else {
+            None
+        };
+        Some(IndicatorEvent {
+            line,
+            high,
+            edge_ms: since,
+            confirmed_ms: now_ms,
+            anomaly,
+        })
+    }
+
+    // A rising edge against the expected order, moving past it
+    fn check_sequence(&mut self, line: IndicatorLine) -> Option<IndicatorAnomaly> {
+        let position = self.config.sequence.iter().position(|&l| l == line)?;
+        let expected = self.next_expected();
+        if position < self.next_in_sequence {
+            return Some(IndicatorAnomaly::Late);
+        }
+
+        self.next_in_sequence = position + 1;
+        match expected {
+            Some(expected) if expected != line => Some(IndicatorAnomaly::OutOfOrder { expected }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: IndicatorConfig = IndicatorConfig {
+        debounce_ms: 100,
+        ..IndicatorConfig::DEFAULT
+    };
+
+    fn te(te1: bool, te2: bool, te3: bool) -> IndicatorStates {
+        IndicatorBuilder::new()
+            .gse1(false)
+            .gse2(false)
+            .te_ra(false)
+            .te_rb(false)
+            .te1(te1)
+            .te2(te2)
+            .te3(te3)
+            .build()
+    }
+
+    // Feed `sample` every 10ms from `from` up to but not including `to`, returning the events seen
+    fn hold(
+        monitor: &mut IndicatorMonitor<8>,
+        from: u64,
+        to: u64,
+        sample: IndicatorStates,
+    ) -> [Option<IndicatorEvent>; 4] {
+        let mut seen = [None; 4];
+        let events = (from..to)
+            .step_by(10)
+            .flat_map(|now| monitor.update(now, sample))
+            .flatten();
+        for (slot, event) in seen.iter_mut().zip(events) {
+            *slot = Some(event);
+        }
+        seen
+    }
+
+    #[test]
+    fn test_edges_timestamped_in_order() {
+        let mut monitor = IndicatorMonitor::<8>::new(CONFIG);
+        assert_eq!(
+            hold(&mut monitor, 0, 100, te(false, false, false)),
+            [None; 4]
+        );
+        assert_eq!(monitor.next_expected(), Some(IndicatorLine::Te1));
+
+        let [te1, ..] = hold(&mut monitor, 100, 300, te(true, false, false));
+        assert_eq!(
+            te1,
+            Some(IndicatorEvent {
+                line: IndicatorLine::Te1,
+                high: true,
+                edge_ms: 100,
+                confirmed_ms: 200,
+                anomaly: None,
+            })
+        );
+        hold(&mut monitor, 300, 500, te(true, true, false));
+        hold(&mut monitor, 500, 700, te(true, true, true));
+        let [te1_low, ..] = hold(&mut monitor, 700, 900, te(false, true, true));
+        assert_eq!(te1_low.map(|e| (e.high, e.anomaly)), Some((false, None)));
+
+        assert_eq!(monitor.rose_at_ms(IndicatorLine::Te1), Some(100));
+        assert_eq!(monitor.rose_at_ms(IndicatorLine::Te2), Some(300));
+        assert_eq!(monitor.rose_at_ms(IndicatorLine::Te3), Some(500));
+        assert_eq!(monitor.fell_at_ms(IndicatorLine::Te1), Some(700));
+        assert_eq!(monitor.next_expected(), None);
+        assert_eq!(monitor.anomalies(), 0);
+        assert_eq!(monitor.states(), te(false, true, true));
+        assert_eq!(monitor.log().len(), 4);
+    }
+
+    #[test]
+    fn test_glitch_is_flagged_not_taken() {
+        let mut monitor = IndicatorMonitor::<8>::new(CONFIG);
+        hold(&mut monitor, 0, 50, te(false, true, false));
+        let [glitch, none, ..] = hold(&mut monitor, 50, 100, te(false, false, false));
+
+        assert_eq!(none, None);
+        assert_eq!(
+            glitch,
+            Some(IndicatorEvent {
+                line: IndicatorLine::Te2,
+                high: true,
+                edge_ms: 0,
+                confirmed_ms: 50,
+                anomaly: Some(IndicatorAnomaly::Glitch),
+            })
+        );
+        assert_eq!(monitor.rose_at_ms(IndicatorLine::Te2), None);
+        assert_eq!(monitor.states(), te(false, false, false));
+        // A glitch doesn't use up its place in the sequence
+        assert_eq!(monitor.next_expected(), Some(IndicatorLine::Te1));
+        assert_eq!(monitor.anomalies(), 1);
+    }
+
+    #[test]
+    fn test_out_of_order_and_repeats() {
+        let mut monitor = IndicatorMonitor::<8>::new(CONFIG);
+        let [te2, ..] = hold(&mut monitor, 0, 200, te(false, true, false));
+        assert_eq!(
+            te2.and_then(|e| e.anomaly),
+            Some(IndicatorAnomaly::OutOfOrder {
+                expected: IndicatorLine::Te1,
+            })
+        );
+        assert_eq!(monitor.next_expected(), Some(IndicatorLine::Te3));
+
+        // TE-1 late is behind where the sequence has got to
+        let [te1, ..] = hold(&mut monitor, 200, 400, te(true, true, false));
+        assert_eq!(te1.and_then(|e| e.anomaly), Some(IndicatorAnomaly::Late));
+
+        let [te3, ..] = hold(&mut monitor, 400, 600, te(true, true, true));
+        assert_eq!(
+            te3.map(|e| (e.line, e.anomaly)),
+            Some((IndicatorLine::Te3, None))
+        );
+        assert_eq!(monitor.anomalies(), 2);
+    }
+
+    #[test]
+    fn test_already_high_at_start_in_order() {
+        // Coming back up after a reboot partway through
+        let mut monitor = IndicatorMonitor::<8>::new(IndicatorConfig {
+            debounce_ms: 0,
+            ..IndicatorConfig::DEFAULT
+        });
+        let events = monitor.update(1000, te(true, true, false));
+        let lines: [_; 2] =
+            core::array::from_fn(|i| events.iter().flatten().nth(i).map(|e| e.line));
+        assert_eq!(lines, [Some(IndicatorLine::Te1), Some(IndicatorLine::Te2)]);
+        assert_eq!(monitor.anomalies(), 0);
+        assert_eq!(monitor.next_expected(), Some(IndicatorLine::Te3));
+    }
+
+    #[test]
+    fn test_log_keeps_latest_and_round_trips() {
+        let mut monitor = IndicatorMonitor::<2>::new(IndicatorConfig {
+            debounce_ms: 0,
+            ..IndicatorConfig::DEFAULT
+        });
+        monitor.update(0, te(true, false, false));
+        monitor.update(10, te(true, true, false));
+        monitor.update(20, te(true, true, true));
+
+        let log = monitor.log();
+        assert_eq!(log.dropped(), 1);
+        let lines: [_; 2] = core::array::from_fn(|i| log.iter().nth(i).map(|e| e.line));
+        assert_eq!(lines, [Some(IndicatorLine::Te2), Some(IndicatorLine::Te3)]);
+
+        let mut buffer = [0u8; 64];
+        let len = log.to_bytes(&mut buffer).unwrap();
+        assert_eq!(IndicatorLog::<2>::from_bytes(&buffer[..len]).unwrap(), *log);
+        // Too many to fit a smaller log
+        assert!(IndicatorLog::<1>::from_bytes(&buffer[..len]).is_err());
+    }
+}
@@ -2,5 +2,7 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
 pub mod battery_state;
+pub mod indicator_monitor;
 pub mod indicators;
 pub mod rbf;
+pub mod rbf_monitor;
@@ -0,0 +1,561 @@
+#![warn(missing_docs)]
+
+//! Debounced RBF tracking. A connector bouncing while the vehicle is handled shouldn't arm
+//! anything, so a new level only counts once it has held for the debounce time, and anything
+//! that can't be trusted reads as inserted. With a second pin on the same plug, the two have to
+//! agree, and a disagreement that lasts is latched as a fault until it's cleared by hand.
+//!
+//! The monitor doesn't read pins or keep time itself: feed it a sample and the time it was taken,
+//! from an RTIC task or a thread.
+
+use bincode::{Decode, Encode};
+use embedded_hal::digital::InputPin;
+
+use crate::rbf::RbfState;
+
+/// How long a level has to hold, and how much trouble is tolerated before calling a fault
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RbfConfig {
+    /// A new level counts once it has held this long, in ms
+    pub debounce_ms: u64,
+    /// Two pins disagreeing this long is a fault, in ms
+    pub disagree_ms: u64,
+    /// Failed reads in a row before it's a fault
+    pub max_read_errors: u8,
+}
+
+impl RbfConfig {
+    /// Half a second to settle, a second of disagreement or five failed reads to fault
+    pub const DEFAULT: RbfConfig = RbfConfig {
+        debounce_ms: 500,
+        disagree_ms: 1000,
+        max_read_errors: 5,
+    };
+}
+
+impl Default for RbfConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Why the RBF can't be trusted
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub enum RbfFault {
+    /// The two pins read differently for longer than allowed
+    Disagree,
+    /// The pins couldn't be read
+    ReadError,
+}
+
+/// What the RBF is known to be doing
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub enum RbfStatus {
+    /// No level has held long enough yet
+    Settling,
+    /// Inserted, held for the debounce time
+    Inserted,
+    /// Removed, held for the debounce time
+    Removed,
+    /// Latched until [`RbfMonitor::clear_fault`]
+    Fault(RbfFault),
+}
+
+impl RbfStatus {
+    /// Inhibited unless the RBF is known to be out
+    pub fn inhibition(&self) -> RbfState {
+        match self {
+            RbfStatus::Removed => RbfState::Uninhibited,
+            _ => RbfState::Inhibited,
+        }
+    }
+}
+
+/// A change of [`RbfStatus`]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub struct RbfTransition {
+    /// Status before
+    pub from: RbfStatus,
+    /// Status now
+    pub to: RbfStatus,
+    /// When the level first read as it does now, or when the trouble started for a fault, in ms
+    pub edge_ms: u64,
+    /// When it was confirmed, in ms
+    pub confirmed_ms: u64,
+}
+
+/// Debounces RBF samples into an [`RbfStatus`]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone)]
+pub struct RbfMonitor {
+    config: RbfConfig,
+    status: RbfStatus,
+    // Level being timed, and when it was first read
+    candidate: Option<(bool, u64)>,
+    disagree_since: Option<u64>,
+    read_errors: u8,
+    first_error_ms: u64,
+    inserted_at_ms: Option<u64>,
+    removed_at_ms: Option<u64>,
+    last_transition: Option<RbfTransition>,
+}
+
+impl RbfMonitor {
+    /// Nothing read yet
+    pub const fn new(config: RbfConfig) -> Self {
+        Self {
+            config,
+            status: RbfStatus::Settling,
+            candidate: None,
+            disagree_since: None,
+            read_errors: 0,
+            first_error_ms: 0,
+            inserted_at_ms: None,
+            removed_at_ms: None,
+            last_transition: None,
+        }
+    }
+
+    /// Take a sample from a single pin: whether it reads inserted, or `None` if it couldn't be
+    /// read
+    pub fn update(&mut self, now_ms: u64, inserted: Option<bool>) -> Option<RbfTransition> {
+        match inserted {
+            Some(inserted) => self.level(now_ms, inserted),
+            None => self.read_error(now_ms),
+        }
+    }
+
+    /// Take a sample from both pins, each as for [`RbfMonitor::update`]
+    pub fn update_pair(
+        &mut self,
+        now_ms: u64,
+        primary: Option<bool>,
+        secondary: Option<bool>,
+    ) -> Option<RbfTransition> {
+        match (primary, secondary) {
+            (Some(primary), Some(secondary)) if primary == secondary => self.level(now_ms, primary),
+            (Some(_), Some(_)) => self.disagree(now_ms),
+            _ => self.read_error(now_ms),
+        }
+    }
+
+    /// Current status
+    pub fn status(&self) -> RbfStatus {
+        self.status
+    }
+
+    /// Inhibited unless the RBF is known to be out
+    pub fn inhibition(&self) -> RbfState {
+        self.status.inhibition()
+    }
+
+    /// When the RBF last went in, by the first sample of the level that held, in ms
+    pub fn inserted_at_ms(&self) -> Option<u64> {
+        self.inserted_at_ms
+    }
+
+    /// When the RBF last came out, by the first sample of the level that held, in ms
+    pub fn removed_at_ms(&self) -> Option<u64> {
+        self.removed_at_ms
+    }
+
+    /// The most recent change of status
+    pub fn last_transition(&self) -> Option<RbfTransition> {
+        self.last_transition
+    }
+
+    /// Start over from [`RbfStatus::Settling`] after a fault, keeping the edge times. Does nothing
+    /// otherwise.
+    pub fn clear_fault(&mut self) {
+        if let RbfStatus::Fault(_) = self.status {
+            let timestamps = (
+                self.inserted_at_ms,
+                self.removed_at_ms,
+                self.last_transition,
+            );
+            *self = Self::new(self.config);
+            (
+                self.inserted_at_ms,
+                self.removed_at_ms,
+                self.last_transition,
+            ) = timestamps;
+        }
+    }
+
+    fn level(&mut self, now_ms: u64, inserted: bool) -> Option<RbfTransition> {
+        if self.faulted() {
+            return None;
+        }
+        self.read_errors = 0;
+        self.disagree_since = None;
+
+        let since = match self.candidate {
+            Some((level, since)) if level == inserted => since,
+            _ => {
+                self.candidate = Some((inserted, now_ms));
+                now_ms
+            }
+        };
+        if now_ms.saturating_sub(since) < self.config.debounce_ms {
+            return None;
+        }
+
+        let to = if inserted {
+            RbfStatus::Inserted
+        } else {
+            RbfStatus::Removed
+        };
+        if self.status == to {
+            return None;
+        }
+        if inserted {
+            self.inserted_at_ms = Some(since);
+        } else {
+            self.removed_at_ms = Some(since);
+        }
+        self.transition(to, since, now_ms)
+    }
+
+    fn disagree(&mut self, now_ms: u64) -> Option<RbfTransition> {
+        if self.faulted() {
+            return None;
+        }
+        self.read_errors = 0;
+        // Neither level is holding while they disagree
+        self.candidate = None;
+
+        let since = *self.disagree_since.get_or_insert(now_ms);
+        if now_ms.saturating_sub(since) < self.config.disagree_ms {
+            return None;
+        }
+        self.transition(RbfStatus::Fault(RbfFault::Disagree), since, now_ms)
+    }
+
+    fn read_error(&mut self, now_ms: u64) -> Option<RbfTransition> {
+        if self.faulted() {
+            return None;
+        }
+        // A lone failed read doesn't break the level being timed
+        if self.read_errors == 0 {
+            self.first_error_ms = now_ms;
+        }
+        self.read_errors = self.read_errors.saturating_add(1);
+        if self.read_errors < self.config.max_read_errors {
+            return None;
+        }
+        self.transition(
+            RbfStatus::Fault(RbfFault::ReadError),
+            self.first_error_ms,
+            now_ms,
+        )
+    }
+
+    fn faulted(&self) -> bool {
+        matches!(self.status, RbfStatus::Fault(_))
+    }
+
+    fn transition(&mut self, to: RbfStatus, edge_ms: u64, now_ms: u64) -> Option<RbfTransition> {
+        let transition = RbfTransition {
+            from: self.status,
+            to,
+            edge_ms,
+            confirmed_ms: now_ms,
+        };
+        self.status = to;
+        self.last_transition = Some(transition);
+        Some(transition)
+    }
+}
+
+/// An RBF pin and which level means inserted, read for an [`RbfMonitor`]
+pub struct RbfLine<T: InputPin> {
+    pin: T,
+    active_high: bool,
+}
+
+impl<T: InputPin> RbfLine<T> {
+    /// High means inserted
+    pub fn active_high(pin: T) -> Self {
+        Self {
+            pin,
+            active_high: true,
+        }
+    }
+
+    /// Low means inserted
+    pub fn active_low(pin: T) -> Self {
+        Self {
+            pin,
+            active_high: false,
+        }
+    }
+
+    /// Whether it reads inserted, or `None` if it couldn't be read
+    pub fn read(&mut self) -> Option<bool> {
+        self.pin.is_high().ok().map(|high| self.inserted_when(high))
+    }
+
+    /// Whether the pin at `high` means inserted, for levels it reports some other way
+    pub fn inserted_when(&self, high: bool) -> bool {
+        high == self.active_high
+    }
+
+    /// The pin itself
+    pub fn pin_mut(&mut self) -> &mut T {
+        &mut self.pin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_hal::digital::{ErrorKind, ErrorType};
+
+    use super::*;
+
+    const CONFIG: RbfConfig = RbfConfig {
+        debounce_ms: 100,
+        disagree_ms: 300,
+        max_read_errors: 3,
+    };
+
+    // Feed `inserted` every 10ms from `from` up to but not including `to`, returning the
+    // transitions seen
+    fn hold(
+        monitor: &mut RbfMonitor,
+        from: u64,
+        to: u64,
+        inserted: Option<bool>,
+    ) -> [Option<RbfTransition>; 2] {
+        let mut seen = [None; 2];
+        let mut count = 0;
+        for now in (from..to).step_by(10) {
+            if let Some(transition) = monitor.update(now, inserted) {
+                seen[count] = Some(transition);
+                count += 1;
+            }
+        }
+        seen
+    }
+
+    fn settled(inserted: bool) -> RbfMonitor {
+        let mut monitor = RbfMonitor::new(CONFIG);
+        hold(&mut monitor, 0, 200, Some(inserted));
+        monitor
+    }
+
+    #[test]
+    fn test_settles_after_debounce() {
+        let mut monitor = RbfMonitor::new(CONFIG);
+        assert_eq!(monitor.status(), RbfStatus::Settling);
+        assert_eq!(monitor.inhibition(), RbfState::Inhibited);
+
+        assert_eq!(hold(&mut monitor, 0, 100, Some(true)), [None; 2]);
+        let transition = monitor.update(100, Some(true)).unwrap();
+        assert_eq!(
+            transition,
+            RbfTransition {
+                from: RbfStatus::Settling,
+                to: RbfStatus::Inserted,
+                edge_ms: 0,
+                confirmed_ms: 100,
+            }
+        );
+        assert_eq!(monitor.inserted_at_ms(), Some(0));
+        assert_eq!(monitor.removed_at_ms(), None);
+        // Holding there changes nothing more
+        assert_eq!(hold(&mut monitor, 110, 500, Some(true)), [None; 2]);
+        assert_eq!(monitor.last_transition(), Some(transition));
+    }
+
+    #[test]
+    fn test_removal_timestamped_at_edge() {
+        let mut monitor = settled(true);
+        let [removed, none] = hold(&mut monitor, 200, 400, Some(false));
+
+        assert_eq!(none, None);
+        let removed = removed.unwrap();
+        assert_eq!(removed.from, RbfStatus::Inserted);
+        assert_eq!(removed.to, RbfStatus::Removed);
+        assert_eq!((removed.edge_ms, removed.confirmed_ms), (200, 300));
+        assert_eq!(monitor.removed_at_ms(), Some(200));
+        assert_eq!(monitor.inserted_at_ms(), Some(0));
+        assert_eq!(monitor.inhibition(), RbfState::Uninhibited);
+    }
+
+    #[test]
+    fn test_bounces_are_ignored() {
+        let mut monitor = settled(true);
+
+        // Out for 90ms at a time, never long enough
+        let mut now = 200;
+        for _ in 0..10 {
+            assert_eq!(hold(&mut monitor, now, now + 90, Some(false)), [None; 2]);
+            assert_eq!(
+                hold(&mut monitor, now + 90, now + 100, Some(true)),
+                [None; 2]
+            );
+            now += 100;
+        }
+        assert_eq!(monitor.status(), RbfStatus::Inserted);
+        assert_eq!(monitor.inhibition(), RbfState::Inhibited);
+
+        // A bounce restarts the clock on the way out as well
+        let mut monitor = settled(false);
+        hold(&mut monitor, 200, 250, Some(true));
+        hold(&mut monitor, 250, 260, Some(false));
+        assert_eq!(hold(&mut monitor, 260, 340, Some(true)), [None; 2]);
+        assert_eq!(
+            monitor.update(360, Some(true)).map(|t| t.edge_ms),
+            Some(260)
+        );
+    }
+
+    #[test]
+    fn test_reinsertion_inhibits_again() {
+        let mut monitor = settled(false);
+        let [inserted, _] = hold(&mut monitor, 200, 400, Some(true));
+        assert_eq!(inserted.map(|t| t.to), Some(RbfStatus::Inserted));
+        assert_eq!(monitor.inserted_at_ms(), Some(200));
+        assert_eq!(monitor.removed_at_ms(), Some(0));
+        assert_eq!(monitor.inhibition(), RbfState::Inhibited);
+    }
+
+    #[test]
+    fn test_read_errors() {
+        // A couple of failed reads don't interrupt the level being timed
+        let mut monitor = RbfMonitor::new(CONFIG);
+        hold(&mut monitor, 0, 50, Some(false));
+        hold(&mut monitor, 50, 70, None);
+        let [removed, _] = hold(&mut monitor, 70, 110, Some(false));
+        assert_eq!(removed.map(|t| t.edge_ms), Some(0));
+
+        // Enough in a row latches a fault, timed from the first
+        let [fault, _] = hold(&mut monitor, 110, 200, None);
+        let fault = fault.unwrap();
+        assert_eq!(fault.from, RbfStatus::Removed);
+        assert_eq!(fault.to, RbfStatus::Fault(RbfFault::ReadError));
+        assert_eq!((fault.edge_ms, fault.confirmed_ms), (110, 130));
+        assert_eq!(monitor.inhibition(), RbfState::Inhibited);
+
+        // Good reads don't bring it back
+        assert_eq!(hold(&mut monitor, 200, 1000, Some(false)), [None; 2]);
+        assert_eq!(monitor.status(), RbfStatus::Fault(RbfFault::ReadError));
+    }
+
+    #[test]
+    fn test_pins_have_to_agree() {
+        let mut monitor = RbfMonitor::new(CONFIG);
+        for now in (0..100).step_by(10) {
+            assert_eq!(monitor.update_pair(now, Some(false), Some(false)), None);
+        }
+        assert_eq!(
+            monitor
+                .update_pair(100, Some(false), Some(false))
+                .map(|t| t.to),
+            Some(RbfStatus::Removed)
+        );
+
+        // A short disagreement is a bounce, and restarts the debounce
+        for now in (110..300).step_by(10) {
+            assert_eq!(monitor.update_pair(now, Some(true), Some(false)), None);
+        }
+        for now in (300..390).step_by(10) {
+            assert_eq!(monitor.update_pair(now, Some(true), Some(true)), None);
+        }
+        assert_eq!(
+            monitor.update_pair(400, Some(true), Some(true)),
+            Some(RbfTransition {
+                from: RbfStatus::Removed,
+                to: RbfStatus::Inserted,
+                edge_ms: 300,
+                confirmed_ms: 400,
+            })
+        );
+
+        // A long one is a fault
+        for now in (410..710).step_by(10) {
+            assert_eq!(monitor.update_pair(now, Some(false), Some(true)), None);
+        }
+        assert_eq!(
+            monitor.update_pair(710, Some(false), Some(true)),
+            Some(RbfTransition {
+                from: RbfStatus::Inserted,
+                to: RbfStatus::Fault(RbfFault::Disagree),
+                edge_ms: 410,
+                confirmed_ms: 710,
+            })
+        );
+        assert_eq!(monitor.update_pair(720, Some(false), Some(false)), None);
+        assert_eq!(monitor.inhibition(), RbfState::Inhibited);
+    }
+
+    #[test]
+    fn test_pair_read_error() {
+        let mut monitor = RbfMonitor::new(CONFIG);
+        for now in [0, 10] {
+            assert_eq!(monitor.update_pair(now, Some(true), None), None);
+        }
+        assert_eq!(
+            monitor.update_pair(20, None, Some(true)).map(|t| t.to),
+            Some(RbfStatus::Fault(RbfFault::ReadError))
+        );
+    }
+
+    #[test]
+    fn test_clear_fault_starts_over() {
+        let mut monitor = settled(false);
+        hold(&mut monitor, 200, 300, None);
+        assert!(matches!(monitor.status(), RbfStatus::Fault(_)));
+
+        monitor.clear_fault();
+        assert_eq!(monitor.status(), RbfStatus::Settling);
+        assert_eq!(monitor.removed_at_ms(), Some(0));
+
+        let [removed, _] = hold(&mut monitor, 300, 500, Some(false));
+        assert_eq!(
+            removed.map(|t| (t.from, t.to, t.edge_ms)),
+            Some((RbfStatus::Settling, RbfStatus::Removed, 300))
+        );
+        assert_eq!(monitor.removed_at_ms(), Some(300));
+
+        // Not faulted, nothing to clear
+        monitor.clear_fault();
+        assert_eq!(monitor.status(), RbfStatus::Removed);
+    }
+
+    // A pin reading from a list, failing where it's None
+    struct Pin<'a>(&'a [Option<bool>]);
+
+    impl ErrorType for Pin<'_> {
+        type Error = ErrorKind;
+    }
+
+    impl InputPin for Pin<'_> {
+        fn is_high(&mut self) -> Result<bool, Self::Error> {
+            let (first, rest) = self.0.split_first().unwrap();
+            self.0 = rest;
+            first.ok_or(ErrorKind::Other)
+        }
+
+        fn is_low(&mut self) -> Result<bool, Self::Error> {
+            self.is_high().map(|high| !high)
+        }
+    }
+
+    #[test]
+    fn test_line_polarity() {
+        let levels = [Some(true), Some(false), None];
+        let mut high = RbfLine::active_high(Pin(&levels));
+        assert_eq!([high.read(), high.read(), high.read()], levels);
+
+        let mut low = RbfLine::active_low(Pin(&levels));
+        assert_eq!(
+            [low.read(), low.read(), low.read()],
+            [Some(false), Some(true), None]
+        );
+    }
+}
@@ -4,6 +4,9 @@ use bin_packets::packets::ApplicationPacket;
 use chrono::prelude::*;
 use csv::Writer;
 use indexmap::IndexMap;
+use serde_reflection::{
+    ContainerFormat, Format, Named, Registry, Tracer, TracerConfig, VariantFormat,
+};
 use std::{
     collections::{HashMap, HashSet},
     fs::{OpenOptions, read_dir},
@@ -17,6 +20,9 @@ use std::{
 // or a datetime stamp
 pub struct CSVPacketTranslator {
     created_file_list: HashSet<String>,
+    // Layout of every packet type, traced from the packet definitions so the columns of a
+    // file don't depend on which optional fields the first packet had filled in
+    packet_formats: Registry,
     original_file_iterations: HashMap<String, i32>,
     output_directory: PathBuf,
     current_time: DateTime<Local>,
@@ -60,46 +66,99 @@ impl CSVPacketTranslator {
             },
         );
 
+        // Enums nested in packets are written as a single column, so only the packet types
+        // themselves need every variant traced
+        let mut tracer = Tracer::new(TracerConfig::default());
+        tracer
+            .trace_simple_type::<ApplicationPacket>()
+            .expect("Failure to trace the packet formats");
+
         Ok(CSVPacketTranslator {
             output_directory: output_path,
             original_file_iterations,
             created_file_list: HashSet::new(),
+            packet_formats: tracer.registry_unchecked(),
             current_time: Local::now(),
             file_name_format,
         })
     }
 
-    // Recursively collect the headers of a struct
+    // Recursively collect the headers of a struct, following its format rather than the value
+    // so an optional substruct has the same columns whether it's filled in or not
     // This is recursive because some structs can have many layers of substructs
-    // with their own headers that must also be collected
+    // with their own headers that must also be collected. Fields of a substruct are
+    // named after it, like `health.faults`, so two substructs of the same type don't
+    // share columns
     fn collect_packet_headers(
         &self,
-        packet: &serde_json::Value,
-    ) -> Option<IndexMap<String, String>> {
-        let mut result = None;
-
-        if let Some(field) = packet.as_object() {
-            let mut incomplete_map = IndexMap::new();
-            for key in field.keys() {
-                if let Some(map) = self.collect_packet_headers(&field[key]) {
-                    // Over here an insertion would have to be added if we also want to view the name
-                    // of sub_structs, but this might be unneccessary because it is counter intuitive to the
-                    // format of CSV files
-                    incomplete_map.extend(map);
-                } else {
-                    // Add in primitive values with any quotes removed
-                    incomplete_map.insert(
-                        key.to_string(),
-                        field[key]
-                            .to_string()
-                            .replace(&['(', ')', ',', '\"', ';', ':', '\''][..], ""),
-                    );
+        name: &str,
+        format: &Format,
+        value: &serde_json::Value,
+        headers: &mut IndexMap<String, String>,
+    ) {
+        if let Some(fields) = self.struct_fields(format) {
+            return self.collect_field_headers(Some(name), fields, value, headers);
+        }
+        match format {
+            Format::TypeName(type_name) => match self.packet_formats.get(type_name) {
+                Some(ContainerFormat::NewTypeStruct(inner)) => {
+                    self.collect_packet_headers(name, inner, value, headers);
                 }
-            }
-            result = Some(incomplete_map);
+                _ => Self::insert_value(name, value, headers),
+            },
+            // A None leaves the substruct's columns empty
+            Format::Option(inner) => self.collect_packet_headers(name, inner, value, headers),
+            _ => Self::insert_value(name, value, headers),
+        }
+    }
+
+    fn collect_field_headers(
+        &self,
+        parent: Option<&str>,
+        fields: &[Named<Format>],
+        value: &serde_json::Value,
+        headers: &mut IndexMap<String, String>,
+    ) {
+        for field in fields {
+            let name = match parent {
+                Some(parent) => format!("{parent}.{}", field.name),
+                None => field.name.clone(),
+            };
+            self.collect_packet_headers(&name, &field.value, &value[&field.name], headers);
         }
+    }
 
-        result
+    // Add in primitive values with any quotes removed, and nothing for a missing value
+    fn insert_value(name: &str, value: &serde_json::Value, headers: &mut IndexMap<String, String>) {
+        let value = match value {
+            serde_json::Value::Null => String::new(),
+            value => value
+                .to_string()
+                .replace(&['(', ')', ',', '\"', ';', ':', '\''][..], ""),
+        };
+        headers.insert(name.to_string(), value);
+    }
+
+    // Fields of `format`, if it's a struct with named fields
+    fn struct_fields(&self, format: &Format) -> Option<&[Named<Format>]> {
+        match format {
+            Format::TypeName(type_name) => match self.packet_formats.get(type_name)? {
+                ContainerFormat::Struct(fields) => Some(fields),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // Format of the packet variant named `struct_name`
+    fn variant_format(&self, struct_name: &str) -> Option<&VariantFormat> {
+        match self.packet_formats.get("ApplicationPacket")? {
+            ContainerFormat::Enum(variants) => variants
+                .values()
+                .find(|variant| variant.name == struct_name)
+                .map(|variant| &variant.value),
+            _ => None,
+        }
     }
 
     pub fn file_write(&mut self, packet: ApplicationPacket) {
@@ -158,17 +217,35 @@ impl CSVPacketTranslator {
                         .expect("Uh oh, output file couldn't open");
                     let mut writer = Writer::from_writer(output_file);
 
-                    // Get the map of all struct values and append the values in csv format to the file
-                    if let Some(headers_map) = self.collect_packet_headers(&packet_struct) {
-                        if self.created_file_list.contains(struct_name) {
-                            writer.write_record(headers_map.values()).unwrap();
-                        } else {
-                            // Create new file with headers, and list
-                            writer.write_record(headers_map.keys()).unwrap();
-                            writer.write_record(headers_map.values()).unwrap();
-
-                            self.created_file_list.insert(struct_name.clone());
+                    // Get the map of all struct values, named from the struct's own fields down
+                    let value = &field[struct_name];
+                    let mut headers_map = IndexMap::new();
+                    match self.variant_format(struct_name) {
+                        Some(VariantFormat::Struct(fields)) => {
+                            self.collect_field_headers(None, fields, value, &mut headers_map);
                         }
+                        Some(VariantFormat::NewType(inner)) => match self.struct_fields(inner) {
+                            Some(fields) => {
+                                self.collect_field_headers(None, fields, value, &mut headers_map);
+                            }
+                            None => self.collect_packet_headers(
+                                struct_name,
+                                inner,
+                                value,
+                                &mut headers_map,
+                            ),
+                        },
+                        _ => Self::insert_value(struct_name, value, &mut headers_map),
+                    }
+
+                    if self.created_file_list.contains(struct_name) {
+                        writer.write_record(headers_map.values()).unwrap();
+                    } else {
+                        // Create new file with headers, and list
+                        writer.write_record(headers_map.keys()).unwrap();
+                        writer.write_record(headers_map.values()).unwrap();
+
+                        self.created_file_list.insert(struct_name.clone());
                     }
                     // If the struct name is known in our internal list, we can safely assume we have an old file
                     // and just append the values to the existing csv without adding the headers
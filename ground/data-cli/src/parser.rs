@@ -3,6 +3,7 @@
 use crate::csv_translator::CSVPacketTranslator;
 
 use bin_packets::packets::ApplicationPacket;
+use bin_packets::storage::{is_segment, SegmentEnd, SegmentReader};
 use bincode::{config::standard, decode_from_std_read};
 
 use std::{
@@ -19,7 +20,42 @@ pub struct DataParser {
 }
 
 impl DataParser {
-    pub fn parse_file(mut self, read_file_path: &Path) {
+    pub fn parse_file(self, read_file_path: &Path) {
+        // Logs from before segments were introduced are bare bincode, one packet after another
+        match is_segment(read_file_path) {
+            Ok(true) => self.parse_segment(read_file_path),
+            Ok(false) => self.parse_raw(read_file_path),
+            Err(e) => eprintln!("Error reading raw data from file: {e}"),
+        }
+    }
+
+    fn parse_segment(mut self, read_file_path: &Path) {
+        let mut reader = match SegmentReader::open(read_file_path) {
+            Ok(reader) => reader,
+            Err(e) => {
+                eprintln!("Error reading segment: {e}");
+                return;
+            }
+        };
+
+        for record in reader.by_ref() {
+            self.write_decoded_packet(record.packet);
+        }
+
+        match reader.end() {
+            SegmentEnd::Torn => eprintln!(
+                "Segment cut short after byte {}, the rest was lost mid-write",
+                reader.valid_len()
+            ),
+            SegmentEnd::Corrupt => eprintln!(
+                "Corrupt record at byte {}, stopped reading there",
+                reader.valid_len()
+            ),
+            _ => {}
+        }
+    }
+
+    fn parse_raw(mut self, read_file_path: &Path) {
         let file = File::open(read_file_path);
 
         match file {
@@ -2,6 +2,7 @@
 
 use std::time::Duration;
 
+use bin_packets::data::{BoardHealth, EjectorStatus, IcarusStatus, JupiterStatus, RelayStatus};
 use bin_packets::packets::ApplicationPacket;
 use bincode::{config::standard, decode_from_slice};
 use clap::{Parser, Subcommand};
@@ -75,7 +76,10 @@ fn listen(port: &str) -> ! {
         while !incoming_chars.is_empty() {
             match decode_from_slice::<ApplicationPacket, _>(&incoming_chars, standard()) {
                 Ok((packet, consumed)) => {
-                    println!("{packet:?}");
+                    match status_line(&packet) {
+                        Some(line) => println!("{line}"),
+                        None => println!("{packet:?}"),
+                    }
                     incoming_chars = incoming_chars.split_off(consumed);
                 }
                 #[allow(unused_variables)]
@@ -91,3 +95,67 @@ fn listen(port: &str) -> ! {
         }
     }
 }
+
+// One line for a board status packet, with its faults named
+fn status_line(packet: &ApplicationPacket) -> Option<String> {
+    let line = match packet {
+        ApplicationPacket::EjectorStatus(status) => {
+            let actuations = status
+                .actuations()
+                .map(|result| format!("{:?} {:?}", result.target, result.outcome))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!(
+                "Ejector {:?} | {} | deployed {:?} [{actuations}]",
+                status.phase,
+                health_line(&status.health, &EjectorStatus::FAULT_NAMES),
+                status.deployed
+            )
+        }
+        ApplicationPacket::IcarusStatus(status) => format!(
+            "ICARUS {:?} | {}",
+            status.phase,
+            health_line(&status.health, &IcarusStatus::FAULT_NAMES)
+        ),
+        ApplicationPacket::JupiterStatus(status) => format!(
+            "JUPITER {:?} | {} | subsystems up {:#04x} down {:#04x}, {} restarts",
+            status.phase,
+            health_line(&status.health, &JupiterStatus::FAULT_NAMES),
+            status.subsystems.healthy,
+            status.subsystems.degraded,
+            status.subsystems.restarts
+        ),
+        ApplicationPacket::RelayStatus(status) => format!(
+            "Relay | {}",
+            health_line(&status.health, &RelayStatus::FAULT_NAMES)
+        ),
+        _ => return None,
+    };
+    Some(line)
+}
+
+fn health_line(health: &BoardHealth, fault_names: &[&str]) -> String {
+    let faults = match health.faults {
+        0 => "none".to_string(),
+        _ => health
+            .fault_names(fault_names)
+            .collect::<Vec<_>>()
+            .join(", "),
+    };
+    let optional = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    format!(
+        "#{} up {}s, {}s in phase | RBF {} | battery {} | queue {}/{} | last cmd {} | faults: {faults}",
+        health.packet_number,
+        health.uptime_ms / 1000,
+        health.time_in_phase_ms / 1000,
+        optional(
+            health
+                .rbf_inserted
+                .map(|inserted| if inserted { "in" } else { "out" }.to_string())
+        ),
+        optional(health.battery_mv.map(|mv| format!("{mv} mV"))),
+        health.queue_depth,
+        health.queue_max,
+        optional(health.last_command.map(|seq| seq.to_string())),
+    )
+}
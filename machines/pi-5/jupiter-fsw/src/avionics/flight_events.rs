@@ -0,0 +1,495 @@
+#![warn(missing_docs)]
+
+//! Launch, burnout and apogee from the accelerometers alone, as a check on the timer events. A
+//! miswired TE line shifts the whole timeline; this doesn't depend on any of them.
+//!
+//! Up is whatever the accelerometer read on the pad. Vertical velocity is integrated along it
+//! from the start of launch, assuming the flight stays near vertical.
+//!
+//! A launch is only believed once its burnout has followed with a plausible boost. Anything else,
+//! like the payload being knocked about on the way to the pad, drops the detector back to waiting.
+
+use std::time::Duration;
+
+use bin_packets::phases::JupiterPhase;
+use embedded_hal::digital::PinState;
+use log::{error, warn};
+
+use crate::timing;
+
+/// Standard gravity, m/s^2
+const STANDARD_GRAVITY: f32 = 9.80665;
+
+/// Weight of each new pad sample in the up estimate
+const PAD_FILTER_WEIGHT: f32 = 0.01;
+
+/// Thresholds and how long each has to hold before it counts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DetectorConfig {
+    /// Total acceleration that means the motor is burning, in g
+    pub launch_accel_g: f32,
+    /// How long it has to hold for a launch, so a bump on the pad doesn't count
+    pub launch_persistence: Duration,
+    /// Acceleration along up below which the motor is out, in g. Coasting reads drag, below zero.
+    pub burnout_accel_g: f32,
+    /// How long it has to stay out
+    pub burnout_persistence: Duration,
+    /// Shortest burn that counts as a boost
+    pub min_burn: Duration,
+    /// Longest wait for burnout after launch before calling the launch false
+    pub max_burn: Duration,
+    /// Vertical velocity a boost has to reach by burnout, in m/s
+    pub min_burnout_velocity: f32,
+    /// How long vertical velocity has to stay at or below zero for the apogee region
+    pub apogee_persistence: Duration,
+}
+
+impl Default for DetectorConfig {
+    fn default() -> Self {
+        Self {
+            launch_accel_g: 3.0,
+            launch_persistence: Duration::from_millis(250),
+            burnout_accel_g: 0.5,
+            burnout_persistence: Duration::from_millis(200),
+            min_burn: Duration::from_secs(1),
+            max_burn: Duration::from_secs(12),
+            min_burnout_velocity: 50.0,
+            apogee_persistence: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Something the detector saw
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlightEvent {
+    /// Motor ignition and liftoff
+    Launch,
+    /// Motor out, coasting
+    Burnout,
+    /// Vertical velocity gone to zero
+    ApogeeRegion,
+    /// A launch with no burnout in time, or not enough of a boost behind it. Back on the pad.
+    FalseLaunch,
+}
+
+/// An event and when it started, which is the start of its persistence window rather than when
+/// the window closed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DetectedEvent {
+    /// What happened
+    pub event: FlightEvent,
+    /// When, on the clock the samples were given on
+    pub at: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stage {
+    Pad,
+    Boost,
+    Coast,
+    Apogee,
+}
+
+/// Watches accelerations for the flight events, in order
+pub struct FlightEventDetector {
+    config: DetectorConfig,
+    stage: Stage,
+    up: Option<[f32; 3]>,
+    // Start of the window the next event is waiting out
+    pending_since: Option<Duration>,
+    last_sample: Option<Duration>,
+    velocity: f32,
+    launch_at: Option<Duration>,
+    burnout_at: Option<Duration>,
+    apogee_at: Option<Duration>,
+}
+
+fn magnitude(v: [f32; 3]) -> f32 {
+    (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt()
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+impl FlightEventDetector {
+    /// A detector on the pad
+    pub fn new(config: DetectorConfig) -> Self {
+        Self {
+            config,
+            stage: Stage::Pad,
+            up: None,
+            pending_since: None,
+            last_sample: None,
+            velocity: 0.0,
+            launch_at: None,
+            burnout_at: None,
+            apogee_at: None,
+        }
+    }
+
+    /// Feed an acceleration in g taken at `t`. Returns an event the sample completed, if any.
+    pub fn update(&mut self, t: Duration, accel: [f32; 3]) -> Option<DetectedEvent> {
+        let dt = self
+            .last_sample
+            .map(|last| t.saturating_sub(last).as_secs_f32())
+            .unwrap_or(0.0);
+        self.last_sample = Some(t);
+
+        match self.stage {
+            Stage::Pad => self.on_pad(t, dt, accel),
+            Stage::Boost => self.on_boost(t, dt, accel),
+            Stage::Coast => {
+                self.integrate(dt, accel);
+                self.persist(t, self.velocity <= 0.0, self.config.apogee_persistence)
+                    .map(|at| {
+                        self.stage = Stage::Apogee;
+                        self.apogee_at = Some(at);
+                        DetectedEvent { event: FlightEvent::ApogeeRegion, at }
+                    })
+            }
+            Stage::Apogee => None,
+        }
+    }
+
+    fn on_pad(&mut self, t: Duration, dt: f32, accel: [f32; 3]) -> Option<DetectedEvent> {
+        let burning = magnitude(accel) >= self.config.launch_accel_g;
+
+        if !burning {
+            // Only learn up from samples that aren't part of a launch
+            self.up = Some(match self.up {
+                Some(up) => std::array::from_fn(|i| up[i] + (accel[i] - up[i]) * PAD_FILTER_WEIGHT),
+                None => accel,
+            });
+            self.pending_since = None;
+            self.velocity = 0.0;
+            return None;
+        }
+
+        // Velocity counts from the first burning sample, thrown away if it was a bump
+        if self.pending_since.is_some() {
+            self.integrate(dt, accel);
+        }
+        let at = self.persist(t, true, self.config.launch_persistence)?;
+        self.stage = Stage::Boost;
+        self.launch_at = Some(at);
+        Some(DetectedEvent { event: FlightEvent::Launch, at })
+    }
+
+    fn on_boost(&mut self, t: Duration, dt: f32, accel: [f32; 3]) -> Option<DetectedEvent> {
+        let launch = self.launch_at?;
+        self.integrate(dt, accel);
+        let axial = self.axial(accel);
+
+        match self.persist(t, axial < self.config.burnout_accel_g, self.config.burnout_persistence) {
+            Some(at) => {
+                let burn = at.saturating_sub(launch);
+                if burn < self.config.min_burn || self.velocity < self.config.min_burnout_velocity {
+                    return Some(self.false_launch(launch));
+                }
+                self.stage = Stage::Coast;
+                self.burnout_at = Some(at);
+                Some(DetectedEvent { event: FlightEvent::Burnout, at })
+            }
+            // A burnout window already open by the deadline is allowed to finish
+            None if self.pending_since.unwrap_or(t).saturating_sub(launch) > self.config.max_burn => {
+                Some(self.false_launch(launch))
+            }
+            None => None,
+        }
+    }
+
+    fn false_launch(&mut self, launch: Duration) -> DetectedEvent {
+        self.stage = Stage::Pad;
+        self.pending_since = None;
+        self.velocity = 0.0;
+        self.launch_at = None;
+        DetectedEvent { event: FlightEvent::FalseLaunch, at: launch }
+    }
+
+    // Holds a condition since the start of its window, giving the window's start once it's held
+    // long enough
+    fn persist(&mut self, t: Duration, condition: bool, persistence: Duration) -> Option<Duration> {
+        if !condition {
+            self.pending_since = None;
+            return None;
+        }
+        let since = *self.pending_since.get_or_insert(t);
+        if t.saturating_sub(since) >= persistence {
+            self.pending_since = None;
+            Some(since)
+        } else {
+            None
+        }
+    }
+
+    // Acceleration along up, in g. On the pad that's 1.
+    fn axial(&self, accel: [f32; 3]) -> f32 {
+        match self.up {
+            Some(up) if magnitude(up) > 0.0 => dot(accel, up) / magnitude(up),
+            // Never saw the pad, the best guess is that it's all along the flight
+            _ => magnitude(accel),
+        }
+    }
+
+    fn integrate(&mut self, dt: f32, accel: [f32; 3]) {
+        self.velocity += (self.axial(accel) - 1.0) * STANDARD_GRAVITY * dt;
+    }
+
+    /// Vertical velocity since launch, m/s
+    pub fn vertical_velocity(&self) -> f32 {
+        self.velocity
+    }
+
+    /// When launch was, once it's been seen, until it's found to be false
+    pub fn launch_at(&self) -> Option<Duration> {
+        self.launch_at
+    }
+
+    /// When burnout was, once it's been seen
+    pub fn burnout_at(&self) -> Option<Duration> {
+        self.burnout_at
+    }
+
+    /// When the apogee region started, once it's been seen
+    pub fn apogee_at(&self) -> Option<Duration> {
+        self.apogee_at
+    }
+
+    /// Mission time at `now` going by the detected launch, in whole seconds like the timer
+    /// estimate
+    pub fn mission_time(&self, now: Duration) -> Option<i32> {
+        self.launch_at.map(|launch| {
+            if now >= launch {
+                (now - launch).as_secs() as i32
+            } else {
+                -((launch - now).as_secs() as i32)
+            }
+        })
+    }
+}
+
+/// What to do with the timer estimate given the accelerometer's
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeCheck {
+    /// Close enough, leave it
+    Agrees,
+    /// Off by more than the tolerance, move it to the accelerometer's time
+    Recalibrate {
+        /// Mission time to calibrate to
+        to: i32,
+        /// Timer estimate minus the accelerometer's, positive when the timer runs ahead
+        error: i32,
+    },
+    /// Too far out to believe either way, leave it and say so
+    Implausible {
+        /// Timer estimate minus the accelerometer's
+        error: i32,
+    },
+}
+
+/// Bounds for letting a detected launch move the mission clock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeCheckPolicy {
+    /// Disagreement left alone, in seconds
+    pub tolerance: i32,
+    /// Disagreement past which the detection is more likely wrong than the timeline, in seconds
+    pub max_correction: i32,
+}
+
+impl TimeCheckPolicy {
+    /// Compare the timer estimate against the accelerometer's at the same moment
+    pub fn evaluate(&self, estimate: i32, accel: i32) -> TimeCheck {
+        let error = estimate - accel;
+        if error.abs() <= self.tolerance {
+            TimeCheck::Agrees
+        } else if error.abs() <= self.max_correction {
+            TimeCheck::Recalibrate { to: accel, error }
+        } else {
+            TimeCheck::Implausible { error }
+        }
+    }
+}
+
+/// Whether the vehicle can be flying, going by the state machine and TE-1. Before the timeline
+/// reaches launch only TE-1 says so, and well into the flight a burnout is too late to be real.
+pub fn may_be_in_flight(phase: JupiterPhase, te1: PinState) -> bool {
+    match phase {
+        JupiterPhase::PowerOn => te1 == PinState::High,
+        JupiterPhase::Launch | JupiterPhase::CamStart => true,
+        _ => false,
+    }
+}
+
+impl TimeCheckPolicy {
+    /// Move the mission clock onto a detected launch once `detected` is its burnout, if the
+    /// vehicle can be flying and the two are close enough. Returns what was decided, `None` when
+    /// the detection wasn't a candidate at all. The state machine checkpoints a moved clock on
+    /// its next update.
+    pub fn correct_clock(
+        &self,
+        detected: &DetectedEvent,
+        detector: &FlightEventDetector,
+        now: Duration,
+        phase: JupiterPhase,
+        te1: PinState,
+    ) -> Option<TimeCheck> {
+        if detected.event != FlightEvent::Burnout {
+            return None;
+        }
+        if !may_be_in_flight(phase, te1) {
+            warn!("Burnout detected in {phase:?} with TE1 {te1:?}, leaving the clock alone");
+            return None;
+        }
+
+        let accel_t = detector.mission_time(now)?;
+        let check = self.evaluate(timing::t_time_estimate(), accel_t);
+        match check {
+            TimeCheck::Agrees => {}
+            TimeCheck::Recalibrate { to, error } => {
+                warn!("Timer estimate off by {error}s from the detected launch, recalibrating");
+                timing::calibrate_to(to);
+            }
+            TimeCheck::Implausible { error } => {
+                error!("Detected launch is {error}s off the timer estimate, not trusting it");
+            }
+        }
+        Some(check)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A sample every 5ms, 200Hz like the IMU thread
+    const STEP_MS: u64 = 5;
+
+    // Run a profile of (seconds, acceleration in g) segments along `up` through a detector, with
+    // a little deterministic wobble on every axis
+    fn replay(detector: &mut FlightEventDetector, up: [f32; 3], profile: &[(f32, f32)]) -> Vec<DetectedEvent> {
+        let mut events = Vec::new();
+        let mut t_ms = 0u64;
+        for &(secs, g) in profile {
+            let end = t_ms + (secs * 1000.0) as u64;
+            while t_ms < end {
+                let wobble = ((t_ms / STEP_MS) % 7) as f32 * 0.01 - 0.03;
+                let accel = std::array::from_fn(|i| up[i] * g + wobble);
+                events.extend(detector.update(Duration::from_millis(t_ms), accel));
+                t_ms += STEP_MS;
+            }
+        }
+        events
+    }
+
+    fn near(at: Duration, secs: f32, within: f32) -> bool {
+        (at.as_secs_f32() - secs).abs() <= within
+    }
+
+    #[test]
+    fn test_synthetic_flight() {
+        let mut detector = FlightEventDetector::new(DetectorConfig::default());
+        // 2s on the pad, 3s at 10g, then coasting against 0.2g of drag
+        let events = replay(&mut detector, [0.0, 0.0, 1.0], &[(2.0, 1.0), (3.0, 10.0), (30.0, -0.2)]);
+
+        assert_eq!(
+            events.iter().map(|e| e.event).collect::<Vec<_>>(),
+            [FlightEvent::Launch, FlightEvent::Burnout, FlightEvent::ApogeeRegion]
+        );
+        assert!(near(events[0].at, 2.0, 0.01), "{events:?}");
+        assert!(near(events[1].at, 5.0, 0.01), "{events:?}");
+
+        // 9g for 3s is 264.8m/s, gone at 1.2g in another 22.5s
+        assert!(near(events[2].at, 27.5, 0.3), "{events:?}");
+
+        assert_eq!(detector.mission_time(Duration::from_secs(12)), Some(10));
+        assert_eq!(detector.mission_time(Duration::from_secs(1)), Some(-1));
+    }
+
+    #[test]
+    fn test_bump_on_pad_is_not_launch() {
+        let mut detector = FlightEventDetector::new(DetectorConfig::default());
+        // Dropped on the pad, 6g for 100ms
+        let events = replay(&mut detector, [0.0, 0.0, 1.0], &[(2.0, 1.0), (0.1, 6.0), (5.0, 1.0)]);
+        assert!(events.is_empty(), "{events:?}");
+        assert_eq!(detector.launch_at(), None);
+        assert_eq!(detector.vertical_velocity(), 0.0);
+    }
+
+    #[test]
+    fn test_mounted_sideways() {
+        // Up along -x, and the ascent picks it up from the pad
+        let mut detector = FlightEventDetector::new(DetectorConfig::default());
+        let events = replay(&mut detector, [-1.0, 0.0, 0.0], &[(1.0, 1.0), (2.0, 8.0), (30.0, 0.0)]);
+        assert_eq!(events.len(), 3, "{events:?}");
+
+        // 7g for 2s is 137.3m/s, gone at 1g in another 14s
+        assert!(near(events[2].at, 17.0, 0.3), "{events:?}");
+    }
+
+    #[test]
+    fn test_time_check() {
+        let policy = TimeCheckPolicy {
+            tolerance: 2,
+            max_correction: 60,
+        };
+        assert_eq!(policy.evaluate(10, 9), TimeCheck::Agrees);
+        assert_eq!(policy.evaluate(-20, 5), TimeCheck::Recalibrate { to: 5, error: -25 });
+        assert_eq!(policy.evaluate(150, 5), TimeCheck::Implausible { error: 145 });
+    }
+
+    #[test]
+    fn test_handling_spike_is_false_launch() {
+        let mut detector = FlightEventDetector::new(DetectorConfig::default());
+        // Knocked at 4g for 400ms, long enough to look like ignition, then back at rest
+        let events = replay(&mut detector, [0.0, 0.0, 1.0], &[(2.0, 1.0), (0.4, 4.0), (20.0, 1.0)]);
+        assert_eq!(
+            events.iter().map(|e| e.event).collect::<Vec<_>>(),
+            [FlightEvent::Launch, FlightEvent::FalseLaunch]
+        );
+        assert_eq!(detector.launch_at(), None);
+
+        // Set down hard: a short boost then a drop below the burnout threshold
+        let mut detector = FlightEventDetector::new(DetectorConfig::default());
+        let events = replay(&mut detector, [0.0, 0.0, 1.0], &[(2.0, 1.0), (0.4, 4.0), (0.5, 0.0), (5.0, 1.0)]);
+        assert_eq!(events.last().map(|e| e.event), Some(FlightEvent::FalseLaunch), "{events:?}");
+        assert_eq!(detector.launch_at(), None);
+        assert_eq!(detector.vertical_velocity(), 0.0);
+    }
+
+    #[test]
+    fn test_handling_spike_leaves_clock_alone() {
+        let _clock = timing::lock_clock();
+        let policy = TimeCheckPolicy {
+            tolerance: 2,
+            max_correction: 180,
+        };
+        timing::calibrate_to(-100);
+        let offset = timing::calibration_offset();
+
+        // Handled while TE1 is up, so only the detector stands in the way
+        let mut detector = FlightEventDetector::new(DetectorConfig::default());
+        let events = replay(&mut detector, [0.0, 0.0, 1.0], &[(2.0, 1.0), (0.4, 4.0), (20.0, 1.0)]);
+        for event in &events {
+            let check = policy.correct_clock(event, &detector, Duration::from_secs(23), JupiterPhase::PowerOn, PinState::High);
+            assert_eq!(check, None);
+        }
+        assert_eq!(timing::calibration_offset(), offset);
+
+        // A real flight still can't move it while nothing else says it's flying
+        let mut detector = FlightEventDetector::new(DetectorConfig::default());
+        let events = replay(&mut detector, [0.0, 0.0, 1.0], &[(2.0, 1.0), (3.0, 10.0), (1.0, -0.2)]);
+        let burnout = events.iter().find(|e| e.event == FlightEvent::Burnout).unwrap();
+        let now = Duration::from_secs(6);
+        assert_eq!(policy.correct_clock(burnout, &detector, now, JupiterPhase::PowerOn, PinState::Low), None);
+        assert_eq!(timing::calibration_offset(), offset);
+
+        // With TE1 up it does, onto the launch
+        assert!(matches!(
+            policy.correct_clock(burnout, &detector, now, JupiterPhase::PowerOn, PinState::High),
+            Some(TimeCheck::Recalibrate { to: 4, .. })
+        ));
+        // Give or take a second ticking over
+        assert!((4..=5).contains(&timing::t_time_estimate()));
+    }
+}
@@ -0,0 +1,96 @@
+#![warn(missing_docs)]
+
+//! Combining the low-g and high-g accelerometers into one reading that keeps the low-g's
+//! resolution until it saturates, then hands each axis over to the high-g
+
+/// Share of full scale past which a low-g axis is treated as saturated. Readings clip a little
+/// before the nominal range and the filters ring near it.
+pub const SATURATION_FRACTION: f32 = 0.9;
+
+/// Which sensor an axis of a fused reading came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AxisSource {
+    /// The low-g accelerometer, in range
+    LowG,
+    /// The high-g accelerometer, because the low-g was saturated or missing
+    HighG,
+}
+
+/// One fused acceleration, in g
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FusedAccel {
+    /// Acceleration per axis
+    pub vector: [f32; 3],
+    /// Where each axis came from
+    pub sources: [AxisSource; 3],
+}
+
+impl FusedAccel {
+    /// Whether any axis needed the high-g
+    pub fn saturated(&self) -> bool {
+        self.sources.contains(&AxisSource::HighG)
+    }
+}
+
+/// Fuse a low-g reading with full scale `low_range_g` and a high-g reading, per axis. Either can
+/// be missing; with neither there's nothing to fuse.
+pub fn fuse(low: Option<[f32; 3]>, low_range_g: f32, high: Option<[f32; 3]>) -> Option<FusedAccel> {
+    let limit = low_range_g * SATURATION_FRACTION;
+
+    match (low, high) {
+        (None, None) => None,
+        (Some(low), None) => Some(FusedAccel {
+            vector: low,
+            sources: [AxisSource::LowG; 3],
+        }),
+        (None, Some(high)) => Some(FusedAccel {
+            vector: high,
+            sources: [AxisSource::HighG; 3],
+        }),
+        (Some(low), Some(high)) => {
+            let mut fused = FusedAccel {
+                vector: low,
+                sources: [AxisSource::LowG; 3],
+            };
+            for axis in 0..3 {
+                if low[axis].abs() >= limit {
+                    fused.vector[axis] = high[axis];
+                    fused.sources[axis] = AxisSource::HighG;
+                }
+            }
+            Some(fused)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_range_uses_low_g() {
+        let fused = fuse(Some([0.1, -0.2, 1.0]), 16.0, Some([0.0, 0.0, 1.2])).unwrap();
+        assert_eq!(fused.vector, [0.1, -0.2, 1.0]);
+        assert!(!fused.saturated());
+    }
+
+    #[test]
+    fn test_boost_hands_over_per_axis() {
+        // Boost along z clips the low-g at 16g, the other axes stay fine
+        let fused = fuse(Some([0.3, 0.1, 15.9]), 16.0, Some([0.0, 0.0, 38.5])).unwrap();
+        assert_eq!(fused.vector, [0.3, 0.1, 38.5]);
+        assert_eq!(fused.sources, [AxisSource::LowG, AxisSource::LowG, AxisSource::HighG]);
+        assert!(fused.saturated());
+
+        // Clipped negative too
+        let fused = fuse(Some([-16.0, 0.0, 0.0]), 16.0, Some([-20.0, 0.0, 0.0])).unwrap();
+        assert_eq!(fused.vector[0], -20.0);
+    }
+
+    #[test]
+    fn test_missing_sensors() {
+        assert_eq!(fuse(None, 16.0, None), None);
+        assert_eq!(fuse(None, 16.0, Some([1.0, 2.0, 3.0])).unwrap().sources, [AxisSource::HighG; 3]);
+        assert_eq!(fuse(Some([1.0, 2.0, 3.0]), 16.0, None).unwrap().vector, [1.0, 2.0, 3.0]);
+    }
+}
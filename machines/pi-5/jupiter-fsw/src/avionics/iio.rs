@@ -0,0 +1,267 @@
+#![warn(missing_docs)]
+
+//! IMUs the kernel already drives, read through sysfs under `/sys/bus/iio/devices`
+
+use std::fs::{self, File, read_dir};
+use std::io;
+use std::io::Read;
+use std::num::ParseIntError;
+use std::path::{Path, PathBuf};
+use std::string::FromUtf8Error;
+use std::fmt;
+
+/// Standard gravity, IIO reports acceleration in m/s^2
+const STANDARD_GRAVITY: f32 = 9.80665;
+
+/// Full scale of the 16-bit raw readings, in counts
+const FULL_SCALE_COUNTS: f32 = 32768.0;
+
+#[derive(Debug)]
+#[allow(dead_code)]
+pub enum Error {
+    Io(io::Error),
+    SensorNotFound(String),
+    Parse(ParseIntError),
+    Utf(FromUtf8Error),
+    /// A value sysfs gave back that isn't a number
+    BadValue(String),
+}
+
+impl From<io::Error> for Error {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<std::string::FromUtf8Error> for Error {
+    fn from(value: std::string::FromUtf8Error) -> Self {
+        Self::Utf(value)
+    }
+}
+
+impl From<ParseIntError> for Error {
+    fn from(value: ParseIntError) -> Self {
+        Self::Parse(value)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "IO error: {}", err),
+            Error::SensorNotFound(sensor) => write!(f, "Sensor not found: {}", sensor),
+            Error::Parse(err) => write!(f, "Integer parsing error: {}", err),
+            Error::Utf(err) => write!(f, "UTF-8 conversion error: {}", err),
+            Error::BadValue(value) => write!(f, "Not a number: {}", value),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::SensorNotFound(_) => None,
+            Error::Parse(err) => Some(err),
+            Error::Utf(err) => Some(err),
+            Error::BadValue(_) => None,
+        }
+    }
+}
+
+/// Return the iio device directory for a given name, if it exists
+pub(super) fn iio_device_directory(sensor_name: &str) -> Result<PathBuf, Error> {
+    match read_dir("/sys/bus/iio/devices/")?
+        .filter_map(|x| x.ok())
+        .map(|x| x.path())
+        .map(|mut x| {
+            x.push("name");
+            x
+        })
+        .filter_map(|name_path| {
+            if let Ok(file) = File::open(&name_path) {
+                Some((file, name_path))
+            } else {
+                None
+            }
+        })
+        .find_map(|mut pair| {
+            let mut buffer = Vec::new();
+            pair.0.read_to_end(&mut buffer).ok();
+            if String::from_utf8_lossy(&buffer).trim() == sensor_name {
+                Some(pair.1.parent().unwrap().to_owned())
+            } else {
+                None
+            }
+        }) {
+        Some(val) => Ok(val),
+        _ => Err(Error::SensorNotFound(sensor_name.into())),
+    }
+}
+
+fn read_value(path: &Path) -> Result<String, Error> {
+    let mut buffer = Vec::new();
+    File::open(path)?.read_to_end(&mut buffer)?;
+    Ok(String::from_utf8(buffer)?.trim().to_string())
+}
+
+fn read_f32(path: &Path) -> Result<f32, Error> {
+    let value = read_value(path)?;
+    value.parse().map_err(|_| Error::BadValue(value))
+}
+
+/// An accelerometer, and gyro if it has one, under one IIO device directory. Accelerations come
+/// back in g and rates in degrees per second.
+pub struct IioDevice {
+    dir: PathBuf,
+    accel_scale: Option<f32>,
+    gyro_scale: Option<f32>,
+}
+
+impl IioDevice {
+    /// The IIO device the kernel calls `name`, e.g. `lsm6dsl_accel`
+    pub fn find(name: &str) -> Result<Self, Error> {
+        Self::at(iio_device_directory(name)?)
+    }
+
+    /// The IIO device in `dir`
+    pub fn at<P: AsRef<Path>>(dir: P) -> Result<Self, Error> {
+        let dir = dir.as_ref().to_path_buf();
+        if !dir.join("in_accel_x_raw").exists() {
+            return Err(Error::SensorNotFound(format!("accelerometer in {}", dir.display())));
+        }
+
+        let mut device = Self {
+            dir,
+            accel_scale: None,
+            gyro_scale: None,
+        };
+        device.reload_scales();
+        Ok(device)
+    }
+
+    /// Whether the device has a gyro channel
+    pub fn has_gyro(&self) -> bool {
+        self.dir.join("in_anglvel_x_raw").exists()
+    }
+
+    // Scales are per LSB: m/s^2 for acceleration, rad/s for rate
+    fn reload_scales(&mut self) {
+        self.accel_scale = read_f32(&self.dir.join("in_accel_scale")).ok();
+        self.gyro_scale = read_f32(&self.dir.join("in_anglvel_scale")).ok();
+    }
+
+    fn read_axes(&self, channel: &str, scale: f32) -> Result<[f32; 3], Error> {
+        let mut axes = [0.0; 3];
+        for (axis, name) in axes.iter_mut().zip(["x", "y", "z"]) {
+            let raw = read_value(&self.dir.join(format!("in_{channel}_{name}_raw")))?.parse::<i32>()?;
+            *axis = raw as f32 * scale;
+        }
+        Ok(axes)
+    }
+
+    /// Acceleration in g
+    pub fn read_accel(&self) -> Result<[f32; 3], Error> {
+        // The LSM6DSL at its boot range, for drivers that don't publish a scale
+        let scale = self.accel_scale.unwrap_or(0.061e-3 * STANDARD_GRAVITY);
+        self.read_axes("accel", scale / STANDARD_GRAVITY)
+    }
+
+    /// Rate in degrees per second, if there's a gyro
+    pub fn read_gyro(&self) -> Result<Option<[f32; 3]>, Error> {
+        match self.gyro_scale {
+            Some(scale) if self.has_gyro() => Ok(Some(self.read_axes("anglvel", scale.to_degrees())?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Full scale of the accelerometer, in g
+    pub fn accel_range_g(&self) -> Option<f32> {
+        self.accel_scale.map(|scale| scale * FULL_SCALE_COUNTS / STANDARD_GRAVITY)
+    }
+
+    /// Pick the smallest accelerometer range that covers `range_g`, or the largest there is
+    pub fn set_accel_range(&mut self, range_g: f32) -> Result<f32, Error> {
+        let available = read_value(&self.dir.join("in_accel_scale_available"))?;
+        let mut scales: Vec<f32> = available.split_whitespace().filter_map(|s| s.parse().ok()).collect();
+        scales.sort_by(f32::total_cmp);
+
+        let wanted = range_g * STANDARD_GRAVITY / FULL_SCALE_COUNTS;
+        let Some(&scale) = scales.iter().find(|&&s| s >= wanted).or(scales.last()) else {
+            return Err(Error::BadValue(available));
+        };
+
+        fs::write(self.dir.join("in_accel_scale"), format!("{scale}"))?;
+        self.reload_scales();
+        Ok(self.accel_range_g().unwrap_or(scale * FULL_SCALE_COUNTS / STANDARD_GRAVITY))
+    }
+
+    /// Set the output data rate, in Hz
+    pub fn set_sampling_frequency(&mut self, hz: f32) -> Result<(), Error> {
+        let path = ["in_accel_sampling_frequency", "sampling_frequency"]
+            .iter()
+            .map(|name| self.dir.join(name))
+            .find(|path| path.exists())
+            .ok_or_else(|| Error::SensorNotFound(format!("sampling frequency in {}", self.dir.display())))?;
+        fs::write(path, format!("{hz}"))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake_device(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("jupiter_iio_{}_{name}", std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        for (file, value) in [
+            ("in_accel_x_raw", "100"),
+            ("in_accel_y_raw", "-200"),
+            ("in_accel_z_raw", "16393"),
+            // 0.061mg per LSB, the LSM6DSL at 2g
+            ("in_accel_scale", "0.000598"),
+            ("in_accel_scale_available", "0.000598 0.001196 0.002392 0.004785"),
+            ("in_accel_sampling_frequency", "104"),
+            ("in_anglvel_x_raw", "0"),
+            ("in_anglvel_y_raw", "0"),
+            ("in_anglvel_z_raw", "114"),
+            // 70mdps per LSB
+            ("in_anglvel_scale", "0.001221730"),
+        ] {
+            fs::write(dir.join(file), format!("{value}\n")).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_reads_in_g_and_dps() {
+        let device = IioDevice::at(fake_device("read")).unwrap();
+        let accel = device.read_accel().unwrap();
+        assert!((accel[2] - 1.0).abs() < 0.01, "{accel:?}");
+        assert!((accel[1] + 0.0122).abs() < 0.001, "{accel:?}");
+
+        let gyro = device.read_gyro().unwrap().unwrap();
+        assert!((gyro[2] - 7.98).abs() < 0.01, "{gyro:?}");
+        assert!((device.accel_range_g().unwrap() - 2.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_configure() {
+        let dir = fake_device("configure");
+        let mut device = IioDevice::at(&dir).unwrap();
+
+        let range = device.set_accel_range(6.0).unwrap();
+        assert!((range - 8.0).abs() < 0.05, "{range}");
+        assert_eq!(read_value(&dir.join("in_accel_scale")).unwrap(), "0.002392");
+
+        // Past the biggest there is, take the biggest
+        assert!((device.set_accel_range(100.0).unwrap() - 16.0).abs() < 0.05);
+
+        device.set_sampling_frequency(416.0).unwrap();
+        assert_eq!(read_value(&dir.join("in_accel_sampling_frequency")).unwrap(), "416");
+
+        assert!(IioDevice::at(dir.join("missing")).is_err());
+    }
+}
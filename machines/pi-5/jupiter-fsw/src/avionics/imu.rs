@@ -1,18 +1,50 @@
+use std::sync::mpsc::{Receiver, TrySendError, sync_channel};
+use std::thread;
+use std::time::{Duration, Instant};
+
 use linux_embedded_hal::{I2cdev, I2CError, Delay};
-use adxl345_driver2::{i2c::Device, AdxlError, Adxl345Reader};
+use adxl345_driver2::{i2c::Device, AdxlError, Adxl345Reader, Adxl345Writer};
 use bmi323::{Bmi323, AccelConfig, GyroConfig, OutputDataRate, AccelerometerRange, GyroscopeRange, interface::I2cInterface,
      Error as BmiError};
-use adxl345_driver2::i2c::Device as AdxlDevice;
 use bin_packets::packets::ApplicationPacket;
-use log::{error, info};
+use log::{error, info, warn};
+
+use super::fusion::{self, FusedAccel};
+use super::iio::{self, IioDevice};
 
 const SCALE_MULTIPLIER: f32 = 0.049;
 
+/// The ADXL375 has one range
+const ADXL375_RANGE_G: f32 = 200.0;
+
+const BMI323_ADDRESS: u8 = 0x68;
+
+/// BMI323 at its widest so it covers as much of boost as it can, at a rate that still catches
+/// the burnout transient
+pub const LOW_G_CONFIG: ImuConfig = ImuConfig {
+    accel_range_g: 16.0,
+    gyro_range_dps: 2000.0,
+    odr_hz: 200.0,
+};
+
+/// ADXL375, range is fixed
+pub const HIGH_G_CONFIG: ImuConfig = ImuConfig {
+    accel_range_g: ADXL375_RANGE_G,
+    gyro_range_dps: 0.0,
+    odr_hz: 200.0,
+};
+
+// Samples the main loop can fall behind by before new ones are dropped, a couple of seconds
+const SAMPLE_QUEUE: usize = 512;
+
 #[derive(Debug)]
 pub enum IMUError {
     BusFailed(I2CError),
     SensorFailed(AdxlError),
     BMIFail(BmiError<I2CError>),
+    Iio(iio::Error),
+    /// None of the sensors came up
+    NoSensors,
 }
 
 impl From<I2CError> for IMUError {
@@ -33,112 +65,391 @@ impl From<BmiError<I2CError>> for IMUError {
     }
 }
 
-pub struct HighGAccel {
+impl From<iio::Error> for IMUError {
+    fn from(err: iio::Error) -> Self {
+        IMUError::Iio(err)
+    }
+}
+
+/// Range and output data rate to ask a sensor for. Each takes the nearest it supports at or above
+/// what's asked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImuConfig {
+    /// Accelerometer full scale, in g
+    pub accel_range_g: f32,
+    /// Gyro full scale, in degrees per second
+    pub gyro_range_dps: f32,
+    /// Output data rate, in Hz
+    pub odr_hz: f32,
+}
+
+/// One read of a sensor, acceleration in g and rate in degrees per second
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ImuReading {
+    pub accel: Option<[f32; 3]>,
+    pub gyro: Option<[f32; 3]>,
+}
+
+/// What a sensor is flown for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImuRole {
+    /// Fine resolution, saturates in boost. Its gyro is the one logged.
+    LowG,
+    /// Coarse, covers boost
+    HighG,
+}
+
+/// An IMU, whatever bus it's on
+pub trait Imu: Send {
+    /// For the logs
+    fn name(&self) -> &str;
+
+    /// Set range and rate
+    fn configure(&mut self, config: &ImuConfig) -> Result<(), IMUError>;
+
+    /// Accelerometer full scale in use, in g
+    fn accel_range_g(&self) -> f32;
+
+    /// Read whatever the sensor has
+    fn read(&mut self) -> Result<ImuReading, IMUError>;
+}
+
+/// Bosch BMI323 accelerometer and gyro over I2C
+pub struct Bmi323Imu {
+    bmi: Bmi323<I2cInterface<I2cdev>, Delay>,
+    range_g: f32,
+}
+
+impl Bmi323Imu {
+    pub fn new(bus: &str, address: u8) -> Result<Self, IMUError> {
+        let i2c = I2cdev::new(bus).map_err(I2CError::from)?;
+        let mut bmi = Bmi323::new_with_i2c(i2c, address, Delay);
+        bmi.init()?;
+        Ok(Self { bmi, range_g: 8.0 })
+    }
+}
+
+fn bmi_odr(hz: f32) -> OutputDataRate {
+    match hz {
+        hz if hz <= 50.0 => OutputDataRate::Odr50hz,
+        hz if hz <= 100.0 => OutputDataRate::Odr100hz,
+        hz if hz <= 200.0 => OutputDataRate::Odr200hz,
+        hz if hz <= 400.0 => OutputDataRate::Odr400hz,
+        hz if hz <= 800.0 => OutputDataRate::Odr800hz,
+        _ => OutputDataRate::Odr1600hz,
+    }
+}
+
+fn bmi_accel_range(g: f32) -> (AccelerometerRange, f32) {
+    match g {
+        g if g <= 2.0 => (AccelerometerRange::G2, 2.0),
+        g if g <= 4.0 => (AccelerometerRange::G4, 4.0),
+        g if g <= 8.0 => (AccelerometerRange::G8, 8.0),
+        _ => (AccelerometerRange::G16, 16.0),
+    }
+}
+
+fn bmi_gyro_range(dps: f32) -> GyroscopeRange {
+    match dps {
+        dps if dps <= 125.0 => GyroscopeRange::DPS125,
+        dps if dps <= 250.0 => GyroscopeRange::DPS250,
+        dps if dps <= 500.0 => GyroscopeRange::DPS500,
+        dps if dps <= 1000.0 => GyroscopeRange::DPS1000,
+        _ => GyroscopeRange::DPS2000,
+    }
+}
+
+impl Imu for Bmi323Imu {
+    fn name(&self) -> &str {
+        "BMI323"
+    }
+
+    fn configure(&mut self, config: &ImuConfig) -> Result<(), IMUError> {
+        let (range, range_g) = bmi_accel_range(config.accel_range_g);
+        let accel_config = AccelConfig::builder()
+            .odr(bmi_odr(config.odr_hz))
+            .range(range)
+            .build();
+        self.bmi.set_accel_config(accel_config)?;
+        self.range_g = range_g;
+
+        let gyro_config = GyroConfig::builder()
+            .odr(bmi_odr(config.odr_hz))
+            .range(bmi_gyro_range(config.gyro_range_dps))
+            .build();
+        self.bmi.set_gyro_config(gyro_config)?;
+        Ok(())
+    }
+
+    fn accel_range_g(&self) -> f32 {
+        self.range_g
+    }
+
+    fn read(&mut self) -> Result<ImuReading, IMUError> {
+        let accel = self.bmi.read_accel_data_scaled()?;
+        let gyro = self.bmi.read_gyro_data_scaled().ok();
+        Ok(ImuReading {
+            accel: Some([accel.x, accel.y, accel.z]),
+            gyro: gyro.map(|g| [g.x, g.y, g.z]),
+        })
+    }
+}
+
+/// Analog Devices ADXL375 high-g accelerometer over I2C
+pub struct Adxl375 {
     sensor: Device<I2cdev>,
 }
 
-impl HighGAccel {
-    pub fn new(device: Device<I2cdev>) -> Self  {
-        Self { sensor: device }
+impl Adxl375 {
+    pub fn new(bus: &str) -> Result<Self, IMUError> {
+        let i2c = I2cdev::new(bus).map_err(I2CError::from)?;
+        Ok(Self { sensor: Device::new(i2c)? })
     }
+}
+
+// BW_RATE codes, each doubling from 0.1Hz at 0x00 to 3200Hz at 0x0F
+fn adxl_rate_code(hz: f32) -> u8 {
+    (0x00u8..=0x0F)
+        .find(|&code| 3200.0 / 2f32.powi(15 - code as i32) >= hz)
+        .unwrap_or(0x0F)
+}
 
-    pub fn read_data(&mut self) -> Result<[f32; 3], IMUError> {
+impl Imu for Adxl375 {
+    fn name(&self) -> &str {
+        "ADXL375"
+    }
+
+    fn configure(&mut self, config: &ImuConfig) -> Result<(), IMUError> {
+        self.sensor.set_bandwidth_rate(adxl_rate_code(config.odr_hz))?;
+        // Measure mode
+        self.sensor.set_power_control(0x08)?;
+        Ok(())
+    }
+
+    fn accel_range_g(&self) -> f32 {
+        ADXL375_RANGE_G
+    }
+
+    fn read(&mut self) -> Result<ImuReading, IMUError> {
         let (raw_x, raw_y, raw_z) = self.sensor.acceleration()?;
-        Ok([
-            raw_x as f32 * SCALE_MULTIPLIER,
-            raw_y as f32 * SCALE_MULTIPLIER,
-            raw_z as f32 * SCALE_MULTIPLIER,
-        ])
+        Ok(ImuReading {
+            accel: Some([
+                raw_x as f32 * SCALE_MULTIPLIER,
+                raw_y as f32 * SCALE_MULTIPLIER,
+                raw_z as f32 * SCALE_MULTIPLIER,
+            ]),
+            gyro: None,
+        })
     }
 }
 
+/// An IMU the kernel drives, e.g. the LSM6DSL
+pub struct IioImu {
+    name: String,
+    device: IioDevice,
+}
+
+impl IioImu {
+    pub fn find(name: &str) -> Result<Self, IMUError> {
+        Ok(Self {
+            name: name.to_string(),
+            device: IioDevice::find(name)?,
+        })
+    }
+}
+
+impl Imu for IioImu {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn configure(&mut self, config: &ImuConfig) -> Result<(), IMUError> {
+        self.device.set_accel_range(config.accel_range_g)?;
+        self.device.set_sampling_frequency(config.odr_hz)?;
+        Ok(())
+    }
+
+    fn accel_range_g(&self) -> f32 {
+        self.device.accel_range_g().unwrap_or(2.0)
+    }
+
+    fn read(&mut self) -> Result<ImuReading, IMUError> {
+        Ok(ImuReading {
+            accel: Some(self.device.read_accel()?),
+            gyro: self.device.read_gyro()?,
+        })
+    }
+}
+
+struct Sensor {
+    role: ImuRole,
+    imu: Box<dyn Imu>,
+    odr_hz: f32,
+}
+
 pub struct AvionicsImuManager {
-    bmi: Bmi323<I2cInterface<I2cdev>, Delay>,
-    // high_g: HighGAccel,
+    sensors: Vec<Sensor>,
 }
 
 
 #[derive(Default)]
 pub struct IMU_Results {
-    // pub high_range: Option<ApplicationPacket>,
+    pub high_range: Option<ApplicationPacket>,
     pub low_range: Option<ApplicationPacket>,
     pub gyro: Option<ApplicationPacket>,
+    /// Low-g and high-g combined, what flight logic should look at
+    pub fused: Option<FusedAccel>,
+    /// When the sensors were read, since startup
+    pub elapsed: Duration,
+}
+
+impl IMU_Results {
+    /// Every packet read, for logging
+    pub fn packets(&self) -> impl Iterator<Item = ApplicationPacket> {
+        [self.low_range, self.high_range, self.gyro].into_iter().flatten()
+    }
 }
 
 impl AvionicsImuManager {
+    /// The JUPITER IMUs: BMI323 low-g and ADXL375 high-g on I2C 1. Either can be missing, not
+    /// both.
     pub fn new() -> Result<Self, IMUError> {
-        let i2c_bmi = I2cdev::new("/dev/i2c-1").map_err(I2CError::from)?;
-        // let i2c_adxl = I2cdev::new("/dev/i2c-1").map_err(I2CError::from)?;
+        let mut manager = Self { sensors: Vec::new() };
+        let mut last_error = None;
 
-        // let adxl_device = AdxlDevice::new(i2c_adxl)?;
-        // let high_g = HighGAccel::new(adxl_device);
+        match Bmi323Imu::new("/dev/i2c-1", BMI323_ADDRESS) {
+            Ok(bmi) => manager.add(ImuRole::LowG, Box::new(bmi), LOW_G_CONFIG),
+            Err(e) => {
+                error!("BMI323 not found: {e:?}");
+                last_error = Some(e);
+            }
+        }
 
-        let delay = Delay; 
-        let mut bmi = Bmi323::new_with_i2c(i2c_bmi, 0x68, delay);
-        bmi.init()?; 
+        match Adxl375::new("/dev/i2c-1") {
+            Ok(adxl) => manager.add(ImuRole::HighG, Box::new(adxl), HIGH_G_CONFIG),
+            Err(e) => {
+                error!("ADXL375 not found: {e:?}");
+                last_error = Some(e);
+            }
+        }
 
-        let accel_config = AccelConfig::builder()
-            .odr(OutputDataRate::Odr100hz)
-            .range(AccelerometerRange::G8)
-            .build();
-        bmi.set_accel_config(accel_config)?;
+        if manager.sensors.is_empty() {
+            return Err(last_error.unwrap_or(IMUError::NoSensors));
+        }
+        Ok(manager)
+    }
 
-        let gyro_config = GyroConfig::builder()
-            .odr(OutputDataRate::Odr100hz)
-            .range(GyroscopeRange::DPS2000)
-            .build();
-        bmi.set_gyro_config(gyro_config)?;
+    /// No sensors yet, see [`AvionicsImuManager::add`]
+    pub fn empty() -> Self {
+        Self { sensors: Vec::new() }
+    }
 
-        Ok(Self { bmi
-            // , high_g 
-        })
+    /// Configure a sensor and read it from now on. One that won't take its configuration is read
+    /// as it is.
+    pub fn add(&mut self, role: ImuRole, mut imu: Box<dyn Imu>, config: ImuConfig) {
+        match imu.configure(&config) {
+            Ok(()) => info!("{} configured: {:?}, range {}g", imu.name(), config, imu.accel_range_g()),
+            Err(e) => warn!("{} running unconfigured: {e:?}", imu.name()),
+        }
+        self.sensors.push(Sensor {
+            role,
+            imu,
+            odr_hz: config.odr_hz,
+        });
+    }
+
+    /// Fastest output data rate of any sensor
+    pub fn sample_rate_hz(&self) -> f32 {
+        self.sensors.iter().map(|s| s.odr_hz).fold(0.0, f32::max)
     }
 
     pub fn read_all(&mut self, startup: std::time::Instant) -> IMU_Results {
-        
-        let mut results = IMU_Results::default();
-
-        let timestamp_ms: u64 = std::time::Instant::now()
-                            .duration_since(startup)
-                            .as_millis() as u64;
-        
-        // if let Ok(adxl_data) = self.high_g.read_data() {
-        //     info!("High-G (ADXL375): x={}, y={}, z={}", adxl_data[0], adxl_data[1], adxl_data[2]);
-            
-        //     results.high_range = Some(ApplicationPacket::JupiterAccelerometer {
-        //         timestamp_ms,
-        //         vector: adxl_data,
-        //     });
-        // } else {
-        //     error!("Failed to read High-G ADXL375");
-        // }
-
-        if let Ok(bmi_accel) = self.bmi.read_accel_data_scaled() {
-            // info!("Low-G (BMI323): x={}, y={}, z={}", bmi_accel.x, bmi_accel.y, bmi_accel.z);
-            
-            results.low_range = Some(ApplicationPacket::AccelerometerData { 
-                timestamp: timestamp_ms, 
-                x: bmi_accel.x, 
-                y: bmi_accel.y, 
-                z: bmi_accel.z
-            });
-        } else {
-            // error!("Failed to read Low-G BMI323 Accelerometer");
-        }
 
-        if let Ok(bmi_gyro) = self.bmi.read_gyro_data_scaled() {
-            // info!("Gyro (BMI323): x={}, y={}, z={}", bmi_gyro.x, bmi_gyro.y, bmi_gyro.z);
-            
-            results.gyro = Some(ApplicationPacket::GyroscopeData { 
-                timestamp: timestamp_ms, 
-                x: bmi_gyro.x, 
-                y: bmi_gyro.y, 
-                z: bmi_gyro.z
-            });
-        } else {
-            // error!("Failed to read BMI323 Gyroscope");
+        let mut results = IMU_Results {
+            elapsed: startup.elapsed(),
+            ..Default::default()
+        };
+
+        let timestamp_ms: u64 = results.elapsed.as_millis() as u64;
+
+        let mut low = None;
+        let mut low_range_g = 0.0;
+        let mut high = None;
+
+        for sensor in &mut self.sensors {
+            // Read failures are left to the heartbeat, at this rate logging each would flood
+            let Ok(reading) = sensor.imu.read() else {
+                continue;
+            };
+
+            match sensor.role {
+                ImuRole::LowG => {
+                    if let Some(accel) = reading.accel && low.is_none() {
+                        low = Some(accel);
+                        low_range_g = sensor.imu.accel_range_g();
+                        results.low_range = Some(ApplicationPacket::AccelerometerData {
+                            timestamp: timestamp_ms,
+                            x: accel[0],
+                            y: accel[1],
+                            z: accel[2],
+                        });
+                    }
+                    if let Some(gyro) = reading.gyro && results.gyro.is_none() {
+                        results.gyro = Some(ApplicationPacket::GyroscopeData {
+                            timestamp: timestamp_ms,
+                            x: gyro[0],
+                            y: gyro[1],
+                            z: gyro[2],
+                        });
+                    }
+                }
+                ImuRole::HighG => {
+                    if let Some(accel) = reading.accel && high.is_none() {
+                        high = Some(accel);
+                        results.high_range = Some(ApplicationPacket::JupiterAccelerometer {
+                            timestamp_ms,
+                            vector: accel,
+                        });
+                    }
+                }
+            }
         }
 
+        results.fused = fusion::fuse(low, low_range_g, high);
         results
     }
-}
\ No newline at end of file
+
+    /// Read at the sensors' rate on a thread of its own, so logging keeps every sample however
+    /// slow the main loop runs
+    pub fn spawn(mut self, startup: Instant) -> Receiver<IMU_Results> {
+        let (tx, rx) = sync_channel(SAMPLE_QUEUE);
+        let period = Duration::from_secs_f32(1.0 / self.sample_rate_hz().max(1.0));
+
+        thread::spawn(move || {
+            let mut next = Instant::now();
+            let mut dropped: u64 = 0;
+            loop {
+                match tx.try_send(self.read_all(startup)) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(_)) => {
+                        dropped += 1;
+                        if dropped.is_power_of_two() {
+                            warn!("Main loop behind, {dropped} new IMU samples dropped");
+                        }
+                    }
+                    Err(TrySendError::Disconnected(_)) => return,
+                }
+
+                next += period;
+                let now = Instant::now();
+                if next > now {
+                    thread::sleep(next - now);
+                } else {
+                    next = now;
+                }
+            }
+        });
+
+        rx
+    }
+}
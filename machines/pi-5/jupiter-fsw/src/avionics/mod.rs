@@ -1,91 +1,8 @@
 #![warn(missing_docs)]
 
-use std::fs::{File, read_dir};
-use std::io;
-use std::io::Read;
-use std::num::ParseIntError;
-use std::path::PathBuf;
-use std::string::FromUtf8Error;
-use std::fmt;
-
-pub mod lsm6dsl;
+pub mod flight_events;
+pub mod fusion;
+pub mod iio;
 pub mod imu;
 
-#[derive(Debug)]
-#[allow(dead_code)]
-pub enum Error {
-    Io(io::Error),
-    SensorNotFound(String),
-    Parse(ParseIntError),
-    Utf(FromUtf8Error),
-}
-
-impl From<io::Error> for Error {
-    fn from(value: io::Error) -> Self {
-        Self::Io(value)
-    }
-}
-
-impl From<std::string::FromUtf8Error> for Error {
-    fn from(value: std::string::FromUtf8Error) -> Self {
-        Self::Utf(value)
-    }
-}
-
-impl From<ParseIntError> for Error {
-    fn from(value: ParseIntError) -> Self {
-        Self::Parse(value)
-    }
-}
-
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Error::Io(err) => write!(f, "IO error: {}", err),
-            Error::SensorNotFound(sensor) => write!(f, "Sensor not found: {}", sensor),
-            Error::Parse(err) => write!(f, "Integer parsing error: {}", err),
-            Error::Utf(err) => write!(f, "UTF-8 conversion error: {}", err),
-        }
-    }
-}
-
-impl std::error::Error for Error {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        match self {
-            Error::Io(err) => Some(err),
-            Error::SensorNotFound(_) => None,
-            Error::Parse(err) => Some(err),
-            Error::Utf(err) => Some(err),
-        }
-    }
-}
-
-/// Return the iio device directory for a given name, if it exists
-pub(super) fn iio_device_directory(sensor_name: &str) -> Result<PathBuf, Error> {
-    match read_dir("/sys/bus/iio/devices/")?
-        .filter_map(|x| x.ok())
-        .map(|x| x.path())
-        .map(|mut x| {
-            x.push("name");
-            x
-        })
-        .filter_map(|name_path| {
-            if let Ok(file) = File::open(&name_path) {
-                Some((file, name_path))
-            } else {
-                None
-            }
-        })
-        .find_map(|mut pair| {
-            let mut buffer = Vec::new();
-            pair.0.read_to_end(&mut buffer).ok();
-            if String::from_utf8_lossy(&buffer).trim() == sensor_name {
-                Some(pair.1.parent().unwrap().to_owned())
-            } else {
-                None
-            }
-        }) {
-        Some(val) => Ok(val),
-        _ => Err(Error::SensorNotFound(sensor_name.into())),
-    }
-}
+pub use iio::Error;
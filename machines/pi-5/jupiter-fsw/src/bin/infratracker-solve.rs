@@ -0,0 +1,132 @@
+//! Batch-solve saved infratracker frames with the flight pipeline
+//!
+//! ```text
+//! infratracker-solve [--dark <file or dir>]... [--pixel-map <file>] <file or dir>...
+//! ```
+//!
+//! Writes a CSV row per frame to stdout and a summary to stderr. Darks are combined the same way
+//! the flight thread combines its boot darks; the saved `dark_frame.tiff` works on its own.
+
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use DarkAverager::{BadPixelThresholds, DarkEstimator, ImageAveragerFromBuffer, PixelMap};
+
+use jupiter_fsw::solve::SolvePipeline;
+use jupiter_fsw::solve::frames::{Frame, frames_in};
+use jupiter_fsw::solve::report::{CSV_HEADER, Summary, write_row};
+
+const USAGE: &str = "usage: infratracker-solve [--dark <file or dir>]... [--pixel-map <file>] <file or dir>...";
+
+struct Args {
+    frames: Vec<PathBuf>,
+    darks: Vec<PathBuf>,
+    pixel_map: Option<PathBuf>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        frames: Vec::new(),
+        darks: Vec::new(),
+        pixel_map: None,
+    };
+
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--dark" => args.darks.push(iter.next().ok_or("--dark needs a path")?.into()),
+            "--pixel-map" => args.pixel_map = Some(iter.next().ok_or("--pixel-map needs a path")?.into()),
+            "-h" | "--help" => return Err(String::new()),
+            flag if flag.starts_with("--") => return Err(format!("unknown option {flag}")),
+            path => args.frames.push(path.into()),
+        }
+    }
+
+    if args.frames.is_empty() {
+        return Err("no frames given".to_string());
+    }
+    Ok(args)
+}
+
+fn load_dark(paths: &[PathBuf], pixel_map: Option<&PathBuf>) -> Result<ImageAveragerFromBuffer, String> {
+    let frames = frames_in(paths).map_err(|e| format!("darks: {e}"))?;
+    let darks = frames
+        .iter()
+        .map(Frame::load)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("darks: {e}"))?;
+    eprintln!("Combining {} dark frames", darks.len());
+
+    let mut averager = ImageAveragerFromBuffer::new_with_options(darks, DarkEstimator::sigma_clipped(), &BadPixelThresholds::default())
+        .map_err(|e| format!("darks: {e}"))?;
+
+    if let Some(path) = pixel_map {
+        let map = PixelMap::load(path).map_err(|e| format!("pixel map: {e}"))?;
+        averager.merge_pixel_map(&map).map_err(|e| format!("pixel map: {e}"))?;
+    }
+    Ok(averager)
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            if !e.is_empty() {
+                eprintln!("{e}");
+            }
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut pipeline = SolvePipeline::new();
+    if !args.darks.is_empty() {
+        match load_dark(&args.darks, args.pixel_map.as_ref()) {
+            Ok(dark) => pipeline = pipeline.with_dark(dark),
+            Err(e) => {
+                eprintln!("{e}");
+                return ExitCode::FAILURE;
+            }
+        }
+    } else if args.pixel_map.is_some() {
+        eprintln!("--pixel-map only applies along with --dark");
+        return ExitCode::FAILURE;
+    }
+
+    let frames = match frames_in(&args.frames) {
+        Ok(frames) => frames,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut summary = Summary::default();
+
+    if writeln!(out, "{CSV_HEADER}").is_err() {
+        return ExitCode::FAILURE;
+    }
+    for frame in &frames {
+        let solution = pipeline.solve_frame(frame);
+        summary.add(&solution);
+        if write_row(&mut out, frame, &solution).is_err() {
+            // Output closed, e.g. piped into head
+            break;
+        }
+    }
+
+    eprintln!(
+        "Solved {}/{} frames ({:.0}%), mean {:.1} stars matched, mean RMS residual {:.1}\", mean {:.1}ms per frame, slowest {:.1}ms",
+        summary.solved,
+        summary.frames,
+        summary.solve_rate() * 100.0,
+        summary.mean_matched,
+        summary.mean_rms_arcsec,
+        summary.mean_total.as_secs_f64() * 1000.0,
+        summary.max_total.as_secs_f64() * 1000.0,
+    );
+    ExitCode::SUCCESS
+}
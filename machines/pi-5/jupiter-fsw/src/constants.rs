@@ -5,3 +5,15 @@
 // Pin numbers
 pub const RBF_PIN: &str = "GPIO17"; // G6
 pub const EJECTION_IND_PIN: &str = "GPIO12"; // G3
+
+/// Flight state checkpoint, rewritten on every state transition
+pub const CHECKPOINT_PATH: &str = "/home/terminus/flight_state.json";
+
+/// Indicator event log, rewritten whenever a line changes. Earlier boots' logs are kept beside it
+/// as `.1`, `.2` and so on, newest first.
+pub const INDICATOR_LOG_PATH: &str = "/home/terminus/indicator_events.bin";
+
+/// Catalog the `synthetic_camera` build renders star fields from, as `ra_deg,dec_deg,magnitude`
+/// lines
+#[cfg(feature = "synthetic_camera")]
+pub const SYNTHETIC_CATALOG_PATH: &str = "/home/terminus/star_catalog.csv";
@@ -0,0 +1,491 @@
+#![warn(missing_docs)]
+
+//! Numbering commands for the Ejector and making sure the ones that matter get there. Every
+//! command goes out with a sequence number, and the Ejector answers each copy it gets with an ACK
+//! or NACK carrying that number, the command as it read it, and the phase it ended up in. One
+//! command at a time can be sent reliably: it's repeated under the same number until a reply comes
+//! back or the attempts run out, so a late reply to an earlier copy still counts.
+//!
+//! Nothing on the UART is checksummed, and a lost byte can leave the rest of a packet decoding as
+//! some other command under the same number. Replies only count when the echoed command is the
+//! one that was sent.
+
+use std::time::Duration;
+
+use bin_packets::commands::{CommandPacket, CommandReply, NackReason};
+use bin_packets::packets::ApplicationPacket;
+use bin_packets::phases::EjectorPhase;
+
+/// How long to wait on a reply, and how many times to ask
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Wait this long after a copy goes out before sending another
+    pub timeout: Duration,
+    /// Copies sent before giving up, including the first
+    pub max_attempts: u32,
+}
+
+impl RetryPolicy {
+    /// The Ejector reads its UART every 10ms and replies within its 50ms downlink poll, and the
+    /// main loop reads replies every 100ms, so 300ms is three chances to see one. Five attempts
+    /// bounds the wait at 1.5s.
+    pub const EJECTOR: RetryPolicy = RetryPolicy {
+        timeout: Duration::from_millis(300),
+        max_attempts: 5,
+    };
+
+    /// Longest a command can take to be given up on
+    pub fn deadline(&self) -> Duration {
+        self.timeout * self.max_attempts
+    }
+}
+
+/// Where a reliably sent command has got to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// Still waiting on a reply
+    Pending,
+    /// Accepted, leaving the Ejector in `phase`
+    Acked(EjectorPhase),
+    /// Refused, the Ejector still in `phase`
+    Nacked {
+        /// What the Ejector gave as the reason
+        reason: NackReason,
+        /// Phase it stayed in
+        phase: EjectorPhase,
+    },
+    /// Every attempt went unanswered
+    TimedOut,
+}
+
+// The command being sent reliably
+#[derive(Debug, Clone, Copy)]
+struct Outstanding {
+    seq: u16,
+    command: CommandPacket,
+    attempts: u32,
+    sent_at: Option<Duration>,
+    delivery: Delivery,
+}
+
+/// Command numbering and retries for the Ejector link
+#[derive(Debug)]
+pub struct CommandLink {
+    policy: RetryPolicy,
+    next_seq: u16,
+    last_seq: Option<u16>,
+    outstanding: Option<Outstanding>,
+}
+
+impl CommandLink {
+    /// A link retrying by `policy`
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self {
+            policy,
+            next_seq: 0,
+            last_seq: None,
+            outstanding: None,
+        }
+    }
+
+    fn next_seq(&mut self) -> u16 {
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        self.last_seq = Some(seq);
+        seq
+    }
+
+    /// Number of the last command stamped or sent, if there's been one
+    pub fn last_seq(&self) -> Option<u16> {
+        self.last_seq
+    }
+
+    /// Number a command that's sent without waiting on the reply, like the status colors that go
+    /// out every second anyway
+    pub fn stamp(&mut self, command: CommandPacket) -> ApplicationPacket {
+        ApplicationPacket::Command {
+            seq: self.next_seq(),
+            command,
+        }
+    }
+
+    /// Start sending `command` reliably, abandoning any command still outstanding. The first copy
+    /// goes out on the next [`CommandLink::poll`]. Returns its sequence number.
+    pub fn send(&mut self, command: CommandPacket) -> u16 {
+        let seq = self.next_seq();
+        self.outstanding = Some(Outstanding {
+            seq,
+            command,
+            attempts: 0,
+            sent_at: None,
+            delivery: Delivery::Pending,
+        });
+        seq
+    }
+
+    /// The next copy of the outstanding command, if one is due. Gives up once the last attempt has
+    /// waited out its timeout.
+    pub fn poll(&mut self, now: Duration) -> Option<ApplicationPacket> {
+        let policy = self.policy;
+        let outstanding = self.outstanding.as_mut()?;
+        if outstanding.delivery != Delivery::Pending {
+            return None;
+        }
+        if let Some(sent_at) = outstanding.sent_at
+            && now.saturating_sub(sent_at) < policy.timeout
+        {
+            return None;
+        }
+
+        if outstanding.attempts >= policy.max_attempts {
+            outstanding.delivery = Delivery::TimedOut;
+            return None;
+        }
+        outstanding.attempts += 1;
+        outstanding.sent_at = Some(now);
+        Some(ApplicationPacket::Command {
+            seq: outstanding.seq,
+            command: outstanding.command,
+        })
+    }
+
+    /// Take a reply off the link. Returns whether it answered the outstanding command; replies to
+    /// stamped commands or to a garbled copy, and repeats for one already answered, change
+    /// nothing.
+    pub fn receive(
+        &mut self,
+        seq: u16,
+        command: CommandPacket,
+        reply: CommandReply,
+        phase: EjectorPhase,
+    ) -> bool {
+        let Some(outstanding) = self
+            .outstanding
+            .as_mut()
+            .filter(|o| o.seq == seq && o.command == command)
+        else {
+            return false;
+        };
+        if outstanding.delivery == Delivery::Pending {
+            outstanding.delivery = match reply {
+                CommandReply::Ack => Delivery::Acked(phase),
+                CommandReply::Nack(reason) => Delivery::Nacked { reason, phase },
+            };
+        }
+        true
+    }
+
+    /// How the command numbered `seq` is getting on, if it's the one being sent reliably
+    pub fn delivery(&self, seq: u16) -> Option<Delivery> {
+        self.outstanding
+            .filter(|o| o.seq == seq)
+            .map(|o| o.delivery)
+    }
+
+    /// Copies of the outstanding command sent so far
+    pub fn attempts(&self) -> u32 {
+        self.outstanding.map_or(0, |o| o.attempts)
+    }
+
+    /// Whether the last command sent reliably went unanswered
+    pub fn timed_out(&self) -> bool {
+        self.outstanding
+            .is_some_and(|o| o.delivery == Delivery::TimedOut)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::io::{Read, Write};
+    use std::rc::Rc;
+
+    use bin_packets::device::std::Device;
+    use bin_packets::device::{PacketReader, PacketWriter};
+
+    use super::*;
+
+    const EJECT: CommandPacket = CommandPacket::EjectorPhaseSet(EjectorPhase::Ejection);
+
+    // One direction of the UART, losing bytes as they're written
+    #[derive(Clone)]
+    struct Wire {
+        bytes: Rc<RefCell<VecDeque<u8>>>,
+        loss: Rc<RefCell<Loss>>,
+    }
+
+    // Which bytes get lost. A fixed seed keeps the runs repeatable.
+    struct Loss {
+        percent: u32,
+        state: u32,
+        lost: u32,
+    }
+
+    impl Loss {
+        fn drops(&mut self) -> bool {
+            self.state = self.state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let dropped = (self.state >> 16) % 100 < self.percent;
+            self.lost += dropped as u32;
+            dropped
+        }
+    }
+
+    impl Wire {
+        fn new(percent: u32, seed: u32) -> Self {
+            Self {
+                bytes: Rc::default(),
+                loss: Rc::new(RefCell::new(Loss {
+                    percent,
+                    state: seed,
+                    lost: 0,
+                })),
+            }
+        }
+
+        fn lost(&self) -> u32 {
+            self.loss.borrow().lost
+        }
+    }
+
+    // A board's end of the link, reading one wire and writing the other
+    struct End {
+        rx: Wire,
+        tx: Wire,
+    }
+
+    impl Read for End {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let mut bytes = self.rx.bytes.borrow_mut();
+            let n = buf.len().min(bytes.len());
+            for (slot, byte) in buf.iter_mut().zip(bytes.drain(..n)) {
+                *slot = byte;
+            }
+            Ok(n)
+        }
+    }
+
+    impl Write for End {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            for &byte in buf {
+                if !self.tx.loss.borrow_mut().drops() {
+                    self.tx.bytes.borrow_mut().push_back(byte);
+                }
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // The Ejector's side, answering every command it makes out the way rx_from_jupiter does
+    struct Ejector {
+        device: Device<End>,
+        phase: EjectorPhase,
+        refuse: bool,
+        received: u32,
+    }
+
+    impl Ejector {
+        fn service(&mut self) {
+            while let Some(packet) = self.device.read() {
+                let ApplicationPacket::Command { seq, command } = packet else {
+                    continue;
+                };
+                self.received += 1;
+                let reply = match command {
+                    _ if self.refuse => CommandReply::Nack(NackReason::InvalidPhase),
+                    CommandPacket::EjectorPhaseSet(EjectorPhase::Ejection) => {
+                        self.phase = EjectorPhase::Ejection;
+                        CommandReply::Ack
+                    }
+                    _ => CommandReply::Nack(NackReason::Unsupported),
+                };
+                let ack = ApplicationPacket::CommandAck {
+                    seq,
+                    command,
+                    reply,
+                    phase: self.phase,
+                };
+                self.device.write(ack).unwrap();
+            }
+        }
+    }
+
+    struct Bench {
+        link: CommandLink,
+        jupiter: Device<End>,
+        ejector: Ejector,
+        up: Wire,
+        down: Wire,
+    }
+
+    impl Bench {
+        fn new(up_loss: u32, down_loss: u32, seed: u32) -> Self {
+            let up = Wire::new(up_loss, seed);
+            let down = Wire::new(down_loss, seed.wrapping_mul(31));
+            Self {
+                link: CommandLink::new(RetryPolicy::EJECTOR),
+                jupiter: Device::new(End {
+                    rx: down.clone(),
+                    tx: up.clone(),
+                }),
+                ejector: Ejector {
+                    device: Device::new(End {
+                        rx: up.clone(),
+                        tx: down.clone(),
+                    }),
+                    phase: EjectorPhase::Standby,
+                    refuse: false,
+                    received: 0,
+                },
+                up,
+                down,
+            }
+        }
+
+        // Send `command` and run the main loop every 100ms until it's resolved. Returns the
+        // outcome and when it was known.
+        fn deliver(&mut self, command: CommandPacket) -> (Delivery, Duration) {
+            let seq = self.link.send(command);
+            let mut now = Duration::ZERO;
+            loop {
+                while let Some(packet) = self.jupiter.read() {
+                    if let ApplicationPacket::CommandAck {
+                        seq,
+                        command,
+                        reply,
+                        phase,
+                    } = packet
+                    {
+                        self.link.receive(seq, command, reply, phase);
+                    }
+                }
+                if let Some(packet) = self.link.poll(now) {
+                    self.jupiter.write(packet).unwrap();
+                }
+
+                let delivery = self.link.delivery(seq).unwrap();
+                if delivery != Delivery::Pending {
+                    return (delivery, now);
+                }
+                assert!(now <= RetryPolicy::EJECTOR.deadline(), "never gave up");
+
+                self.ejector.service();
+                now += Duration::from_millis(100);
+            }
+        }
+    }
+
+    #[test]
+    fn test_clean_link_acks_first_copy() {
+        let mut bench = Bench::new(0, 0, 1);
+        let (delivery, at) = bench.deliver(EJECT);
+
+        assert_eq!(delivery, Delivery::Acked(EjectorPhase::Ejection));
+        assert_eq!(bench.link.attempts(), 1);
+        assert_eq!(bench.ejector.received, 1);
+        assert_eq!(at, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_lossy_link_gets_through_on_retries() {
+        let mut acked = 0;
+        let mut retried = 0;
+        for seed in 1..=50 {
+            let mut bench = Bench::new(2, 2, seed);
+            let (delivery, at) = bench.deliver(EJECT);
+            assert!(at <= RetryPolicy::EJECTOR.deadline());
+
+            // A damaged reply can still pass for one to this command and read as a NACK or the
+            // wrong phase, which sends the caller to the pin early. It never has the Ejector
+            // ejecting when it isn't.
+            if delivery == Delivery::Acked(EjectorPhase::Ejection) {
+                assert_eq!(bench.ejector.phase, EjectorPhase::Ejection, "seed {seed}");
+                acked += 1;
+            }
+            if bench.link.attempts() > 1 {
+                retried += 1;
+                assert!(bench.up.lost() + bench.down.lost() > 0);
+            }
+        }
+        // Each attempt loses something about a quarter of the time at 2%, and a packet cut
+        // short swallows the start of the next one
+        assert!(acked >= 45, "only {acked} of 50 got through");
+        assert!(retried > 0, "loss never cost a retry");
+    }
+
+    #[test]
+    fn test_dead_link_times_out() {
+        let mut bench = Bench::new(100, 0, 1);
+        let (delivery, at) = bench.deliver(EJECT);
+
+        assert_eq!(delivery, Delivery::TimedOut);
+        assert_eq!(bench.link.attempts(), RetryPolicy::EJECTOR.max_attempts);
+        assert_eq!(bench.ejector.received, 0);
+        // The last copy waited out its timeout too
+        assert_eq!(at, RetryPolicy::EJECTOR.deadline());
+        assert!(bench.link.poll(at * 2).is_none());
+        assert!(bench.link.timed_out());
+    }
+
+    #[test]
+    fn test_lost_replies_are_retried_and_repeats_ignored() {
+        // Commands get through, replies don't
+        let mut bench = Bench::new(0, 100, 1);
+        let (delivery, _) = bench.deliver(EJECT);
+        assert_eq!(delivery, Delivery::TimedOut);
+        // Every copy was acted on, which is why handling one has to be idempotent
+        assert_eq!(bench.ejector.received, RetryPolicy::EJECTOR.max_attempts);
+        assert_eq!(bench.ejector.phase, EjectorPhase::Ejection);
+    }
+
+    #[test]
+    fn test_nack_ends_delivery() {
+        let mut bench = Bench::new(0, 0, 1);
+        bench.ejector.refuse = true;
+        let (delivery, _) = bench.deliver(EJECT);
+
+        assert_eq!(
+            delivery,
+            Delivery::Nacked {
+                reason: NackReason::InvalidPhase,
+                phase: EjectorPhase::Standby,
+            }
+        );
+        assert_eq!(bench.link.attempts(), 1);
+    }
+
+    #[test]
+    fn test_late_reply_counts_and_others_dont() {
+        let mut link = CommandLink::new(RetryPolicy::EJECTOR);
+        assert_eq!(link.last_seq(), None);
+        let colors = link.stamp(CommandPacket::Ping);
+        let seq = link.send(EJECT);
+        assert!(matches!(colors, ApplicationPacket::Command { seq: 0, .. }));
+        assert_eq!(seq, 1);
+        assert_eq!(link.last_seq(), Some(1));
+
+        assert!(link.poll(Duration::ZERO).is_some());
+        assert!(link.poll(Duration::from_millis(299)).is_none());
+        assert!(link.poll(Duration::from_millis(300)).is_some());
+        assert_eq!(link.attempts(), 2);
+
+        // A reply to the stamped command isn't for this one, nor is one to a garbled copy
+        assert!(!link.receive(0, CommandPacket::Ping, CommandReply::Ack, EjectorPhase::Standby));
+        let garbled = CommandPacket::EjectorPhaseSet(EjectorPhase::Standby);
+        assert!(!link.receive(seq, garbled, CommandReply::Ack, EjectorPhase::Standby));
+        assert_eq!(link.delivery(seq), Some(Delivery::Pending));
+
+        // Reply to the first copy arrives after the second went out
+        assert!(link.receive(seq, EJECT, CommandReply::Ack, EjectorPhase::Ejection));
+        let nack = CommandReply::Nack(NackReason::Unsupported);
+        assert!(link.receive(seq, EJECT, nack, EjectorPhase::Ejection));
+        assert_eq!(link.delivery(seq), Some(Delivery::Acked(EjectorPhase::Ejection)));
+        assert!(link.poll(Duration::from_secs(10)).is_none());
+        assert_eq!(link.delivery(0), None);
+        assert!(!link.timed_out());
+    }
+}
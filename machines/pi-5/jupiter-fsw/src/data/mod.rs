@@ -1,4 +1,8 @@
 #![warn(missing_docs)]
 
+pub mod commands;
 pub mod packets;
+#[cfg(feature = "packet_logging")]
+pub mod storage;
 pub mod status;
+pub mod telemetry;
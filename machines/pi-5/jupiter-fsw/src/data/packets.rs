@@ -1,68 +1,51 @@
 #![warn(missing_docs)]
 
-use std::{
-    fs::File,
-    path::{Path, PathBuf},
-};
+#[cfg(feature = "packet_logging")]
+use log::error;
+#[cfg(not(feature = "packet_logging"))]
+use log::warn;
 
-use log::info;
+use bin_packets::packets::ApplicationPacket;
 
-use bin_packets::{
-    device::{PacketWriter, std::Device},
-    packets::ApplicationPacket,
-};
+#[cfg(feature = "packet_logging")]
+use super::storage::{PacketStore, StorageConfig};
 
+/// Where main sends packets to be kept. Only stores anything with the `packet_logging` feature,
+/// otherwise packets are dropped.
 pub struct OnboardPacketStorage {
-    file: Device<File>,
+    #[cfg(feature = "packet_logging")]
+    store: Option<PacketStore>,
 }
 
 impl OnboardPacketStorage {
-    fn new(file: File) -> Self {
-        Self {
-            file: Device::new(file),
+    /// Store a packet, logging rather than failing if it can't be
+    pub fn write<T: Into<ApplicationPacket>>(&mut self, packet: T) {
+        #[cfg(feature = "packet_logging")]
+        if let Some(store) = self.store.as_mut()
+            && let Err(e) = store.append(&packet.into())
+        {
+            error!("Failed to store packet: {e:?}");
         }
-    }
 
-    pub fn write<T: Into<ApplicationPacket>>(&mut self, packet: T) {
-        self.file.write(packet.into()).ok();
+        #[cfg(not(feature = "packet_logging"))]
+        let _ = packet;
     }
 
+    /// Open storage for this run under `$HOME/data/packets`, after recovering the last one
+    #[cfg(feature = "packet_logging")]
     pub fn get_current_run() -> Self {
         let home = std::env::var("HOME").expect("No $HOME variable? What the fuck?");
-        let dir_path = format!("{home}/data/packets");
-        // Get the directory if it doesn't exist
-        info!("Making directory...");
-        std::process::Command::new("mkdir")
-            .arg(dir_path.clone())
-            .arg("-p")
-            .spawn()
-            .unwrap()
-            .wait()
+        let store = PacketStore::open(StorageConfig::new(format!("{home}/data/packets")))
+            .inspect_err(|e| error!("Onboard packet storage unavailable: {e:?}"))
             .ok();
 
-        let path = Path::new(&dir_path);
-
-        info!("Finding new name...");
-        let mut max = 0;
-        std::fs::read_dir(path).unwrap().for_each(|dir| {
-            if let Ok(name) = dir {
-                info!("Found {}", name.file_name().to_string_lossy());
-                let x = name
-                    .file_name()
-                    .to_string_lossy()
-                    .parse::<u32>()
-                    .unwrap_or(0);
-                if x > max {
-                    max = x;
-                }
-            }
-        });
-        let mut path = PathBuf::from(path);
-        path.push(format! {"{}", max + 1});
-        let file = File::create(path).unwrap();
-
-        info!("Log file created");
+        Self { store }
+    }
 
-        Self::new(file)
+    /// Storage that drops everything
+    #[cfg(not(feature = "packet_logging"))]
+    pub fn get_current_run() -> Self {
+        warn!("Built without packet_logging, packets will not be stored");
+        Self {}
     }
 }
@@ -19,6 +19,7 @@
 // Ejector pico
 
 use bin_packets::{
+    data::SubsystemHealth,
     phases::JupiterPhase, 
     device::{Device, PacketWriter},
     rgbstatus::{RGBOptions, WireColor}
@@ -48,6 +49,7 @@ pub struct ExperimentColorState {
     latest_infratracker: Option<Instant>,
     latest_avionics: Option<Instant>,
     latest_phase: JupiterPhase,
+    latest_health: SubsystemHealth,
 }   
 
 impl ExperimentColorState {
@@ -58,6 +60,7 @@ impl ExperimentColorState {
             latest_infratracker: None,
             latest_avionics: None,
             latest_phase: JupiterPhase::PowerOn,
+            latest_health: SubsystemHealth::default(),
         }
     }
 
@@ -70,6 +73,10 @@ impl ExperimentColorState {
         self.latest_phase = jupiter_phase;
     }
 
+    pub fn feed_health(&mut self, health: SubsystemHealth) {
+        self.latest_health = health;
+    }
+
     fn is_active(latest: Option<Instant>, now: Instant) -> bool {
         latest.is_some_and(|t| now.duration_since(t) <= STATUS_TIMEOUT)
     }
@@ -86,15 +93,27 @@ impl ExperimentColorState {
             (true, true)   => COLOR_GREEN,
             (true, false)  => COLOR_YELLOW,
             (false, true)  => COLOR_PINK,
+            (false, false) if self.latest_health.is_degraded(SubsystemHealth::GUARD) => COLOR_RED,
             (false, false) => COLOR_OFF,
         };
 
+        // Red once the supervisor has given up on the thread, orange while it's being restarted
+        let infra_color = if self.latest_health.is_degraded(SubsystemHealth::INFRATRACKER) {
+            COLOR_RED
+        } else if self.latest_health.restarts > 0 && !self.latest_health.is_healthy(SubsystemHealth::INFRATRACKER) {
+            COLOR_ORANGE
+        } else if infra_active {
+            COLOR_CYAN
+        } else {
+            COLOR_OFF
+        };
+
         
         RGBOptions { 
             RBF: None, 
             HaLow: None, 
             Esp: None, 
-            Infratracker: if infra_active { COLOR_CYAN } else { COLOR_OFF }, 
+            Infratracker: infra_color, 
             Guard: guard_color,
             Jupiter: match self.latest_phase {
                 JupiterPhase::PowerOn => COLOR_GREEN,
@@ -0,0 +1,421 @@
+#![warn(missing_docs)]
+
+//! Onboard log of every packet JUPITER sees. Packets go into numbered segment files under
+//! `$HOME/data/packets`, framed with a checksum per record (see [`bin_packets::storage`]), with a
+//! small JSON index next to each segment saying what's in it and when it was logged.
+//!
+//! Segments roll over on size or age, and get synced to disk every so often rather than on every
+//! packet. On startup any segment whose index doesn't match it, which is the one that was open
+//! when the power went, gets scanned, has a torn last record cut off, and its index rebuilt.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use log::{error, info, warn};
+
+use bin_packets::packets::ApplicationPacket;
+use bin_packets::storage::{
+    encode_record, is_segment, write_segment_header, EncodeError, SegmentEnd, SegmentReader, SEGMENT_HEADER_LEN,
+};
+use serde::{Deserialize, Serialize};
+
+/// When segments roll over and get synced
+#[derive(Debug, Clone)]
+pub struct StorageConfig {
+    /// Directory the segments go in
+    pub dir: PathBuf,
+    /// Start a new segment once the current one reaches this size
+    pub max_segment_bytes: u64,
+    /// Start a new segment once the current one has been open this long
+    pub max_segment_age: Duration,
+    /// Sync at least this often while packets are coming in
+    pub sync_interval: Duration,
+    /// Sync after this many packets, whatever the time
+    pub sync_records: u32,
+}
+
+impl StorageConfig {
+    /// Flight defaults, storing into `dir`. Losing up to a second of packets to a power cut is
+    /// fine, wearing out the SD card syncing every 100ms is not.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            max_segment_bytes: 8 * 1024 * 1024,
+            max_segment_age: Duration::from_secs(60),
+            sync_interval: Duration::from_secs(1),
+            sync_records: 256,
+        }
+    }
+}
+
+/// What's in a segment, kept next to it as `<segment>.idx`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SegmentIndex {
+    /// Segment number, which is also its file name
+    pub segment: u32,
+    /// Records in the segment
+    pub records: u32,
+    /// Size of the segment as of this index, header included
+    pub bytes: u64,
+    /// Time the first record was logged, in ms since the unix epoch
+    pub first_unix_ms: Option<u64>,
+    /// Time the last record was logged, in ms since the unix epoch
+    pub last_unix_ms: Option<u64>,
+    /// How many of each packet type the segment holds
+    pub packet_counts: BTreeMap<String, u32>,
+}
+
+impl SegmentIndex {
+    fn new(segment: u32) -> Self {
+        Self {
+            segment,
+            bytes: SEGMENT_HEADER_LEN,
+            ..Default::default()
+        }
+    }
+
+    fn add(&mut self, record_len: usize, logged_unix_ms: u64, packet: &ApplicationPacket) {
+        self.records += 1;
+        self.bytes += record_len as u64;
+        self.first_unix_ms.get_or_insert(logged_unix_ms);
+        self.last_unix_ms = Some(logged_unix_ms);
+        *self.packet_counts.entry(packet.name().to_string()).or_default() += 1;
+    }
+
+    /// Read the index for `segment` in `dir`, None if there isn't a readable one
+    pub fn load(dir: &Path, segment: u32) -> Option<Self> {
+        let bytes = fs::read(index_path(dir, segment)).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    // Written to the side and renamed over, so a power cut leaves either the old or new index
+    fn save(&self, dir: &Path) -> Result<(), StorageError> {
+        let path = index_path(dir, self.segment);
+        let temp = path.with_extension("idx.tmp");
+        fs::write(&temp, serde_json::to_vec(self)?)?;
+        fs::rename(temp, path)?;
+        Ok(())
+    }
+}
+
+/// Why storing a packet failed
+#[derive(Debug)]
+pub enum StorageError {
+    /// The filesystem let us down
+    Io(std::io::Error),
+    /// The packet couldn't be encoded
+    Encode(EncodeError),
+    /// The index couldn't be written
+    Index(serde_json::Error),
+}
+
+impl From<std::io::Error> for StorageError {
+    fn from(e: std::io::Error) -> Self {
+        StorageError::Io(e)
+    }
+}
+
+impl From<EncodeError> for StorageError {
+    fn from(e: EncodeError) -> Self {
+        StorageError::Encode(e)
+    }
+}
+
+impl From<serde_json::Error> for StorageError {
+    fn from(e: serde_json::Error) -> Self {
+        StorageError::Index(e)
+    }
+}
+
+fn segment_path(dir: &Path, segment: u32) -> PathBuf {
+    dir.join(segment.to_string())
+}
+
+fn index_path(dir: &Path, segment: u32) -> PathBuf {
+    dir.join(format!("{segment}.idx"))
+}
+
+// Numbers of everything in `dir` named like a segment, older raw logs included
+fn segment_numbers(dir: &Path) -> std::io::Result<Vec<u32>> {
+    let mut numbers: Vec<u32> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .collect();
+    numbers.sort_unstable();
+    Ok(numbers)
+}
+
+fn unix_ms_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Scan every segment in `dir` whose index is missing or out of date, cut off a torn last record
+/// and write a fresh index. Returns the segments that were touched.
+pub fn recover(dir: &Path) -> Result<Vec<SegmentIndex>, StorageError> {
+    let mut recovered = Vec::new();
+
+    for segment in segment_numbers(dir)? {
+        let path = segment_path(dir, segment);
+        // Logs from before segments were a thing are left alone
+        if !is_segment(&path)? {
+            continue;
+        }
+
+        let len = fs::metadata(&path)?.len();
+        if SegmentIndex::load(dir, segment).is_some_and(|index| index.bytes == len) {
+            continue;
+        }
+
+        let mut index = SegmentIndex::new(segment);
+        let mut reader = SegmentReader::open(&path)?;
+        let mut last_len = reader.valid_len();
+        while let Some(record) = reader.next() {
+            let record_len = (reader.valid_len() - last_len) as usize;
+            last_len = reader.valid_len();
+            index.add(record_len, record.logged_unix_ms, &record.packet);
+        }
+
+        match reader.end() {
+            SegmentEnd::Torn => {
+                warn!("Segment {segment} has a torn tail, truncating from {len} to {} bytes", reader.valid_len());
+                OpenOptions::new().write(true).open(&path)?.set_len(reader.valid_len())?;
+            }
+            SegmentEnd::Corrupt => {
+                // Not something a power cut does, so keep the bytes for a closer look on the ground
+                error!("Segment {segment} has a corrupt record at byte {}, indexing up to it", reader.valid_len());
+            }
+            _ => {}
+        }
+
+        index.save(dir)?;
+        recovered.push(index);
+    }
+
+    Ok(recovered)
+}
+
+/// Appends packets to rolling segments in a directory
+pub struct PacketStore {
+    config: StorageConfig,
+    writer: BufWriter<File>,
+    index: SegmentIndex,
+    opened: Instant,
+    last_sync: Instant,
+    unsynced: u32,
+}
+
+impl PacketStore {
+    /// Recover whatever the last run left behind and start a new segment after it
+    pub fn open(config: StorageConfig) -> Result<Self, StorageError> {
+        fs::create_dir_all(&config.dir)?;
+
+        for index in recover(&config.dir)? {
+            info!("Recovered segment {}: {} records", index.segment, index.records);
+        }
+
+        let segment = segment_numbers(&config.dir)?.last().map_or(1, |last| last + 1);
+        let writer = Self::create_segment(&config.dir, segment)?;
+        let now = Instant::now();
+        Ok(Self {
+            config,
+            writer,
+            index: SegmentIndex::new(segment),
+            opened: now,
+            last_sync: now,
+            unsynced: 0,
+        })
+    }
+
+    fn create_segment(dir: &Path, segment: u32) -> Result<BufWriter<File>, StorageError> {
+        info!("Starting packet segment {segment}");
+        let mut writer = BufWriter::new(File::create(segment_path(dir, segment))?);
+        write_segment_header(&mut writer)?;
+        Ok(writer)
+    }
+
+    /// The segment being written to
+    pub fn segment(&self) -> u32 {
+        self.index.segment
+    }
+
+    /// Index of the segment being written to, as of the last append
+    pub fn index(&self) -> &SegmentIndex {
+        &self.index
+    }
+
+    /// Log a packet
+    pub fn append(&mut self, packet: &ApplicationPacket) -> Result<(), StorageError> {
+        if self.index.bytes >= self.config.max_segment_bytes || self.opened.elapsed() >= self.config.max_segment_age {
+            self.rotate()?;
+        }
+
+        let logged_unix_ms = unix_ms_now();
+        let record = encode_record(logged_unix_ms, packet)?;
+        self.writer.write_all(&record)?;
+        self.index.add(record.len(), logged_unix_ms, packet);
+
+        self.unsynced += 1;
+        if self.unsynced >= self.config.sync_records || self.last_sync.elapsed() >= self.config.sync_interval {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Get everything appended so far onto the disk, and the index along with it
+    pub fn sync(&mut self) -> Result<(), StorageError> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_data()?;
+        self.index.save(&self.config.dir)?;
+        self.last_sync = Instant::now();
+        self.unsynced = 0;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<(), StorageError> {
+        self.sync()?;
+        let segment = self.index.segment + 1;
+        self.writer = Self::create_segment(&self.config.dir, segment)?;
+        self.index = SegmentIndex::new(segment);
+        self.opened = Instant::now();
+        Ok(())
+    }
+}
+
+impl Drop for PacketStore {
+    fn drop(&mut self) {
+        if let Err(e) = self.sync() {
+            error!("Failed to sync packet segment on close: {e:?}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("jupiter_packets_{}_{name}", std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn geiger(n: u16) -> ApplicationPacket {
+        ApplicationPacket::GeigerData {
+            timestamp_ms: n as u64,
+            recorded_pulses: n,
+        }
+    }
+
+    fn read_segment(dir: &Path, segment: u32) -> (Vec<ApplicationPacket>, SegmentEnd) {
+        let mut reader = SegmentReader::open(segment_path(dir, segment)).unwrap();
+        let packets = reader.by_ref().map(|r| r.packet).collect();
+        (packets, reader.end())
+    }
+
+    #[test]
+    fn test_append_and_index() {
+        let dir = temp_dir("index");
+        // An old raw log from before segments
+        fs::write(dir.join("3"), b"raw bincode").unwrap();
+
+        let mut store = PacketStore::open(StorageConfig::new(&dir)).unwrap();
+        assert_eq!(store.segment(), 4);
+        for n in 0..5 {
+            store.append(&geiger(n)).unwrap();
+        }
+        store.append(&ApplicationPacket::InfratrackerData { timestamp: 0, quaternion: [1.0, 0.0, 0.0, 0.0] }).unwrap();
+        drop(store);
+
+        let index = SegmentIndex::load(&dir, 4).unwrap();
+        assert_eq!(index.records, 6);
+        assert_eq!(index.packet_counts["GeigerData"], 5);
+        assert_eq!(index.packet_counts["InfratrackerData"], 1);
+        assert_eq!(index.bytes, fs::metadata(dir.join("4")).unwrap().len());
+        assert!(index.first_unix_ms <= index.last_unix_ms);
+
+        let (packets, end) = read_segment(&dir, 4);
+        assert_eq!(packets.len(), 6);
+        assert_eq!(end, SegmentEnd::Clean);
+    }
+
+    #[test]
+    fn test_rotates_on_size() {
+        let dir = temp_dir("rotate");
+        let mut config = StorageConfig::new(&dir);
+        config.max_segment_bytes = 100;
+
+        let mut store = PacketStore::open(config).unwrap();
+        for n in 0..20 {
+            store.append(&geiger(n)).unwrap();
+        }
+        let last = store.segment();
+        drop(store);
+
+        assert!(last > 2);
+        let total: u32 = (1..=last).map(|s| SegmentIndex::load(&dir, s).unwrap().records).sum();
+        assert_eq!(total, 20);
+        for segment in 1..last {
+            // Rolls over on the first append past the limit
+            assert!(SegmentIndex::load(&dir, segment).unwrap().bytes < 100 + 40);
+        }
+    }
+
+    #[test]
+    fn test_syncs_on_record_count() {
+        let dir = temp_dir("sync");
+        let mut config = StorageConfig::new(&dir);
+        config.sync_records = 3;
+        config.sync_interval = Duration::from_secs(3600);
+
+        let mut store = PacketStore::open(config).unwrap();
+        store.append(&geiger(0)).unwrap();
+        store.append(&geiger(1)).unwrap();
+        assert!(SegmentIndex::load(&dir, 1).is_none());
+
+        store.append(&geiger(2)).unwrap();
+        assert_eq!(SegmentIndex::load(&dir, 1).unwrap().records, 3);
+        assert_eq!(fs::metadata(dir.join("1")).unwrap().len(), store.index().bytes);
+    }
+
+    #[test]
+    fn test_recovers_torn_tail() {
+        let dir = temp_dir("torn");
+        let mut store = PacketStore::open(StorageConfig::new(&dir)).unwrap();
+        for n in 0..10 {
+            store.append(&geiger(n)).unwrap();
+        }
+        // Leaves the old index behind, as a power cut between syncs would
+        store.sync().unwrap();
+        store.append(&geiger(10)).unwrap();
+        store.writer.flush().unwrap();
+        std::mem::forget(store);
+
+        // Power goes partway through writing the last record
+        let path = dir.join("1");
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 3).unwrap();
+
+        let store = PacketStore::open(StorageConfig::new(&dir)).unwrap();
+        assert_eq!(store.segment(), 2);
+
+        let (packets, end) = read_segment(&dir, 1);
+        assert_eq!(packets.len(), 10);
+        assert_eq!(end, SegmentEnd::Clean);
+
+        let index = SegmentIndex::load(&dir, 1).unwrap();
+        assert_eq!(index.records, 10);
+        assert_eq!(index.bytes, fs::metadata(&path).unwrap().len());
+
+        // Nothing left to do the next time around
+        drop(store);
+        assert!(recover(&dir).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,464 @@
+#![warn(missing_docs)]
+
+//! Downlink scheduling. Every packet bound for a link is offered here first, and goes out in
+//! priority order as the link's byte budget allows. Each packet type gets a priority, an optional
+//! minimum interval, and a decimation policy, so high-rate sensor data thins out before it can
+//! crowd out Status and commands.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use bin_packets::packets::ApplicationPacket;
+
+/// How urgently a packet type needs to get down, most urgent first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Status, health and commands, always sent first
+    Critical,
+    /// Science results
+    High,
+    /// Anything without a policy
+    Normal,
+    /// High-rate sensor data, only sent with budget to spare
+    Low,
+}
+
+const PRIORITIES: usize = 4;
+
+impl Priority {
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// How to thin out a packet type that's offered faster than it's worth sending
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decimation {
+    /// Keep every packet
+    All,
+    /// Keep one of every `n` offered
+    OneIn(u32),
+    /// Keep only the newest waiting, replacing an older one still in the queue
+    Latest,
+}
+
+/// How one packet type is scheduled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketPolicy {
+    /// Where it goes in the queue
+    pub priority: Priority,
+    /// Packets offered sooner than this after the last one kept are dropped
+    pub min_interval: Option<Duration>,
+    /// How it's thinned out before the rate limit
+    pub decimation: Decimation,
+}
+
+impl PacketPolicy {
+    /// Every packet kept, at this priority
+    pub const fn new(priority: Priority) -> Self {
+        Self {
+            priority,
+            min_interval: None,
+            decimation: Decimation::All,
+        }
+    }
+
+    /// At most one packet per `interval`
+    pub const fn at_most_every(mut self, interval: Duration) -> Self {
+        self.min_interval = Some(interval);
+        self
+    }
+
+    /// Thin packets out
+    pub const fn decimated(mut self, decimation: Decimation) -> Self {
+        self.decimation = decimation;
+        self
+    }
+}
+
+impl Default for PacketPolicy {
+    fn default() -> Self {
+        Self::new(Priority::Normal)
+    }
+}
+
+/// What a link can carry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LinkBudget {
+    /// Sustained rate
+    pub bytes_per_second: u32,
+    /// Most that can go out at once after the link has been quiet
+    pub burst_bytes: u32,
+}
+
+/// What happened to an offered packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Offered {
+    /// Waiting to go out
+    Queued,
+    /// Took the place of an older packet of the same type
+    Replaced,
+    /// Thinned out by the decimation policy
+    Decimated,
+    /// Came too soon after the last one
+    RateLimited,
+}
+
+/// Running totals, for the logs and health
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TelemetryStats {
+    /// Packets waiting now
+    pub depth: usize,
+    /// Most packets ever waiting at once
+    pub max_depth: usize,
+    /// Packets sent
+    pub sent: u64,
+    /// Bytes sent
+    pub sent_bytes: u64,
+    /// Packets thinned out or replaced before being sent
+    pub decimated: u64,
+    /// Packets dropped for coming too fast
+    pub rate_limited: u64,
+    /// Packets dropped because their queue was full
+    pub overflowed: u64,
+}
+
+struct Queued {
+    packet: ApplicationPacket,
+    bytes: usize,
+}
+
+#[derive(Default)]
+struct TypeState {
+    offered: u64,
+    last_kept: Option<Duration>,
+}
+
+/// Per-link downlink scheduler
+pub struct TelemetryScheduler {
+    budget: LinkBudget,
+    policies: HashMap<&'static str, PacketPolicy>,
+    queues: [VecDeque<Queued>; PRIORITIES],
+    capacity: usize,
+    types: HashMap<&'static str, TypeState>,
+    tokens: f64,
+    last_refill: Option<Duration>,
+    stats: TelemetryStats,
+}
+
+impl TelemetryScheduler {
+    /// Packets waiting per priority before the oldest start being dropped
+    pub const DEFAULT_CAPACITY: usize = 64;
+
+    /// A scheduler for a link with this budget, every packet type at [`Priority::Normal`]. The
+    /// bucket starts full.
+    pub fn new(budget: LinkBudget) -> Self {
+        Self {
+            budget,
+            policies: HashMap::new(),
+            queues: Default::default(),
+            capacity: Self::DEFAULT_CAPACITY,
+            types: HashMap::new(),
+            tokens: budget.burst_bytes as f64,
+            last_refill: None,
+            stats: TelemetryStats::default(),
+        }
+    }
+
+    /// The JUPITER link to the Ejector: Status, health and commands. It's the UART the Eject
+    /// command goes down, and the Ejector only reads commands off it, so sensor and science
+    /// packets stay onboard rather than crowding it.
+    pub fn jupiter(budget: LinkBudget) -> Self {
+        Self::new(budget)
+            .with_policy("Status", PacketPolicy::new(Priority::Critical))
+            .with_policy("JupiterStatus", PacketPolicy::new(Priority::Critical))
+            .with_policy("Command", PacketPolicy::new(Priority::Critical))
+    }
+
+    /// Schedule packets named `name` (see [`ApplicationPacket::name`]) this way
+    pub fn with_policy(mut self, name: &'static str, policy: PacketPolicy) -> Self {
+        self.policies.insert(name, policy);
+        self
+    }
+
+    /// Hold up to `capacity` packets per priority
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// The policy a packet is scheduled under
+    pub fn policy(&self, packet: &ApplicationPacket) -> PacketPolicy {
+        self.policies.get(packet.name()).copied().unwrap_or_default()
+    }
+
+    /// Offer a packet at `now` (time since boot) for sending
+    pub fn offer(&mut self, packet: ApplicationPacket, now: Duration) -> Offered {
+        let policy = self.policy(&packet);
+        let state = self.types.entry(packet.name()).or_default();
+        state.offered += 1;
+
+        if let Decimation::OneIn(n) = policy.decimation
+            && !(state.offered - 1).is_multiple_of(n.max(1) as u64)
+        {
+            self.stats.decimated += 1;
+            return Offered::Decimated;
+        }
+
+        if let (Some(interval), Some(last)) = (policy.min_interval, state.last_kept)
+            && now.saturating_sub(last) < interval
+        {
+            self.stats.rate_limited += 1;
+            return Offered::RateLimited;
+        }
+        state.last_kept = Some(now);
+
+        let queued = Queued {
+            bytes: packet.encoded_len(),
+            packet,
+        };
+        let queue = &mut self.queues[policy.priority.index()];
+
+        if policy.decimation == Decimation::Latest
+            && let Some(older) = queue.iter_mut().find(|q| q.packet.name() == queued.packet.name())
+        {
+            *older = queued;
+            self.stats.decimated += 1;
+            return Offered::Replaced;
+        }
+
+        if queue.len() >= self.capacity {
+            queue.pop_front();
+            self.stats.overflowed += 1;
+        }
+        queue.push_back(queued);
+
+        self.stats.depth = self.depth();
+        self.stats.max_depth = self.stats.max_depth.max(self.stats.depth);
+        Offered::Queued
+    }
+
+    /// The next packet to send at `now`, if there's one waiting and the budget has room for it.
+    /// Call until it returns `None`.
+    pub fn poll(&mut self, now: Duration) -> Option<ApplicationPacket> {
+        self.refill(now);
+
+        // Strictly by priority. A Critical packet waiting on budget holds everything else back
+        // rather than letting smaller, less important packets take the bytes it needs.
+        let queue = self.queues.iter_mut().find(|q| !q.is_empty())?;
+        let bytes = queue.front()?.bytes;
+
+        // A packet bigger than the burst can never fit, so it goes once the bucket is full
+        let burst = self.budget.burst_bytes as f64;
+        if self.tokens < bytes as f64 && self.tokens < burst {
+            return None;
+        }
+
+        let sent = queue.pop_front()?;
+        self.tokens -= bytes as f64;
+        self.stats.sent += 1;
+        self.stats.sent_bytes += bytes as u64;
+        self.stats.depth = self.depth();
+        Some(sent.packet)
+    }
+
+    fn refill(&mut self, now: Duration) {
+        if let Some(last) = self.last_refill {
+            let elapsed = now.saturating_sub(last).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * self.budget.bytes_per_second as f64).min(self.budget.burst_bytes as f64);
+        }
+        self.last_refill = Some(now);
+    }
+
+    /// Packets waiting
+    pub fn depth(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum()
+    }
+
+    /// Totals so far
+    pub fn stats(&self) -> TelemetryStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bin_packets::commands::CommandPacket;
+    use bin_packets::data::{BoardHealth, JupiterStatus, SubsystemHealth};
+    use bin_packets::phases::JupiterPhase;
+
+    const SERIAL: LinkBudget = LinkBudget {
+        bytes_per_second: 2000,
+        burst_bytes: 200,
+    };
+
+    fn accel(n: u64) -> ApplicationPacket {
+        ApplicationPacket::AccelerometerData {
+            timestamp: n,
+            x: 0.0,
+            y: 0.0,
+            z: 9.8,
+        }
+    }
+
+    fn status(n: u64) -> ApplicationPacket {
+        ApplicationPacket::JupiterStatus(JupiterStatus {
+            phase: JupiterPhase::PowerOn,
+            health: BoardHealth {
+                uptime_ms: n,
+                ..BoardHealth::default()
+            },
+            subsystems: SubsystemHealth::default(),
+        })
+    }
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    // A link that also carries science and the IMU, solves ahead of the IMU and the IMU thinned
+    // to what's left
+    fn science_link(budget: LinkBudget) -> TelemetryScheduler {
+        let imu = PacketPolicy::new(Priority::Low)
+            .decimated(Decimation::OneIn(5))
+            .at_most_every(ms(200));
+
+        TelemetryScheduler::jupiter(budget)
+            .with_policy(
+                "InfratrackerData",
+                PacketPolicy::new(Priority::High).decimated(Decimation::Latest),
+            )
+            .with_policy("AccelerometerData", imu)
+    }
+
+    // Run the link for `duration`, with producers offering into it every 10ms
+    fn run(
+        scheduler: &mut TelemetryScheduler,
+        duration: u64,
+        mut produce: impl FnMut(u64) -> Vec<ApplicationPacket>,
+    ) -> Vec<(u64, ApplicationPacket)> {
+        let mut sent = Vec::new();
+        for t in (0..duration).step_by(10) {
+            for packet in produce(t) {
+                scheduler.offer(packet, ms(t));
+            }
+            while let Some(packet) = scheduler.poll(ms(t)) {
+                sent.push((t, packet));
+            }
+        }
+        sent
+    }
+
+    #[test]
+    fn test_status_not_starved_by_imu() {
+        let mut scheduler = science_link(SERIAL);
+
+        // IMU offered far faster than the link could carry even decimated, status once a second
+        let sent = run(&mut scheduler, 10_000, |t| {
+            let mut packets: Vec<_> = (0..20).map(|i| accel(t * 100 + i)).collect();
+            if t % 1000 == 0 {
+                packets.push(status(t));
+            }
+            packets
+        });
+
+        let status_sent: Vec<_> = sent.iter().filter(|(_, p)| p.name() == "JupiterStatus").collect();
+        assert_eq!(status_sent.len(), 10);
+        // Out the same tick it was offered
+        for (t, p) in status_sent {
+            let ApplicationPacket::JupiterStatus(status) = p else { unreachable!() };
+            assert_eq!(*t, status.health.uptime_ms);
+        }
+
+        // IMU only at the rate limit
+        let imu = sent.iter().filter(|(_, p)| p.name() == "AccelerometerData").count();
+        assert!((45..=51).contains(&imu), "{imu}");
+        assert!(scheduler.stats().decimated > 0 && scheduler.stats().rate_limited > 0);
+    }
+
+    #[test]
+    fn test_budget_is_respected() {
+        let mut scheduler = TelemetryScheduler::new(SERIAL);
+        let sent = run(&mut scheduler, 5_000, |t| (0..5).map(|i| accel(t + i)).collect());
+
+        let bytes: usize = sent.iter().map(|(_, p)| p.encoded_len()).sum();
+        // Five seconds at the sustained rate plus the burst it started with
+        assert!(bytes <= 5 * 2000 + 200, "{bytes}");
+        assert!(bytes >= 5 * 2000 - 200, "{bytes}");
+
+        // More offered than sent, so the queue filled and overflowed
+        let stats = scheduler.stats();
+        assert_eq!(stats.max_depth, TelemetryScheduler::DEFAULT_CAPACITY);
+        assert!(stats.overflowed > 0);
+        assert_eq!(stats.sent, sent.len() as u64);
+    }
+
+    #[test]
+    fn test_priority_order() {
+        let mut scheduler = science_link(LinkBudget {
+            bytes_per_second: 0,
+            burst_bytes: 1000,
+        });
+        scheduler.offer(accel(1), ms(0));
+        scheduler.offer(
+            ApplicationPacket::InfratrackerData {
+                timestamp: 1,
+                quaternion: [1.0, 0.0, 0.0, 0.0],
+            },
+            ms(0),
+        );
+        scheduler.offer(
+            ApplicationPacket::Command {
+                seq: 0,
+                command: CommandPacket::Ping,
+            },
+            ms(0),
+        );
+        assert_eq!(scheduler.depth(), 3);
+
+        let order: Vec<_> = std::iter::from_fn(|| scheduler.poll(ms(0))).map(|p| p.name()).collect();
+        assert_eq!(order, ["Command", "InfratrackerData", "AccelerometerData"]);
+        assert_eq!(scheduler.stats().max_depth, 3);
+        assert_eq!(scheduler.stats().depth, 0);
+    }
+
+    #[test]
+    fn test_latest_replaces() {
+        let mut scheduler = science_link(LinkBudget {
+            bytes_per_second: 0,
+            burst_bytes: 0,
+        });
+        let solve = |timestamp| ApplicationPacket::InfratrackerData {
+            timestamp,
+            quaternion: [1.0, 0.0, 0.0, 0.0],
+        };
+
+        assert_eq!(scheduler.offer(solve(1), ms(0)), Offered::Queued);
+        assert_eq!(scheduler.offer(solve(2), ms(0)), Offered::Replaced);
+        assert_eq!(scheduler.depth(), 1);
+
+        // No budget at all, but an empty bucket that can't fill still lets one through
+        let Some(ApplicationPacket::InfratrackerData { timestamp, .. }) = scheduler.poll(ms(0)) else {
+            panic!("nothing sent");
+        };
+        assert_eq!(timestamp, 2);
+    }
+
+    #[test]
+    fn test_one_in_n_and_rate_limit() {
+        let mut scheduler = TelemetryScheduler::new(SERIAL)
+            .with_policy("AccelerometerData", PacketPolicy::new(Priority::Low).decimated(Decimation::OneIn(3)))
+            .with_policy("GeigerData", PacketPolicy::new(Priority::Normal).at_most_every(ms(100)));
+
+        let kept: Vec<_> = (0..9).map(|n| scheduler.offer(accel(n), ms(0))).collect();
+        assert_eq!(kept.iter().filter(|o| **o == Offered::Queued).count(), 3);
+        assert_eq!(kept[0], Offered::Queued);
+        assert_eq!(kept[1], Offered::Decimated);
+
+        let geiger = |t| ApplicationPacket::GeigerData { timestamp_ms: t, recorded_pulses: 1 };
+        assert_eq!(scheduler.offer(geiger(0), ms(0)), Offered::Queued);
+        assert_eq!(scheduler.offer(geiger(1), ms(50)), Offered::RateLimited);
+        assert_eq!(scheduler.offer(geiger(2), ms(100)), Offered::Queued);
+    }
+}
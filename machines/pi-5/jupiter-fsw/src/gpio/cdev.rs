@@ -0,0 +1,147 @@
+#![warn(missing_docs)]
+
+//! Lines through the kernel's GPIO character device (/dev/gpiochipN), the same interface
+//! libgpiod and its gpioget/gpioset tools use. Requests hold the line until they're dropped, so
+//! nothing else can grab TE or RBF lines out from under us.
+
+use std::os::unix::io::AsRawFd;
+use std::time::Duration;
+
+use gpio_cdev::{Chip, EventRequestFlags, EventType, Line, LineEventHandle, LineHandle, LineRequestFlags};
+use log::debug;
+
+use super::{CONSUMER, Edge, EdgeEvent, LineBackend, Pin, PinError};
+
+enum Handle {
+    Input(LineEventHandle),
+    Output(LineHandle),
+}
+
+/// A reserved line on a gpiochip
+pub struct CdevLine {
+    name: String,
+    handle: Handle,
+}
+
+impl CdevLine {
+    /// Request the line as an input, with both edges reported
+    pub fn input(pin: &Pin) -> Result<Self, PinError> {
+        let line = find_line(pin)?;
+        let handle = line
+            .events(request_flags(pin, LineRequestFlags::INPUT), EventRequestFlags::BOTH_EDGES, CONSUMER)
+            .map_err(|e| PinError::Line(pin.pin().to_string(), e))?;
+
+        debug!("Reserved {} as an input on {}", pin.pin(), pin.chip().display());
+        Ok(Self {
+            name: pin.pin().to_string(),
+            handle: Handle::Input(handle),
+        })
+    }
+
+    /// Request the line as an output
+    pub fn output(pin: &Pin, initial: bool) -> Result<Self, PinError> {
+        let line = find_line(pin)?;
+        let handle = line
+            .request(request_flags(pin, LineRequestFlags::OUTPUT), initial as u8, CONSUMER)
+            .map_err(|e| PinError::Line(pin.pin().to_string(), e))?;
+
+        debug!("Reserved {} as an output on {}", pin.pin(), pin.chip().display());
+        Ok(Self {
+            name: pin.pin().to_string(),
+            handle: Handle::Output(handle),
+        })
+    }
+
+    fn error(&self, e: gpio_cdev::Error) -> PinError {
+        PinError::Line(self.name.clone(), e)
+    }
+}
+
+impl LineBackend for CdevLine {
+    fn get(&self) -> Result<bool, PinError> {
+        let value = match &self.handle {
+            Handle::Input(handle) => handle.get_value(),
+            Handle::Output(handle) => handle.get_value(),
+        };
+        value.map(|v| v != 0).map_err(|e| self.error(e))
+    }
+
+    fn set(&self, active: bool) -> Result<(), PinError> {
+        match &self.handle {
+            Handle::Output(handle) => handle.set_value(active as u8).map_err(|e| self.error(e)),
+            Handle::Input(_) => Err(PinError::ParseError(format!("{} is an input", self.name))),
+        }
+    }
+
+    fn next_edge(&mut self, timeout: Duration) -> Result<Option<EdgeEvent>, PinError> {
+        let handle = match &mut self.handle {
+            Handle::Input(handle) => handle,
+            Handle::Output(_) => {
+                // Outputs never report edges
+                std::thread::sleep(timeout);
+                return Ok(None);
+            }
+        };
+
+        if !wait_readable(handle, timeout).map_err(PinError::IoError)? {
+            return Ok(None);
+        }
+
+        let event = handle.get_event().map_err(|e| PinError::Line(self.name.clone(), e))?;
+        let edge = match event.event_type() {
+            EventType::RisingEdge => Edge::Rising,
+            EventType::FallingEdge => Edge::Falling,
+        };
+        Ok(Some(EdgeEvent {
+            edge,
+            timestamp_ns: event.timestamp(),
+        }))
+    }
+}
+
+fn request_flags(pin: &Pin, direction: LineRequestFlags) -> LineRequestFlags {
+    if pin.is_active_low() {
+        direction | LineRequestFlags::ACTIVE_LOW
+    } else {
+        direction
+    }
+}
+
+// Lines can be given by name ("GPIO17", as gpioinfo lists them) or by offset
+fn find_line(pin: &Pin) -> Result<Line, PinError> {
+    let mut chip = Chip::new(pin.chip()).map_err(|e| PinError::Line(pin.pin().to_string(), e))?;
+
+    if let Ok(offset) = pin.pin().parse::<u32>() {
+        return chip.get_line(offset).map_err(|e| PinError::Line(pin.pin().to_string(), e));
+    }
+
+    chip.lines()
+        .find(|line| {
+            line.info()
+                .map(|info| info.name() == Some(pin.pin()))
+                .unwrap_or(false)
+        })
+        .ok_or_else(|| PinError::LineNotFound(format!("{} on {}", pin.pin(), pin.chip().display())))
+}
+
+// True once there's an event to read, false on timeout
+fn wait_readable(handle: &LineEventHandle, timeout: Duration) -> std::io::Result<bool> {
+    let mut fd = libc::pollfd {
+        fd: handle.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let timeout_ms = timeout.as_millis().min(i32::MAX as u128) as i32;
+
+    // Safety: one valid pollfd, and the fd is kept open by the handle for the whole call
+    let ready = unsafe { libc::poll(&mut fd, 1, timeout_ms) };
+    if ready < 0 {
+        let err = std::io::Error::last_os_error();
+        // A signal cut the wait short, treat it like a timeout and let the caller go around again
+        if err.kind() == std::io::ErrorKind::Interrupted {
+            return Ok(false);
+        }
+        return Err(err);
+    }
+    Ok(ready > 0 && fd.revents & libc::POLLIN != 0)
+}
@@ -0,0 +1,99 @@
+#![warn(missing_docs)]
+
+//! An in-memory line for tests and bench runs without the hardware. The test keeps a
+//! [`FakeControl`] and flips the line from there, edges get queued up the same way the kernel
+//! queues them.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use super::{Edge, EdgeEvent, LineBackend, PinError, monotonic_ns};
+
+#[derive(Default)]
+struct FakeState {
+    level: bool,
+    edges: VecDeque<EdgeEvent>,
+}
+
+type Shared = Arc<(Mutex<FakeState>, Condvar)>;
+
+/// The line end, goes into a [`super::read::ReadPin`] or [`super::write::WritePin`]
+pub struct FakeLine {
+    shared: Shared,
+}
+
+/// The test's end of a [`FakeLine`]
+#[derive(Clone)]
+pub struct FakeControl {
+    shared: Shared,
+}
+
+impl FakeLine {
+    /// A line starting at `level`, and the handle to drive it with
+    pub fn new(level: bool) -> (FakeLine, FakeControl) {
+        let shared: Shared = Arc::new((
+            Mutex::new(FakeState {
+                level,
+                edges: VecDeque::new(),
+            }),
+            Condvar::new(),
+        ));
+        (
+            FakeLine {
+                shared: shared.clone(),
+            },
+            FakeControl { shared },
+        )
+    }
+}
+
+impl FakeControl {
+    /// Move the line, queuing an edge if the level actually changed
+    pub fn set(&self, level: bool) {
+        let (state, changed) = &*self.shared;
+        let mut state = state.lock().unwrap();
+        if state.level != level {
+            state.level = level;
+            let edge = if level { Edge::Rising } else { Edge::Falling };
+            state.edges.push_back(EdgeEvent {
+                edge,
+                timestamp_ns: monotonic_ns(),
+            });
+            changed.notify_all();
+        }
+    }
+
+    /// Where the line is, for checking what an output was driven to
+    pub fn level(&self) -> bool {
+        self.shared.0.lock().unwrap().level
+    }
+}
+
+impl LineBackend for FakeLine {
+    fn get(&self) -> Result<bool, PinError> {
+        Ok(self.shared.0.lock().unwrap().level)
+    }
+
+    fn set(&self, active: bool) -> Result<(), PinError> {
+        // Outputs don't generate edges on the kernel side either
+        self.shared.0.lock().unwrap().level = active;
+        Ok(())
+    }
+
+    fn next_edge(&mut self, timeout: Duration) -> Result<Option<EdgeEvent>, PinError> {
+        let deadline = Instant::now() + timeout;
+        let (state, changed) = &*self.shared;
+        let mut state = state.lock().unwrap();
+        loop {
+            if let Some(edge) = state.edges.pop_front() {
+                return Ok(Some(edge));
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            state = changed.wait_timeout(state, deadline - now).unwrap().0;
+        }
+    }
+}
@@ -1,15 +1,33 @@
 #![warn(missing_docs)]
 
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
 use embedded_hal::digital::{Error, ErrorKind};
 
+pub mod cdev;
+pub mod fake;
 pub mod read;
 pub mod write;
 
+/// Name the lines are reserved under, shows up in `gpioinfo`
+pub const CONSUMER: &str = "jupiter-fsw";
+
+/// The Pi 5's header GPIOs live on the RP1, which comes up as the first chip
+pub const DEFAULT_CHIP: &str = "/dev/gpiochip0";
+
 #[derive(Debug)]
 #[allow(dead_code)]
 pub enum PinError {
     IoError(std::io::Error),
     ParseError(String),
+    /// No line with this name or offset on the chip
+    LineNotFound(String),
+    /// The kernel refused a request or read on the line, including when something else already
+    /// holds it
+    Line(String, gpio_cdev::Error),
+    /// The line couldn't be opened when the pin was built, every access reports this
+    Unavailable(String),
 }
 
 impl Error for PinError {
@@ -17,23 +35,176 @@ impl Error for PinError {
         match self {
             PinError::IoError(_) => ErrorKind::Other,
             PinError::ParseError(_) => ErrorKind::Other,
+            PinError::LineNotFound(_) => ErrorKind::Other,
+            PinError::Line(_, _) => ErrorKind::Other,
+            PinError::Unavailable(_) => ErrorKind::Other,
+        }
+    }
+}
+
+/// Which way a line changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    /// Went active
+    Rising,
+    /// Went inactive
+    Falling,
+}
+
+impl Edge {
+    /// The level the line is at after this edge
+    pub fn level(self) -> bool {
+        matches!(self, Edge::Rising)
+    }
+}
+
+/// A change on an input line. Edges are logical, so on an active-low line a rising edge is the
+/// wire going low.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EdgeEvent {
+    /// Which way the line went
+    pub edge: Edge,
+    /// Taken by the kernel in the interrupt handler, in nanoseconds. CLOCK_MONOTONIC on kernels
+    /// since 5.7, CLOCK_REALTIME before that.
+    pub timestamp_ns: u64,
+}
+
+/// An input that keeps the edges it saw between polls, so a monitor can be told when a line
+/// moved rather than when it was next read
+pub trait EdgeSource {
+    /// Every edge since the last call, oldest first
+    fn take_edges(&mut self) -> Vec<EdgeEvent>;
+}
+
+/// Milliseconds since it was started, on the clock the kernel stamps edges with, so polled
+/// samples and edge timestamps can go to the same monitor
+#[derive(Debug, Clone, Copy)]
+pub struct EdgeClock {
+    start_ns: u64,
+}
+
+impl EdgeClock {
+    /// Counting from now
+    pub fn start() -> Self {
+        Self {
+            start_ns: monotonic_ns(),
         }
     }
+
+    /// Counting from `start_ns` on the monotonic clock, for lining edges up with made up times
+    #[cfg(test)]
+    pub fn from_ns(start_ns: u64) -> Self {
+        Self { start_ns }
+    }
+
+    /// Time since the start
+    pub fn now_ms(&self) -> u64 {
+        self.ms(monotonic_ns())
+    }
+
+    /// When `event` happened, on the same scale as [`EdgeClock::now_ms`]
+    pub fn edge_ms(&self, event: &EdgeEvent) -> u64 {
+        self.ms(event.timestamp_ns)
+    }
+
+    fn ms(&self, ns: u64) -> u64 {
+        ns.saturating_sub(self.start_ns) / 1_000_000
+    }
+}
+
+/// CLOCK_MONOTONIC in nanoseconds, what newer kernels stamp line events with
+pub fn monotonic_ns() -> u64 {
+    let mut now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // Safety: CLOCK_MONOTONIC is always available and `now` is a valid timespec
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    now.tv_sec as u64 * 1_000_000_000 + now.tv_nsec as u64
+}
+
+/// What [`read::ReadPin`] and [`write::WritePin`] sit on top of, so they can run against the
+/// kernel or against a fake in tests. Values are logical, with active-low already applied.
+pub trait LineBackend: Send {
+    /// Current level of the line
+    fn get(&self) -> Result<bool, PinError>;
+
+    /// Drive the line, only meaningful for outputs
+    fn set(&self, active: bool) -> Result<(), PinError>;
+
+    /// Next edge on the line, or None if nothing happens within `timeout`
+    fn next_edge(&mut self, timeout: Duration) -> Result<Option<EdgeEvent>, PinError>;
 }
 
 #[derive(Debug)]
 pub struct Pin {
     pin: String,
+    chip: PathBuf,
+    active_low: bool,
 }
 
 impl Pin {
     pub fn new(pin: &str) -> Self {
         Pin {
             pin: pin.to_string(),
+            chip: PathBuf::from(DEFAULT_CHIP),
+            active_low: false,
         }
     }
 
+    /// Look the line up on a different chip, e.g. one made by gpio-sim
+    pub fn on_chip<P: AsRef<Path>>(mut self, chip: P) -> Self {
+        self.chip = chip.as_ref().to_path_buf();
+        self
+    }
+
+    /// Have the kernel invert the line, so low on the wire reads as active
+    pub fn active_low(mut self) -> Self {
+        self.active_low = true;
+        self
+    }
+
     pub fn pin(&self) -> &str {
         &self.pin
     }
+
+    /// The gpiochip the line is looked up on
+    pub fn chip(&self) -> &Path {
+        &self.chip
+    }
+
+    /// Whether the line gets requested active-low
+    pub fn is_active_low(&self) -> bool {
+        self.active_low
+    }
+
+    /// Reserve the line as an input with edge events
+    pub fn input(self) -> Result<read::ReadPin, PinError> {
+        let line = cdev::CdevLine::input(&self)?;
+        Ok(read::ReadPin::with_backend(&self.pin, Box::new(line)))
+    }
+
+    /// Reserve the line as an output, driven to `initial` straight away
+    pub fn output(self, initial: bool) -> Result<write::WritePin, PinError> {
+        let line = cdev::CdevLine::output(&self, initial)?;
+        Ok(write::WritePin::with_backend(&self.pin, Box::new(line)))
+    }
+}
+
+// Stands in for a line that failed to open, so a missing pin shows up as errors on use rather
+// than taking the flight software down at boot
+pub(crate) struct Unavailable(pub(crate) String);
+
+impl LineBackend for Unavailable {
+    fn get(&self) -> Result<bool, PinError> {
+        Err(PinError::Unavailable(self.0.clone()))
+    }
+
+    fn set(&self, _active: bool) -> Result<(), PinError> {
+        Err(PinError::Unavailable(self.0.clone()))
+    }
+
+    fn next_edge(&mut self, _timeout: Duration) -> Result<Option<EdgeEvent>, PinError> {
+        Err(PinError::Unavailable(self.0.clone()))
+    }
 }
@@ -1,75 +1,110 @@
 #![warn(missing_docs)]
 
+use std::time::{Duration, Instant};
+
 use embedded_hal::digital::{ErrorType, InputPin};
-use log::{debug, error, warn};
+use log::{debug, error};
 
-use super::{Pin, PinError};
-use std::process::{Command, Stdio};
+use super::{Edge, EdgeEvent, EdgeSource, LineBackend, Pin, PinError, Unavailable};
 
+/// An input line, reserved for as long as this is alive
 pub struct ReadPin {
     pin: String,
+    line: Box<dyn LineBackend>,
 }
 
 impl ReadPin {
-    pub(super) fn new<T: Into<String>>(pin: T) -> Self {
-        ReadPin { pin: pin.into() }
+    /// Wrap an already opened line, used for fakes in tests
+    pub fn with_backend(pin: &str, line: Box<dyn LineBackend>) -> Self {
+        ReadPin {
+            pin: pin.to_string(),
+            line,
+        }
+    }
+
+    /// The line's name, as it was given to [`Pin::new`]
+    pub fn pin(&self) -> &str {
+        &self.pin
     }
 }
 
 impl From<Pin> for ReadPin {
     fn from(pin: Pin) -> Self {
-        let mut cmd = Command::new("gpioget")
-            .arg("-c")
-            .arg("0")
-            .arg(pin.pin())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-            .unwrap();
-
-        cmd.wait().unwrap();
-        Self::new(pin.pin())
+        let name = pin.pin().to_string();
+        pin.input().unwrap_or_else(|e| {
+            error!("Failed to reserve input {name}: {e:?}");
+            Self::with_backend(&name, Box::new(Unavailable(name.clone())))
+        })
     }
 }
 
 impl ReadPin {
-    pub fn read(&self) -> Result<bool, super::PinError> {
-        let output = Command::new("gpioget")
-            .arg("-c")
-            .arg("0")
-            .arg(&self.pin)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-            .map_err(|e| {
-                warn!("Failed to read pin {}: {}", self.pin, e);
-                PinError::IoError(e)
-            })?
-            .wait_with_output()
-            .map_err(|e| {
-                warn!("Failed to read pin {}: {}", self.pin, e);
-                PinError::IoError(e)
-            })?;
-
-        match output.status.success() {
-            false => {
-                let err = String::from_utf8_lossy(&output.stderr);
-                error!("Failed to read pin {}: {}", self.pin, err);
-                Err(PinError::ParseError(err.to_string()))
+    pub fn read(&self) -> Result<bool, PinError> {
+        let value = self.line.get()?;
+        debug!("Pin {} is {}", self.pin, if value { "high" } else { "low" });
+        Ok(value)
+    }
+
+    /// Wait for the line to change and stay changed. Once an edge comes in, every further edge
+    /// within `debounce` restarts the wait, and the line has to have ended up on the other side
+    /// of where it started. Bounces that come back to where they started are thrown away.
+    ///
+    /// `edge` picks which direction to wait for, None takes either. The event carries the
+    /// timestamp of the first edge of the burst, the closest thing to when the contact actually
+    /// moved. None if nothing settled within `timeout`.
+    pub fn wait_for_edge(
+        &mut self,
+        edge: Option<Edge>,
+        debounce: Duration,
+        timeout: Duration,
+    ) -> Result<Option<EdgeEvent>, PinError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            let Some(first) = self.line.next_edge(remaining)? else {
+                return Ok(None);
+            };
+
+            let mut last = first;
+            while let Some(bounce) = self.line.next_edge(debounce)? {
+                last = bounce;
+            }
+
+            // Where the line was before the burst is the opposite of where the first edge took it
+            let started = !first.edge.level();
+            let settled = last.edge.level();
+            if settled == started {
+                debug!("Pin {} glitched and came back", self.pin);
+                continue;
             }
 
-            true => {
-                let line = String::from_utf8_lossy(&output.stdout);
-                if line.contains("inactive") {
-                    debug!("Pin {} is low", self.pin);
-                    Ok(false)
-                } else if line.contains("active") {
-                    debug!("Pin {} is high", self.pin);
-                    Ok(true)
-                } else {
-                    let err = String::from_utf8_lossy(&output.stderr);
-                    error!("Failed to parse pin state: {err}");
-                    Err(PinError::ParseError(err.to_string()))
+            let event = EdgeEvent {
+                edge: last.edge,
+                timestamp_ns: first.timestamp_ns,
+            };
+            if edge.is_none_or(|wanted| wanted == event.edge) {
+                debug!("Pin {} edge {:?} at {}ns", self.pin, event.edge, event.timestamp_ns);
+                return Ok(Some(event));
+            }
+        }
+    }
+}
+
+impl EdgeSource for ReadPin {
+    // Raw edges, bounces and all, it's up to the monitor to debounce them. A line that can't be
+    // read has none to give.
+    fn take_edges(&mut self) -> Vec<EdgeEvent> {
+        let mut edges = Vec::new();
+        loop {
+            match self.line.next_edge(Duration::ZERO) {
+                Ok(Some(edge)) => edges.push(edge),
+                Ok(None) => return edges,
+                Err(e) => {
+                    debug!("Pin {} edges unavailable: {:?}", self.pin, e);
+                    return edges;
                 }
             }
         }
@@ -89,4 +124,98 @@ impl InputPin for ReadPin {
         let value = self.read()?;
         Ok(!value)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpio::fake::FakeLine;
+    use crate::gpio::monotonic_ns;
+
+    const DEBOUNCE: Duration = Duration::from_millis(20);
+    const TIMEOUT: Duration = Duration::from_millis(200);
+
+    fn fake_pin(level: bool) -> (ReadPin, crate::gpio::fake::FakeControl) {
+        let (line, control) = FakeLine::new(level);
+        (ReadPin::with_backend("GPIO6", Box::new(line)), control)
+    }
+
+    #[test]
+    fn test_read_follows_line() {
+        let (mut pin, control) = fake_pin(false);
+        assert!(pin.is_low().unwrap());
+        control.set(true);
+        assert!(pin.is_high().unwrap());
+    }
+
+    #[test]
+    fn test_bounces_collapse_to_one_edge() {
+        let (mut pin, control) = fake_pin(false);
+        for level in [true, false, true, false, true] {
+            control.set(level);
+        }
+
+        let event = pin.wait_for_edge(None, DEBOUNCE, TIMEOUT).unwrap().unwrap();
+        assert_eq!(event.edge, Edge::Rising);
+        // Nothing left over from the bounces
+        assert!(pin.wait_for_edge(None, DEBOUNCE, DEBOUNCE).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_glitch_is_ignored() {
+        let (mut pin, control) = fake_pin(false);
+        control.set(true);
+        control.set(false);
+        assert!(pin.wait_for_edge(None, DEBOUNCE, Duration::from_millis(60)).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_waits_for_requested_direction() {
+        let (mut pin, control) = fake_pin(false);
+        let driver = control.clone();
+        let handle = std::thread::spawn(move || {
+            driver.set(true);
+            std::thread::sleep(Duration::from_millis(50));
+            driver.set(false);
+        });
+
+        let event = pin.wait_for_edge(Some(Edge::Falling), DEBOUNCE, TIMEOUT).unwrap().unwrap();
+        assert_eq!(event.edge, Edge::Falling);
+        assert!(!control.level());
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_edge_keeps_first_timestamp() {
+        let (mut pin, control) = fake_pin(true);
+        control.set(false);
+        std::thread::sleep(Duration::from_millis(5));
+        let mark = monotonic_ns();
+        control.set(true);
+        control.set(false);
+
+        let event = pin.wait_for_edge(Some(Edge::Falling), DEBOUNCE, TIMEOUT).unwrap().unwrap();
+        assert_eq!(event.edge, Edge::Falling);
+        assert!(event.timestamp_ns < mark);
+    }
+
+    #[test]
+    fn test_take_edges_drains_queue() {
+        let (mut pin, control) = fake_pin(false);
+        control.set(true);
+        control.set(false);
+
+        let edges = pin.take_edges();
+        assert_eq!(edges.iter().map(|e| e.edge).collect::<Vec<_>>(), [Edge::Rising, Edge::Falling]);
+        assert!(edges[0].timestamp_ns <= edges[1].timestamp_ns);
+        assert!(pin.take_edges().is_empty());
+    }
+
+    #[test]
+    fn test_unavailable_line_errors() {
+        let mut pin = ReadPin::with_backend("GPIO99", Box::new(Unavailable("GPIO99".into())));
+        assert!(matches!(pin.read(), Err(PinError::Unavailable(_))));
+        assert!(pin.wait_for_edge(None, DEBOUNCE, TIMEOUT).is_err());
+        assert!(pin.take_edges().is_empty());
+    }
+}
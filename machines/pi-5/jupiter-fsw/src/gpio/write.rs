@@ -1,48 +1,76 @@
 #![warn(missing_docs)]
 
-use log::{info, warn};
+use embedded_hal::digital::{ErrorType, OutputPin};
+use log::{debug, error};
 
-use super::Pin;
-use std::process::Command;
+use super::{LineBackend, Pin, PinError, Unavailable};
 
+/// An output line. The line stays reserved and driven for as long as this is alive.
 pub struct WritePin {
     pin: String,
+    line: Box<dyn LineBackend>,
 }
 
 impl WritePin {
-    pub(super) fn new<T: Into<String>>(pin: T) -> Self {
-        WritePin { pin: pin.into() }
+    /// Wrap an already opened line, used for fakes in tests
+    pub fn with_backend(pin: &str, line: Box<dyn LineBackend>) -> Self {
+        WritePin {
+            pin: pin.to_string(),
+            line,
+        }
+    }
+
+    /// The line's name, as it was given to [`Pin::new`]
+    pub fn pin(&self) -> &str {
+        &self.pin
     }
 }
 
 impl From<Pin> for WritePin {
     fn from(pin: Pin) -> Self {
-        Self::new(pin.pin())
+        let name = pin.pin().to_string();
+        // Outputs come up inactive, the same as the old gpioset calls left them
+        pin.output(false).unwrap_or_else(|e| {
+            error!("Failed to reserve output {name}: {e:?}");
+            Self::with_backend(&name, Box::new(Unavailable(name.clone())))
+        })
     }
 }
 
 impl WritePin {
-    pub fn write(&self, high: bool) -> Result<(), super::PinError> {
-        let arg = format!("{}={}", self.pin, if high { "active" } else { "inactive" });
-        info!("Executing command: gpioset -c 0 {arg}");
-        let mut cmd = Command::new("gpioset")
-            .arg("-c")
-            .arg("0")
-            .arg(arg)
-            .spawn()
-            .map_err(|e| {
-                warn!("Failed to spawn command: {e}");
-                super::PinError::IoError(e)
-            })?;
-
-        // Wait for 200ms
-        std::thread::sleep(std::time::Duration::from_millis(1000));
-
-        // Try and kill the child
-        if let Err(e) = cmd.kill() {
-            warn!("Failed to kill process (already dead?): {e}");
-        }
+    pub fn write(&self, high: bool) -> Result<(), PinError> {
+        debug!("Setting {} {}", self.pin, if high { "active" } else { "inactive" });
+        self.line.set(high)
+    }
+}
+
+impl ErrorType for WritePin {
+    type Error = PinError;
+}
 
-        Ok(())
+impl OutputPin for WritePin {
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.write(true)
     }
-}
\ No newline at end of file
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.write(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpio::fake::FakeLine;
+
+    #[test]
+    fn test_write_drives_line() {
+        let (line, control) = FakeLine::new(false);
+        let mut pin = WritePin::with_backend("GPIO12", Box::new(line));
+
+        pin.write(true).unwrap();
+        assert!(control.level());
+        pin.set_low().unwrap();
+        assert!(!control.level());
+    }
+}
@@ -0,0 +1,5 @@
+#![warn(missing_docs)]
+
+//! Parts of the JUPITER flight software that are also useful on the ground
+
+pub mod solve;
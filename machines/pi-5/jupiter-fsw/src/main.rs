@@ -7,19 +7,18 @@ use std::{
 };
 
 use aether::color;
-use avionics::lsm6dsl::Lsm6DslAccel;
 use bin_packets::{
-    data::status, device::{PacketReader, PacketWriter, std::Device}, packets::ApplicationPacket
+    data::{BoardHealth, JupiterStatus, status}, device::{PacketReader, PacketWriter, std::Device}, packets::ApplicationPacket
 };
-use common_states::rbf::ActiveHighRbf;
-use constants::{EJECTION_IND_PIN, RBF_PIN};
+use common_states::rbf_monitor::RbfLine;
+use constants::{CHECKPOINT_PATH, EJECTION_IND_PIN, RBF_PIN};
 use data::packets::OnboardPacketStorage;
 use env_logger::Env;
 
 use gpio::{Pin, read::ReadPin, write::WritePin};
 use i2cdev::linux::LinuxI2CDevice;
-use states::JupiterStateMachine;
-use tasks::{Atmega, spawn_camera_thread, InfratrackerThread};
+use states::{JupiterStateMachine, checkpoint::CheckpointStore};
+use tasks::{Atmega, camera_task, InfratrackerThread, Policy, Subsystem, Supervisor, spawn_watchdog};
 use std::sync::atomic::{AtomicBool, Ordering};
 
 mod avionics;
@@ -30,11 +29,14 @@ mod states;
 mod tasks;
 mod timing;
 
+use data::commands::{CommandLink, RetryPolicy};
 use data::status::ExperimentColorState;
-use log::{error, info};
+use data::telemetry::{LinkBudget, TelemetryScheduler};
+use log::{error, info, warn};
 use tasks::{RbfTask, GpioHardware, LogMonitor, TRACKING};
 use bin_packets::commands::CommandPacket;
 use avionics::imu::{AvionicsImuManager, IMUError};
+use avionics::flight_events::{DetectorConfig, FlightEvent, FlightEventDetector, TimeCheckPolicy};
 
 use std::rc::Rc;
 use std::cell::RefCell;
@@ -46,6 +48,27 @@ static SERIAL_PORT: &str = "/dev/ttyS0";
 
 pub const STATUS_INTERVAL: u64 = 1000;
 
+// How often the RBF pin is read, in ms
+const RBF_INTERVAL: u64 = 100;
+
+// The main loop runs every 100ms, anything past this is a hang rather than a slow iteration
+const MAIN_LOOP_WATCHDOG: Duration = Duration::from_secs(5);
+
+// 115200 baud is 11.5kB/s, the rest is left for commands the states write straight to the port.
+// The port is the Ejector's UART, so only status and commands are scheduled onto it.
+const SERIAL_BUDGET: LinkBudget = LinkBudget {
+    bytes_per_second: 8000,
+    burst_bytes: 512,
+};
+
+// A detected launch, once its burnout confirms it, moves the mission clock if it's off by more
+// than a couple of seconds. Power on is a guessed 150s before launch, past 180s out the detection
+// is the likelier mistake.
+const LAUNCH_TIME_CHECK: TimeCheckPolicy = TimeCheckPolicy {
+    tolerance: 2,
+    max_correction: 180,
+};
+
 fn main() {
     let env = Env::default().filter_or("LOG_LEVEL", "info");
     env_logger::init_from_env(env);
@@ -87,34 +110,65 @@ fn main() {
     #[cfg(not(feature = "legacy_atmega"))]
     let hardware = GpioHardware::new();
 
-    // Main camera
-    // spawn_camera_thread();
+    let mut supervisor = Supervisor::new();
+
+    // Main camera. ffmpeg backs off for up to 10s between failed starts.
+    // supervisor.supervise(Subsystem::MainCam, Duration::from_secs(30), Policy::Restart { max_restarts: 5, backoff: Duration::from_secs(5) }, camera_task);
     // TRACKING.store(true, Ordering::Relaxed);
 
-    let mut avionics = match AvionicsImuManager::new() {
+    // Sensors are read on their own thread at their full rate, every sample is logged onboard.
+    // None of it goes down the Ejector's UART, the Ejector has nowhere to send it.
+    let imu_samples = match AvionicsImuManager::new() {
         Ok(manager) => {
-            info!("Avionics IMU Manager initialized successfully.");
-            Some(manager)
+            info!("Avionics IMU Manager initialized successfully, sampling at {}Hz.", manager.sample_rate_hz());
+            Some(manager.spawn(startup))
         }
         Err(e) => {
             match e {
                 IMUError::BusFailed(i2c_err) => error!("IMU Init Failed (I2C Bus Error): {:?}", i2c_err),
                 IMUError::SensorFailed(adxl_err) => error!("IMU Init Failed (ADXL375 Error): {:?}", adxl_err),
                 IMUError::BMIFail(bmi_err) => error!("IMU Init Failed (BMI323 Error): {:?}", bmi_err),
+                IMUError::Iio(iio_err) => error!("IMU Init Failed (IIO Error): {:?}", iio_err),
+                IMUError::NoSensors => error!("IMU Init Failed: no sensors"),
             }
             None
         }
     };
 
+    let mut flight_events = FlightEventDetector::new(DetectorConfig::default());
+
     let mut onboard_packet_storage = OnboardPacketStorage::get_current_run();
 
     let (infratracker_thread, infratracker_packet_rx) = InfratrackerThread::new();
-    let infratracker_handle = infratracker_thread.begin_startracking();
+    // Grabs can take up to 5s to time out
+    supervisor.supervise(
+        Subsystem::Infratracker,
+        Duration::from_secs(20),
+        Policy::Restart { max_restarts: 5, backoff: Duration::from_secs(5) },
+        move |heartbeat| infratracker_thread.run(heartbeat),
+    );
+
+    let imu_heartbeat = supervisor.monitor(Subsystem::Imu, Duration::from_secs(2));
+    let guard_heartbeat = supervisor.monitor(Subsystem::Guard, Duration::from_secs(10));
+    let serial_heartbeat = supervisor.monitor(Subsystem::Serial, Duration::from_secs(10));
+
+    // A hung main loop can't be restarted from inside, so let the service do it and resume from
+    // the checkpoint
+    let main_heartbeat = spawn_watchdog(MAIN_LOOP_WATCHDOG, || {
+        error!("Main loop hung, exiting for a restart");
+        std::process::exit(1);
+    });
+
+    let rbf = RbfTask::new(RbfLine::active_high(Pin::new(RBF_PIN).into())).spawn(RBF_INTERVAL);
+
+    let commands = Rc::new(RefCell::new(CommandLink::new(RetryPolicy::EJECTOR)));
 
     let mut state_machine = JupiterStateMachine::new(
         hardware, 
         ejection_pin, 
-        Rc::clone(&interface)
+        Rc::clone(&interface),
+        Rc::clone(&commands),
+        CheckpointStore::new(CHECKPOINT_PATH),
     );
     let mut counter = 0;
 
@@ -127,16 +181,35 @@ fn main() {
 
     let mut last_rgb_options = color_status.current_status();
 
+    let mut downlink = TelemetryScheduler::jupiter(SERIAL_BUDGET);
+
     let mut last_update = Instant::now();
     let status_interval = Duration::from_millis(STATUS_INTERVAL);
 
+    let mut status_number: u16 = 0;
+    let mut phase = state_machine.phase();
+    let mut phase_since = startup;
+    let mut overflowed = 0;
+
 
     loop {
+        main_heartbeat.beat();
+
         if let Some(iface) = interface.borrow_mut().as_mut() {
             while let Some(packet) = iface.read() {
+                serial_heartbeat.beat();
                 match &packet {
-                    ApplicationPacket::GeigerData { .. } => color_status.feed_geiger(),
-                    ApplicationPacket::ThermocoupleData { .. }=> color_status.feed_thermocouple(),
+                    ApplicationPacket::GeigerData { .. } => {
+                        color_status.feed_geiger();
+                        guard_heartbeat.beat();
+                    }
+                    ApplicationPacket::ThermocoupleData { .. }=> {
+                        color_status.feed_thermocouple();
+                        guard_heartbeat.beat();
+                    }
+                    ApplicationPacket::CommandAck { seq, command, reply, phase } => {
+                        commands.borrow_mut().receive(*seq, *command, *reply, *phase);
+                    }
                     _ => {}
                 }
 
@@ -148,6 +221,7 @@ fn main() {
         // Update geiger feed either if we get a geiger packet through serial, or have file update
         if guard_monitor.is_updated() {
             color_status.feed_geiger();
+            guard_heartbeat.beat();
         }
 
 
@@ -165,47 +239,38 @@ fn main() {
             info!("Got a infratracker packet: {quat:?}");
         }
 
-        if let Some(ref mut a) = avionics {
-            let imu_data = a.read_all(startup);
+        if let Some(ref samples) = imu_samples {
             let mut imu_alive = false;
 
-            // if let Some(packet) = imu_data.high_range {
-            //     imu_alive = true;
-            //     onboard_packet_storage.write(packet.clone());
-                
-            //     #[cfg(feature = "packet_logging")]
-            //     info!("Got High-G Accel packet: {packet:?}");
-            // } else {
-            //     #[cfg(feature = "packet_logging")]
-            //     error!("Read failure: High-G (ADXL375) missing from read_all");
-            // }
-
-            if let Some(packet) = imu_data.low_range {
-                imu_alive = true;
-                onboard_packet_storage.write(packet.clone());
-                
-                #[cfg(feature = "packet_logging")]
-                info!("Got Low-G Accel packet: {packet:?}");
-            } else {
-                #[cfg(feature = "packet_logging")]
-                error!("Read failure: Low-G (BMI323) Accel missing from read_all");
-            }
+            while let Ok(imu_data) = samples.try_recv() {
+                if let Some(fused) = imu_data.fused
+                    && let Some(detected) = flight_events.update(imu_data.elapsed, fused.vector)
+                {
+                    let accel_t = flight_events.mission_time(startup.elapsed()).unwrap_or_default();
+                    let estimate_t = timing::t_time_estimate();
+                    info!("Accelerometer saw {:?}, now T{accel_t:+} by it and T{estimate_t:+} by the timer", detected.event);
+
+                    // Only a launch its burnout has confirmed gets to move the clock
+                    if detected.event == FlightEvent::Burnout {
+                        let te1 = state_machine.indicators().te1();
+                        LAUNCH_TIME_CHECK.correct_clock(&detected, &flight_events, startup.elapsed(), state_machine.phase(), te1);
+                    }
+                }
+
+                for packet in imu_data.packets() {
+                    imu_alive = true;
+                    onboard_packet_storage.write(packet);
 
-            if let Some(packet) = imu_data.gyro {
-                imu_alive = true;
-                onboard_packet_storage.write(packet.clone());
-                
-                #[cfg(feature = "packet_logging")]
-                info!("Got Low-G Gyro packet: {packet:?}");
-            } else {
-                #[cfg(feature = "packet_logging")]
-                error!("Read failure: Low-G (BMI323) Gyro missing from read_all");
+                    #[cfg(feature = "packet_logging")]
+                    info!("Got IMU packet: {packet:?}");
+                }
             }
 
             // If any data gotten from IMU's, update health
             if imu_alive {
                 // info!("Avionics alive");
                 color_status.feed_avionics();
+                imu_heartbeat.beat();
             }
         }
 
@@ -217,16 +282,72 @@ fn main() {
         let now = Instant::now();
 
         // Send new rgb colors on state change
+        if rgb_options != last_rgb_options {
+            downlink.offer(commands.borrow_mut().stamp(CommandPacket::ColorSet(rgb_options)), now.duration_since(startup));
+            last_rgb_options = rgb_options;
+        }
+
+        if state_machine.phase() != phase {
+            phase = state_machine.phase();
+            phase_since = now;
+        }
+
         if now.duration_since(last_update) >= status_interval {
+            let health = supervisor.check();
+            color_status.feed_health(health);
+
+            let stats = downlink.stats();
+            let mut faults = 0;
+            if health.degraded != 0 {
+                faults |= JupiterStatus::FAULT_SUBSYSTEM;
+            }
+            if commands.borrow().timed_out() {
+                faults |= JupiterStatus::FAULT_COMMAND;
+            }
+            if stats.overflowed > overflowed {
+                faults |= JupiterStatus::FAULT_QUEUE_FULL;
+            }
+            overflowed = stats.overflowed;
+
+            let status_packet = ApplicationPacket::JupiterStatus(JupiterStatus {
+                phase,
+                health: BoardHealth {
+                    uptime_ms: now.duration_since(startup).as_millis() as u64,
+                    time_in_phase_ms: now.duration_since(phase_since).as_millis() as u64,
+                    packet_number: status_number,
+                    rbf_inserted: Some(rbf.read().into()),
+                    // Nothing on Jupiter measures the battery, the Atmega only drives its latch
+                    battery_mv: None,
+                    faults,
+                    queue_depth: stats.depth.min(u16::MAX as usize) as u16,
+                    queue_max: stats.max_depth.min(u16::MAX as usize) as u16,
+                    last_command: commands.borrow().last_seq(),
+                },
+                subsystems: health,
+            });
+            status_number = status_number.wrapping_add(1);
+            onboard_packet_storage.write(status_packet);
+
             let current_rgb_options = color_status.current_status();
+            last_rgb_options = current_rgb_options;
 
             // info!("Status update");
-            if let Some(iface) = interface.borrow_mut().as_mut() {
-                if let Err(e) = iface.write(ApplicationPacket::Command(CommandPacket::ColorSet(current_rgb_options))) {
-                    error!("Failed to write color packet down: {e}");
+            downlink.offer(commands.borrow_mut().stamp(CommandPacket::ColorSet(current_rgb_options)), now.duration_since(startup));
+            downlink.offer(status_packet, now.duration_since(startup));
+
+            info!(
+                "Downlink: {} queued (max {}), {} sent, {} thinned, {} rate limited, {} overflowed",
+                stats.depth, stats.max_depth, stats.sent, stats.decimated, stats.rate_limited, stats.overflowed
+            );
+            last_update = now;
+        }
+
+        if let Some(iface) = interface.borrow_mut().as_mut() {
+            while let Some(packet) = downlink.poll(now.duration_since(startup)) {
+                if let Err(e) = iface.write(packet) {
+                    error!("Failed to write {} packet down: {e}", packet.name());
                 }
             }
-            last_update = now;
         }
 
         if counter % 10 == 0 {
@@ -0,0 +1,116 @@
+#![warn(missing_docs)]
+
+//! Finding and loading saved frames
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use image::{ImageBuffer, Luma};
+
+/// Prefix the infratracker thread saves frames under, followed by the unix ms they were grabbed
+pub const FRAME_PREFIX: &str = "infratracker";
+
+/// A frame on disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Where it is
+    pub path: PathBuf,
+    /// When it was grabbed, in unix ms, if the file name says
+    pub timestamp: Option<u64>,
+}
+
+impl Frame {
+    /// A frame at `path`, taking the timestamp from the name if it's one the thread saved
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let timestamp = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.strip_prefix(FRAME_PREFIX))
+            .and_then(|stamp| stamp.parse().ok());
+        Self { path, timestamp }
+    }
+
+    /// Read the frame in as 8-bit mono, the same as the solver sees live
+    pub fn load(&self) -> image::ImageResult<ImageBuffer<Luma<u8>, Vec<u8>>> {
+        Ok(image::open(&self.path)?.to_luma8())
+    }
+}
+
+fn is_tiff(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("tiff") || ext.eq_ignore_ascii_case("tif"))
+}
+
+/// Every TIFF named in `paths`, with directories expanded to the TIFFs directly inside them.
+/// Frames come back in the order they were grabbed where the names say, by name otherwise.
+/// The thread's dark frame lives alongside the frames and isn't one, so it's left out.
+pub fn frames_in<P: AsRef<Path>>(paths: &[P]) -> io::Result<Vec<Frame>> {
+    let mut frames = Vec::new();
+
+    for path in paths {
+        let path = path.as_ref();
+        if path.is_dir() {
+            for entry in fs::read_dir(path)? {
+                let entry = entry?.path();
+                if entry.is_file() && is_tiff(&entry) && !is_dark_frame(&entry) {
+                    frames.push(Frame::new(entry));
+                }
+            }
+        } else if path.exists() {
+            frames.push(Frame::new(path));
+        } else {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} not found", path.display())));
+        }
+    }
+
+    frames.sort_by(|a, b| (a.timestamp, &a.path).cmp(&(b.timestamp, &b.path)));
+    frames.dedup();
+    Ok(frames)
+}
+
+fn is_dark_frame(path: &Path) -> bool {
+    path.file_stem().and_then(|stem| stem.to_str()) == Some("dark_frame")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("jupiter_frames_{}_{name}", std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_timestamp_from_name() {
+        assert_eq!(Frame::new("/x/infratracker1750000000123.tiff").timestamp, Some(1_750_000_000_123));
+        assert_eq!(Frame::new("/x/bench_shot.tiff").timestamp, None);
+    }
+
+    #[test]
+    fn test_directory_listing() {
+        let dir = temp_dir("listing");
+        for name in ["infratracker300.tiff", "infratracker20.tiff", "dark_frame.tiff", "notes.txt", "b.TIF"] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+
+        let frames = frames_in(&[&dir]).unwrap();
+        let names: Vec<_> = frames
+            .iter()
+            .map(|f| f.path.file_name().unwrap().to_str().unwrap())
+            .collect();
+        // Unstamped frames first, then in the order they were grabbed
+        assert_eq!(names, ["b.TIF", "infratracker20.tiff", "infratracker300.tiff"]);
+
+        // Naming a frame twice doesn't solve it twice
+        let again = frames_in(&[dir.clone(), dir.join("infratracker20.tiff")]).unwrap();
+        assert_eq!(again.len(), 3);
+
+        assert!(frames_in(&[dir.join("missing.tiff")]).is_err());
+    }
+}
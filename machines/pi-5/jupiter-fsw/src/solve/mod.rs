@@ -0,0 +1,351 @@
+#![warn(missing_docs)]
+
+//! The infratracker's attitude solve, from a frame in to an attitude out: dark subtraction,
+//! centroiding, undistortion, pyramid star identification and QUEST. The flight thread runs it on
+//! live frames, and `infratracker-solve` runs it over saved ones.
+
+pub mod frames;
+pub mod report;
+pub mod residual;
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use image::{ImageBuffer, Luma};
+
+use aether::attitude::Quaternion;
+use aether::reference_frame::{Body, ICRF};
+use wayfarer::perception::camera_model::CameraModel;
+use wayfarer::perception::centroiding::Starfinder;
+use wayfarer::startrack::quest::quest_real;
+use wayfarer::startrack::solver::Startracker;
+
+use DarkAverager::{DarkError, ImageAveragerFromBuffer};
+
+use frames::Frame;
+use residual::Residuals;
+
+/// An 8-bit mono frame, as grabbed and as saved
+pub type GrayFrame = ImageBuffer<Luma<u8>, Vec<u8>>;
+
+/// Attitude of the body frame relative to ICRF
+pub type Attitude = Quaternion<f32, ICRF<f32>, Body<f32>>;
+
+/// How long each step of a solve took
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SolveTiming {
+    /// Dark frame subtraction and bad pixel correction
+    pub dark: Duration,
+    /// Centroiding and undistortion
+    pub centroid: Duration,
+    /// Star identification and QUEST
+    pub solve: Duration,
+}
+
+impl SolveTiming {
+    /// The whole solve
+    pub fn total(&self) -> Duration {
+        self.dark + self.centroid + self.solve
+    }
+}
+
+/// Why a frame didn't give an attitude
+#[derive(Debug)]
+pub enum SolveError {
+    /// The frame couldn't be read
+    Load(image::ImageError),
+    /// The dark frame didn't fit the image
+    Dark(DarkError),
+    /// Star identification failed
+    Identify(String),
+}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolveError::Load(e) => write!(f, "frame not loaded: {e}"),
+            SolveError::Dark(e) => write!(f, "dark frame not applied: {e}"),
+            SolveError::Identify(e) => write!(f, "pyramid solve failed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for SolveError {}
+
+impl From<image::ImageError> for SolveError {
+    fn from(e: image::ImageError) -> Self {
+        SolveError::Load(e)
+    }
+}
+
+/// Everything learned solving one frame
+#[derive(Debug)]
+pub struct FrameSolution {
+    /// Solved attitude, or why there isn't one
+    pub attitude: Result<Attitude, SolveError>,
+    /// Centroids found in the frame
+    pub stars_found: usize,
+    /// Centroids matched to the catalog
+    pub stars_matched: usize,
+    /// Fit of the attitude to the matched stars, zero when there's no attitude
+    pub residuals: Residuals,
+    /// Time spent in each step
+    pub timing: SolveTiming,
+}
+
+impl FrameSolution {
+    fn failed(error: SolveError, timing: SolveTiming) -> Self {
+        Self {
+            attitude: Err(error),
+            stars_found: 0,
+            stars_matched: 0,
+            residuals: Residuals::default(),
+            timing,
+        }
+    }
+
+    /// The attitude as `[w, i, j, k]`, the way it's downlinked
+    pub fn quaternion(&self) -> Option<[f32; 4]> {
+        self.attitude.as_ref().ok().map(|q| [q.w(), q.i(), q.j(), q.k()])
+    }
+}
+
+// Catalog and observed vectors come back from the solver as 3-vectors
+fn components<V: std::ops::Index<usize, Output = f32>>(v: &V) -> [f32; 3] {
+    [v[0], v[1], v[2]]
+}
+
+/// The solve, set up once and run on as many frames as needed
+pub struct SolvePipeline {
+    finder: Starfinder,
+    model: CameraModel,
+    tracker: Startracker,
+    dark: Option<ImageAveragerFromBuffer>,
+}
+
+impl Default for SolvePipeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SolvePipeline {
+    /// The flight configuration, without a dark frame
+    pub fn new() -> Self {
+        Self {
+            finder: Starfinder::default(),
+            model: CameraModel::default(),
+            tracker: Startracker::default(),
+            dark: None,
+        }
+    }
+
+    /// Subtract this dark frame, and correct its bad pixels, before centroiding
+    pub fn with_dark(mut self, dark: ImageAveragerFromBuffer) -> Self {
+        self.dark = Some(dark);
+        self
+    }
+
+    /// The dark frame in use, if any
+    pub fn dark(&self) -> Option<&ImageAveragerFromBuffer> {
+        self.dark.as_ref()
+    }
+
+    /// Solve a frame in place. The frame is left dark subtracted.
+    pub fn solve(&self, img: &mut GrayFrame) -> FrameSolution {
+        let mut timing = SolveTiming::default();
+
+        let start = Instant::now();
+        if let Some(dark) = &self.dark
+            && let Err(e) = dark.apply_average(img)
+        {
+            timing.dark = start.elapsed();
+            return FrameSolution::failed(SolveError::Dark(e), timing);
+        }
+        timing.dark = start.elapsed();
+
+        let start = Instant::now();
+        let mut centroids = self.finder.star_find(img);
+        self.model.undistort_centroids(&mut centroids);
+        let stars_found = centroids.len();
+        timing.centroid = start.elapsed();
+
+        let start = Instant::now();
+        let result = self.tracker.adaptive_pyramid_solve(centroids);
+        let (attitude, stars_matched, residuals) = match result {
+            Ok((refs, body)) => {
+                let q: Attitude = quest_real(&refs, &body);
+                let refs: Vec<_> = refs.iter().map(components).collect();
+                let body: Vec<_> = body.iter().map(components).collect();
+                let fit = residual::residuals([q.w(), q.i(), q.j(), q.k()], &refs, &body);
+                (Ok(q), refs.len(), fit)
+            }
+            Err(e) => (Err(SolveError::Identify(e.to_string())), 0, Residuals::default()),
+        };
+        timing.solve = start.elapsed();
+
+        FrameSolution {
+            attitude,
+            stars_found,
+            stars_matched,
+            residuals,
+            timing,
+        }
+    }
+
+    /// Load a saved frame and solve it
+    pub fn solve_frame(&self, frame: &Frame) -> FrameSolution {
+        match frame.load() {
+            Ok(mut img) => self.solve(&mut img),
+            Err(e) => FrameSolution::failed(e.into(), SolveTiming::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use camera::{Camera, CatalogStar, Optics, SensorModel, StarCatalog, SyntheticCamera, TriggerMode};
+
+    use super::*;
+    use crate::solve::frames::Frame;
+    use crate::solve::report::{CSV_HEADER, write_row};
+
+    // Orion, from the Hipparcos positions, as ra_deg, dec_deg, magnitude. The belt and sword are
+    // in view of `orion_camera`, the shoulders and feet are off the edge.
+    const ORION: [(f32, f32, f32); 14] = [
+        (83.0017, -0.2991, 2.23),
+        (84.0534, -1.2019, 1.69),
+        (85.1897, -1.9426, 1.74),
+        (84.6865, -2.6000, 3.77),
+        (81.1192, -2.3971, 3.35),
+        (83.8458, -4.8383, 4.59),
+        (83.8186, -5.3897, 5.13),
+        (83.8583, -5.9099, 2.77),
+        (84.7965, 4.1216, 4.57),
+        (86.9391, -9.6696, 2.06),
+        (81.2828, 6.3497, 1.64),
+        (88.7929, 7.4071, 0.42),
+        (78.6345, -8.2016, 0.13),
+        (83.7845, 9.9342, 3.39),
+    ];
+
+    fn catalog(stars: &[(f32, f32, f32)]) -> StarCatalog {
+        StarCatalog::new(
+            stars
+                .iter()
+                .map(|&(ra, dec, magnitude)| CatalogStar::from_ra_dec(ra, dec, magnitude))
+                .collect(),
+        )
+    }
+
+    // Body +z on (ra, dec), with body x as close to reference +x as it gets
+    fn pointing(ra_deg: f32, dec_deg: f32) -> [f32; 4] {
+        let z = CatalogStar::from_ra_dec(ra_deg, dec_deg, 0.0).direction;
+        let x = [1.0 - z[0] * z[0], -z[0] * z[1], -z[0] * z[2]];
+        let norm = x.iter().map(|c| c * c).sum::<f32>().sqrt();
+        let x = x.map(|c| c / norm);
+        let y = [
+            z[1] * x[2] - z[2] * x[1],
+            z[2] * x[0] - z[0] * x[2],
+            z[0] * x[1] - z[1] * x[0],
+        ];
+
+        // Rows of the attitude matrix are the body axes
+        let w = 0.5 * (1.0 + x[0] + y[1] + z[2]).sqrt();
+        [w, (y[2] - z[1]) / (4.0 * w), (z[0] - x[2]) / (4.0 * w), (x[1] - y[0]) / (4.0 * w)]
+    }
+
+    // Rotation between two attitudes in degrees, whichever sign either quaternion has
+    fn separation_deg(a: [f32; 4], b: [f32; 4]) -> f32 {
+        let dot: f32 = a.iter().zip(&b).map(|(a, b)| a * b).sum();
+        2.0 * dot.abs().min(1.0).acos().to_degrees()
+    }
+
+    // The camera the synthetic_camera build flies, on Orion's belt
+    fn orion_camera(stars: &[(f32, f32, f32)], sensor: SensorModel) -> SyntheticCamera {
+        let camera = SyntheticCamera::new(catalog(stars), Optics::with_fov(1600, 1200, 12.0), sensor);
+        camera.attitude().set(pointing(84.0, -2.5));
+        camera
+    }
+
+    #[test]
+    fn test_solves_synthetic_camera_frames() {
+        let mut camera = orion_camera(&ORION, SensorModel::default());
+        let truth = camera.attitude().get();
+        camera.set_trigger_mode(TriggerMode::Software).unwrap();
+        camera.start().unwrap();
+
+        let pipeline = SolvePipeline::new();
+        for _ in 0..3 {
+            camera.trigger().unwrap();
+            let mut frame = camera.grab(Duration::from_secs(1)).unwrap();
+            let solution = pipeline.solve(&mut frame.image);
+
+            let q = solution
+                .quaternion()
+                .unwrap_or_else(|| panic!("no attitude: {:?}", solution.attitude));
+            assert!(separation_deg(q, truth) < 0.1, "{q:?} against {truth:?}");
+        }
+    }
+
+    // Solve one ideal frame of `stars` at the Orion pointing, returning the true attitude too
+    fn solve_rendered(stars: &[(f32, f32, f32)]) -> (FrameSolution, [f32; 4]) {
+        let mut camera = orion_camera(stars, SensorModel::ideal());
+        let truth = camera.attitude().get();
+        let mut image = camera.render();
+        (SolvePipeline::new().solve(&mut image), truth)
+    }
+
+    fn csv_row(solution: &FrameSolution) -> Vec<String> {
+        let mut out = Vec::new();
+        write_row(&mut out, &Frame::new("infratracker1750000000123.tiff"), solution).unwrap();
+        let row = String::from_utf8(out).unwrap();
+        let columns: Vec<_> = row.trim_end().split(',').map(str::to_string).collect();
+        assert_eq!(columns.len(), CSV_HEADER.split(',').count());
+        columns
+    }
+
+    #[test]
+    fn test_known_frame() {
+        let (solution, truth) = solve_rendered(&ORION);
+
+        let q = solution
+            .quaternion()
+            .unwrap_or_else(|| panic!("no attitude: {:?}", solution.attitude));
+        assert!(separation_deg(q, truth) < 0.05, "{q:?} against {truth:?}");
+
+        // Eight stars are in view, and a noiseless frame should give up most of them
+        assert!((4..=8).contains(&solution.stars_found), "found {}", solution.stars_found);
+        assert!(solution.stars_matched >= 4 && solution.stars_matched <= solution.stars_found);
+        assert!(solution.residuals.rms_arcsec < 30.0, "{:?}", solution.residuals);
+        assert!(solution.residuals.max_arcsec >= solution.residuals.rms_arcsec);
+
+        let columns = csv_row(&solution);
+        assert_eq!(columns[1], "1750000000123");
+        assert_eq!(columns[2], "true");
+        let solved: Vec<f32> = columns[3..7].iter().map(|c| c.parse().unwrap()).collect();
+        assert_eq!(solved, q);
+        assert_eq!(columns[7], solution.stars_found.to_string());
+        assert_eq!(columns[8], solution.stars_matched.to_string());
+        assert_eq!(columns[15], "");
+    }
+
+    #[test]
+    fn test_too_few_stars() {
+        // Just Alnilam and Mintaka, not enough for a pyramid
+        let (solution, _) = solve_rendered(&ORION[..2]);
+
+        assert!(matches!(solution.attitude, Err(SolveError::Identify(_))), "{:?}", solution.attitude);
+        assert_eq!(solution.quaternion(), None);
+        assert_eq!(solution.stars_found, 2);
+        assert_eq!(solution.stars_matched, 0);
+        assert_eq!(solution.residuals, Residuals::default());
+
+        let columns = csv_row(&solution);
+        assert_eq!(columns[2], "false");
+        assert!(columns[3..7].iter().all(|c| c.is_empty()));
+        assert!(!columns[15].is_empty());
+    }
+}
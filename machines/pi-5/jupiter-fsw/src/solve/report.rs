@@ -0,0 +1,138 @@
+#![warn(missing_docs)]
+
+//! Tabulating solves, one CSV row per frame and a summary at the end
+
+use std::io::{self, Write};
+use std::time::Duration;
+
+use super::FrameSolution;
+use super::frames::Frame;
+
+/// Column names, matching [`write_row`]
+pub const CSV_HEADER: &str =
+    "file,timestamp_ms,solved,w,i,j,k,stars_found,stars_matched,rms_arcsec,max_arcsec,dark_ms,centroid_ms,solve_ms,total_ms,error";
+
+fn ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+/// Write one frame's solve as a CSV row
+pub fn write_row<W: Write>(out: &mut W, frame: &Frame, solution: &FrameSolution) -> io::Result<()> {
+    let timestamp = frame.timestamp.map(|t| t.to_string()).unwrap_or_default();
+    let [w, i, j, k] = solution
+        .quaternion()
+        .map(|q| q.map(|c| c.to_string()))
+        .unwrap_or_default();
+    // Commas would split the column
+    let error = solution
+        .attitude
+        .as_ref()
+        .err()
+        .map(|e| e.to_string().replace(',', ";"))
+        .unwrap_or_default();
+    let timing = &solution.timing;
+
+    writeln!(
+        out,
+        "{},{timestamp},{},{w},{i},{j},{k},{},{},{:.2},{:.2},{:.2},{:.2},{:.2},{:.2},{error}",
+        frame.path.display(),
+        solution.attitude.is_ok(),
+        solution.stars_found,
+        solution.stars_matched,
+        solution.residuals.rms_arcsec,
+        solution.residuals.max_arcsec,
+        ms(timing.dark),
+        ms(timing.centroid),
+        ms(timing.solve),
+        ms(timing.total()),
+    )
+}
+
+/// Totals over a batch of solves
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Summary {
+    /// Frames attempted
+    pub frames: usize,
+    /// Frames that gave an attitude
+    pub solved: usize,
+    /// Mean of the per-frame RMS residual over solved frames, in arcseconds
+    pub mean_rms_arcsec: f32,
+    /// Mean matched stars over solved frames
+    pub mean_matched: f32,
+    /// Mean time per frame, solved or not
+    pub mean_total: Duration,
+    /// Slowest frame
+    pub max_total: Duration,
+}
+
+impl Summary {
+    /// Add a frame's solve in
+    pub fn add(&mut self, solution: &FrameSolution) {
+        let total = solution.timing.total();
+        self.mean_total = (self.mean_total * self.frames as u32 + total) / (self.frames as u32 + 1);
+        self.max_total = self.max_total.max(total);
+        self.frames += 1;
+
+        if solution.attitude.is_ok() {
+            let n = self.solved as f32;
+            self.mean_rms_arcsec = (self.mean_rms_arcsec * n + solution.residuals.rms_arcsec) / (n + 1.0);
+            self.mean_matched = (self.mean_matched * n + solution.stars_matched as f32) / (n + 1.0);
+            self.solved += 1;
+        }
+    }
+
+    /// Share of frames solved, zero with no frames
+    pub fn solve_rate(&self) -> f32 {
+        if self.frames == 0 {
+            return 0.0;
+        }
+        self.solved as f32 / self.frames as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solve::residual::Residuals;
+    use crate::solve::{SolveError, SolveTiming};
+
+    fn failed(total_ms: u64) -> FrameSolution {
+        FrameSolution {
+            attitude: Err(SolveError::Identify("too few stars, need 4".to_string())),
+            stars_found: 2,
+            stars_matched: 0,
+            residuals: Residuals::default(),
+            timing: SolveTiming {
+                solve: Duration::from_millis(total_ms),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_failed_row() {
+        let mut out = Vec::new();
+        write_row(&mut out, &Frame::new("infratracker42.tiff"), &failed(3)).unwrap();
+        let row = String::from_utf8(out).unwrap();
+
+        let columns: Vec<_> = row.trim_end().split(',').collect();
+        assert_eq!(columns.len(), CSV_HEADER.split(',').count());
+        assert_eq!(columns[1], "42");
+        assert_eq!(columns[2], "false");
+        assert_eq!(columns[14], "3.00");
+        assert!(columns[15].contains("too few stars; need 4"));
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut summary = Summary::default();
+        assert_eq!(summary.solve_rate(), 0.0);
+
+        summary.add(&failed(10));
+        summary.add(&failed(30));
+        assert_eq!(summary.frames, 2);
+        assert_eq!(summary.solved, 0);
+        assert_eq!(summary.mean_total, Duration::from_millis(20));
+        assert_eq!(summary.max_total, Duration::from_millis(30));
+    }
+}
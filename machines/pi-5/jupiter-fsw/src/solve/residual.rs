@@ -0,0 +1,124 @@
+#![warn(missing_docs)]
+
+//! How well a solved attitude fits the stars it was solved from
+
+/// Arcseconds in a radian
+const ARCSEC_PER_RAD: f32 = 206_264.8;
+
+/// Angular misfit between matched catalog and observed stars
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Residuals {
+    /// Root mean square over all matched stars, in arcseconds
+    pub rms_arcsec: f32,
+    /// Worst single star, in arcseconds
+    pub max_arcsec: f32,
+}
+
+/// Rotation taking reference frame vectors into the body frame for a `[w, i, j, k]` quaternion,
+/// using the same convention as QUEST (body = A * reference)
+pub fn attitude_matrix(q: [f32; 4]) -> [[f32; 3]; 3] {
+    let [w, x, y, z] = q;
+    [
+        [w * w + x * x - y * y - z * z, 2.0 * (x * y + w * z), 2.0 * (x * z - w * y)],
+        [2.0 * (x * y - w * z), w * w - x * x + y * y - z * z, 2.0 * (y * z + w * x)],
+        [2.0 * (x * z + w * y), 2.0 * (y * z - w * x), w * w - x * x - y * y + z * z],
+    ]
+}
+
+fn normalized(v: [f32; 3]) -> [f32; 3] {
+    let norm = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    if norm == 0.0 {
+        return v;
+    }
+    [v[0] / norm, v[1] / norm, v[2] / norm]
+}
+
+// Angle between two unit vectors, stable for the tiny angles that matter here
+fn angle_between(a: [f32; 3], b: [f32; 3]) -> f32 {
+    let cross = [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ];
+    let sin = (cross[0] * cross[0] + cross[1] * cross[1] + cross[2] * cross[2]).sqrt();
+    let cos = a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+    sin.atan2(cos)
+}
+
+/// Residuals of `q` against pairs of catalog (`references`) and observed (`body`) unit vectors.
+/// Pairs past the end of the shorter list are ignored.
+pub fn residuals(q: [f32; 4], references: &[[f32; 3]], body: &[[f32; 3]]) -> Residuals {
+    let a = attitude_matrix(q);
+    let mut sum_sq = 0.0;
+    let mut max: f32 = 0.0;
+    let mut count = 0;
+
+    for (r, b) in references.iter().zip(body) {
+        let r = normalized(*r);
+        let predicted = [
+            a[0][0] * r[0] + a[0][1] * r[1] + a[0][2] * r[2],
+            a[1][0] * r[0] + a[1][1] * r[1] + a[1][2] * r[2],
+            a[2][0] * r[0] + a[2][1] * r[1] + a[2][2] * r[2],
+        ];
+        let err = angle_between(normalized(predicted), normalized(*b)) * ARCSEC_PER_RAD;
+        sum_sq += err * err;
+        max = max.max(err);
+        count += 1;
+    }
+
+    if count == 0 {
+        return Residuals::default();
+    }
+    Residuals {
+        rms_arcsec: (sum_sq / count as f32).sqrt(),
+        max_arcsec: max,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rotate(q: [f32; 4], v: [f32; 3]) -> [f32; 3] {
+        let a = attitude_matrix(q);
+        [
+            a[0][0] * v[0] + a[0][1] * v[1] + a[0][2] * v[2],
+            a[1][0] * v[0] + a[1][1] * v[1] + a[1][2] * v[2],
+            a[2][0] * v[0] + a[2][1] * v[1] + a[2][2] * v[2],
+        ]
+    }
+
+    #[test]
+    fn test_identity_is_identity() {
+        assert_eq!(attitude_matrix([1.0, 0.0, 0.0, 0.0]), [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]);
+    }
+
+    #[test]
+    fn test_exact_fit_has_no_residual() {
+        // 90 degrees about z
+        let half = std::f32::consts::FRAC_PI_4;
+        let q = [half.cos(), 0.0, 0.0, half.sin()];
+        let refs = [[1.0, 0.0, 0.0], [0.0, 0.6, 0.8], [0.3, -0.4, 0.866]];
+        let body: Vec<_> = refs.iter().map(|r| rotate(q, *r)).collect();
+
+        // Passive rotation, so the x axis shows up along -y in the body frame
+        assert!((body[0][1] + 1.0).abs() < 1e-6);
+
+        let fit = residuals(q, &refs, &body);
+        assert!(fit.rms_arcsec < 1.0, "{fit:?}");
+    }
+
+    #[test]
+    fn test_offset_star_shows_up() {
+        let q = [1.0, 0.0, 0.0, 0.0];
+        let refs = [[0.0, 0.0, 1.0], [0.0, 0.0, 1.0]];
+        // Second star 100 arcseconds off
+        let off = 100.0 / ARCSEC_PER_RAD;
+        let body = [[0.0, 0.0, 1.0], [off.sin(), 0.0, off.cos()]];
+
+        let fit = residuals(q, &refs, &body);
+        assert!((fit.max_arcsec - 100.0).abs() < 0.5, "{fit:?}");
+        assert!((fit.rms_arcsec - 100.0 / 2f32.sqrt()).abs() < 0.5, "{fit:?}");
+        assert_eq!(residuals(q, &[], &[]), Residuals::default());
+    }
+}
@@ -5,40 +5,54 @@ use log::info;
 
 use crate::states::shutdown::Shutdown;
 
-use super::traits::{StateContext, ValidState};
+use super::timeline::{POWEROFF_T, TE3_T};
+use super::traits::{StateContext, Transition, ValidState};
 use crate::tasks::hardware::BoardHardware;
-use crate::{timing::{self, t_time_estimate}};
+use crate::timing;
 
 /// JUPITER PHASE: Battery Power
 ///
-/// S
+/// Running off the battery latch until it's released at T+600
 #[derive(Debug, Clone, Default)]
 pub struct BatteryPower {}
 
 impl BatteryPower {
-    pub fn enter() -> Self {
-        // TE 3 at 347, recalibrate time
-        timing::calibrate_to(347);
-        return Self {}
+    /// Start running on battery, recalibrating if it was TE3 that started it
+    pub fn enter(te3_seen: bool) -> Self {
+        if te3_seen {
+            timing::calibrate_to(TE3_T);
+        }
+        Self {}
     }
 }
 
 // For this - we're going to pull low at estimated T+600 seconds always, it's not informed by a pin
 // the latch is triggered on entering this state, not by this state
-static POWEROFF_T_TIME_SECS: i32 = 600;
-
 impl ValidState for BatteryPower {
     fn phase(&self) -> JupiterPhase {
         JupiterPhase::BatteryPower
     }
 
-    fn next(&self, ctx: &mut StateContext) -> Box<dyn ValidState> {
-        if ctx.t_time > POWEROFF_T_TIME_SECS {
+    fn next(&mut self, ctx: &mut StateContext) -> Transition {
+        if ctx.mission_time() > POWEROFF_T {
             info!("Powering off latch");
-            Box::new(Shutdown::enter())
+            Transition::to(Shutdown::default(), format!("past T+{POWEROFF_T} power off"))
         } else {
-            // No change
-            Box::new(Self::default())
+            Transition::Stay
         }
     }
 }
+
+#[cfg(all(test, not(feature = "legacy_atmega")))]
+mod tests {
+    use super::*;
+    use crate::states::traits::test_support::Bench;
+
+    #[test]
+    fn test_shuts_down_after_poweroff_time() {
+        let mut bench = Bench::new();
+        assert!(matches!(BatteryPower::default().next(bench.at(TE3_T, POWEROFF_T)), Transition::Stay));
+        let transition = BatteryPower::default().next(bench.at(TE3_T, POWEROFF_T + 1));
+        assert_eq!(transition.phase(), Some(JupiterPhase::Shutdown));
+    }
+}
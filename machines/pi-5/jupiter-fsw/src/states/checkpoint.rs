@@ -0,0 +1,302 @@
+#![warn(missing_docs)]
+
+//! Flight state saved to disk on every transition, so a brown-out reboot mid-flight picks up
+//! where it left off instead of starting over at PowerOn with a -150s time guess.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bin_packets::phases::JupiterPhase;
+use serde::{Deserialize, Serialize};
+
+/// Bumped whenever the checkpoint layout changes, older files are ignored
+pub const CHECKPOINT_VERSION: u32 = 1;
+
+/// Anything written longer ago than this is from a previous run, not a reboot. The whole flight
+/// is over in about ten minutes.
+pub const MAX_CHECKPOINT_AGE_MS: i64 = 20 * 60 * 1000;
+
+/// Mission times outside this are nonsense, whatever the file says
+pub const T_TIME_RANGE: std::ops::RangeInclusive<i32> = -1800..=1800;
+
+/// Things that must only ever happen once per flight
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MissionFlags {
+    /// The ejection line has been asserted
+    pub ejection_fired: bool,
+    /// The Ejector acknowledged EjectorPhaseSet(Ejection)
+    pub ejector_phase_sent: bool,
+}
+
+/// Everything needed to resume the state machine after a reboot
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FlightCheckpoint {
+    /// Layout version, see [`CHECKPOINT_VERSION`]
+    pub version: u32,
+    /// Phase the machine was in
+    pub phase: JupiterPhase,
+    /// Mission time when this was written
+    pub t_time: i32,
+    /// Mission time the phase was entered at
+    pub phase_entered_t: i32,
+    /// Offset between time since power on and mission time, as it was before the reboot
+    pub calibration_offset: i32,
+    /// One-shot actions already taken
+    pub flags: MissionFlags,
+    /// Wall-clock time of T+0, in ms since the unix epoch
+    pub t_zero_unix_ms: i64,
+    /// Wall-clock time this was written, in ms since the unix epoch
+    pub written_unix_ms: i64,
+}
+
+/// Why a checkpoint couldn't be used
+#[derive(Debug)]
+pub enum CheckpointError {
+    /// Reading or writing the file failed
+    Io(std::io::Error),
+    /// The file isn't a checkpoint, or was cut short
+    Corrupt(serde_json::Error),
+    /// Written by a different layout
+    Version(u32),
+    /// Written too long ago to be from this flight
+    Stale {
+        /// How long ago it was written
+        age_ms: i64,
+    },
+    /// Written after now by the wall clock, so how long ago it was can't be known
+    ClockBehind {
+        /// How far ahead of the clock it was written
+        ahead_ms: i64,
+    },
+    /// The contents don't add up
+    Invalid(&'static str),
+}
+
+impl From<std::io::Error> for CheckpointError {
+    fn from(e: std::io::Error) -> Self {
+        CheckpointError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for CheckpointError {
+    fn from(e: serde_json::Error) -> Self {
+        CheckpointError::Corrupt(e)
+    }
+}
+
+impl FlightCheckpoint {
+    /// Snapshot the state at `now_unix_ms`
+    pub fn new(
+        phase: JupiterPhase,
+        t_time: i32,
+        phase_entered_t: i32,
+        calibration_offset: i32,
+        flags: MissionFlags,
+        now_unix_ms: i64,
+    ) -> Self {
+        Self {
+            version: CHECKPOINT_VERSION,
+            phase,
+            t_time,
+            phase_entered_t,
+            calibration_offset,
+            flags,
+            t_zero_unix_ms: now_unix_ms - t_time as i64 * 1000,
+            written_unix_ms: now_unix_ms,
+        }
+    }
+
+    /// Check the checkpoint makes sense on its own
+    pub fn validate(&self) -> Result<(), CheckpointError> {
+        if self.version != CHECKPOINT_VERSION {
+            return Err(CheckpointError::Version(self.version));
+        }
+        if !T_TIME_RANGE.contains(&self.t_time) {
+            return Err(CheckpointError::Invalid("mission time out of range"));
+        }
+        if self.phase_entered_t > self.t_time || !T_TIME_RANGE.contains(&self.phase_entered_t) {
+            return Err(CheckpointError::Invalid("phase entered after the checkpoint was written"));
+        }
+        if self.t_zero_unix_ms + self.t_time as i64 * 1000 != self.written_unix_ms {
+            return Err(CheckpointError::Invalid("wall-clock anchor doesn't match mission time"));
+        }
+        if self.flags.ejection_fired
+            && matches!(self.phase, JupiterPhase::PowerOn | JupiterPhase::Launch | JupiterPhase::CamStart)
+        {
+            return Err(CheckpointError::Invalid("ejection fired before despin"));
+        }
+        Ok(())
+    }
+
+    /// Mission time to resume at, given the wall clock now. There's no RTC battery, so a clock
+    /// that's gone backwards could put a checkpoint left over from the bench anywhere; one from
+    /// the future is as untrustworthy as one that's too old.
+    pub fn resume_t_time(&self, now_unix_ms: i64) -> Result<i32, CheckpointError> {
+        self.validate()?;
+
+        let age_ms = now_unix_ms - self.written_unix_ms;
+        if age_ms > MAX_CHECKPOINT_AGE_MS {
+            return Err(CheckpointError::Stale { age_ms });
+        }
+        if age_ms < 0 {
+            return Err(CheckpointError::ClockBehind { ahead_ms: -age_ms });
+        }
+        Ok(((now_unix_ms - self.t_zero_unix_ms) / 1000) as i32)
+    }
+}
+
+/// Where checkpoints live. Writes go to a temporary file first and get renamed over the old
+/// one, so a brown-out mid-write leaves the previous checkpoint in place.
+pub struct CheckpointStore {
+    path: PathBuf,
+}
+
+impl CheckpointStore {
+    /// Store checkpoints at `path`
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Where the checkpoint is kept
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn temp_path(&self) -> PathBuf {
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(".tmp");
+        self.path.with_file_name(name)
+    }
+
+    /// Write the checkpoint, replacing any previous one
+    pub fn save(&self, checkpoint: &FlightCheckpoint) -> Result<(), CheckpointError> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let temp = self.temp_path();
+        let mut file = File::create(&temp)?;
+        file.write_all(&serde_json::to_vec(checkpoint)?)?;
+        file.sync_all()?;
+        fs::rename(&temp, &self.path)?;
+
+        // The rename isn't durable until the directory is synced
+        if let Some(dir) = self.path.parent() {
+            File::open(dir)?.sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// The last checkpoint written, None if there isn't one
+    pub fn load(&self) -> Result<Option<FlightCheckpoint>, CheckpointError> {
+        match fs::read(&self.path) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Remove the checkpoint once the flight is over, so the next power on starts fresh
+    pub fn clear(&self) -> Result<(), CheckpointError> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Wall-clock now, in ms since the unix epoch
+pub fn unix_ms_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_750_000_000_000;
+
+    fn temp_store(name: &str) -> CheckpointStore {
+        let dir = std::env::temp_dir().join(format!("jupiter_checkpoint_{}_{name}", std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        CheckpointStore::new(dir.join("flight_state.json"))
+    }
+
+    fn checkpoint(phase: JupiterPhase, t_time: i32) -> FlightCheckpoint {
+        FlightCheckpoint::new(phase, t_time, t_time, -150, MissionFlags::default(), NOW)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let store = temp_store("round_trip");
+        assert!(store.load().unwrap().is_none());
+
+        let mut saved = checkpoint(JupiterPhase::EjectDeployable, 81);
+        saved.flags.ejection_fired = true;
+        store.save(&saved).unwrap();
+        store.save(&saved).unwrap();
+
+        assert_eq!(store.load().unwrap(), Some(saved));
+        assert!(!store.temp_path().exists());
+
+        store.clear().unwrap();
+        assert!(store.load().unwrap().is_none());
+        store.clear().unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_file() {
+        let store = temp_store("corrupt");
+        store.save(&checkpoint(JupiterPhase::Launch, 10)).unwrap();
+        let bytes = fs::read(store.path()).unwrap();
+        fs::write(store.path(), &bytes[..bytes.len() / 2]).unwrap();
+        assert!(matches!(store.load(), Err(CheckpointError::Corrupt(_))));
+    }
+
+    #[test]
+    fn test_resume_time() {
+        let saved = checkpoint(JupiterPhase::RocketDespin, 78);
+        // Rebooting took 25 seconds
+        assert_eq!(saved.resume_t_time(NOW + 25_000).unwrap(), 103);
+        // Clock went backwards, so there's no telling when it's from
+        assert!(matches!(
+            saved.resume_t_time(NOW - 60_000),
+            Err(CheckpointError::ClockBehind { ahead_ms: 60_000 })
+        ));
+        // Left over from some earlier run
+        assert!(matches!(
+            saved.resume_t_time(NOW + MAX_CHECKPOINT_AGE_MS + 1),
+            Err(CheckpointError::Stale { .. })
+        ));
+    }
+
+    #[test]
+    fn test_sanity_checks() {
+        let mut bad = checkpoint(JupiterPhase::Launch, 10);
+        bad.version = CHECKPOINT_VERSION + 1;
+        assert!(matches!(bad.validate(), Err(CheckpointError::Version(_))));
+
+        let mut bad = checkpoint(JupiterPhase::Launch, 10);
+        bad.t_time = 100_000;
+        assert!(matches!(bad.validate(), Err(CheckpointError::Invalid(_))));
+
+        let mut bad = checkpoint(JupiterPhase::Launch, 10);
+        bad.t_zero_unix_ms += 5000;
+        assert!(bad.validate().is_err());
+
+        let mut bad = checkpoint(JupiterPhase::CamStart, 60);
+        bad.flags.ejection_fired = true;
+        assert!(bad.validate().is_err());
+
+        let mut bad = checkpoint(JupiterPhase::Infratracking, 90);
+        bad.phase_entered_t = 95;
+        assert!(bad.validate().is_err());
+    }
+}
@@ -1,50 +1,254 @@
 #![warn(missing_docs)]
 
 use bin_packets::phases::JupiterPhase;
-use bin_packets::packets::ApplicationPacket;
 use bin_packets::commands::CommandPacket;
 use bin_packets::phases::EjectorPhase;
 use embedded_hal::digital::PinState;
-use bin_packets::device::Device;
 use bin_packets::device::PacketWriter;
 
-use crate::states::{battery_power::BatteryPower, infratracker::InfratrackerStart};
+use crate::data::commands::{Delivery, RetryPolicy};
+use crate::states::infratracker::InfratrackerStart;
+use crate::timing;
 
-use super::traits::{StateContext, ValidState};
+use super::timeline::EJECTION_T;
+use super::traits::{StateContext, Transition, ValidState};
 use log::{info, error};
 
+const EJECT: CommandPacket = CommandPacket::EjectorPhaseSet(EjectorPhase::Ejection);
+
+/// At EJECTION_T, asserts the ejection line and commands the Ejector over the UART. The line is
+/// the hardwired backup and goes up whatever the UART says; the reply is only recorded, and a
+/// missing or bad one logged.
 #[derive(Debug, Clone, Copy, Default)]
-pub struct Ejection {}
+pub struct Ejection {
+    // Sequence number of the eject command, once it's been sent
+    command: Option<u16>,
+}
+
+impl Ejection {
+    // Send the next copy of the command if one is due. None if there's no UART to send it on.
+    fn deliver(ctx: &mut StateContext, seq: u16) -> Option<Delivery> {
+        let mut interface = ctx.interface.borrow_mut();
+        let iface = interface.as_mut()?;
+        let mut link = ctx.commands.borrow_mut();
+
+        if let Some(packet) = link.poll(timing::uptime()) {
+            let attempt = link.attempts();
+            let max = RetryPolicy::EJECTOR.max_attempts;
+            match iface.write(packet) {
+                Ok(_) => info!("Eject command {attempt}/{max} sent to Ejector"),
+                Err(e) => error!("Failed to send Eject command {attempt}/{max} to Ejector over UART: {e}"),
+            }
+        }
+        // Nothing else sends reliably, but if something did it took over the link
+        Some(link.delivery(seq).unwrap_or(Delivery::TimedOut))
+    }
+
+    fn fire(ctx: &mut StateContext) {
+        if let Err(e) = ctx.ejection_pin.write(true) {
+            error!("Failed to assert ejection pin: {:?}", e);
+        } else {
+            ctx.flags.ejection_fired = true;
+        }
+    }
+}
 
 impl ValidState for Ejection {
     fn phase(&self) -> bin_packets::phases::JupiterPhase {
         JupiterPhase::EjectDeployable
     }
 
-    fn next(&self, ctx: &mut StateContext) -> Box<dyn ValidState> {
-        if ctx.t_time >= 83 {
-            info!("Ejection time wait complete, entering infratracker");
-            if let Err(e) = ctx.ejection_pin.write(true) {
-                error!("Failed to assert ejection pin: {:?}", e);
-            }
-            let i = 1;
-            if let Some(iface) = ctx.interface.borrow_mut().as_mut() {
-                // for i in 1..=5 {
-                    let cmd = ApplicationPacket::Command(
-                        CommandPacket::EjectorPhaseSet(EjectorPhase::Ejection)
-                    ); 
-                    
-                    match iface.write(cmd) {
-                        Ok(_) => info!("Eject command {}/5 successfully sent to Ejector.", i),
-                        Err(e) => error!("Failed to send Eject command {}/5 to Ejector over UART: {}", i, e),
-                    }
-                // }
-            } else {
-                error!("Cannot send Eject command: UART interface is unavailable.");
-            }
-            return Box::new(InfratrackerStart::enter());
-        } else {
-            return Box::new(Self::default());
-        }        
+    fn next(&mut self, ctx: &mut StateContext) -> Transition {
+        if ctx.mission_time() < EJECTION_T {
+            return Transition::Stay;
+        }
+
+        // Flags already set on the way in are from before a reboot. The Ejector has what it needs
+        // either way, but the pin only counts as asserted once it has been.
+        if self.command.is_none() && (ctx.flags.ejection_fired || ctx.flags.ejector_phase_sent) {
+            info!("Ejection already done before a reboot, not commanding the Ejector again");
+            if !ctx.flags.ejection_fired {
+                Self::fire(ctx);
+            }
+            return Transition::to(InfratrackerStart::enter(), format!("T+{EJECTION_T} ejection"));
+        }
+
+        let seq = match self.command {
+            Some(seq) => seq,
+            None => {
+                info!("Ejection time wait complete, asserting ejection pin and commanding the Ejector");
+                Self::fire(ctx);
+                let seq = ctx.commands.borrow_mut().send(EJECT);
+                self.command = Some(seq);
+                seq
+            }
+        };
+
+        match Self::deliver(ctx, seq) {
+            Some(Delivery::Pending) => return Transition::Stay,
+            Some(Delivery::Acked(EjectorPhase::Ejection)) => {
+                info!("Ejector acknowledged the Eject command");
+                ctx.flags.ejector_phase_sent = true;
+            }
+            Some(Delivery::Acked(phase)) => {
+                error!("Ejector acknowledged the Eject command but is in {phase:?}, relying on the ejection pin");
+            }
+            Some(Delivery::Nacked { reason, phase }) => {
+                error!("Ejector refused the Eject command ({reason:?}) in {phase:?}, relying on the ejection pin");
+            }
+            Some(Delivery::TimedOut) => {
+                error!("No reply from the Ejector to the Eject command, relying on the ejection pin");
+            }
+            None => {
+                error!("Cannot send Eject command: UART interface is unavailable, relying on the ejection pin");
+            }
+        }
+
+        // Again on the way out, in case the first write failed
+        if !ctx.flags.ejection_fired {
+            Self::fire(ctx);
+        }
+        info!("Entering infratracker");
+        Transition::to(InfratrackerStart::enter(), format!("T+{EJECTION_T} ejection"))
+    }
+}
+
+#[cfg(all(test, not(feature = "legacy_atmega")))]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use bin_packets::commands::{CommandReply, NackReason};
+
+    use super::*;
+    use crate::data::commands::CommandLink;
+    use crate::states::traits::test_support::Bench;
+
+    // A bench with a UART and a link that gives up after two copies a few ms apart. Returns the
+    // bytes the UART has taken.
+    fn uart_bench() -> (Bench, Arc<Mutex<Vec<u8>>>) {
+        let mut bench = Bench::new();
+        let written = bench.with_uart();
+        *bench.ctx.commands.borrow_mut() = CommandLink::new(RetryPolicy {
+            timeout: Duration::from_millis(5),
+            max_attempts: 2,
+        });
+        (bench, written)
+    }
+
+    // Send the command, checking the pin is already up while the reply is awaited
+    fn send(bench: &mut Bench, ejection: &mut Ejection) -> u16 {
+        assert!(matches!(ejection.next(bench.at(82, EJECTION_T)), Transition::Stay));
+        assert!(bench.ejection_line.level());
+        assert!(bench.ctx.flags.ejection_fired);
+        ejection.command.unwrap()
+    }
+
+    fn reply(bench: &mut Bench, seq: u16, reply: CommandReply, phase: EjectorPhase) {
+        assert!(bench.ctx.commands.borrow_mut().receive(seq, EJECT, reply, phase));
+    }
+
+    #[test]
+    fn test_fires_at_ejection_time() {
+        let mut bench = Bench::new();
+        assert!(matches!(Ejection::default().next(bench.at(82, EJECTION_T - 1)), Transition::Stay));
+        assert!(!bench.ejection_line.level());
+
+        let transition = Ejection::default().next(bench.at(82, EJECTION_T));
+        assert_eq!(transition.phase(), Some(JupiterPhase::Infratracking));
+        assert!(bench.ejection_line.level());
+        assert!(bench.ctx.flags.ejection_fired);
+        // No UART on the bench, so it went straight to the pin
+        assert!(!bench.ctx.flags.ejector_phase_sent);
+    }
+
+    #[test]
+    fn test_acked_still_fires() {
+        let (mut bench, written) = uart_bench();
+        let mut ejection = Ejection::default();
+        let seq = send(&mut bench, &mut ejection);
+        assert!(!written.lock().unwrap().is_empty());
+
+        reply(&mut bench, seq, CommandReply::Ack, EjectorPhase::Ejection);
+        let transition = ejection.next(bench.at(82, EJECTION_T));
+        assert_eq!(transition.phase(), Some(JupiterPhase::Infratracking));
+        assert!(bench.ejection_line.level());
+        assert!(bench.ctx.flags.ejector_phase_sent);
+        assert!(bench.ctx.flags.ejection_fired);
+    }
+
+    #[test]
+    fn test_acked_in_wrong_phase_fires() {
+        let (mut bench, _) = uart_bench();
+        let mut ejection = Ejection::default();
+        let seq = send(&mut bench, &mut ejection);
+
+        reply(&mut bench, seq, CommandReply::Ack, EjectorPhase::Standby);
+        let transition = ejection.next(bench.at(82, EJECTION_T));
+        assert_eq!(transition.phase(), Some(JupiterPhase::Infratracking));
+        assert!(bench.ejection_line.level());
+        assert!(!bench.ctx.flags.ejector_phase_sent);
+    }
+
+    #[test]
+    fn test_nacked_fires() {
+        let (mut bench, _) = uart_bench();
+        let mut ejection = Ejection::default();
+        let seq = send(&mut bench, &mut ejection);
+
+        reply(&mut bench, seq, CommandReply::Nack(NackReason::InvalidPhase), EjectorPhase::Standby);
+        let transition = ejection.next(bench.at(82, EJECTION_T));
+        assert_eq!(transition.phase(), Some(JupiterPhase::Infratracking));
+        assert!(bench.ejection_line.level());
+        assert!(!bench.ctx.flags.ejector_phase_sent);
+    }
+
+    #[test]
+    fn test_retries_exhausted_fires() {
+        let (mut bench, _) = uart_bench();
+        let mut ejection = Ejection::default();
+        send(&mut bench, &mut ejection);
+
+        // Two copies 5ms apart, then 5ms more waiting on the last
+        let mut transition = Transition::Stay;
+        for _ in 0..100 {
+            std::thread::sleep(Duration::from_millis(1));
+            transition = ejection.next(bench.at(82, EJECTION_T));
+            if !matches!(transition, Transition::Stay) {
+                break;
+            }
+        }
+        assert_eq!(transition.phase(), Some(JupiterPhase::Infratracking));
+        assert_eq!(bench.ctx.commands.borrow().attempts(), 2);
+        assert!(bench.ctx.commands.borrow().timed_out());
+        assert!(bench.ejection_line.level());
+        assert!(!bench.ctx.flags.ejector_phase_sent);
+    }
+
+    #[test]
+    fn test_superseded_command_times_out_and_fires() {
+        let (mut bench, _) = uart_bench();
+        let mut ejection = Ejection::default();
+        send(&mut bench, &mut ejection);
+
+        // Something else took over the link, so no reply to the eject will ever count
+        bench.ctx.commands.borrow_mut().send(CommandPacket::EjectorPhaseSet(EjectorPhase::Standby));
+        let transition = ejection.next(bench.at(82, EJECTION_T));
+        assert_eq!(transition.phase(), Some(JupiterPhase::Infratracking));
+        assert!(bench.ejection_line.level());
+        assert!(!bench.ctx.flags.ejector_phase_sent);
+    }
+
+    #[test]
+    fn test_acknowledged_before_reboot_still_fires() {
+        let (mut bench, written) = uart_bench();
+        bench.ctx.flags.ejector_phase_sent = true;
+
+        // The pin never went high before the reboot, so it does now, without a second Eject
+        let transition = Ejection::default().next(bench.at(82, EJECTION_T + 1));
+        assert_eq!(transition.phase(), Some(JupiterPhase::Infratracking));
+        assert!(bench.ejection_line.level());
+        assert!(bench.ctx.flags.ejection_fired);
+        assert!(written.lock().unwrap().is_empty());
     }
 }
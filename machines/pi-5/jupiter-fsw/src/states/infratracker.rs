@@ -3,12 +3,14 @@
 use std::sync::atomic::Ordering;
 
 use bin_packets::phases::JupiterPhase;
-use log::info;
+use embedded_hal::digital::PinState;
+use log::{info, warn};
 
-use crate::{states::{battery_power::BatteryPower, shutdown::Shutdown}, tasks::{TRACKING,BoardHardware}, timing::POWER_ON_TIME};
+use crate::{states::{battery_power::BatteryPower, shutdown::Shutdown}, tasks::{TRACKING,BoardHardware}};
 
+use super::timeline::{INFRATRACKER_STOP_T, POWEROFF_T, TE3_TIMEOUT_T};
 use super::{
-    traits::{StateContext, ValidState},
+    traits::{StateContext, Transition, ValidState},
 };
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -20,37 +22,88 @@ impl InfratrackerStart {
     pub fn enter() -> Self {
         info!("Infratracker start - Begin tracking");
         TRACKING.store(true, Ordering::Relaxed);
-        return  Self{tracking:  true};
+        Self { tracking: true }
     }
-}
-static POWEROFF_T_TIME_SECS: i32 = 600;
-static INFRATRACKER_STOP: i32 = 342;
 
+    /// Pick back up after a reboot, tracking only if it wouldn't have stopped yet
+    pub fn resume(t_time: i32) -> Self {
+        let tracking = t_time <= INFRATRACKER_STOP_T;
+        TRACKING.store(tracking, Ordering::Relaxed);
+        Self { tracking }
+    }
+}
 
 impl ValidState for InfratrackerStart {
     fn phase(&self) -> JupiterPhase {
-        return JupiterPhase::Infratracking;
+        JupiterPhase::Infratracking
     }
 
-    fn next(&self, ctx: &mut StateContext) -> Box<dyn ValidState> {
-        let mut next_tracking_state = self.tracking;
-
-        if self.tracking && (ctx.t_time > INFRATRACKER_STOP) {
-            info!("T+342s: InfraTracker Power Down");
+    fn next(&mut self, ctx: &mut StateContext) -> Transition {
+        if self.tracking && ctx.mission_time() > INFRATRACKER_STOP_T {
+            info!("T+{INFRATRACKER_STOP_T}s: InfraTracker Power Down");
             TRACKING.store(false, Ordering::Relaxed);
-            next_tracking_state = false;
+            self.tracking = false;
         }
-        if ctx.t_time > POWEROFF_T_TIME_SECS {
+
+        if ctx.mission_time() > POWEROFF_T {
             info!("System Shutdown");
-           ctx.hardware.deactivate_latch();
-            Box::new(Shutdown::enter())
-        } else if ctx.hardware.pins().unwrap_or_default().te3().into() {
-            // No change
+            ctx.hardware.deactivate_latch();
+            Transition::to(Shutdown::default(), format!("past T+{POWEROFF_T} power off"))
+        } else if ctx.hardware.pins().unwrap_or_default().te3() == PinState::High {
             ctx.hardware.activate_latch();
-            Box::new(BatteryPower::enter())
- 
+            Transition::to(BatteryPower::enter(true), "TE3 high")
+        } else if ctx.mission_time() >= TE3_TIMEOUT_T {
+            warn!("No TE3 by T+{TE3_TIMEOUT_T}, latching battery power without it");
+            ctx.hardware.activate_latch();
+            Transition::to(BatteryPower::enter(false), format!("TE3 timeout at T+{TE3_TIMEOUT_T}"))
         } else {
-            Box::new(Self { tracking: next_tracking_state })
+            Transition::Stay
         }
     }
 }
+
+#[cfg(all(test, not(feature = "legacy_atmega")))]
+mod tests {
+    use super::*;
+    use crate::states::timeline::{EJECTION_T, TE3_T};
+    use crate::states::traits::test_support::Bench;
+
+    #[test]
+    fn test_tracking_stops_in_place() {
+        let mut bench = Bench::new();
+        let mut state = InfratrackerStart::enter();
+        assert!(matches!(state.next(bench.at(EJECTION_T, INFRATRACKER_STOP_T + 1)), Transition::Stay));
+        assert!(!state.tracking);
+        assert!(!TRACKING.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_te3_latches_battery() {
+        let mut bench = Bench::new();
+        bench.board.te3.set(true);
+        let transition = InfratrackerStart::enter().next(bench.at(EJECTION_T, 340));
+        assert_eq!(transition.phase(), Some(JupiterPhase::BatteryPower));
+        assert!(bench.board.battery_latch.level() && bench.board.battery_latch_2.level());
+        assert!((TE3_T..=TE3_T + 1).contains(&crate::timing::t_time_estimate()));
+    }
+
+    #[test]
+    fn test_te3_timeout() {
+        let mut bench = Bench::new();
+        let mut state = InfratrackerStart::enter();
+        assert!(matches!(state.next(bench.at(EJECTION_T, TE3_TIMEOUT_T - 1)), Transition::Stay));
+
+        let transition = state.next(bench.at(EJECTION_T, TE3_TIMEOUT_T));
+        assert_eq!(transition.phase(), Some(JupiterPhase::BatteryPower));
+        assert!(bench.board.battery_latch.level());
+    }
+
+    #[test]
+    fn test_poweroff_beats_te3() {
+        let mut bench = Bench::new();
+        bench.board.te3.set(true);
+        let transition = InfratrackerStart::enter().next(bench.at(EJECTION_T, POWEROFF_T + 1));
+        assert_eq!(transition.phase(), Some(JupiterPhase::Shutdown));
+        assert!(!bench.board.battery_latch.level());
+    }
+}
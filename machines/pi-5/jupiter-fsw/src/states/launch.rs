@@ -3,7 +3,8 @@ use embedded_hal::digital::PinState;
 
 use crate::{states::secondary_cam::StartCameraRecording, tasks::BoardHardware}; //skirt_seperation::SkirtSeperation};
 
-use super::traits::{StateContext, ValidState};
+use super::timeline::CAM_START_T;
+use super::traits::{StateContext, Transition, ValidState};
 
 use log::info;
 
@@ -14,16 +15,33 @@ pub struct Launch {}
 
 impl ValidState for Launch {
     fn phase(&self) -> JupiterPhase {
-        return JupiterPhase::Launch;
+        JupiterPhase::Launch
     }
 
-    fn next(&self, ctx: &mut StateContext) -> Box<dyn ValidState> {
-        if ctx.t_time >= 50 {
+    fn next(&mut self, ctx: &mut StateContext) -> Transition {
+        if ctx.mission_time() >= CAM_START_T {
             info!("50 seconds since launch, start cam");
             ctx.hardware.cams_on();
-            return Box::new(StartCameraRecording::default());
+            Transition::to(StartCameraRecording::default(), format!("T+{CAM_START_T} camera start"))
         } else {
-            return Box::new(Self::default());
+            Transition::Stay
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(all(test, not(feature = "legacy_atmega")))]
+mod tests {
+    use super::*;
+    use crate::states::traits::test_support::Bench;
+
+    #[test]
+    fn test_cameras_start_on_time() {
+        let mut bench = Bench::new();
+        assert!(matches!(Launch::default().next(bench.at(1, CAM_START_T - 1)), Transition::Stay));
+        assert!(!bench.board.cam_active.level());
+
+        let transition = Launch::default().next(bench.at(1, CAM_START_T));
+        assert_eq!(transition.phase(), Some(JupiterPhase::CamStart));
+        assert!(bench.board.cam_active.level());
+    }
+}
@@ -5,7 +5,7 @@ use embedded_hal::digital::PinState;
 
 use super::{
     //skirt_seperation::SkirtSeperation,
-    traits::{StateContext, ValidState},
+    traits::{StateContext, Transition, ValidState},
 };
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -16,7 +16,7 @@ impl ValidState for MainCam {
         todo!()
     }
 
-    fn next(&self, ctx: &mut StateContext) -> Box<dyn ValidState> {
+    fn next(&mut self, ctx: &mut StateContext) -> Transition {
         todo!()
     }
 }
@@ -1,7 +1,7 @@
 #![warn(missing_docs)]
 
 use bin_packets::phases::JupiterPhase;
-use traits::{StateContext, ValidState};
+use traits::{StateContext, Transition, ValidState};
 
 mod battery_power;
 mod ejection;
@@ -14,13 +14,21 @@ mod secondary_cam;
 mod launch;
 mod infratracker;
 
+pub mod timeline;
+
+pub mod checkpoint;
 pub mod traits;
 pub use power_on::*;
 
+use checkpoint::{CheckpointStore, FlightCheckpoint, unix_ms_now};
+use common_states::indicators::IndicatorStates;
+use log::{error, info, warn};
+
 use crate::{
+    data::commands::CommandLink,
     gpio::write::WritePin,
     tasks::Atmega,
-    timing::t_time_estimate,
+    timing::{self, t_time_estimate},
 };
 
 use crate::tasks::{ActiveHardware, BoardHardware};
@@ -32,32 +40,345 @@ use serialport::SerialPort;
 pub struct JupiterStateMachine {
     state: Box<dyn ValidState>,
     context: StateContext,
+    checkpoints: CheckpointStore,
+    // The clock's calibration as last written out. The clock can be moved from outside the
+    // states, by a detected launch, and a reboot has to resume from where it was moved to.
+    checkpointed_offset: i32,
 }
 
 impl JupiterStateMachine {
-    /// Create a new state machine from a pin provider
+    /// Create a new state machine from a pin provider, resuming from the last checkpoint if
+    /// there's a usable one
     pub fn new(
         atmega: ActiveHardware, 
         ejection_pin: WritePin, 
-        interface: Rc<RefCell<Option<Device<Box<dyn SerialPort>>>>>
+        interface: Rc<RefCell<Option<Device<Box<dyn SerialPort>>>>>,
+        commands: Rc<RefCell<CommandLink>>,
+        checkpoints: CheckpointStore,
     ) -> Self {
-        let mut ctx = StateContext::new(atmega, ejection_pin, interface);
-        let state = PowerOn::enter(&mut ctx);
+        let mut ctx = StateContext::new(atmega, ejection_pin, interface, commands);
+
+        let state = match load_checkpoint(&checkpoints) {
+            Some((checkpoint, t_time)) => {
+                timing::calibrate_to(t_time);
+                ctx.t_time = t_time;
+                ctx.entered_t = checkpoint.phase_entered_t;
+                ctx.flags = checkpoint.flags;
+                resume(&checkpoint, &mut ctx)
+            }
+            None => PowerOn::enter(&mut ctx),
+        };
 
-        Self {
+        let mut machine = Self {
             state,
             context: ctx,
-        }
+            checkpoints,
+            checkpointed_offset: timing::calibration_offset(),
+        };
+        machine.checkpoint();
+        machine
     }
 
     /// Update the state machine
     pub fn update(&mut self) {
         self.context.t_time = t_time_estimate();
-        self.state = self.state.next(&mut self.context);
+        let flags = self.context.flags;
+
+        match self.state.next(&mut self.context) {
+            Transition::Stay => {
+                if self.context.flags != flags || timing::calibration_offset() != self.checkpointed_offset {
+                    self.checkpoint();
+                }
+            }
+            Transition::To(next, reason) => {
+                info!(
+                    "{:?} -> {:?} at T{:+} after {}s: {reason}",
+                    self.phase(),
+                    next.phase(),
+                    self.context.t_time,
+                    self.context.time_in_state()
+                );
+                self.state = next;
+                // Entering a state can recalibrate, so take the time after
+                self.context.t_time = t_time_estimate();
+                self.context.entered_t = self.context.t_time;
+                self.checkpoint();
+            }
+        }
+    }
+
+    // Write out where we are. Shutdown is the end of the flight, so the checkpoint goes away
+    // rather than having the next power on resume into it.
+    fn checkpoint(&mut self) {
+        self.checkpointed_offset = timing::calibration_offset();
+        if self.phase() == JupiterPhase::Shutdown {
+            if let Err(e) = self.checkpoints.clear() {
+                error!("Failed to clear flight checkpoint: {e:?}");
+            }
+            return;
+        }
+
+        let checkpoint = FlightCheckpoint::new(
+            self.phase(),
+            t_time_estimate(),
+            self.context.entered_t,
+            self.checkpointed_offset,
+            self.context.flags,
+            unix_ms_now(),
+        );
+        if let Err(e) = self.checkpoints.save(&checkpoint) {
+            error!("Failed to save flight checkpoint: {e:?}");
+        }
     }
 
     /// Get the current phase
     pub fn phase(&self) -> JupiterPhase {
         self.state.phase()
     }
+
+    /// Read the indicators now, all low if they can't be read
+    pub fn indicators(&mut self) -> IndicatorStates {
+        self.context.hardware.pins().unwrap_or_else(|_| IndicatorStates::none())
+    }
+}
+
+// A checkpoint worth resuming from, and the mission time to resume at
+fn load_checkpoint(store: &CheckpointStore) -> Option<(FlightCheckpoint, i32)> {
+    let checkpoint = match store.load() {
+        Ok(Some(checkpoint)) => checkpoint,
+        Ok(None) => return None,
+        Err(e) => {
+            warn!("Ignoring unreadable flight checkpoint: {e:?}");
+            return None;
+        }
+    };
+
+    match checkpoint.resume_t_time(unix_ms_now()) {
+        Ok(t_time) => {
+            warn!(
+                "Resuming {:?} at T{t_time:+} after a reboot (checkpoint written at T{:+}, offset was {})",
+                checkpoint.phase, checkpoint.t_time, checkpoint.calibration_offset
+            );
+            Some((checkpoint, t_time))
+        }
+        Err(e) => {
+            warn!("Ignoring flight checkpoint: {e:?}");
+            None
+        }
+    }
+}
+
+// Rebuild the state a checkpoint was taken in. Goes around each state's enter so nothing gets
+// recalibrated, but puts back any hardware state that doesn't survive a reboot. Time in state
+// comes back with `entered_t`.
+fn resume(checkpoint: &FlightCheckpoint, ctx: &mut StateContext) -> Box<dyn ValidState> {
+    if ctx.flags.ejection_fired {
+        // The indicator line comes up low at boot
+        if let Err(e) = ctx.ejection_pin.write(true) {
+            error!("Failed to reassert ejection pin: {:?}", e);
+        }
+    }
+
+    match checkpoint.phase {
+        JupiterPhase::PowerOn => PowerOn::enter(ctx),
+        JupiterPhase::Launch => Box::new(launch::Launch::default()),
+        JupiterPhase::CamStart => {
+            ctx.hardware.cams_on();
+            Box::new(secondary_cam::StartCameraRecording::default())
+        }
+        JupiterPhase::RocketDespin => Box::new(rocket_despin::RocketDespin::default()),
+        JupiterPhase::EjectDeployable => Box::new(ejection::Ejection::default()),
+        JupiterPhase::Infratracking => Box::new(infratracker::InfratrackerStart::resume(ctx.t_time)),
+        JupiterPhase::BatteryPower => {
+            ctx.hardware.activate_latch();
+            Box::new(battery_power::BatteryPower::default())
+        }
+        JupiterPhase::Shutdown => Box::new(shutdown::Shutdown::default()),
+    }
+}
+
+#[cfg(all(test, not(feature = "legacy_atmega")))]
+mod tests {
+    use super::*;
+    use crate::data::commands::RetryPolicy;
+    use crate::gpio::fake::{FakeControl, FakeLine};
+    use crate::states::checkpoint::MissionFlags;
+    use crate::tasks::GpioHardware;
+
+    fn temp_store(name: &str) -> CheckpointStore {
+        let dir = std::env::temp_dir().join(format!("jupiter_reboot_{}_{name}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        CheckpointStore::new(dir.join("flight_state.json"))
+    }
+
+    // Boots the machine the way main does, against pins that aren't there and a fake ejection line
+    fn boot(store: &CheckpointStore) -> (JupiterStateMachine, FakeControl) {
+        let (line, control) = FakeLine::new(false);
+        let machine = JupiterStateMachine::new(
+            GpioHardware::new(),
+            WritePin::with_backend("GPIO12", Box::new(line)),
+            Rc::new(RefCell::new(None)),
+            Rc::new(RefCell::new(CommandLink::new(RetryPolicy::EJECTOR))),
+            CheckpointStore::new(store.path()),
+        );
+        (machine, control)
+    }
+
+    // What a reboot does to the clock
+    fn power_cycle() {
+        timing::calibrate_to(-150);
+    }
+
+    fn write_checkpoint(store: &CheckpointStore, phase: JupiterPhase, t_time: i32, entered: i32, flags: MissionFlags) {
+        let checkpoint = FlightCheckpoint::new(phase, t_time, entered, -150, flags, unix_ms_now());
+        store.save(&checkpoint).unwrap();
+    }
+
+    #[test]
+    fn test_reboot_resumes_phase_and_time() {
+        let _clock = timing::lock_clock();
+        let store = temp_store("resume");
+
+        timing::calibrate_to(-5);
+        let (mut machine, _) = boot(&store);
+        assert_eq!(machine.phase(), JupiterPhase::PowerOn);
+
+        timing::calibrate_to(2);
+        machine.update();
+        assert_eq!(machine.phase(), JupiterPhase::Launch);
+        drop(machine);
+
+        power_cycle();
+        let (machine, _) = boot(&store);
+        assert_eq!(machine.phase(), JupiterPhase::Launch);
+        assert!((2..=4).contains(&t_time_estimate()));
+    }
+
+    #[test]
+    fn test_clock_correction_survives_reboot() {
+        let _clock = timing::lock_clock();
+        let store = temp_store("correction");
+        write_checkpoint(&store, JupiterPhase::Launch, 2, 0, MissionFlags::default());
+
+        power_cycle();
+        let (mut machine, _) = boot(&store);
+        assert_eq!(machine.phase(), JupiterPhase::Launch);
+
+        // A detected launch moves the clock without the states knowing
+        timing::calibrate_to(20);
+        machine.update();
+        assert_eq!(machine.phase(), JupiterPhase::Launch);
+        drop(machine);
+
+        power_cycle();
+        let (machine, _) = boot(&store);
+        assert_eq!(machine.phase(), JupiterPhase::Launch);
+        assert!((20..=22).contains(&t_time_estimate()));
+    }
+
+    #[test]
+    fn test_reboot_after_ejection_does_not_refire() {
+        let _clock = timing::lock_clock();
+        let store = temp_store("ejection");
+        let flags = MissionFlags {
+            ejection_fired: true,
+            ejector_phase_sent: true,
+        };
+        write_checkpoint(&store, JupiterPhase::EjectDeployable, 84, 81, flags);
+
+        power_cycle();
+        let (mut machine, ejection_line) = boot(&store);
+        assert_eq!(machine.phase(), JupiterPhase::EjectDeployable);
+        // Put back high, since the line comes up low on boot
+        assert!(ejection_line.level());
+
+        machine.update();
+        assert_eq!(machine.phase(), JupiterPhase::Infratracking);
+        assert_eq!(store.load().unwrap().unwrap().flags, flags);
+    }
+
+    #[test]
+    fn test_despin_keeps_entry_time_across_reboot() {
+        let _clock = timing::lock_clock();
+        let store = temp_store("despin");
+        write_checkpoint(&store, JupiterPhase::RocketDespin, 80, 78, MissionFlags::default());
+
+        power_cycle();
+        let (mut machine, _) = boot(&store);
+        // Not recalibrated back to TE2 by re-entering despin
+        assert!((80..=81).contains(&t_time_estimate()));
+
+        timing::calibrate_to(80);
+        machine.update();
+        assert_eq!(machine.phase(), JupiterPhase::RocketDespin);
+
+        timing::calibrate_to(82);
+        machine.update();
+        assert_eq!(machine.phase(), JupiterPhase::EjectDeployable);
+    }
+
+    #[test]
+    fn test_stale_or_corrupt_checkpoint_starts_fresh() {
+        let _clock = timing::lock_clock();
+        let store = temp_store("stale");
+        let mut stale = FlightCheckpoint::new(
+            JupiterPhase::Infratracking,
+            200,
+            90,
+            -150,
+            MissionFlags::default(),
+            unix_ms_now() - 2 * checkpoint::MAX_CHECKPOINT_AGE_MS,
+        );
+        store.save(&stale).unwrap();
+
+        power_cycle();
+        let (machine, _) = boot(&store);
+        assert_eq!(machine.phase(), JupiterPhase::PowerOn);
+        assert!(t_time_estimate() < 0);
+        drop(machine);
+
+        stale.written_unix_ms = unix_ms_now();
+        stale.t_zero_unix_ms = stale.written_unix_ms - 200_000;
+        store.save(&stale).unwrap();
+        std::fs::write(store.path(), b"{\"version\":1,\"pha").unwrap();
+        let (machine, _) = boot(&store);
+        assert_eq!(machine.phase(), JupiterPhase::PowerOn);
+    }
+
+    #[test]
+    fn test_checkpoint_ahead_of_clock_starts_fresh() {
+        let _clock = timing::lock_clock();
+        let store = temp_store("ahead");
+        // Left from the bench, and the clock came up behind where it was then
+        let ahead = FlightCheckpoint::new(
+            JupiterPhase::RocketDespin,
+            80,
+            78,
+            -150,
+            MissionFlags::default(),
+            unix_ms_now() + 60 * 60 * 1000,
+        );
+        store.save(&ahead).unwrap();
+
+        power_cycle();
+        let (machine, ejection_line) = boot(&store);
+        assert_eq!(machine.phase(), JupiterPhase::PowerOn);
+        assert!(t_time_estimate() < 0);
+        assert!(!ejection_line.level());
+    }
+
+    #[test]
+    fn test_shutdown_clears_checkpoint() {
+        let _clock = timing::lock_clock();
+        let store = temp_store("shutdown");
+        write_checkpoint(&store, JupiterPhase::BatteryPower, 601, 347, MissionFlags::default());
+
+        power_cycle();
+        let (mut machine, _) = boot(&store);
+        assert_eq!(machine.phase(), JupiterPhase::BatteryPower);
+
+        machine.update();
+        assert_eq!(machine.phase(), JupiterPhase::Shutdown);
+        assert!(store.load().unwrap().is_none());
+    }
 }
@@ -6,7 +6,7 @@ use log::{info, warn};
 
 use crate::states::launch::Launch;
 
-use super::traits::{StateContext, ValidState};
+use super::traits::{StateContext, Transition, ValidState};
 use crate::tasks::hardware::BoardHardware;
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -23,24 +23,33 @@ impl ValidState for PowerOn {
     fn phase(&self) -> JupiterPhase {
         JupiterPhase::PowerOn
     }
-    // fn enter(ctx: &mut StateContext) -> Box<dyn ValidState> {
-    //     Box::new(Self::default())
-    // }
 
-    fn next(&self, ctx: &mut StateContext) -> Box<dyn ValidState> {
+    fn next(&mut self, ctx: &mut StateContext) -> Transition {
         // if ctx.hardware.pins().unwrap_or_default().te1() == PinState::High {
         //     // Crap, we have late power on for some reason
         //     warn!("Late power on: TE1 is high. Emergency transition to MainCamStart");
-        //     return Box::new(Launch::default());
+        //     return Transition::to(Launch::default(), "TE1 high at power on");
         // }
 
-        if ctx.t_time > 0 {
+        if ctx.mission_time() > 0 {
             info!("Launch!");
             // ctx.hardware.activate_latch();
-            Box::new(Launch::default())
+            Transition::to(Launch::default(), "estimated T+0 reached")
         } else {
-            // Stay in power on
-            Box::new(Self::default())
+            Transition::Stay
         }
     }
 }
+
+#[cfg(all(test, not(feature = "legacy_atmega")))]
+mod tests {
+    use super::*;
+    use crate::states::traits::test_support::Bench;
+
+    #[test]
+    fn test_leaves_after_t_zero() {
+        let mut bench = Bench::new();
+        assert!(matches!(PowerOn::default().next(bench.at(-150, 0)), Transition::Stay));
+        assert_eq!(PowerOn::default().next(bench.at(-150, 1)).phase(), Some(JupiterPhase::Launch));
+    }
+}
@@ -1,47 +1,61 @@
 
 use bin_packets::phases::JupiterPhase;
 
-use crate::{states::{ejection::Ejection, launch::Launch}, timing::{self, t_time_estimate}};
+use crate::{states::{ejection::Ejection, launch::Launch}, timing};
 
-use super::traits::{StateContext, ValidState};
+use super::timeline::{DESPIN_SECS, TE2_T};
+use super::traits::{StateContext, Transition, ValidState};
 
 
 use log::info;
 
 
-const DELAY_TO_EJECT_SEC:i32 = 3; 
-
 #[derive(Debug, Default)]
-pub struct RocketDespin {
-    te2_recieved_at: i32,
-}
+pub struct RocketDespin {}
 
 impl RocketDespin {
-    pub fn enter() -> Self {
-        // Set internal clock to TE+110
+    /// Start despin, recalibrating if it was TE2 that started it
+    pub fn enter(te2_seen: bool) -> Self {
         // 68 - skirt sep
-        // 78 - skirt sep finish
+        // 78 - skirt sep finish, TE2
 
         // TE 3 - 30 second to powerdown - t + 347
-        timing::calibrate_to(78);
-        Self {
-            te2_recieved_at: t_time_estimate(),
+        if te2_seen {
+            timing::calibrate_to(TE2_T);
         }
+        Self {}
     }
 }
 
 impl ValidState for RocketDespin {
     fn phase(&self) -> bin_packets::phases::JupiterPhase {
-        return JupiterPhase::RocketDespin;
+        JupiterPhase::RocketDespin
     }
 
-    fn next(&self, ctx: &mut StateContext) -> Box<dyn ValidState> {
-        if self.te2_recieved_at + DELAY_TO_EJECT_SEC < ctx.t_time {
+    fn next(&mut self, ctx: &mut StateContext) -> Transition {
+        if ctx.time_in_state() > DESPIN_SECS {
             info!("Rocket despin complete, entering ejection.");
-            Box::new(Ejection::default())
-        } 
-        else {
-            return Box::new(Self::default());
+            Transition::to(Ejection::default(), format!("{DESPIN_SECS}s despin wait complete"))
+        } else {
+            Transition::Stay
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(all(test, not(feature = "legacy_atmega")))]
+mod tests {
+    use super::*;
+    use crate::states::traits::test_support::Bench;
+
+    #[test]
+    fn test_waits_out_despin() {
+        let mut bench = Bench::new();
+        let mut state = RocketDespin::enter(false);
+        // Staying doesn't restart the wait
+        for t in TE2_T..=TE2_T + DESPIN_SECS {
+            assert!(matches!(state.next(bench.at(TE2_T, t)), Transition::Stay));
+        }
+        let transition = state.next(bench.at(TE2_T, TE2_T + DESPIN_SECS + 1));
+        assert_eq!(transition.phase(), Some(JupiterPhase::EjectDeployable));
+    }
+}
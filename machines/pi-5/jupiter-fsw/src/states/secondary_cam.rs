@@ -4,29 +4,64 @@ use bin_packets::phases::JupiterPhase;
 
 use crate::states::{launch::Launch, rocket_despin::RocketDespin};
 
-use super::traits::{StateContext, ValidState};
+use super::timeline::TE2_TIMEOUT_T;
+use super::traits::{StateContext, Transition, ValidState};
 use crate::tasks::hardware::BoardHardware;
 
-use log::info;
+use log::{info, warn};
 
 
 #[derive(Debug, Default)]
-pub struct StartCameraRecording {
-    te2_recieved_at: i32,
-}
+pub struct StartCameraRecording {}
 
 impl ValidState for StartCameraRecording {
     fn phase(&self) -> bin_packets::phases::JupiterPhase {
-        return JupiterPhase::CamStart;
+        JupiterPhase::CamStart
     }
 
-    fn next(&self, ctx: &mut StateContext) -> Box<dyn ValidState> {
-        match ctx.hardware.pins().unwrap_or_default().te2() {
-            PinState::High => { 
-                info!("Cam recording complete, entering despin");
-                return Box::new(RocketDespin::enter()); 
-            },
-            PinState::Low => { return Box::new(Self::default()); },
+    fn next(&mut self, ctx: &mut StateContext) -> Transition {
+        if ctx.hardware.pins().unwrap_or_default().te2() == PinState::High {
+            info!("Cam recording complete, entering despin");
+            return Transition::to(RocketDespin::enter(true), "TE2 high");
+        }
+
+        if ctx.mission_time() >= TE2_TIMEOUT_T {
+            warn!("No TE2 by T+{TE2_TIMEOUT_T}, entering despin without it");
+            return Transition::to(RocketDespin::enter(false), format!("TE2 timeout at T+{TE2_TIMEOUT_T}"));
         }
+
+        Transition::Stay
     }
-}
\ No newline at end of file
+}
+
+#[cfg(all(test, not(feature = "legacy_atmega")))]
+mod tests {
+    use super::*;
+    use crate::states::timeline::TE2_T;
+    use crate::timing::t_time_estimate;
+    use crate::states::traits::test_support::Bench;
+
+    #[test]
+    fn test_waits_for_te2() {
+        let mut bench = Bench::new();
+        assert!(matches!(StartCameraRecording::default().next(bench.at(50, 70)), Transition::Stay));
+
+        bench.board.te2.set(true);
+        let transition = StartCameraRecording::default().next(bench.at(50, 70));
+        assert_eq!(transition.phase(), Some(JupiterPhase::RocketDespin));
+        // TE2 puts the clock right
+        assert!((TE2_T..=TE2_T + 1).contains(&t_time_estimate()));
+    }
+
+    #[test]
+    fn test_te2_timeout() {
+        let mut bench = Bench::new();
+        crate::timing::calibrate_to(TE2_TIMEOUT_T);
+        assert!(matches!(StartCameraRecording::default().next(bench.at(50, TE2_TIMEOUT_T - 1)), Transition::Stay));
+
+        let transition = StartCameraRecording::default().next(bench.at(50, TE2_TIMEOUT_T));
+        assert_eq!(transition.phase(), Some(JupiterPhase::RocketDespin));
+        // Nothing to calibrate against
+        assert!((TE2_TIMEOUT_T..=TE2_TIMEOUT_T + 1).contains(&t_time_estimate()));
+    }
+}
@@ -1,55 +1,115 @@
 #![warn(missing_docs)]
 
 use bin_packets::phases::JupiterPhase;
-use log::info;
+use log::{info, warn};
 
-use crate::timing::t_time_estimate;
-
-use super::traits::{StateContext, ValidState};
+use super::timeline::SHUTDOWN_DELAY_SECS;
+use super::traits::{StateContext, Transition, ValidState};
 use std::process::Command;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
 use crate::tasks::BoardHardware;   
 
-static DELAY_TO_SHUTDOWN: i32 = 30;
+// Left for writes to finish once the sync has returned
+const SETTLE: Duration = Duration::from_secs(1);
+
+// A sync that hasn't come back by now isn't going to, power off without it
+const SYNC_TIMEOUT: Duration = Duration::from_secs(60);
 
-#[derive(Debug, Clone, Default)]
+// The filesystem sync runs on a thread of its own, a slow SD card after recording can take longer
+// than the main loop's watchdog allows and this is the one state that mustn't be restarted
+#[derive(Debug, Default)]
 pub struct Shutdown {
-    time_since_switch: i32,
+    sync: Option<(JoinHandle<()>, Instant)>,
+    synced_at: Option<Instant>,
 }
 
 // Not sure if this should just be totally reliant on t_time_estimate, or also
 // rely on the time since battery latch release, so implementing this way for now
-impl Shutdown {
-    pub fn enter() -> Self {
+impl ValidState for Shutdown {
+    fn phase(&self) -> JupiterPhase {
+        JupiterPhase::Shutdown
+    }
+
+    fn next(&mut self, ctx: &mut StateContext) -> Transition {
         //match Command::new(bash).arg("easter-egg/easter-egg").spawn() {
         //    Some(_) => {},
         //    Err(e) => {},
         //}
-        Self {
-            time_since_switch: t_time_estimate(),
+        if ctx.time_in_state() <= SHUTDOWN_DELAY_SECS {
+            return Transition::Stay;
         }
-    }
-}
 
-impl ValidState for Shutdown {
-    fn phase(&self) -> JupiterPhase {
-        JupiterPhase::Shutdown
-    }
+        match &self.sync {
+            None => {
+                let indicators = ctx.hardware.indicators();
+                info!(
+                    "Indicator log: {} events, {} anomalies",
+                    indicators.log().len(),
+                    indicators.anomalies()
+                );
+
+                info!("Syncing filesystem to prevent corruption...");
+                let sync = thread::spawn(|| {
+                    let _ = Command::new("sync").status();
+                });
+                self.sync = Some((sync, Instant::now()));
+                return Transition::Stay;
+            }
+            Some((sync, started)) if self.synced_at.is_none() => {
+                if sync.is_finished() {
+                    self.synced_at = Some(Instant::now());
+                } else if started.elapsed() > SYNC_TIMEOUT {
+                    warn!("Filesystem sync still running after {}s, shutting down anyway", SYNC_TIMEOUT.as_secs());
+                    self.synced_at = Some(Instant::now() - SETTLE);
+                }
+            }
+            Some(_) => {}
+        }
+        if self.synced_at.is_none_or(|synced_at| synced_at.elapsed() < SETTLE) {
+            return Transition::Stay;
+        }
 
-    fn next(&self, ctx: &mut StateContext) -> Box<dyn ValidState> {
-        if self.time_since_switch < ctx.t_time {
-            info!("Syncing filesystem to prevent corruption...");
-            let _ = Command::new("sync").status();
+        info!("Shutting Down!");
+        ctx.hardware.deactivate_latch();
 
-            // Second to finish writes
-            std::thread::sleep(std::time::Duration::from_secs(1));
+        // Still here means the latch didn't take power with it, go round again
+        Transition::to(Self::default(), "latch released, retrying if still powered")
+    }
+}
 
-            info!("Shutting Down!");
-            ctx.hardware.deactivate_latch();
+#[cfg(all(test, not(feature = "legacy_atmega")))]
+mod tests {
+    use super::*;
+    use crate::states::timeline::POWEROFF_T;
+    use crate::states::traits::test_support::Bench;
+    use crate::tasks::BoardHardware;
 
-            Box::new(Self::enter())
-        } else {
-            Box::new(self.clone())
-        }
+    #[test]
+    fn test_releases_latch_after_delay() {
+        let mut bench = Bench::new();
+        bench.ctx.hardware.activate_latch();
+        let entered = POWEROFF_T + 1;
+        let mut shutdown = Shutdown::default();
+        assert!(matches!(shutdown.next(bench.at(entered, entered + SHUTDOWN_DELAY_SECS)), Transition::Stay));
+        assert!(bench.board.battery_latch.level());
+
+        // The sync doesn't hold up the update that starts it, the latch goes once it's done
+        let started = Instant::now();
+        assert!(matches!(shutdown.next(bench.at(entered, entered + SHUTDOWN_DELAY_SECS + 1)), Transition::Stay));
+        assert!(started.elapsed() < SETTLE);
+        let transition = loop {
+            assert!(bench.board.battery_latch.level());
+            match shutdown.next(bench.at(entered, entered + SHUTDOWN_DELAY_SECS + 1)) {
+                Transition::Stay => {}
+                transition => break transition,
+            }
+            assert!(started.elapsed() < SYNC_TIMEOUT);
+            thread::sleep(Duration::from_millis(50));
+        };
+        assert!(started.elapsed() >= SETTLE);
+        assert_eq!(transition.phase(), Some(JupiterPhase::Shutdown));
+        assert!(!bench.board.battery_latch.level());
     }
 }
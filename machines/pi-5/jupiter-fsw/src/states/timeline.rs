@@ -0,0 +1,34 @@
+#![warn(missing_docs)]
+
+//! Mission times the state machine works to, in seconds from launch. Pin events move the clock
+//! onto these when they show up, the timeouts are for when one never does.
+
+/// Cameras start recording
+pub const CAM_START_T: i32 = 50;
+
+/// TE2, end of skirt separation. Despin starts here.
+pub const TE2_T: i32 = 78;
+
+/// Give up waiting for TE2 and despin anyway
+pub const TE2_TIMEOUT_T: i32 = TE2_T + 15;
+
+/// How long despin runs before ejection is allowed
+pub const DESPIN_SECS: i32 = 3;
+
+/// Deployable ejection
+pub const EJECTION_T: i32 = 83;
+
+/// Infratracker stops tracking
+pub const INFRATRACKER_STOP_T: i32 = 342;
+
+/// TE3, 30 seconds before rocket power goes away. Battery latch closes here.
+pub const TE3_T: i32 = 347;
+
+/// Give up waiting for TE3 and go to battery power anyway, still well inside the 30 seconds
+pub const TE3_TIMEOUT_T: i32 = TE3_T + 10;
+
+/// Battery latch released, whether or not anything else has happened
+pub const POWEROFF_T: i32 = 600;
+
+/// Time given to flush files in shutdown before the latch drops
+pub const SHUTDOWN_DELAY_SECS: i32 = 30;
@@ -9,7 +9,9 @@ use crate::{
 };
 
 
+use crate::data::commands::CommandLink;
 use crate::tasks::hardware::{ActiveHardware, BoardHardware};
+use crate::states::checkpoint::MissionFlags;
 
 use std::rc::Rc;
 use std::cell::RefCell;
@@ -20,22 +22,67 @@ use serialport::SerialPort;
 // Active hardware is an alias for atmega or gpios, whichever we are using
 pub struct StateContext {
     pub t_time: i32,
+    /// Mission time the current state was entered at, after any recalibration on entry
+    pub entered_t: i32,
     pub ejection_pin: WritePin,
     pub hardware: ActiveHardware,
     pub interface: Rc<RefCell<Option<Device<Box<dyn SerialPort>>>>>,
+    /// Numbering and retries for commands to the Ejector, fed its replies by the main loop
+    pub commands: Rc<RefCell<CommandLink>>,
+    /// One-shot actions already taken, saved with every checkpoint
+    pub flags: MissionFlags,
 }
 
 impl StateContext {
     pub fn new(
-        hardware: ActiveHardware, 
+        hardware: ActiveHardware,
         ejection_pin: WritePin,
-        interface: Rc<RefCell<Option<Device<Box<dyn SerialPort>>>>>
+        interface: Rc<RefCell<Option<Device<Box<dyn SerialPort>>>>>,
+        commands: Rc<RefCell<CommandLink>>,
     ) -> Self {
+        let t_time = t_time_estimate();
         Self {
-            t_time: t_time_estimate(),
+            t_time,
+            entered_t: t_time,
             ejection_pin,
             hardware,
             interface,
+            commands,
+            flags: MissionFlags::default(),
+        }
+    }
+
+    /// Estimated seconds since launch, as of this update
+    pub fn mission_time(&self) -> i32 {
+        self.t_time
+    }
+
+    /// Seconds spent in the current state. Mission time rather than a local clock, so it carries
+    /// across a reboot along with the checkpoint.
+    pub fn time_in_state(&self) -> i32 {
+        self.t_time - self.entered_t
+    }
+}
+
+/// What a state decided on an update
+pub enum Transition {
+    /// Carry on in the current state, keeping whatever it has stored
+    Stay,
+    /// Move to another state, with why for the log
+    To(Box<dyn ValidState>, String),
+}
+
+impl Transition {
+    /// Move to `state` because of `reason`
+    pub fn to<S: ValidState + 'static>(state: S, reason: impl Into<String>) -> Self {
+        Transition::To(Box::new(state), reason.into())
+    }
+
+    /// The phase being moved to, None when staying put
+    pub fn phase(&self) -> Option<JupiterPhase> {
+        match self {
+            Transition::Stay => None,
+            Transition::To(state, _) => Some(state.phase()),
         }
     }
 }
@@ -45,6 +92,164 @@ pub trait ValidState {
     /// Get the current state as a telemetry phase
     fn phase(&self) -> JupiterPhase;
 
-    /// Transition to the next state
-    fn next(&self, ctx: &mut StateContext) -> Box<dyn ValidState>;
+    /// Check this state's exit conditions against the context. `ctx.t_time` is fresh for this
+    /// update, and `ctx.entered_t` is when this state started.
+    fn next(&mut self, ctx: &mut StateContext) -> Transition;
+}
+
+/// Board and context for exercising states without hardware
+#[cfg(all(test, not(feature = "legacy_atmega")))]
+pub(crate) mod test_support {
+    use std::io::{Read, Write};
+    use std::sync::{Arc, Mutex, MutexGuard};
+    use std::time::Duration;
+
+    use serialport::{ClearBuffer, DataBits, FlowControl, Parity, StopBits};
+
+    use super::*;
+    use crate::data::commands::RetryPolicy;
+    use crate::gpio::fake::FakeLine;
+    use crate::tasks::hardware::{FakeBoard, GpioHardware};
+
+    /// A context on fake pins, holding the mission clock for as long as it's alive
+    pub(crate) struct Bench {
+        pub ctx: StateContext,
+        pub board: FakeBoard,
+        pub ejection_line: crate::gpio::fake::FakeControl,
+        _clock: MutexGuard<'static, ()>,
+    }
+
+    impl Bench {
+        pub fn new() -> Self {
+            let clock = crate::timing::lock_clock();
+            let (hardware, board) = GpioHardware::fake();
+            let (line, ejection_line) = FakeLine::new(false);
+            let ctx = StateContext::new(hardware, WritePin::with_backend("GPIO12", Box::new(line)), Rc::new(RefCell::new(None)), Rc::new(RefCell::new(CommandLink::new(RetryPolicy::EJECTOR))));
+            Self {
+                ctx,
+                board,
+                ejection_line,
+                _clock: clock,
+            }
+        }
+
+        /// Give the context a UART, returning everything written to it
+        pub fn with_uart(&mut self) -> Arc<Mutex<Vec<u8>>> {
+            let written = Arc::new(Mutex::new(Vec::new()));
+            let port: Box<dyn SerialPort> = Box::new(FakePort {
+                written: Arc::clone(&written),
+            });
+            *self.ctx.interface.borrow_mut() = Some(Device::new(port));
+            written
+        }
+
+        /// Put the update at `t_time`, having entered the state at `entered_t`
+        pub fn at(&mut self, entered_t: i32, t_time: i32) -> &mut StateContext {
+            self.ctx.entered_t = entered_t;
+            self.ctx.t_time = t_time;
+            &mut self.ctx
+        }
+    }
+
+    // A UART that keeps whatever's written and never has anything to read
+    struct FakePort {
+        written: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl Read for FakePort {
+        fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+            Ok(0)
+        }
+    }
+
+    impl Write for FakePort {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.written.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SerialPort for FakePort {
+        fn name(&self) -> Option<String> {
+            Some("fake".into())
+        }
+        fn baud_rate(&self) -> serialport::Result<u32> {
+            Ok(115_200)
+        }
+        fn data_bits(&self) -> serialport::Result<DataBits> {
+            Ok(DataBits::Eight)
+        }
+        fn flow_control(&self) -> serialport::Result<FlowControl> {
+            Ok(FlowControl::None)
+        }
+        fn parity(&self) -> serialport::Result<Parity> {
+            Ok(Parity::None)
+        }
+        fn stop_bits(&self) -> serialport::Result<StopBits> {
+            Ok(StopBits::One)
+        }
+        fn timeout(&self) -> Duration {
+            Duration::ZERO
+        }
+        fn set_baud_rate(&mut self, _: u32) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn set_data_bits(&mut self, _: DataBits) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn set_flow_control(&mut self, _: FlowControl) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn set_parity(&mut self, _: Parity) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn set_stop_bits(&mut self, _: StopBits) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn set_timeout(&mut self, _: Duration) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn write_request_to_send(&mut self, _: bool) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn write_data_terminal_ready(&mut self, _: bool) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+            Ok(true)
+        }
+        fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+            Ok(true)
+        }
+        fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+            Ok(false)
+        }
+        fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+            Ok(true)
+        }
+        fn bytes_to_read(&self) -> serialport::Result<u32> {
+            Ok(0)
+        }
+        fn bytes_to_write(&self) -> serialport::Result<u32> {
+            Ok(0)
+        }
+        fn clear(&self, _: ClearBuffer) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+            Ok(Box::new(FakePort {
+                written: Arc::clone(&self.written),
+            }))
+        }
+        fn set_break(&self) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn clear_break(&self) -> serialport::Result<()> {
+            Ok(())
+        }
+    }
 }
@@ -1,6 +1,15 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use common_states::indicator_monitor::{IndicatorConfig, IndicatorLine, IndicatorMonitor};
 use common_states::indicators::IndicatorStates;
+use log::{info, warn};
+
+use crate::constants::INDICATOR_LOG_PATH;
 use crate::tasks::pins::{Atmega, IndicatorError};
-use crate::gpio::{Pin, read::ReadPin, write::WritePin};
+use crate::gpio::{EdgeClock, EdgeEvent, EdgeSource, Pin, read::ReadPin, write::WritePin};
+#[cfg(test)]
+use crate::gpio::fake::{FakeControl, FakeLine};
 
 // Trait for controlling GSE / TE interface and battery latch control responsibilities
 pub trait BoardHardware {
@@ -9,6 +18,8 @@ pub trait BoardHardware {
     fn idle_latch(&mut self);
     fn deactivate_latch(&mut self);
     fn cams_on(&mut self);
+    // Edge times and anomalies for every sample `pins` has read
+    fn indicators(&self) -> &IndicatorMonitor<INDICATOR_LOG_LEN>;
 }
 
 impl BoardHardware for Atmega {
@@ -17,11 +28,127 @@ impl BoardHardware for Atmega {
     fn idle_latch(&mut self) { self.idle_latch() }
     fn deactivate_latch(&mut self) { self.deactivate_latch() }
     fn cams_on(&mut self) {}
+    fn indicators(&self) -> &IndicatorMonitor<INDICATOR_LOG_LEN> { self.indicators() }
+}
+
+/// Indicator events kept in memory and in the saved log
+pub const INDICATOR_LOG_LEN: usize = 64;
+
+// Comfortably more than 64 encoded events
+const INDICATOR_LOG_BYTES: usize = 2048;
+
+// Earlier boots' logs kept beside this one's, as `<path>.1` for the last boot and up from there
+const INDICATOR_LOGS_KEPT: usize = 4;
+
+// Runs every sample a backend reads through the indicator monitor, logging the edges, and saves
+// the event log whenever it grows. Backends that see the kernel's edge events hand them over
+// first, so edges are dated from when the line moved and a glitch between polls still gets
+// logged. The states themselves are passed on as read, so the flight states act on a timer event
+// as soon as it's seen. A reboot starts a new log, and the first save moves the last boot's aside
+// rather than writing over it.
+pub struct IndicatorWatch {
+    monitor: IndicatorMonitor<INDICATOR_LOG_LEN>,
+    clock: EdgeClock,
+    // Every line as of the last sample or edge, for applying the next edge to
+    last: IndicatorStates,
+    path: Option<PathBuf>,
+    rotated: bool,
+    unsaved: bool,
+}
+
+impl IndicatorWatch {
+    // Saving to `path`, or nowhere
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self {
+            monitor: IndicatorMonitor::new(IndicatorConfig::DEFAULT),
+            clock: EdgeClock::start(),
+            last: IndicatorStates::default(),
+            path,
+            rotated: false,
+            unsaved: false,
+        }
+    }
+
+    // `lines` moved as `event` says, ahead of the next sample
+    pub fn edge(&mut self, lines: &[IndicatorLine], event: EdgeEvent) {
+        let mut bits = u8::from(self.last);
+        for (bit, line) in IndicatorLine::ALL.iter().enumerate() {
+            if lines.contains(line) {
+                bits = bits & !(1 << bit) | (event.edge.level() as u8) << bit;
+            }
+        }
+        // Only ever the seven line bits, never the malformed one
+        let states = IndicatorStates::try_from(bits).unwrap_or_default();
+        self.feed(self.clock.edge_ms(&event), states);
+    }
+
+    pub fn sample(&mut self, states: IndicatorStates) {
+        self.feed(self.clock.now_ms(), states);
+        if self.unsaved {
+            self.save();
+            self.unsaved = false;
+        }
+    }
+
+    fn feed(&mut self, now_ms: u64, states: IndicatorStates) {
+        self.last = states;
+        for event in self.monitor.update(now_ms, states).into_iter().flatten() {
+            self.unsaved = true;
+            let level = if event.high { "high" } else { "low" };
+            match event.anomaly {
+                Some(anomaly) => warn!(
+                    "Indicator {:?} went {} at {}ms: {:?}",
+                    event.line, level, event.edge_ms, anomaly
+                ),
+                None => info!("Indicator {:?} went {} at {}ms", event.line, level, event.edge_ms),
+            }
+        }
+    }
+
+    pub fn monitor(&self) -> &IndicatorMonitor<INDICATOR_LOG_LEN> {
+        &self.monitor
+    }
+
+    fn save(&mut self) {
+        let Some(path) = &self.path else { return };
+        if !self.rotated {
+            rotate_logs(path);
+            self.rotated = true;
+        }
+        let mut buffer = vec![0; INDICATOR_LOG_BYTES];
+        let result = match self.monitor.log().to_bytes(&mut buffer) {
+            Ok(len) => fs::write(path, &buffer[..len]).map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        if let Err(e) = result {
+            warn!("Failed to save the indicator log to {}: {}", path.display(), e);
+        }
+    }
+}
+
+// `path` with a boot number on the end
+fn numbered(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{n}"));
+    name.into()
+}
+
+// Move every kept log one boot older, dropping the oldest. Missing ones are skipped.
+fn rotate_logs(path: &Path) {
+    for n in (1..INDICATOR_LOGS_KEPT).rev() {
+        fs::rename(numbered(path, n), numbered(path, n + 1)).ok();
+    }
+    fs::rename(path, numbered(path, 1)).ok();
+}
+
+impl Default for IndicatorWatch {
+    fn default() -> Self {
+        Self::new(Some(PathBuf::from(INDICATOR_LOG_PATH)))
+    }
 }
 
 // When directly reading through jupiter instead of having an atmega interface
 pub struct GpioHardware {
-    gse1: ReadPin,
     gse2: ReadPin,
     te_ra: ReadPin,
     te_rb: ReadPin,
@@ -31,12 +158,12 @@ pub struct GpioHardware {
     battery_latch: WritePin,
     battery_latch_2: WritePin,
     cam_active: WritePin,
+    indicators: IndicatorWatch,
 }
 
 impl GpioHardware {
     pub fn new() -> Self {
         Self {
-            gse1: Pin::new("GPIO6").into(),
             gse2: Pin::new("GPIO25").into(),
             te_ra: Pin::new("GPIO4").into(),
             te_rb: Pin::new("GPIO5").into(),
@@ -46,6 +173,31 @@ impl GpioHardware {
             battery_latch: Pin::new("GPIO13").into(),
             battery_latch_2: Pin::new("GPIO26").into(),
             cam_active: Pin::new("GPIO21").into(),
+            indicators: IndicatorWatch::default(),
+        }
+    }
+}
+
+impl GpioHardware {
+    // Hand the monitor every edge since the last read, in the order they happened
+    fn take_edges(&mut self) {
+        use IndicatorLine::*;
+
+        let mut edges: Vec<(&[IndicatorLine], EdgeEvent)> = Vec::new();
+        let pins: [(&mut ReadPin, &[IndicatorLine]); 6] = [
+            (&mut self.te1, &[Gse1, Te1]),
+            (&mut self.gse2, &[Gse2]),
+            (&mut self.te_ra, &[TeRa]),
+            (&mut self.te_rb, &[TeRb]),
+            (&mut self.te2, &[Te2]),
+            (&mut self.te3, &[Te3]),
+        ];
+        for (pin, lines) in pins {
+            edges.extend(pin.take_edges().into_iter().map(|edge| (lines, edge)));
+        }
+        edges.sort_by_key(|(_, edge)| edge.timestamp_ns);
+        for (lines, edge) in edges {
+            self.indicators.edge(lines, edge);
         }
     }
 }
@@ -53,17 +205,29 @@ impl GpioHardware {
 impl BoardHardware for GpioHardware {
     fn pins(&mut self) -> Result<IndicatorStates, IndicatorError> {
         use common_states::indicators::IndicatorBuilder;
-        
+
+        self.take_edges();
+
+        // GSE1 and TE1 are both wired to GPIO6, and a line can only be reserved once, so they
+        // share the one read
+        let reads = [self.te1.read(), self.gse2.read(), self.te_ra.read(), self.te_rb.read(), self.te2.read(), self.te3.read()];
+        let [te1, gse2, te_ra, te_rb, te2, te3] = reads.map(|read| read.ok());
+
         // If a pin fails to read, we default to false to prevent crashing
-        Ok(IndicatorBuilder::new()
-            .gse1(self.gse1.read().unwrap_or(false))
-            .gse2(self.gse2.read().unwrap_or(false))
-            .te_ra(self.te_ra.read().unwrap_or(false))
-            .te_rb(self.te_rb.read().unwrap_or(false))
-            .te1(self.te1.read().unwrap_or(false))
-            .te2(self.te2.read().unwrap_or(false))
-            .te3(self.te3.read().unwrap_or(false))
-            .build())
+        let states = IndicatorBuilder::new()
+            .gse1(te1.unwrap_or(false))
+            .gse2(gse2.unwrap_or(false))
+            .te_ra(te_ra.unwrap_or(false))
+            .te_rb(te_rb.unwrap_or(false))
+            .te1(te1.unwrap_or(false))
+            .te2(te2.unwrap_or(false))
+            .te3(te3.unwrap_or(false))
+            .build();
+        // The defaults aren't what the lines did, so the monitor only sees clean reads
+        if [te1, gse2, te_ra, te_rb, te2, te3].iter().all(Option::is_some) {
+            self.indicators.sample(states);
+        }
+        Ok(states)
     }
 
     fn activate_latch(&mut self) { self.battery_latch.write(true).ok();
@@ -72,6 +236,62 @@ impl BoardHardware for GpioHardware {
     fn deactivate_latch(&mut self) { self.battery_latch.write(false).ok(); 
                                      self.battery_latch_2.write(false).ok();}
     fn cams_on(&mut self) { self.cam_active.write(true).ok();  }
+    fn indicators(&self) -> &IndicatorMonitor<INDICATOR_LOG_LEN> { self.indicators.monitor() }
+}
+
+// The test's end of every line on a fake board
+#[cfg(test)]
+pub struct FakeBoard {
+    pub gse2: FakeControl,
+    pub te_ra: FakeControl,
+    pub te_rb: FakeControl,
+    pub te1: FakeControl,
+    pub te2: FakeControl,
+    pub te3: FakeControl,
+    pub battery_latch: FakeControl,
+    pub battery_latch_2: FakeControl,
+    pub cam_active: FakeControl,
+}
+
+#[cfg(test)]
+impl GpioHardware {
+    // Every line in memory, all starting low
+    pub fn fake() -> (Self, FakeBoard) {
+        fn input(name: &str) -> (ReadPin, FakeControl) {
+            let (line, control) = FakeLine::new(false);
+            (ReadPin::with_backend(name, Box::new(line)), control)
+        }
+        fn output(name: &str) -> (WritePin, FakeControl) {
+            let (line, control) = FakeLine::new(false);
+            (WritePin::with_backend(name, Box::new(line)), control)
+        }
+
+        let (gse2, gse2_line) = input("GPIO25");
+        let (te_ra, te_ra_line) = input("GPIO4");
+        let (te_rb, te_rb_line) = input("GPIO5");
+        let (te1, te1_line) = input("GPIO6");
+        let (te2, te2_line) = input("GPIO23");
+        let (te3, te3_line) = input("GPIO24");
+        let (battery_latch, latch_line) = output("GPIO13");
+        let (battery_latch_2, latch_2_line) = output("GPIO26");
+        let (cam_active, cam_line) = output("GPIO21");
+
+        // Nowhere to save the log to
+        let indicators = IndicatorWatch::new(None);
+        let hardware = Self { gse2, te_ra, te_rb, te1, te2, te3, battery_latch, battery_latch_2, cam_active, indicators };
+        let board = FakeBoard {
+            gse2: gse2_line,
+            te_ra: te_ra_line,
+            te_rb: te_rb_line,
+            te1: te1_line,
+            te2: te2_line,
+            te3: te3_line,
+            battery_latch: latch_line,
+            battery_latch_2: latch_2_line,
+            cam_active: cam_line,
+        };
+        (hardware, board)
+    }
 }
 
 // Use conditional type alias so atmega and gpio can be switched out with just a config flag.
@@ -80,4 +300,82 @@ impl BoardHardware for GpioHardware {
 pub type ActiveHardware = Atmega;
 
 #[cfg(not(feature = "legacy_atmega"))]
-pub type ActiveHardware = GpioHardware;
\ No newline at end of file
+pub type ActiveHardware = GpioHardware;
+
+#[cfg(test)]
+mod tests {
+    use common_states::indicator_monitor::IndicatorAnomaly;
+    use embedded_hal::digital::PinState;
+
+    use super::*;
+
+    #[test]
+    fn test_glitch_read_as_is_but_logged() {
+        let (mut hardware, board) = GpioHardware::fake();
+        board.te2.set(true);
+        assert_eq!(hardware.pins().unwrap().te2(), PinState::High);
+        board.te2.set(false);
+        assert_eq!(hardware.pins().unwrap().te2(), PinState::Low);
+
+        // Back well inside the debounce time
+        let event = hardware.indicators().log().iter().next().copied().unwrap();
+        assert_eq!(event.line, IndicatorLine::Te2);
+        assert_eq!(event.anomaly, Some(IndicatorAnomaly::Glitch));
+        assert_eq!(hardware.indicators().rose_at_ms(IndicatorLine::Te2), None);
+    }
+
+    #[test]
+    fn test_glitch_between_reads_logged() {
+        let (mut hardware, board) = GpioHardware::fake();
+        board.te3.set(true);
+        board.te3.set(false);
+
+        // The read never sees it high, the edges do
+        assert_eq!(hardware.pins().unwrap().te3(), PinState::Low);
+        let event = hardware.indicators().log().iter().next().copied().unwrap();
+        assert_eq!(event.line, IndicatorLine::Te3);
+        assert_eq!(event.anomaly, Some(IndicatorAnomaly::Glitch));
+    }
+
+    #[test]
+    fn test_edge_dated_from_kernel_timestamp() {
+        let (mut hardware, board) = GpioHardware::fake();
+        board.te1.set(true);
+        std::thread::sleep(std::time::Duration::from_millis(3 * IndicatorConfig::DEFAULT.debounce_ms));
+
+        // Rose well before the read that confirmed it, on both lines GPIO6 carries
+        assert_eq!(hardware.pins().unwrap().te1(), PinState::High);
+        let events: Vec<_> = hardware.indicators().log().iter().copied().collect();
+        assert_eq!(events.len(), 2);
+        for event in events {
+            assert!(matches!(event.line, IndicatorLine::Gse1 | IndicatorLine::Te1));
+            assert!(event.confirmed_ms - event.edge_ms >= 2 * IndicatorConfig::DEFAULT.debounce_ms);
+        }
+    }
+
+    #[test]
+    fn test_log_rotated_per_boot() {
+        let dir = std::env::temp_dir().join(format!("jupiter_indicators_{}", std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("indicator_events.bin");
+
+        // Each boot sees a glitch on a different line, so each log is different
+        let boot = |n: usize| {
+            let mut watch = IndicatorWatch::new(Some(path.clone()));
+            watch.sample(IndicatorStates::try_from(1 << n).unwrap());
+            // Nothing logged yet, so nothing's been moved
+            assert_eq!(path.exists(), n > 0);
+            watch.sample(IndicatorStates::try_from(0).unwrap());
+            fs::read(&path).unwrap()
+        };
+
+        let logs: Vec<_> = (0..6).map(boot).collect();
+        assert_eq!(fs::read(&path).unwrap(), logs[5]);
+        for n in 1..=INDICATOR_LOGS_KEPT {
+            assert_eq!(fs::read(numbered(&path, n)).unwrap(), logs[5 - n]);
+        }
+        assert!(!numbered(&path, INDICATOR_LOGS_KEPT + 1).exists());
+        fs::remove_dir_all(&dir).ok();
+    }
+}
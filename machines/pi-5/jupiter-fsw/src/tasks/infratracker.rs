@@ -7,20 +7,31 @@ use std::time::{Duration, SystemTime, Instant, UNIX_EPOCH};
 
 use bin_packets::packets::ApplicationPacket;
 use lazy_static::lazy_static;
-use log::{info, error};
+
+use super::supervisor::Heartbeat;
+use log::{info, error, warn};
 
 use image::{ImageBuffer, Luma};
 
-use wayfarer::perception::centroiding::Starfinder;
-use wayfarer::perception::camera_model::CameraModel;
-use wayfarer::startrack::solver::Startracker;
-use wayfarer::startrack::quest::quest_real;
+use jupiter_fsw::solve::{GrayFrame, SolvePipeline};
+
+use camera::Camera;
+#[cfg(not(feature = "synthetic_camera"))]
+use camera::BaslerCamera;
+#[cfg(feature = "synthetic_camera")]
+use camera::{Optics, SensorModel, StarCatalog, SyntheticCamera};
+#[cfg(feature = "synthetic_camera")]
+use crate::constants::SYNTHETIC_CATALOG_PATH;
+
+#[cfg(not(any(feature = "pylon", feature = "synthetic_camera")))]
+compile_error!("the infratracker needs a camera, build with `pylon` or `synthetic_camera`");
 
 use aether::attitude::Quaternion;
 use aether::reference_frame::{ICRF, Body};
 
-use DarkAverager::ImageAveragerFromBuffer;
+use DarkAverager::{BadPixelThresholds, DarkEstimator, ImageAveragerFromBuffer, PixelMap, pixel_map::{DEAD, HOT, NOISY}};
 
+#[cfg(feature = "pylon")]
 use pylon_cxx::{NodeMap, EnumNode, IntegerNode,FloatNode, InstantCamera, PylonError  };
 
 const STAR_TRACKER_DIR: &str = "/home/terminus/basler/";
@@ -33,6 +44,7 @@ lazy_static! {
     pub static ref TRACKING: AtomicBool = AtomicBool::new(false);
 }
 
+#[derive(Clone)]
 pub struct InfratrackerThread {
     quaternion_sender: Sender<ApplicationPacket>,
 }
@@ -43,226 +55,216 @@ impl InfratrackerThread {
         (Self { quaternion_sender: quaternion_tx }, quaternion_rx)
     }
 
-    pub fn begin_startracking(self) -> JoinHandle<()> {
+    /// Capture and solve on the Basler until told to stop by the supervisor, checking in on
+    /// `heartbeat` every frame. Runs on the calling thread, see [`Supervisor::supervise`](super::supervisor::Supervisor::supervise).
+    #[cfg(not(feature = "synthetic_camera"))]
+    pub fn run(&self, heartbeat: Heartbeat) {
         info!("Starting Basler camera!");
+        let pylon = pylon_cxx::Pylon::new();
+        match BaslerCamera::first(&pylon) {
+            Ok(mut camera) => {
+                // InfratrackerThread::init_camera(camera.inner());
+                self.run_with(&mut camera, &heartbeat)
+            }
+            Err(e) => error!("Error in running infratracker task: {e}"),
+        }
+    }
+
+    /// Capture and solve a star field rendered from [`SYNTHETIC_CATALOG_PATH`], for running the
+    /// whole loop on the bench without a camera
+    #[cfg(feature = "synthetic_camera")]
+    pub fn run(&self, heartbeat: Heartbeat) {
+        info!("Starting synthetic camera!");
+        match StarCatalog::load(SYNTHETIC_CATALOG_PATH) {
+            Ok(catalog) => {
+                let optics = Optics::with_fov(1600, 1200, 12.0);
+                let mut camera = SyntheticCamera::new(catalog, optics, SensorModel::default());
+                self.run_with(&mut camera, &heartbeat)
+            }
+            Err(e) => error!("Synthetic star catalog not loaded: {e}"),
+        }
+    }
+
+    /// The tracking loop on any camera: boot darks, then grab, dark subtract, solve and save
+    /// while [`TRACKING`] is set
+    pub fn run_with<C: Camera>(&self, camera: &mut C, heartbeat: &Heartbeat) {
         create_dir(STAR_TRACKER_DIR).ok();
         
-        thread::spawn(move || {
-            let result: Result<(), Box<dyn std::error::Error>> = (|| {
-                
-                
-                let (solver_tx, solver_rx) = sync_channel::<(u64, ImageBuffer<Luma<u8>, Vec<u8>>)>(1);
-                let (result_tx, result_rx) = channel::<(u64, Option<Quaternion<f32, ICRF<f32>, Body<f32>>>)>();
-
-                thread::spawn(move || {
-                    let starfinder = Starfinder::default();
-                    let camera_model = CameraModel::default();
-                    let startracker = Startracker::default();
-
-                    // Simple looping thread to that will return solves for every
-                    // image recieved until parent dies
-                    while let Ok((timestamp, img)) = solver_rx.recv() {
-                        let q = Self::solve_attitude(&img, &starfinder, &camera_model, &startracker);
-                        let _ = result_tx.send((timestamp, q));
+        let result: Result<(), Box<dyn std::error::Error>> = (|| {
+            
+            
+            let (solver_tx, solver_rx) = sync_channel::<(u64, ImageBuffer<Luma<u8>, Vec<u8>>)>(1);
+            let (result_tx, result_rx) = channel::<(u64, Option<Quaternion<f32, ICRF<f32>, Body<f32>>>)>();
+
+            thread::spawn(move || {
+                // Darks are already applied by the time frames get here
+                let pipeline = SolvePipeline::new();
+
+                // Simple looping thread to that will return solves for every
+                // image recieved until parent dies
+                while let Ok((timestamp, mut img)) = solver_rx.recv() {
+                    let solution = pipeline.solve(&mut img);
+                    if let Err(e) = &solution.attitude {
+                        error!("{e}");
                     }
-                });
-
-                
-                let pylon = pylon_cxx::Pylon::new();
-                let mut camera = pylon_cxx::TlFactory::instance(&pylon).create_first_device()?;
-
-                let mut pixel_format_node = camera.node_map()?.enum_node("PixelFormat")?;
-
-                // Retrieve the Vec<String> of all formats supported by the connected sensor
-                let available_formats = pixel_format_node.settable_values()?;
-
-                info!("Listing available pixel formats for this camera:");
-                for format in available_formats {
-                    info!(" - {}", format);
+                    let _ = result_tx.send((timestamp, solution.attitude.ok()));
                 }
+            });
 
-                // InfratrackerThread::init_camera(&mut camera);
-
-                let mut was_tracking = false;
-                let mut grab_result = pylon_cxx::GrabResult::new()?;
-
-                camera.open()?;
-                info!("Camera opened and idling. Waiting for TRACKING signal..."); 
+            let mut was_tracking = false;
 
-                let frame_interval = Duration::from_millis(CAPTURE_RATE);
-                let mut next_frame_time = Instant::now();
+            info!("Camera opened and idling. Waiting for TRACKING signal..."); 
 
-                camera.start_grabbing(&pylon_cxx::GrabOptions::default()
-                    .strategy(pylon_cxx::GrabStrategy::LatestImageOnly))?;
+            let frame_interval = Duration::from_millis(CAPTURE_RATE);
+            let mut next_frame_time = Instant::now();
 
-                let mut darkframe_source: Vec<ImageBuffer<Luma<u8>, Vec<u8>>> = vec![];
+            camera.start()?;
 
-                for _ in 0..20 {
-                    match camera.retrieve_result(500, &mut grab_result, pylon_cxx::TimeoutHandling::Return) {
-                        Ok(true) if grab_result.grab_succeeded().unwrap_or(false) =>
-                        {
-                            let raw_buffer: &[u8] = grab_result.buffer()?;
-                            let width = grab_result.width()?;
-                            let height = grab_result.height()?;
+            let mut darkframe_source: Vec<ImageBuffer<Luma<u8>, Vec<u8>>> = vec![];
 
-                            darkframe_source.push(ImageBuffer::from_raw(width, height, raw_buffer.to_vec())
-                                .expect("Buffer size mismatch"));
-                            thread::sleep(Duration::from_millis(200)); 
+            for _ in 0..20 {
+                heartbeat.beat();
+                match camera.grab(Duration::from_millis(500)) {
+                    Ok(frame) => {
+                        darkframe_source.push(frame.image);
+                        thread::sleep(Duration::from_millis(200)); 
+                    }
+                    Err(e) => {
+                        error!("Dark frame dropped: {e}");
+                    }
+                }
+            }
+            
+            // Twenty frames at 200ms apart is plenty of time for a cosmic ray to land in one
+            let mut avger = ImageAveragerFromBuffer::new_with_options(darkframe_source, DarkEstimator::sigma_clipped(), &BadPixelThresholds::default())
+                .inspect_err(|e| error!("Dark frame failed, running without: {e}"))
+                .ok();
+            if let Some(ref mut averager) = avger {
+                 if let Err(e) = averager.get_average().save(format!("{STAR_TRACKER_DIR}/dark_frame.tiff")) {
+                    error!("Dark frame image save error, bad directory");
+                }
 
+                // Keep the bad pixels found on earlier boots, add whatever showed up in this set of darks
+                let map_path = format!("{STAR_TRACKER_DIR}/pixel_map.bin");
+                match PixelMap::load(&map_path) {
+                    Ok(saved) => {
+                        if let Err(e) = averager.merge_pixel_map(&saved) {
+                            error!("Saved pixel map not used: {e}");
                         }
-                        Err(e) => {
-                            error!("Pylon error: {e}");
-                        }
-                        _ => {
-                            error!("No Pylon error reported, but grab failed");
-                        }
+                    }
+                    Err(e) => info!("No saved pixel map loaded: {e}"),
+                }
+                let map = averager.pixel_map();
+                info!("Pixel map: {} hot, {} noisy, {} dead", map.count(HOT), map.count(NOISY), map.count(DEAD));
+                if let Err(e) = map.save(&map_path) {
+                    error!("Pixel map save error: {e}");
+                }
+            }
+           
 
+            camera.stop()?;
+            
+            let (save_tx, save_rx) = channel::<(u64, GrayFrame)>();
 
-                    }
+            thread::spawn(move || {
+                while let Ok((stamp, img)) = save_rx.recv() {
+                    img.save(format!("{STAR_TRACKER_DIR}/infratracker{stamp}.tiff")).ok();
                 }
-                
-                let avger = ImageAveragerFromBuffer::new_with_source(darkframe_source);
-                if let Some(ref averager) = avger {
-                     if let Err(e) = averager.get_average().save(format!("{STAR_TRACKER_DIR}/dark_frame.tiff")) {
-                        error!("Dark frame image save error, bad directory");
-                    }
+            });
+
+            // Cam loop
+            loop {
+                if !heartbeat.beat() {
+                    // Stalled long enough to be replaced, the new thread owns the camera now
+                    warn!("Superseded infratracker thread exiting");
+                    return Ok(());
                 }
-               
 
-                camera.stop_grabbing()?;
-                camera.close()?;
-                
-                let (save_tx, save_rx) = channel::<(u64, Vec<u8>, u32, u32)>();
+                let is_tracking = TRACKING.load(Ordering::Relaxed);
 
-                thread::spawn(move || {
-                    while let Ok((stamp, buf, w, h)) = save_rx.recv() {
-                        let img = ImageBuffer::<Luma<u8>, _>::from_raw(w, h, buf).unwrap(); //-Unwrap-
-                        img.save(format!("{STAR_TRACKER_DIR}/infratracker{stamp}.tiff")).ok();
-                    }
-                });
-
-                // Cam loop
-                loop {
-                    let is_tracking = TRACKING.load(Ordering::Relaxed);
-
-                    // Handle camera start/stop
-                    if is_tracking && !was_tracking {
-                        info!("Tracking on, start grabbing");
-                        camera.open()?;
-
-                        camera.start_grabbing(&pylon_cxx::GrabOptions::default()
-                            .strategy(pylon_cxx::GrabStrategy::LatestImageOnly))?;
-                        was_tracking = true;
-                        next_frame_time = Instant::now() + frame_interval; // Initialize metronome
-                    } 
-                    else if !is_tracking && was_tracking {
-                        info!("Tracking disabled. Safely stop grabbing");
-                        camera.stop_grabbing()?;
-                        camera.close()?;
-
-                        was_tracking = false;
-                    }
+                // Handle camera start/stop
+                if is_tracking && !was_tracking {
+                    info!("Tracking on, start grabbing");
+                    camera.start()?;
+                    was_tracking = true;
+                    next_frame_time = Instant::now() + frame_interval; // Initialize metronome
+                } 
+                else if !is_tracking && was_tracking {
+                    info!("Tracking disabled. Safely stop grabbing");
+                    camera.stop()?;
 
-                    if is_tracking {
-                        if camera.is_grabbing() {
-                            
-                            // Set the next time we'll take a picture now
-                            // and adjust later based off of how much
-                            // time spent on computation
-                            next_frame_time += frame_interval;
-
-                            match camera.retrieve_result(5000, &mut grab_result, pylon_cxx::TimeoutHandling::Return) {
-                                Ok(true) if grab_result.grab_succeeded().unwrap_or(false) => {
-                                    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
-                                    
-                                    let raw_buffer: &[u8] = grab_result.buffer()?;
-                                    let width = grab_result.width()?;
-                                    let height = grab_result.height()?;
-
-                                    // Copy
-                                    let img_vec = raw_buffer.to_vec(); 
-                                    let mut solve_img = ImageBuffer::from_raw(width, height, img_vec.clone())
-                                        .expect("Buffer size mismatch");
-
-                                    if let Some(ref averager) = avger {              
-                                        averager.apply_average(&mut solve_img);
+                    was_tracking = false;
+                }
+
+                if is_tracking {
+                    if camera.is_streaming() {
+                        
+                        // Set the next time we'll take a picture now
+                        // and adjust later based off of how much
+                        // time spent on computation
+                        next_frame_time += frame_interval;
+
+                        match camera.grab(Duration::from_millis(5000)) {
+                            Ok(frame) => {
+                                let timestamp = frame.unix_ms();
+
+                                // Copy
+                                let mut solve_img = frame.image.clone();
+
+                                if let Some(ref averager) = avger {              
+                                    if let Err(e) = averager.apply_average(&mut solve_img) {
+                                        error!("Dark frame not applied: {e}");
                                     }
+                                }
 
-                                    // Try sending an image to be solved
-                                    match solver_tx.try_send((timestamp, solve_img)) {
-                                        Ok(_) => {
-                                            // Wait for the solver up to 600ms leaving 400ms buffer for save and sleep
-                                            // May want to adjust to handle initial case and then switch to tracking mode
-                                            // But infratracker particularly has to deal with large rotations
-                                            // so it's likely it will just have to stay in LOST IN SPACE mode 
-                                            // the entire times
-                                            while let Ok((ret_stamp, Some(quaternion))) = result_rx.try_recv() {
-                                                self.send_packet(timestamp, quaternion);
-                                            }
-                                        }
-                                        Err(TrySendError::Full(_)) => {
-                                            error!("Solver thread hung, Skipping telemetry to save image.");
-                                        }
-                                        Err(TrySendError::Disconnected(_)) => {
-                                            error!("Solver thread dead");
+                                // Try sending an image to be solved
+                                match solver_tx.try_send((timestamp, solve_img)) {
+                                    Ok(_) => {
+                                        // Wait for the solver up to 600ms leaving 400ms buffer for save and sleep
+                                        // May want to adjust to handle initial case and then switch to tracking mode
+                                        // But infratracker particularly has to deal with large rotations
+                                        // so it's likely it will just have to stay in LOST IN SPACE mode 
+                                        // the entire times
+                                        while let Ok((ret_stamp, Some(quaternion))) = result_rx.try_recv() {
+                                            self.send_packet(timestamp, quaternion);
                                         }
                                     }
-
-                                    save_tx.send((timestamp, img_vec, width, height)).ok();
-                                    // Do file save with zero copy
-                                    // cuz we can get away with it
-                                    // let local_img: ImageBuffer<Luma<u8>, &[u8]> = 
-                                    //     ImageBuffer::from_raw(width, height, raw_buffer).unwrap();
-                                    
-                                    // if let Err(e) = local_img.save(format!("{STAR_TRACKER_DIR}/infratracker{timestamp}.tiff")) {
-                                    //     error!("Image save error, bad directory")
-                                    // }
-                                }
-                                _ => {
-                                    error!("Timeout or grab fail");
+                                    Err(TrySendError::Full(_)) => {
+                                        error!("Solver thread hung, Skipping telemetry to save image.");
+                                    }
+                                    Err(TrySendError::Disconnected(_)) => {
+                                        error!("Solver thread dead");
+                                    }
                                 }
+
+                                save_tx.send((timestamp, frame.image)).ok();
+                            }
+                            Err(e) => {
+                                error!("Timeout or grab fail: {e}");
                             }
                         }
+                    }
 
-                        let now = Instant::now();
-                        if next_frame_time > now {
-                            thread::sleep(next_frame_time - now);
-                        } else {
-                            // Startracking and saving took longer than 1 second
-                            // so immediately solve next frame
-                            next_frame_time = now;
-                        }
-                    } 
-                    else {
-                        // Idle loop
-                        thread::sleep(Duration::from_millis(200)); 
+                    let now = Instant::now();
+                    if next_frame_time > now {
+                        thread::sleep(next_frame_time - now);
+                    } else {
+                        // Startracking and saving took longer than 1 second
+                        // so immediately solve next frame
+                        next_frame_time = now;
                     }
+                } 
+                else {
+                    // Idle loop
+                    thread::sleep(Duration::from_millis(200)); 
                 }
-                #[allow(unreachable_code)]
-                Ok(())
-            })();
-            
-            if let Err(thread_error) = result {
-                error!("Error in running infratracker task: {thread_error}")
             }
-        })
-    }
-
-    fn solve_attitude(
-        img: &ImageBuffer<Luma<u8>, Vec<u8>>, 
-        finder: &Starfinder, 
-        model: &CameraModel, 
-        solver: &Startracker
-    ) -> Option<Quaternion<f32, ICRF<f32>, Body<f32>>> {
-        let mut centroids = finder.star_find(img);
-        model.undistort_centroids(&mut centroids);
+            #[allow(unreachable_code)]
+            Ok(())
+        })();
         
-        match solver.adaptive_pyramid_solve(centroids) {
-            Ok((refs, body)) => Some(quest_real(&refs, &body)),
-            Err(e) => {
-                error!("Pyramid solve failed: {}", e);
-                None
-            }
+        if let Err(thread_error) = result {
+            error!("Error in running infratracker task: {thread_error}")
         }
     }
 
@@ -277,6 +279,7 @@ impl InfratrackerThread {
     }
 
 
+    #[cfg(feature = "pylon")]
     fn init_camera(camera: &mut InstantCamera<'_>) {
          if let Ok(node_map) = camera.node_map() {
                     // PixelFormat (Enum)
@@ -10,37 +10,42 @@ use std::{
     time::Duration,
 };
 
-use log::{error, info};
+use log::{error, info, warn};
+
+use super::supervisor::Heartbeat;
 
 use crate::timing::t_time_estimate;
 
 const VIDEO_DIRECTORY: &str = "/home/terminus/video/";
 
-/// Internal task for running the actual camera task information
-fn camera_task() -> ! {
+/// Run the main camera, checking in on `heartbeat` while ffmpeg records. Meant to be run under
+/// the supervisor, which starts it again if it stops checking in.
+pub fn camera_task(heartbeat: Heartbeat) {
     // Wait until after the delay
     while t_time_estimate() < -30 {
+        heartbeat.beat();
         sleep(Duration::from_millis(1000));
     }
     info!("Starting main camera!");
 
     create_dir(VIDEO_DIRECTORY).ok();
 
-    // T+ 302 stop for TE-2
-    run_recording_segment(Some(302));
-
-    // T+570 stop
-    run_recording_segment(Some(570));
-
-    loop {
-        run_recording_segment(None);
+    // T+ 302 stop for TE-2, then T+570 stop. A restart partway through picks up with whichever
+    // segment is still to come.
+    for stop_at_t in [302, 570] {
+        if t_time_estimate() < stop_at_t && !run_recording_segment(Some(stop_at_t), &heartbeat) {
+            return;
+        }
     }
+
+    while run_recording_segment(None, &heartbeat) {}
 }
 
-fn run_recording_segment(stop_at_t: Option<i32>) {
+// Record one segment, false if the supervisor has replaced this thread
+fn run_recording_segment(stop_at_t: Option<i32>, heartbeat: &Heartbeat) -> bool {
     let mut fail_count = 0;
 
-    'retry: loop {
+    loop {
         // figure out next filename
         let highest = read_dir(VIDEO_DIRECTORY)
             .unwrap() // -Unwrap-
@@ -98,11 +103,25 @@ fn run_recording_segment(stop_at_t: Option<i32>) {
                 });
             }
 
-        // now block until ffmpeg exits
-        match child.wait() {
+        // now wait for ffmpeg to exit, checking in while it records
+        let exit = loop {
+            if !heartbeat.beat() {
+                warn!("Superseded camera thread stopping ffmpeg");
+                let _ = child.kill();
+                let _ = child.wait();
+                return false;
+            }
+            match child.try_wait() {
+                Ok(Some(status)) => break Ok(status),
+                Ok(None) => sleep(Duration::from_millis(500)),
+                Err(e) => break Err(e),
+            }
+        };
+
+        match exit {
             Ok(status) if status.success() => {
                 info!("Finished segment {}", highest + 1);
-                break 'retry;
+                return true;
             }
             Ok(status) => {
                 error!("ffmpeg exited with {status} – retrying… (fail_count={fail_count})",);
@@ -118,8 +137,3 @@ fn run_recording_segment(stop_at_t: Option<i32>) {
         sleep(Duration::from_millis(backoff));
     }
 }
-
-/// Spawn the camera task
-pub fn spawn_camera_thread() {
-    std::thread::spawn(|| camera_task());
-}
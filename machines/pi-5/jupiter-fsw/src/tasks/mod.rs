@@ -6,10 +6,12 @@ mod rbf;
 mod infratracker;
 pub mod hardware;
 mod guard_monitor;
+mod supervisor;
 
 pub use main_cam::*;
 pub use pins::*;
 pub use rbf::*;
 pub use infratracker::*;
 pub use hardware::*;
-pub use guard_monitor::*;
\ No newline at end of file
+pub use guard_monitor::*;
+pub use supervisor::*;
\ No newline at end of file
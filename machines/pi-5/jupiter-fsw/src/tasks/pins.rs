@@ -7,17 +7,24 @@ use i2cdev::{
 
 use common_states::{
     battery_state::BatteryState,
+    indicator_monitor::IndicatorMonitor,
     indicators::{IndicatorStates, MalformedIndicatorError},
 };
 
+use super::hardware::{IndicatorWatch, INDICATOR_LOG_LEN};
+
 /// ATMega abstraction
 pub struct Atmega {
     device: LinuxI2CDevice,
+    indicators: IndicatorWatch,
 }
 
 impl From<LinuxI2CDevice> for Atmega {
     fn from(device: LinuxI2CDevice) -> Self {
-        Self { device }
+        Self {
+            device,
+            indicators: IndicatorWatch::default(),
+        }
     }
 }
 
@@ -47,9 +54,18 @@ impl Atmega {
 
     pub fn pins(&mut self) -> Result<IndicatorStates, IndicatorError> {
         // Let this error out in order to not crash when battery latch is set.
-        Ok(IndicatorStates::try_from(
-            self.device.smbus_read_byte().unwrap_or(0),
-        )?)
+        let read = self.device.smbus_read_byte().ok();
+        let states = IndicatorStates::try_from(read.unwrap_or(0))?;
+        // The fallback isn't what the lines did, so the monitor only sees real reads
+        if read.is_some() {
+            self.indicators.sample(states);
+        }
+        Ok(states)
+    }
+
+    /// Edge times and anomalies for every sample read
+    pub fn indicators(&self) -> &IndicatorMonitor<INDICATOR_LOG_LEN> {
+        self.indicators.monitor()
     }
 
     /// Write one byte to register 0x00 (SMBus “command” 0x00).
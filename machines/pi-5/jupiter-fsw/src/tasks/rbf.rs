@@ -4,29 +4,31 @@ use std::sync::{Arc, Mutex};
 use std::thread::spawn;
 
 use common_states::rbf::RbfState;
-use common_states::rbf::{ActiveHighRbf, RbfIndicator};
+use common_states::rbf_monitor::{RbfConfig, RbfLine, RbfMonitor};
+use embedded_hal::digital::InputPin;
+use log::info;
 
-use crate::gpio::read::ReadPin;
+use crate::gpio::{EdgeClock, EdgeSource, read::ReadPin};
 
 /// The task spawner for the RBF reader
 pub struct RbfTask {
-    indicator: ActiveHighRbf<ReadPin>,
+    line: RbfLine<ReadPin>,
     state: Arc<Mutex<RbfState>>,
 }
 
 impl RbfTask {
-    pub fn new(mut indicator: ActiveHighRbf<ReadPin>) -> Self {
-        let state = indicator.get_inhibition();
+    /// Reads `line`, inhibited until it has settled
+    pub fn new(line: RbfLine<ReadPin>) -> Self {
         Self {
-            indicator,
-            state: Arc::new(Mutex::new(state)),
+            line,
+            state: Arc::new(Mutex::new(RbfState::Inhibited)),
         }
     }
 
     pub fn spawn(self, interval_ms: u64) -> RbfReader {
         let update_state = self.state.clone();
         spawn(move || {
-            rbf_states_thread(self.indicator, update_state, interval_ms);
+            rbf_states_thread(self.line, update_state, interval_ms);
         });
         RbfReader::from(self.state)
     }
@@ -49,17 +51,163 @@ impl From<Arc<Mutex<RbfState>>> for RbfReader {
     }
 }
 
-fn rbf_states_thread<T: RbfIndicator>(
-    mut indicator: T,
+// The RBF's debounced state, latched inhibited if it was in when the thread started. A read that
+// fails reaches the monitor as one, and counts as inserted. The edges the kernel saw since the
+// last sample go in first at their own timestamps, so a transition is dated from when the pin
+// moved rather than when it was next polled.
+struct RbfWatch<T: InputPin + EdgeSource> {
+    line: RbfLine<T>,
+    clock: EdgeClock,
+    monitor: RbfMonitor,
+    inhibited_at_init: bool,
+}
+
+impl<T: InputPin + EdgeSource> RbfWatch<T> {
+    fn new(mut line: RbfLine<T>, clock: EdgeClock) -> Self {
+        let inhibited_at_init = line.read().unwrap_or(true);
+        Self {
+            line,
+            clock,
+            monitor: RbfMonitor::new(RbfConfig::DEFAULT),
+            inhibited_at_init,
+        }
+    }
+
+    fn sample(&mut self, now_ms: u64) -> RbfState {
+        for edge in self.line.pin_mut().take_edges() {
+            let inserted = self.line.inserted_when(edge.edge.level());
+            self.update(self.clock.edge_ms(&edge), Some(inserted));
+        }
+        let inserted = self.line.read();
+        self.update(now_ms, inserted);
+        if self.inhibited_at_init {
+            RbfState::Inhibited
+        } else {
+            self.monitor.inhibition()
+        }
+    }
+
+    fn update(&mut self, now_ms: u64, inserted: Option<bool>) {
+        if let Some(transition) = self.monitor.update(now_ms, inserted) {
+            info!("RBF {:?} -> {:?}", transition.from, transition.to);
+        }
+    }
+}
+
+fn rbf_states_thread<T: InputPin + EdgeSource>(
+    line: RbfLine<T>,
     state: Arc<Mutex<RbfState>>,
     update_interval: u64,
 ) -> ! {
+    // Debounced so a bouncing connector reads as inhibited until it settles
+    let clock = EdgeClock::start();
+    let mut watch = RbfWatch::new(line, clock);
     loop {
+        let sampled = watch.sample(clock.now_ms());
         {
             // Explicit context
             let mut state = state.lock().unwrap(); //-Unwrap-
-            *state = indicator.get_inhibition();
+            *state = sampled;
         }
         std::thread::sleep(std::time::Duration::from_millis(update_interval));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    use common_states::rbf_monitor::{RbfFault, RbfStatus};
+    use embedded_hal::digital::{ErrorKind, ErrorType};
+
+    use super::*;
+    use crate::gpio::{Edge, EdgeEvent};
+
+    type Level = Rc<Cell<Option<bool>>>;
+    type Edges = Rc<RefCell<Vec<EdgeEvent>>>;
+
+    // A pin the test moves, reading None as a failure, with whatever edges the test queues
+    struct Pin(Level, Edges);
+
+    impl ErrorType for Pin {
+        type Error = ErrorKind;
+    }
+
+    impl InputPin for Pin {
+        fn is_high(&mut self) -> Result<bool, Self::Error> {
+            self.0.get().ok_or(ErrorKind::Other)
+        }
+
+        fn is_low(&mut self) -> Result<bool, Self::Error> {
+            self.is_high().map(|high| !high)
+        }
+    }
+
+    impl EdgeSource for Pin {
+        fn take_edges(&mut self) -> Vec<EdgeEvent> {
+            self.1.take()
+        }
+    }
+
+    fn watch(level: Option<bool>) -> (RbfWatch<Pin>, Level) {
+        let (watch, level, _) = watch_edges(level);
+        (watch, level)
+    }
+
+    // Edge timestamps in ns line up with the sample times in ms
+    fn watch_edges(level: Option<bool>) -> (RbfWatch<Pin>, Level, Edges) {
+        let level = Rc::new(Cell::new(level));
+        let edges = Rc::new(RefCell::new(Vec::new()));
+        let pin = Pin(level.clone(), edges.clone());
+        (RbfWatch::new(RbfLine::active_high(pin), EdgeClock::from_ns(0)), level, edges)
+    }
+
+    #[test]
+    fn test_failed_reads_stay_inhibited() {
+        let (mut watch, level) = watch(Some(false));
+        for now in (0..=500).step_by(100) {
+            watch.sample(now);
+        }
+        assert_eq!(watch.sample(600), RbfState::Uninhibited);
+
+        // Reads failing isn't the pin coming out, it's a fault
+        level.set(None);
+        let mut state = RbfState::Uninhibited;
+        for now in (700..=1200).step_by(100) {
+            state = watch.sample(now);
+        }
+        assert_eq!(state, RbfState::Inhibited);
+        assert_eq!(watch.monitor.status(), RbfStatus::Fault(RbfFault::ReadError));
+    }
+
+    #[test]
+    fn test_unreadable_at_start_latches() {
+        let (mut watch, level) = watch(None);
+        level.set(Some(false));
+        for now in (0..=1000).step_by(100) {
+            assert_eq!(watch.sample(now), RbfState::Inhibited);
+        }
+    }
+
+    #[test]
+    fn test_removal_dated_by_edge() {
+        let (mut watch, level, edges) = watch_edges(Some(true));
+        for now in (0..=600).step_by(100) {
+            watch.sample(now);
+        }
+        assert_eq!(watch.monitor.status(), RbfStatus::Inserted);
+
+        // Pulled just after one poll, seen as low by the next
+        edges.borrow_mut().push(EdgeEvent {
+            edge: Edge::Falling,
+            timestamp_ns: 1_020_000_000,
+        });
+        level.set(Some(false));
+        for now in (1100..=1600).step_by(100) {
+            watch.sample(now);
+        }
+        assert_eq!(watch.monitor.status(), RbfStatus::Removed);
+        assert_eq!(watch.monitor.removed_at_ms(), Some(1020));
+    }
+}
@@ -0,0 +1,513 @@
+#![warn(missing_docs)]
+
+//! Keeps track of whether each part of the flight software is still doing its job. Threads and
+//! main loop subsystems check in through a [`Heartbeat`], and [`Supervisor::check`] restarts or
+//! gives up on anything that has panicked, returned, or stopped checking in.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use bin_packets::data::SubsystemHealth;
+use log::{error, info, warn};
+
+/// The parts of JUPITER that get watched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    /// Avionics IMU reads
+    Imu,
+    /// GUARD radiation logging
+    Guard,
+    /// Infratracker capture and solve thread
+    Infratracker,
+    /// Main camera recording
+    MainCam,
+    /// Serial link to the Ejector
+    Serial,
+}
+
+impl Subsystem {
+    /// This subsystem's bit in [`SubsystemHealth`]
+    pub fn bit(self) -> u8 {
+        match self {
+            Subsystem::Imu => SubsystemHealth::IMU,
+            Subsystem::Guard => SubsystemHealth::GUARD,
+            Subsystem::Infratracker => SubsystemHealth::INFRATRACKER,
+            Subsystem::MainCam => SubsystemHealth::MAIN_CAM,
+            Subsystem::Serial => SubsystemHealth::SERIAL,
+        }
+    }
+
+    /// Name for logs and thread names
+    pub fn name(self) -> &'static str {
+        match self {
+            Subsystem::Imu => "imu",
+            Subsystem::Guard => "guard",
+            Subsystem::Infratracker => "infratracker",
+            Subsystem::MainCam => "main_cam",
+            Subsystem::Serial => "serial",
+        }
+    }
+}
+
+/// What to do when a supervised thread fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// Start it again, waiting `backoff` first, up to `max_restarts` times before giving up
+    Restart {
+        /// Restarts allowed before the subsystem is marked degraded for good
+        max_restarts: u16,
+        /// Wait between the failure and the restart
+        backoff: Duration,
+    },
+    /// Mark it degraded and leave it be
+    Degrade,
+}
+
+// Shared between the supervisor and every heartbeat handed out for one subsystem
+struct Slot {
+    epoch: Instant,
+    last_beat_ms: AtomicU64,
+    generation: AtomicU32,
+}
+
+impl Slot {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            epoch: Instant::now(),
+            last_beat_ms: AtomicU64::new(0),
+            generation: AtomicU32::new(0),
+        })
+    }
+
+    fn now_ms(&self) -> u64 {
+        self.epoch.elapsed().as_millis() as u64
+    }
+
+    fn touch(&self) {
+        self.last_beat_ms.store(self.now_ms(), Ordering::Relaxed);
+    }
+
+    fn age(&self) -> Duration {
+        Duration::from_millis(self.now_ms().saturating_sub(self.last_beat_ms.load(Ordering::Relaxed)))
+    }
+}
+
+/// Handle a subsystem uses to say it's still alive
+#[derive(Clone)]
+pub struct Heartbeat {
+    slot: Arc<Slot>,
+    generation: u32,
+}
+
+impl Heartbeat {
+    /// Check in. Returns false once the supervisor has given up on this run of the subsystem,
+    /// so a thread that comes back from a stall knows to get out of the way of its replacement.
+    pub fn beat(&self) -> bool {
+        if self.slot.generation.load(Ordering::Relaxed) != self.generation {
+            return false;
+        }
+        self.slot.touch();
+        true
+    }
+
+    /// Time since the last check in
+    pub fn age(&self) -> Duration {
+        self.slot.age()
+    }
+}
+
+type Body = Arc<dyn Fn(Heartbeat) + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Running,
+    RestartAt(Instant),
+    Degraded,
+}
+
+struct Entry {
+    subsystem: Subsystem,
+    timeout: Duration,
+    policy: Policy,
+    slot: Arc<Slot>,
+    // None for subsystems polled from the main loop, which can't be restarted from here
+    body: Option<Body>,
+    handle: Option<JoinHandle<()>>,
+    status: Status,
+    restarts: u16,
+}
+
+impl Entry {
+    fn heartbeat(&self) -> Heartbeat {
+        Heartbeat {
+            slot: Arc::clone(&self.slot),
+            generation: self.slot.generation.load(Ordering::Relaxed),
+        }
+    }
+
+    fn start(&mut self) {
+        let Some(body) = self.body.clone() else {
+            return;
+        };
+        self.slot.touch();
+        let heartbeat = self.heartbeat();
+        let spawned = thread::Builder::new()
+            .name(self.subsystem.name().to_string())
+            .spawn(move || body(heartbeat));
+
+        match spawned {
+            Ok(handle) => {
+                self.handle = Some(handle);
+                self.status = Status::Running;
+            }
+            Err(e) => {
+                error!("Failed to spawn {} thread: {e}", self.subsystem.name());
+                self.handle = None;
+                self.status = Status::Degraded;
+            }
+        }
+    }
+
+    // Why the subsystem should be considered failed, if it should
+    fn failure(&mut self) -> Option<String> {
+        if self.handle.as_ref().is_some_and(|h| h.is_finished()) {
+            let handle = self.handle.take().unwrap(); // -Unwrap- checked just above
+            return Some(match handle.join() {
+                Ok(()) => "thread returned".to_string(),
+                Err(_) => "thread panicked".to_string(),
+            });
+        }
+
+        let age = self.slot.age();
+        (age > self.timeout).then(|| format!("no heartbeat for {}ms", age.as_millis()))
+    }
+}
+
+/// Watches subsystems and applies each one's [`Policy`] when it fails
+pub struct Supervisor {
+    entries: Vec<Entry>,
+    restarts: u16,
+}
+
+impl Supervisor {
+    /// Nothing supervised yet
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            restarts: 0,
+        }
+    }
+
+    /// Run `body` on its own thread, expecting a beat at least every `timeout`. The body should
+    /// loop for as long as the flight lasts; returning, panicking and stalling all count as
+    /// failures and get `policy` applied.
+    pub fn supervise<F>(&mut self, subsystem: Subsystem, timeout: Duration, policy: Policy, body: F)
+    where
+        F: Fn(Heartbeat) + Send + Sync + 'static,
+    {
+        let mut entry = Entry {
+            subsystem,
+            timeout,
+            policy,
+            slot: Slot::new(),
+            body: Some(Arc::new(body)),
+            handle: None,
+            status: Status::Running,
+            restarts: 0,
+        };
+        entry.start();
+        self.entries.push(entry);
+    }
+
+    /// Watch something polled from the main loop. It gets marked degraded while it goes longer
+    /// than `timeout` without a beat, and healthy again once beats come back.
+    pub fn monitor(&mut self, subsystem: Subsystem, timeout: Duration) -> Heartbeat {
+        let slot = Slot::new();
+        slot.touch();
+        let entry = Entry {
+            subsystem,
+            timeout,
+            policy: Policy::Degrade,
+            slot,
+            body: None,
+            handle: None,
+            status: Status::Running,
+            restarts: 0,
+        };
+        let heartbeat = entry.heartbeat();
+        self.entries.push(entry);
+        heartbeat
+    }
+
+    /// Look over everything, restart or degrade what has failed, and report where things stand
+    pub fn check(&mut self) -> SubsystemHealth {
+        let now = Instant::now();
+        let mut health = SubsystemHealth {
+            restarts: self.restarts,
+            ..Default::default()
+        };
+
+        for entry in &mut self.entries {
+            let name = entry.subsystem.name();
+
+            if entry.body.is_none() {
+                // Main loop subsystems come and go with their beats
+                let failed = entry.slot.age() > entry.timeout;
+                if failed && entry.status == Status::Running {
+                    warn!("{name} degraded: no heartbeat for {}ms", entry.slot.age().as_millis());
+                    entry.status = Status::Degraded;
+                } else if !failed && entry.status == Status::Degraded {
+                    info!("{name} recovered");
+                    entry.status = Status::Running;
+                }
+            } else {
+                match entry.status {
+                    Status::Running => {
+                        if let Some(reason) = entry.failure() {
+                            // Anything still holding the old heartbeat is now stale
+                            entry.slot.generation.fetch_add(1, Ordering::Relaxed);
+                            entry.handle = None;
+
+                            match entry.policy {
+                                Policy::Restart { max_restarts, backoff } if entry.restarts < max_restarts => {
+                                    error!("{name} failed ({reason}), restarting in {}ms", backoff.as_millis());
+                                    entry.status = Status::RestartAt(now + backoff);
+                                }
+                                _ => {
+                                    error!("{name} failed ({reason}), marking degraded");
+                                    entry.status = Status::Degraded;
+                                }
+                            }
+                        }
+                    }
+                    Status::RestartAt(at) if now >= at => {
+                        info!("Restarting {name}");
+                        entry.start();
+                        entry.restarts += 1;
+                        self.restarts = self.restarts.saturating_add(1);
+                        health.restarts = self.restarts;
+                    }
+                    _ => {}
+                }
+            }
+
+            match entry.status {
+                Status::Running => health.healthy |= entry.subsystem.bit(),
+                Status::Degraded => health.degraded |= entry.subsystem.bit(),
+                // Neither while waiting on a restart
+                Status::RestartAt(_) => {}
+            }
+        }
+
+        health
+    }
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Watch the main loop from a thread of its own. If the returned heartbeat goes `timeout`
+/// without a beat, `on_stall` runs once. Nothing in the main loop can notice it has hung, so in
+/// flight this exits the process and lets the service restart it into the last checkpoint.
+pub fn spawn_watchdog<F>(timeout: Duration, on_stall: F) -> Heartbeat
+where
+    F: FnOnce() + Send + 'static,
+{
+    let slot = Slot::new();
+    slot.touch();
+    let heartbeat = Heartbeat {
+        slot: Arc::clone(&slot),
+        generation: 0,
+    };
+
+    let fired = AtomicBool::new(false);
+    let spawned = thread::Builder::new().name("watchdog".to_string()).spawn(move || {
+        let poll = (timeout / 4).max(Duration::from_millis(10));
+        while !fired.load(Ordering::Relaxed) {
+            thread::sleep(poll);
+            if slot.age() > timeout {
+                error!("Main loop stalled for {}ms", slot.age().as_millis());
+                fired.store(true, Ordering::Relaxed);
+            }
+        }
+        on_stall();
+    });
+
+    if let Err(e) = spawned {
+        error!("Failed to spawn watchdog: {e}");
+    }
+    heartbeat
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::mpsc::channel;
+
+    const TIMEOUT: Duration = Duration::from_millis(100);
+
+    fn restart(max_restarts: u16) -> Policy {
+        Policy::Restart {
+            max_restarts,
+            backoff: Duration::from_millis(10),
+        }
+    }
+
+    // Keep checking until `done` holds or a second passes
+    fn check_until(supervisor: &mut Supervisor, done: impl Fn(&SubsystemHealth) -> bool) -> SubsystemHealth {
+        let deadline = Instant::now() + Duration::from_secs(1);
+        loop {
+            let health = supervisor.check();
+            if done(&health) || Instant::now() > deadline {
+                return health;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_healthy_thread_stays_up() {
+        let mut supervisor = Supervisor::new();
+        supervisor.supervise(Subsystem::Infratracker, TIMEOUT, restart(3), |hb| {
+            while hb.beat() {
+                thread::sleep(Duration::from_millis(10));
+            }
+        });
+
+        thread::sleep(TIMEOUT * 2);
+        let health = supervisor.check();
+        assert!(health.is_healthy(SubsystemHealth::INFRATRACKER));
+        assert_eq!(health.restarts, 0);
+    }
+
+    #[test]
+    fn test_panicking_thread_is_restarted() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&runs);
+
+        let mut supervisor = Supervisor::new();
+        supervisor.supervise(Subsystem::Infratracker, TIMEOUT, restart(3), move |hb| {
+            // Dies on the first run, fine after that
+            if counted.fetch_add(1, Ordering::SeqCst) == 0 {
+                panic!("injected fault");
+            }
+            while hb.beat() {
+                thread::sleep(Duration::from_millis(10));
+            }
+        });
+
+        let health = check_until(&mut supervisor, |h| h.restarts == 1 && runs.load(Ordering::SeqCst) == 2);
+        assert_eq!(health.restarts, 1);
+        assert!(health.is_healthy(SubsystemHealth::INFRATRACKER));
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_stalled_thread_is_replaced() {
+        let (stale_tx, stale_rx) = channel();
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&runs);
+
+        let mut supervisor = Supervisor::new();
+        supervisor.supervise(Subsystem::MainCam, TIMEOUT, restart(3), move |hb| {
+            if counted.fetch_add(1, Ordering::SeqCst) == 0 {
+                // Hang well past the timeout, then find out we've been replaced
+                thread::sleep(TIMEOUT * 4);
+                stale_tx.send(hb.beat()).unwrap();
+                return;
+            }
+            while hb.beat() {
+                thread::sleep(Duration::from_millis(10));
+            }
+        });
+
+        let health = check_until(&mut supervisor, |h| h.restarts == 1 && h.is_healthy(SubsystemHealth::MAIN_CAM));
+        assert!(health.is_healthy(SubsystemHealth::MAIN_CAM));
+        assert!(!stale_rx.recv_timeout(Duration::from_secs(1)).unwrap());
+
+        // The old thread finishing doesn't take the new one down with it
+        let health = supervisor.check();
+        assert!(health.is_healthy(SubsystemHealth::MAIN_CAM));
+        assert_eq!(health.restarts, 1);
+    }
+
+    #[test]
+    fn test_gives_up_after_max_restarts() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&runs);
+
+        let mut supervisor = Supervisor::new();
+        supervisor.supervise(Subsystem::Infratracker, TIMEOUT, restart(2), move |_| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            panic!("injected fault");
+        });
+
+        let health = check_until(&mut supervisor, |h| h.is_degraded(SubsystemHealth::INFRATRACKER));
+        assert!(health.is_degraded(SubsystemHealth::INFRATRACKER));
+        assert!(!health.is_healthy(SubsystemHealth::INFRATRACKER));
+        assert_eq!(health.restarts, 2);
+
+        // Stays given up on
+        thread::sleep(TIMEOUT);
+        assert!(supervisor.check().is_degraded(SubsystemHealth::INFRATRACKER));
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_degrade_policy_does_not_restart() {
+        let mut supervisor = Supervisor::new();
+        supervisor.supervise(Subsystem::Serial, TIMEOUT, Policy::Degrade, |_| {});
+
+        let health = check_until(&mut supervisor, |h| h.is_degraded(SubsystemHealth::SERIAL));
+        assert!(health.is_degraded(SubsystemHealth::SERIAL));
+        assert_eq!(health.restarts, 0);
+    }
+
+    #[test]
+    fn test_monitored_subsystem_recovers() {
+        let mut supervisor = Supervisor::new();
+        let imu = supervisor.monitor(Subsystem::Imu, TIMEOUT);
+        let guard = supervisor.monitor(Subsystem::Guard, TIMEOUT);
+
+        let health = supervisor.check();
+        assert!(health.is_healthy(SubsystemHealth::IMU));
+        assert!(health.is_healthy(SubsystemHealth::GUARD));
+
+        // IMU goes quiet, GUARD keeps going
+        let stalled_until = Instant::now() + TIMEOUT * 2;
+        while Instant::now() < stalled_until {
+            guard.beat();
+            thread::sleep(Duration::from_millis(10));
+        }
+        let health = supervisor.check();
+        assert!(health.is_degraded(SubsystemHealth::IMU));
+        assert!(health.is_healthy(SubsystemHealth::GUARD));
+
+        assert!(imu.beat());
+        let health = supervisor.check();
+        assert!(health.is_healthy(SubsystemHealth::IMU));
+        assert_eq!(health.degraded, 0);
+    }
+
+    #[test]
+    fn test_watchdog_fires_on_stall() {
+        let (tx, rx) = channel();
+        let heartbeat = spawn_watchdog(TIMEOUT, move || tx.send(()).unwrap());
+
+        // Beating keeps it quiet
+        for _ in 0..20 {
+            heartbeat.beat();
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(rx.try_recv().is_err());
+
+        // Then the main loop hangs
+        assert!(rx.recv_timeout(Duration::from_secs(1)).is_ok());
+    }
+}
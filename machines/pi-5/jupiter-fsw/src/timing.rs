@@ -19,11 +19,14 @@ lazy_static! {
 
 /// Seconds elapsed since power-on.
 pub fn power_on_time() -> i32 {
-    let now = SystemTime::now();
-    let dur = now
+    uptime().as_secs() as i32
+}
+
+/// Time since power-on, for timeouts that need better than a second
+pub fn uptime() -> Duration {
+    SystemTime::now()
         .duration_since(*POWER_ON_TIME)
-        .unwrap_or(Duration::from_secs(0));
-    dur.as_secs() as i32
+        .unwrap_or(Duration::from_secs(0))
 }
 
 pub fn t_time_estimate() -> i32 {
@@ -31,6 +34,22 @@ pub fn t_time_estimate() -> i32 {
     power_on_time() + T_CALIBRATION_OFFSET.load(Ordering::Relaxed)
 }
 
+// Mission time is one global, tests that move it take turns
+#[cfg(test)]
+static CLOCK_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// Hold the mission clock for the length of a test
+#[cfg(test)]
+pub(crate) fn lock_clock() -> std::sync::MutexGuard<'static, ()> {
+    // A failed test shouldn't take every later one down with it
+    CLOCK_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Current offset between time since power on and mission time
+pub fn calibration_offset() -> i32 {
+    T_CALIBRATION_OFFSET.load(Ordering::Relaxed)
+}
+
 pub fn calibrate_to(truth: i32) {
     let elapsed = power_on_time();
     // we want: elapsed + new_offset == truth  →  new_offset = truth - elapsed
@@ -2,34 +2,40 @@ mod networking;
 mod tasks;
 
 use std::{
-    fs::{File, OpenOptions},
-    io::{BufReader, BufWriter, Read, Write},
-    mem,
-    net::{TcpListener, TcpStream},
+    io::Write,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
     thread,
     time::Duration,
 };
 
-use bincode::{
-    config::{Configuration, LittleEndian, NoLimit, Varint, standard},
-    serde::{decode_from_reader, encode_into_slice},
-};
+use bincode::config::standard;
 use env_logger::Builder;
-use log::{LevelFilter, error, info};
+use log::{LevelFilter, error, info, warn};
 use rtrb::RingBuffer;
 
+use crate::networking::{
+    config::NetworkConfig,
+    sockets::{PacketListener, TcpFanout},
+    traits::NetworkSocket,
+};
 use crate::tasks::{
     signal_process::SignalProcessor, signal_read::SDRListener, startracker::StartrackerThread,
 };
 
 use signet::{
-    record::packet::SdrPacketLog,
-    sdr::radio_config::BUFF_SIZE,
+    record::{
+        packet::SdrPacketLog,
+        recording::{RecordingHeader, RecordingWriter, repair_recording, verify_recording},
+    },
+    sdr::{accounting::StreamStats, radio_config::RadioConfig},
 };
 
-use bin_packets::data::adcs::AttitudeMetrics;
+use bin_packets::data::adcs::{AttitudeMetrics, SdrHealth};
 use bin_packets::time::Timestamp;
 
+const RECORDING_PATH: &str = "sdr_recording.bin";
 
 fn main() {
     // env_logger::init();
@@ -46,34 +52,36 @@ fn main() {
 
     let (samples_producer, mut samples_consumer) = RingBuffer::<SdrPacketLog>::new(100);
 
-    let _sampling_task = SDRListener::begin_sampling(samples_producer).unwrap();
+    let radio_config = RadioConfig::new(1420.405e6, 3.0e6);
+    let (_sampling_task, stream_counters) =
+        SDRListener::begin_sampling(radio_config, samples_producer).unwrap();
 
     // Refactor to be one combined call, but not ugly.
     let (startracking_thread, quaternion_reciever) = StartrackerThread::new();
     let _startracking_thread_handle = startracking_thread.begin_startracking();
 
-    // start_test_tcp_receiver();
-    // verify_recording("sdr_recording.bin");
-    start_file_recorder();
-
-    std::thread::sleep(Duration::from_millis(500));
-
-    let mut stream = TcpStream::connect("127.0.0.1:7878").expect("Failed to connect to Jupiter");
-    stream
-        .set_nonblocking(true)
-        .expect("Failed to set non-blocking");
-    stream
-        .set_write_timeout(Some(Duration::from_micros(100)))
-        .unwrap();
+    let network_config = NetworkConfig::from_env().unwrap_or_else(|e| {
+        error!("{}, using default network config", e);
+        NetworkConfig::default()
+    });
 
-    std::thread::sleep(Duration::from_millis(500));
+    // start_test_tcp_receiver();
+    start_file_recorder(
+        network_config.recorder,
+        RecordingHeader::new(
+            &radio_config,
+            radio_config.output_sample_rate(),
+            env!("CARGO_PKG_VERSION"),
+        ),
+    );
+
+    // Subscribers connect (and reconnect) on their own threads, no need to wait on them
+    let mut sdr_uplink = TcpFanout::new(network_config.endpoints());
 
     let (signal_processor, packet_tx, estimate_rx) = SignalProcessor::default();
 
     signal_processor.begin_signal_processing();
 
-    let mut packet_buf: [u8; BUFF_SIZE * 10] = [0; BUFF_SIZE * 10];
-
     let mut adcs_buffer: [u8; 1000] = [0; 1000];
     // Run main IO loop in a thread with larger stack to handle large fixed-size arrays
     let io_handle = thread::Builder::new()
@@ -81,27 +89,48 @@ fn main() {
         .stack_size(8 * 1024 * 1024) // 4 MB stack
         .spawn(move || {
             let mut cnt = 0;
+            let mut reported_drops = 0;
+            let mut reported_losses = SdrHealth::default();
             loop {
                 match samples_consumer.read_chunk(1) {
                     Ok(mut read_chunk) => {
                         let (slc_1, _slc_2) = read_chunk.as_mut_slices();
                         let sdr_packet = &mut slc_1[0];
 
-                        if let Ok(bytes_written) =
-                            encode_into_slice(&sdr_packet, packet_buf.as_mut_slice(), standard())
-                        {
-                            // if let Err(e) = socket.send(&packet_buf) {
-                            //     error!("Error sending packet: {}", e);
-                            // }
-                            if let Err(e) = stream.write_all(&packet_buf[..bytes_written]) {
-                                error!("Error sending packet: {}", e);
-                            }
-                        } else {
-                            error!("Error encoding packet");
+                        if let Err(e) = sdr_uplink.send(sdr_packet) {
+                            error!("Error sending packet: {}", e);
                         }
 
                         cnt += 1;
                         if cnt % 30 == 0 {
+                            let drops = sdr_uplink.dropped();
+                            if drops != reported_drops {
+                                for (name, stats) in sdr_uplink.stats() {
+                                    warn!(
+                                        "Uplink {}: sent {}, dropped {}, possibly lost {}, reconnects {}, connected {}",
+                                        name,
+                                        stats.sent.load(Ordering::Relaxed),
+                                        stats.dropped.load(Ordering::Relaxed),
+                                        stats.possibly_lost.load(Ordering::Relaxed),
+                                        stats.reconnects.load(Ordering::Relaxed),
+                                        stats.connected.load(Ordering::Relaxed),
+                                    );
+                                }
+                                reported_drops = drops;
+                            }
+
+                            // Only worth a log line when something new was lost
+                            let sdr_health = sdr_health(&stream_counters.snapshot());
+                            let losses = SdrHealth {
+                                packets: 0,
+                                short_reads: 0,
+                                ..sdr_health
+                            };
+                            if losses != reported_losses {
+                                warn!("SDR stream health: {:?}", sdr_health);
+                                reported_losses = losses;
+                            }
+
                             if let Err(e) = packet_tx.send(Box::new(*sdr_packet)) {
                                 error!("Error Sending Packet Data {}", e);
                             };
@@ -118,6 +147,7 @@ fn main() {
                                             quaternion.k(),
                                         ],
                                         signal_match: estimate,
+                                        sdr_health,
                                     };
                                     if let Ok(bytes_written) = bincode::encode_into_slice(
                                         adcs_packet,
@@ -154,52 +184,76 @@ fn main() {
     io_handle.join().expect("IO thread panicked");
 }
 
-fn start_file_recorder() {
-    if std::path::Path::new("sdr_recording.bin").exists() {
-        let _ = std::fs::remove_file("sdr_recording.bin");
+// Health packets are small on the wire, the counters won't come close to u32 in a flight
+fn sdr_health(stats: &StreamStats) -> SdrHealth {
+    let clamp = |count: u64| count.try_into().unwrap_or(u32::MAX);
+    SdrHealth {
+        packets: clamp(stats.packets),
+        overflows: clamp(stats.device_overflows),
+        ring_drops: clamp(stats.ring_drops),
+        short_reads: clamp(stats.short_reads),
+        discontinuities: clamp(stats.discontinuities),
+        truncated_samples: clamp(stats.truncated_samples),
+    }
+}
+
+fn start_file_recorder(address: SocketAddr, header: RecordingHeader) {
+    if Path::new(RECORDING_PATH).exists() {
+        let _ = std::fs::remove_file(RECORDING_PATH);
     }
 
     thread::Builder::new()
         .name("tcp-recorder".into())
-        .stack_size(4 * 1024 * 1024)
+        // Decoding an SdrPacketLog makes a few copies of it on the stack
+        .stack_size(16 * 1024 * 1024)
         .spawn(move || {
-            let listener = TcpListener::bind("127.0.0.1:7878").expect("Failed to bind");
-            info!("Recorder listening on 127.0.0.1:7878...");
-
-            let mut buffer = [0u8; BUFF_SIZE * 10];
+            let listener = PacketListener::bind(address).expect("Failed to bind");
+            if let Ok(address) = listener.local_addr() {
+                info!("Recorder listening on {}...", address);
+            }
 
-            for stream in listener.incoming() {
-                match stream {
+            // The uplink reconnects after a dropped link, each connection gets its own file so a
+            // reconnect doesn't clobber what was already recorded
+            let mut session = 0;
+            loop {
+                match listener.accept() {
                     Ok(mut stream) => {
-                        info!("Connection established: {:?}", stream.peer_addr());
+                        let recording_path = session_path(session);
+                        session += 1;
 
-                        let file = OpenOptions::new()
-                            .write(true)
-                            .create(true)
-                            .truncate(true)
-                            .open("sdr_recording.bin")
-                            .expect("Failed to open recording file");
-
-                        let mut writer = BufWriter::with_capacity(1024 * 1024, file);
+                        let mut recorder =
+                            match RecordingWriter::create(&recording_path, &header.restamped()) {
+                                Ok(recorder) => recorder,
+                                Err(e) => {
+                                    error!("Failed to open recording file: {}", e);
+                                    continue;
+                                }
+                            };
 
                         loop {
-                            match stream.read(&mut buffer) {
-                                Ok(0) => {
+                            match stream.next_packet::<SdrPacketLog>() {
+                                Ok(Some(sdr_packet)) => {
+                                    if let Err(e) = recorder.write_packet(&sdr_packet) {
+                                        error!("Error writing packet to recording {}", e);
+                                    }
+                                }
+                                Ok(None) => {
                                     info!("Sender disconnected. Closing file.");
                                     break;
                                 }
-                                Ok(bytes_read) => {
-                                    if let Err(e) = writer.write(&buffer[..bytes_read]) {
-                                        error!("Error writing encoded data {}", e);
-                                    }
-                                }
-
                                 Err(e) => {
-                                    error!("Error reading from socket{}", e);
+                                    error!("Error decoding packet from socket {}", e);
+                                    break;
                                 }
                             }
                         }
-                        let _ = writer.flush();
+
+                        if let Err(e) = recorder.finish() {
+                            error!("Error closing recording {}", e);
+                        }
+                        drop(recorder);
+
+                        check_recording(&recording_path);
                     }
                     Err(e) => error!("Connection failed: {}", e),
                 }
@@ -208,42 +262,35 @@ fn start_file_recorder() {
         .unwrap();
 }
 
-fn verify_recording(filepath: &str) {
-    if !std::path::Path::new(filepath).exists() {
-        info!("No recording file found to verify.");
-        return;
+fn session_path(session: u32) -> PathBuf {
+    if session == 0 {
+        PathBuf::from(RECORDING_PATH)
+    } else {
+        let first = Path::new(RECORDING_PATH);
+        let stem = first.file_stem().unwrap_or_default().to_string_lossy();
+        first.with_file_name(format!("{stem}_{session}.bin"))
     }
+}
 
-    info!("Verifying recording from: {}", filepath);
-    let file = File::open(filepath).expect("File not found");
-
-    let mut reader = BufReader::new(file);
-
-    let packet_size = mem::size_of::<SdrPacketLog>();
-
-    let _buffer = vec![0u8; packet_size];
-    let mut count = 0;
-
-    let config = standard();
-
-    loop {
-        count += 1;
-        match decode_from_reader::<
-            SdrPacketLog,
-            &mut BufReader<File>,
-            Configuration<LittleEndian, Varint, NoLimit>,
-        >(&mut reader, config)
-        {
-            Ok(sdr_packet) => {
-                info!(
-                    "Packet #{}: TS={} SampleCount={}",
-                    count, sdr_packet.timestamp, sdr_packet.sample_count
-                );
-            }
-            Err(e) => {
-                error!("File read error: {}", e);
-                break;
+// Run on recorder shutdown - make sure what's on disk is readable, patch it up if not. SigMF
+// export is `signet export` on the ground, a second copy of the IQ won't fit on the Pi
+fn check_recording(filepath: &Path) {
+    info!("Verifying recording from: {}", filepath.display());
+
+    match verify_recording(filepath) {
+        Ok(report) if report.is_clean() => {
+            info!(
+                "Recording OK: {} packets, {} index records",
+                report.packets, report.index_records
+            );
+        }
+        Ok(report) => {
+            warn!("Recording damaged, repairing: {:?}", report);
+            match repair_recording(filepath) {
+                Ok(_) => info!("Recording repaired"),
+                Err(e) => error!("Recording repair failed: {}", e),
             }
         }
+        Err(e) => error!("Could not verify recording: {}", e),
     }
 }
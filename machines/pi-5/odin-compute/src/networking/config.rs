@@ -0,0 +1,68 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use crate::networking::error::IOError;
+use crate::networking::sockets::{Backpressure, Endpoint};
+
+const RECORDER_ADDR_VAR: &str = "ODIN_RECORDER_ADDR";
+const MONITOR_ADDR_VAR: &str = "ODIN_MONITOR_ADDR";
+
+const DEFAULT_RECORDER_ADDR: &str = "127.0.0.1:7878";
+
+/// Where the SDR stream goes. Set through the environment so the ground station can
+/// point a spectrum monitor at the board without a rebuild.
+#[derive(Debug, Clone)]
+pub struct NetworkConfig {
+    /// The onboard recorder listens here, it gets every packet
+    pub recorder: SocketAddr,
+    /// Optional live spectrum monitor, it gets whatever the link can keep up with
+    pub monitor: Option<SocketAddr>,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            recorder: DEFAULT_RECORDER_ADDR.parse().unwrap(),
+            monitor: None,
+        }
+    }
+}
+
+impl NetworkConfig {
+    pub fn from_env() -> Result<Self, IOError> {
+        let mut config = Self::default();
+        if let Some(recorder) = parse_var(RECORDER_ADDR_VAR)? {
+            config.recorder = recorder;
+        }
+        config.monitor = parse_var(MONITOR_ADDR_VAR)?;
+        Ok(config)
+    }
+
+    pub fn endpoints(&self) -> Vec<Endpoint> {
+        // The recorder can't lose packets, so hold the IO loop back for a bit rather than drop.
+        // If it stays behind the SDR ring fills up and that gets reported where it happens.
+        let mut endpoints = vec![
+            Endpoint::new("recorder", self.recorder)
+                .with_queue_depth(32)
+                .with_backpressure(Backpressure::Block(Duration::from_millis(50))),
+        ];
+        if let Some(monitor) = self.monitor {
+            endpoints.push(
+                Endpoint::new("monitor", monitor)
+                    .with_queue_depth(4)
+                    .with_reconnect_interval(Duration::from_secs(2)),
+            );
+        }
+        endpoints
+    }
+}
+
+fn parse_var(name: &'static str) -> Result<Option<SocketAddr>, IOError> {
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| IOError::BadAddress(name, value)),
+        Err(_) => Ok(None),
+    }
+}
@@ -1,3 +1,4 @@
+use bincode::error::{DecodeError, EncodeError};
 use thiserror::Error;
 
 // A wrapper around networking error types for the network socket trait
@@ -8,4 +9,18 @@ pub enum IOError {
     RadioSendError,
     #[error("Error sending packet to Process Thread")]
     PacketChannelSendError,
+    #[error("Error encoding packet: {0}")]
+    Encode(#[from] EncodeError),
+    #[error("Error decoding packet: {0}")]
+    Decode(#[from] DecodeError),
+    #[error("Failed to bind socket: {0}")]
+    Bind(std::io::Error),
+    #[error("Connection failed: {0}")]
+    Connection(std::io::Error),
+    #[error("Invalid address in {0}: {1}")]
+    BadAddress(&'static str, String),
+    #[error("Subscriber {0} is falling behind, packet dropped")]
+    SubscriberBehind(String),
+    #[error("Subscriber {0} has shut down")]
+    SubscriberClosed(String),
 }
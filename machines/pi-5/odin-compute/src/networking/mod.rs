@@ -1,2 +1,4 @@
+pub mod config;
 pub mod error;
+pub mod sockets;
 pub mod traits;
@@ -0,0 +1,416 @@
+use std::io::{BufReader, BufWriter, ErrorKind, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError, sync_channel};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use bincode::{
+    config::standard,
+    error::DecodeError,
+    serde::{decode_from_std_read, encode_to_vec},
+};
+use log::{info, warn};
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::networking::error::IOError;
+use crate::networking::traits::NetworkSocket;
+
+// Encoded frames are shared between every subscriber queue rather than copied per subscriber
+type Frame = Arc<Vec<u8>>;
+
+/// What to do when a subscriber's queue is full
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backpressure {
+    /// Wait up to the given time for the subscriber to catch up, then report it as behind.
+    /// Use for subscribers that need every packet, like the recorder.
+    Block(Duration),
+    /// Drop the packet for this subscriber and count it. Use for live views where the latest
+    /// data matters more than all of it.
+    DropNewest,
+}
+
+/// A place packets get sent to
+#[derive(Debug, Clone)]
+pub struct Endpoint {
+    pub name: String,
+    pub address: SocketAddr,
+    /// Packets that can be waiting on this subscriber before backpressure kicks in
+    pub queue_depth: usize,
+    pub backpressure: Backpressure,
+    /// How long to wait between connection attempts
+    pub reconnect_interval: Duration,
+}
+
+impl Endpoint {
+    pub fn new(name: &str, address: SocketAddr) -> Self {
+        Self {
+            name: name.to_string(),
+            address,
+            queue_depth: 8,
+            backpressure: Backpressure::DropNewest,
+            reconnect_interval: Duration::from_millis(500),
+        }
+    }
+
+    pub fn with_queue_depth(mut self, queue_depth: usize) -> Self {
+        self.queue_depth = queue_depth.max(1);
+        self
+    }
+
+    pub fn with_backpressure(mut self, backpressure: Backpressure) -> Self {
+        self.backpressure = backpressure;
+        self
+    }
+
+    pub fn with_reconnect_interval(mut self, reconnect_interval: Duration) -> Self {
+        self.reconnect_interval = reconnect_interval;
+        self
+    }
+}
+
+/// Counters for a single subscriber, shared with its writer thread
+#[derive(Debug, Default)]
+pub struct SubscriberStats {
+    /// Frames flushed to the socket. Nothing acknowledges them, so a link that drops afterwards
+    /// can still lose whatever the kernel hadn't sent yet.
+    pub sent: AtomicU64,
+    /// Frames that never got a place in the queue
+    pub dropped: AtomicU64,
+    /// Frames written but not flushed when the link dropped. Some may have arrived.
+    pub possibly_lost: AtomicU64,
+    pub reconnects: AtomicU64,
+    pub connected: AtomicBool,
+}
+
+struct Subscriber {
+    endpoint: Endpoint,
+    queue: SyncSender<Frame>,
+    stats: Arc<SubscriberStats>,
+    _handle: JoinHandle<()>,
+}
+
+impl Subscriber {
+    fn spawn(endpoint: Endpoint) -> Self {
+        let (queue, frames) = sync_channel(endpoint.queue_depth);
+        let stats = Arc::new(SubscriberStats::default());
+
+        let worker_endpoint = endpoint.clone();
+        let worker_stats = stats.clone();
+        let handle = thread::Builder::new()
+            .name(format!("net-{}", endpoint.name))
+            .spawn(move || subscriber_thread(worker_endpoint, frames, worker_stats))
+            .expect("Failed to spawn subscriber thread");
+
+        Self {
+            endpoint,
+            queue,
+            stats,
+            _handle: handle,
+        }
+    }
+
+    fn push(&self, frame: &Frame) -> Result<(), IOError> {
+        let name = &self.endpoint.name;
+        match self.endpoint.backpressure {
+            Backpressure::DropNewest => match self.queue.try_send(frame.clone()) {
+                Ok(_) => Ok(()),
+                Err(TrySendError::Full(_)) => {
+                    self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                    Ok(())
+                }
+                Err(TrySendError::Disconnected(_)) => Err(IOError::SubscriberClosed(name.clone())),
+            },
+
+            Backpressure::Block(timeout) => {
+                let deadline = Instant::now() + timeout;
+                let mut pending = frame.clone();
+                loop {
+                    match self.queue.try_send(pending) {
+                        Ok(_) => return Ok(()),
+                        Err(TrySendError::Full(returned)) => {
+                            if Instant::now() >= deadline {
+                                self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                                return Err(IOError::SubscriberBehind(name.clone()));
+                            }
+                            pending = returned;
+                            thread::sleep(Duration::from_micros(200));
+                        }
+                        Err(TrySendError::Disconnected(_)) => {
+                            return Err(IOError::SubscriberClosed(name.clone()));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Owns the connection to one endpoint. Frames are written in batches of whatever is queued
+// and flushed together. If a batch fails it is counted as possibly lost rather than resent,
+// since part of it may already be on the far side.
+fn subscriber_thread(endpoint: Endpoint, frames: Receiver<Frame>, stats: Arc<SubscriberStats>) {
+    loop {
+        let stream =
+            match TcpStream::connect_timeout(&endpoint.address, endpoint.reconnect_interval) {
+                Ok(stream) => stream,
+                Err(_) => {
+                    thread::sleep(endpoint.reconnect_interval);
+                    continue;
+                }
+            };
+        stream.set_nodelay(true).ok();
+        stats.connected.store(true, Ordering::Relaxed);
+        info!("Connected to {} at {}", endpoint.name, endpoint.address);
+
+        let mut writer = BufWriter::with_capacity(256 * 1024, stream);
+        loop {
+            let mut batch = match frames.recv() {
+                Ok(frame) => vec![frame],
+                // The fanout was dropped, nothing left to send
+                Err(_) => return,
+            };
+            while batch.len() < endpoint.queue_depth {
+                match frames.try_recv() {
+                    Ok(frame) => batch.push(frame),
+                    Err(_) => break,
+                }
+            }
+
+            let written = batch
+                .iter()
+                .try_for_each(|frame| writer.write_all(frame))
+                .and_then(|_| writer.flush());
+            if let Err(e) = written {
+                warn!(
+                    "Lost connection to {}, {} packets possibly lost: {}",
+                    endpoint.name,
+                    batch.len(),
+                    e
+                );
+                stats
+                    .possibly_lost
+                    .fetch_add(batch.len() as u64, Ordering::Relaxed);
+                break;
+            }
+            stats.sent.fetch_add(batch.len() as u64, Ordering::Relaxed);
+        }
+
+        stats.connected.store(false, Ordering::Relaxed);
+        stats.reconnects.fetch_add(1, Ordering::Relaxed);
+        thread::sleep(endpoint.reconnect_interval);
+    }
+}
+
+/// Sends every packet to each configured endpoint. Each endpoint gets its own bounded queue and
+/// writer thread, so a slow subscriber only ever affects itself according to its backpressure
+/// policy, and reconnects on its own if the link drops.
+pub struct TcpFanout {
+    subscribers: Vec<Subscriber>,
+}
+
+impl TcpFanout {
+    pub fn new(endpoints: Vec<Endpoint>) -> Self {
+        Self {
+            subscribers: endpoints.into_iter().map(Subscriber::spawn).collect(),
+        }
+    }
+
+    /// Per-subscriber counters, by endpoint name
+    pub fn stats(&self) -> impl Iterator<Item = (&str, &SubscriberStats)> {
+        self.subscribers
+            .iter()
+            .map(|subscriber| (subscriber.endpoint.name.as_str(), subscriber.stats.as_ref()))
+    }
+
+    /// Total packets dropped or possibly lost across all subscribers
+    pub fn dropped(&self) -> u64 {
+        self.stats()
+            .map(|(_, stats)| {
+                stats.dropped.load(Ordering::Relaxed) + stats.possibly_lost.load(Ordering::Relaxed)
+            })
+            .sum()
+    }
+}
+
+impl NetworkSocket for TcpFanout {
+    fn send<T: Serialize>(&mut self, packet: &T) -> Result<(), IOError> {
+        let frame: Frame = Arc::new(encode_to_vec(packet, standard())?);
+
+        // Every subscriber gets a chance at the packet, the first failure is reported
+        let mut result = Ok(());
+        for subscriber in &self.subscribers {
+            if let Err(e) = subscriber.push(&frame)
+                && result.is_ok()
+            {
+                result = Err(e);
+            }
+        }
+        result
+    }
+}
+
+/// The receiving end of a [`TcpFanout`] endpoint
+pub struct PacketListener {
+    listener: TcpListener,
+}
+
+impl PacketListener {
+    pub fn bind(address: SocketAddr) -> Result<Self, IOError> {
+        Ok(Self {
+            listener: TcpListener::bind(address).map_err(IOError::Bind)?,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, IOError> {
+        self.listener.local_addr().map_err(IOError::Bind)
+    }
+
+    /// Block until a sender connects
+    pub fn accept(&self) -> Result<PacketStream, IOError> {
+        let (stream, peer) = self.listener.accept().map_err(IOError::Connection)?;
+        info!("Connection established: {peer}");
+        Ok(PacketStream {
+            reader: BufReader::with_capacity(1024 * 1024, stream),
+        })
+    }
+}
+
+/// A single connection's worth of decoded packets
+pub struct PacketStream {
+    reader: BufReader<TcpStream>,
+}
+
+impl PacketStream {
+    /// Read the next packet. Returns Ok(None) when the sender disconnects.
+    pub fn next_packet<T: DeserializeOwned>(&mut self) -> Result<Option<T>, IOError> {
+        match decode_from_std_read(&mut self.reader, standard()) {
+            Ok(packet) => Ok(Some(packet)),
+            Err(DecodeError::Io { inner, .. })
+                if matches!(
+                    inner.kind(),
+                    ErrorKind::UnexpectedEof | ErrorKind::ConnectionReset
+                ) =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loopback() -> (PacketListener, SocketAddr) {
+        let listener = PacketListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let address = listener.local_addr().unwrap();
+        (listener, address)
+    }
+
+    #[test]
+    fn test_multiple_subscribers() {
+        let (recorder, recorder_addr) = loopback();
+        let (monitor, monitor_addr) = loopback();
+
+        let mut fanout = TcpFanout::new(vec![
+            Endpoint::new("recorder", recorder_addr)
+                .with_backpressure(Backpressure::Block(Duration::from_secs(1))),
+            Endpoint::new("monitor", monitor_addr),
+        ]);
+
+        for i in 0..5u32 {
+            fanout.send(&(i, vec![i as f32; 16])).unwrap();
+        }
+
+        for listener in [recorder, monitor] {
+            let mut stream = listener.accept().unwrap();
+            for i in 0..5u32 {
+                let (n, samples): (u32, Vec<f32>) = stream.next_packet().unwrap().unwrap();
+                assert_eq!(n, i);
+                assert_eq!(samples, vec![i as f32; 16]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_drop_newest_counts_drops() {
+        // Accepted but never read, so the socket buffers fill and the queue backs up
+        let (stalled, stalled_addr) = loopback();
+        let mut fanout = TcpFanout::new(vec![
+            Endpoint::new("stalled", stalled_addr).with_queue_depth(1),
+        ]);
+        let _connection = stalled.accept().unwrap();
+
+        let big = vec![0u8; 1024 * 1024];
+        for _ in 0..64 {
+            fanout.send(&big).unwrap();
+            if fanout.dropped() > 0 {
+                break;
+            }
+        }
+        assert!(fanout.dropped() > 0);
+    }
+
+    #[test]
+    fn test_block_reports_behind() {
+        let (stalled, stalled_addr) = loopback();
+        let mut fanout = TcpFanout::new(vec![
+            Endpoint::new("recorder", stalled_addr)
+                .with_queue_depth(1)
+                .with_backpressure(Backpressure::Block(Duration::from_millis(50))),
+        ]);
+        let _connection = stalled.accept().unwrap();
+
+        let big = vec![0u8; 1024 * 1024];
+        let behind = (0..64).any(|_| {
+            matches!(
+                fanout.send(&big),
+                Err(IOError::SubscriberBehind(ref name)) if name == "recorder"
+            )
+        });
+        assert!(behind);
+    }
+
+    #[test]
+    fn test_reconnect() {
+        let (listener, address) = loopback();
+        let mut fanout = TcpFanout::new(vec![
+            Endpoint::new("recorder", address)
+                .with_backpressure(Backpressure::Block(Duration::from_secs(1)))
+                .with_reconnect_interval(Duration::from_millis(20)),
+        ]);
+
+        fanout.send(&1u32).unwrap();
+        let mut stream = listener.accept().unwrap();
+        assert_eq!(stream.next_packet::<u32>().unwrap(), Some(1));
+        drop(stream);
+
+        // The first writes after the drop land in the dead socket's buffer before the reset is
+        // noticed and are gone, keep sending until the new connection sees data
+        let mut stream = loop {
+            fanout.send(&2u32).unwrap();
+            if let Ok(stream) = listener
+                .listener
+                .set_nonblocking(true)
+                .and_then(|_| listener.listener.accept())
+            {
+                stream.0.set_nonblocking(false).unwrap();
+                break PacketStream {
+                    reader: BufReader::new(stream.0),
+                };
+            }
+            thread::sleep(Duration::from_millis(20));
+        };
+        assert_eq!(stream.next_packet::<u32>().unwrap(), Some(2));
+        assert!(
+            fanout
+                .stats()
+                .any(|(_, stats)| stats.reconnects.load(Ordering::Relaxed) > 0)
+        );
+        // Whatever failed to write when the reset surfaced is accounted for
+        assert!(fanout.dropped() > 0);
+    }
+}
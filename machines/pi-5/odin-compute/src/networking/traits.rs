@@ -1,5 +1,8 @@
 use crate::networking::error::IOError;
-use bin_packets::packets::ApplicationPacket;
-trait NetworkSocket {
-    fn send(application_packet: &ApplicationPacket) -> Result<(), IOError>;
+use serde::Serialize;
+
+// Anything that can ship packets off the board - ApplicationPackets to Jupiter,
+// SdrPacketLogs to the recorder, etc.
+pub trait NetworkSocket {
+    fn send<T: Serialize>(&mut self, packet: &T) -> Result<(), IOError>;
 }
@@ -4,6 +4,7 @@ use signet::{
         packet::SdrPacketLog,
     },
     sdr::{
+        accounting::StreamCounters,
         radio_config::{RadioConfig, TARGET_PACKET_SIZE},
         sdr::SDR,
     },
@@ -13,6 +14,7 @@ use signet::{
     },
 };
 
+use std::sync::Arc;
 use std::thread;
 // use rtrb::{RingBuffer, PushError, PopError, PeekError};
 use rtrb::Producer;
@@ -22,30 +24,32 @@ pub struct SDRListener {}
 
 impl SDRListener {
     pub fn begin_sampling(
+        mini_config: RadioConfig,
         mut samples_producer: Producer<SdrPacketLog>,
-    ) -> Result<thread::JoinHandle<()>, String> {
+    ) -> Result<(thread::JoinHandle<()>, Arc<StreamCounters>), String> {
         // Initialize hardware and analyzer
-        let mini_config = RadioConfig::new(1420.405e6, 3.0e6);
         let signal_config = SignalConfig::default();
         let _spectrum_analyzer =
             SpectrumAnalyzer::new(signal_config.down_size, TARGET_PACKET_SIZE);
         let mut sdr = SDR::new(mini_config).map_err(|s| format!("SDR Not Found {s}"))?;
+        let counters = sdr.counters();
 
         // Repeatedly push to spsc with new data
         let signal_read_handle = thread::Builder::new()
             .name("Signal Read".into())
             .stack_size(2 * 1024 * 1024)
             .spawn(move || {
+                // Where packets go when the ring is full. The device keeps getting drained so the
+                // hole in the stream is a whole number of packets that can be accounted for.
+                let mut overflow_packet = Box::<SdrPacketLog>::default();
                 loop {
                     match samples_producer.write_chunk(1) {
                         Ok(mut write_chunk) => {
                             let (slc_1, _slc_2) = write_chunk.as_mut_slices();
                             let sdr_packet = &mut slc_1[0];
 
-                            match sdr.read_and_timestamp(&mut sdr_packet.samples) {
-                                Ok((timestamp, valid_samples)) => {
-                                    sdr_packet.sample_count = valid_samples;
-                                    sdr_packet.timestamp = timestamp;
+                            match sdr.read_packet(sdr_packet) {
+                                Ok(()) => {
                                     write_chunk.commit(1);
                                 }
 
@@ -54,13 +58,14 @@ impl SDRListener {
                                 }
                             }
                         }
-                        Err(_e) => {
-                            eprintln!("Consumer too slow, no write chunk available")
-                        }
+                        Err(_e) => match sdr.read_packet(&mut overflow_packet) {
+                            Ok(()) => sdr.packet_dropped(&overflow_packet.gap),
+                            Err(e) => eprintln!("Error reading signal from SDR: {}", e),
+                        },
                     }
                 }
             })
             .unwrap();
-        Ok(signal_read_handle)
+        Ok((signal_read_handle, counters))
     }
 }
@@ -1,30 +1,29 @@
-use v4l::buffer::Type;
-use v4l::io::traits::CaptureStream;
+use camera::{Camera, V4lCamera};
 
-use v4l::Device;
-
-use v4l::io::mmap::Stream as MmapStream;
-// use v4l::prelude::*;
-use v4l::buffer::Metadata;
-
-use image::ImageBuffer;
-use v4l::video::Capture;
-
-use image::Luma;
 use wayfarer::startrack::quest::quest_real;
 
 use std::sync::mpsc::{Receiver, Sender, channel};
 use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
 use aether::attitude::Quaternion;
 use aether::reference_frame::{Body, ICRF};
-use log::error;
+use log::{error, info, warn};
 use wayfarer::{
     perception::{camera_model::CameraModel, centroiding::Starfinder},
     startrack::solver::Startracker,
-}; // pub use crate::io::mmap::Stream as MmapStream;
+};
 
-use DarkAverager::ImageAveragerFromBuffer;
+use DarkAverager::{
+    ImageAveragerFromBuffer, PixelMap,
+    pixel_map::{DEAD, HOT, NOISY},
+};
+
+// Bad pixel map carried between boots, next to the SDR recordings
+const PIXEL_MAP_PATH: &str = "pixel_map.bin";
+
+// Frames come in at the sensor rate, a second without one means something's wrong
+const GRAB_TIMEOUT: Duration = Duration::from_secs(1);
 
 // use aether::
 pub struct StartrackerThread {
@@ -44,14 +43,13 @@ impl StartrackerThread {
     }
 
     pub fn begin_startracking(self) -> JoinHandle<()> {
-        let dev = Device::new(0).expect("Failed to open device");
-        let fmt = dev.format().expect("Failed to read format");
-
-        let height = fmt.height;
-        let width = fmt.width;
+        let camera = V4lCamera::new(0).expect("Failed to open device");
+        self.begin_startracking_with(camera)
+    }
 
-        let mut stream: MmapStream = MmapStream::with_buffers(&dev, Type::VideoCapture, 4)
-            .expect("Failed to create buffer stream");
+    // Same loop on any camera, so it can run against a synthetic star field without the hardware
+    pub fn begin_startracking_with<C: Camera + Send + 'static>(self, mut camera: C) -> JoinHandle<()> {
+        camera.start().expect("Failed to start streaming");
 
         let starfinder = Starfinder::default();
         let camera_model = CameraModel::default();
@@ -60,39 +58,37 @@ impl StartrackerThread {
         
         thread::spawn(move || {
 
-            let mut darkframe_source: Vec<ImageBuffer<Luma<u8>, Vec<u8>>> = vec![];
+            let mut darkframe_source = vec![];
             for _ in 0..20
             {
-                let (buf, _meta): (&[u8], &Metadata) = stream.next().expect("Failed to get frame");
-                let buffer = buf.to_vec();
-
-                darkframe_source.push(ImageBuffer::from_raw(width, height, buffer).expect("Buffer size mismatch"));
+                let frame = camera.grab(GRAB_TIMEOUT).expect("Failed to get frame");
+                darkframe_source.push(frame.image);
             }
 
-            let avger = ImageAveragerFromBuffer::new_with_source(darkframe_source);
+            let mut avger = ImageAveragerFromBuffer::new_with_source(darkframe_source)
+                .inspect_err(|e| error!("Dark frame failed, running without: {}", e))
+                .ok();
+            if let Some(ref mut averager) = avger {
+                load_pixel_map(averager);
+            }
 
             loop {
-                let (buf, _meta): (&[u8], &Metadata) = stream.next().expect("Failed to get frame");
-
-                // Looks like I cannot take the raw image buffer with v4l and mutate it, so will have to perform one copy.
-                // This causes an issue based on the way I scan for centroids, where I blot out dead pixels as a pass through. Will
-                // Retest and benchmark later to see if I can get away without doing that, and if so, then this isn't as much a worry.
+                let frame = match camera.grab(GRAB_TIMEOUT) {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        error!("Failed to get frame: {}", e);
+                        continue;
+                    }
+                };
 
                 // Also look into using userptr buffers later on (we own)
+                let mut img = frame.image;
 
-                // println!(
-                //     "Buffer size: {}, seq: {}, timestamp: {}",
-                //     buf.len(),      // buf is a &[u8] slice
-                //     meta.sequence,  // meta is v4l::buffer::Metadata
-                //     meta.timestamp  // timestamp is v4l::buffer::Timestamp
-                // );
-
-                let buffer = buf.to_vec();
-
-                let mut img: ImageBuffer<Luma<u8>, Vec<u8>> =
-                    ImageBuffer::from_raw(width, height, buffer).expect("Buffer size mismatch");
-
-                avger.apply_average(&mut img);
+                if let Some(ref averager) = avger
+                    && let Err(e) = averager.apply_average(&mut img)
+                {
+                    error!("Dark frame not applied: {}", e);
+                }
 
                 let mut centroids = starfinder.star_find(&mut img);
                 camera_model.undistort_centroids(&mut centroids);
@@ -115,3 +111,26 @@ impl StartrackerThread {
         })
     }
 }
+
+// Bad pixels from earlier boots are kept, the ones found in this boot's darks get added on
+fn load_pixel_map(averager: &mut ImageAveragerFromBuffer) {
+    match PixelMap::load(PIXEL_MAP_PATH) {
+        Ok(saved) => {
+            if let Err(e) = averager.merge_pixel_map(&saved) {
+                warn!("Saved pixel map not used, starting fresh: {}", e);
+            }
+        }
+        Err(e) => info!("No saved pixel map loaded: {}", e),
+    }
+
+    let map = averager.pixel_map();
+    info!(
+        "Pixel map: {} hot, {} noisy, {} dead",
+        map.count(HOT),
+        map.count(NOISY),
+        map.count(DEAD)
+    );
+    if let Err(e) = map.save(PIXEL_MAP_PATH) {
+        error!("Failed to save pixel map: {}", e);
+    }
+}
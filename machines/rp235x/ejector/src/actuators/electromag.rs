@@ -2,6 +2,7 @@
 
 #![warn(missing_docs, clippy::unwrap_used)]
 
+use bin_packets::sequence::Polarity;
 use embedded_hal::{digital::OutputPin, pwm::SetDutyCycle};
 use rp235x_hal::pwm::{Channel, FreeRunning, Slice, A};
 
@@ -11,6 +12,15 @@ pub enum ElectroMagnetPolarity {
     Repel,
 }
 
+impl From<Polarity> for ElectroMagnetPolarity {
+    fn from(polarity: Polarity) -> Self {
+        match polarity {
+            Polarity::Attract => ElectroMagnetPolarity::Attract,
+            Polarity::Repel => ElectroMagnetPolarity::Repel,
+        }
+    }
+}
+
 /// H-Bridge struct for controlling the electromagnet
 /// This is currently designed for the:
 pub struct HBridge<P1, P2, P3>
@@ -104,6 +114,16 @@ where
         }
     }
 
+    /// Energize the electromagnet with the given polarity
+    pub fn drive(&mut self, polarity: ElectroMagnetPolarity) -> () {
+        match polarity {
+            ElectroMagnetPolarity::Attract => self.h_bridge.bridge_state_10(),
+            ElectroMagnetPolarity::Repel => self.h_bridge.bridge_state_01(),
+        }
+        self.polarity = polarity;
+        self.enable();
+    }
+
     /// Enable the electromagnet
     pub fn enable(&mut self) -> () {
         self.h_bridge.sleep_pin.set_high().unwrap();
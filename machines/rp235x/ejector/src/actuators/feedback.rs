@@ -0,0 +1,100 @@
+//! Feedback from the Ejector's actuators: the servo potentiometers and magnet current sense on
+//! the ADC. The deployment limit switch is read as a plain input.
+//!
+//! Only built into boards with the `actuator-feedback` feature. The pins and calibrations here
+//! haven't been checked against a board yet.
+
+#![warn(missing_docs)]
+// Without the feature nothing builds the feedback, but the sequencer still carries the type
+#![cfg_attr(not(feature = "actuator-feedback"), allow(dead_code))]
+
+use embedded_hal_0_2::adc::OneShot;
+use rp235x_hal::{
+    adc::AdcPin,
+    gpio::{FunctionNull, Pin, PullDown},
+    Adc,
+};
+use sequencer::{CurrentLimits, DeploymentSwitch, MotionLimits, PotCalibration};
+
+use crate::device_constants::pins::{
+    DeploymentSwitchPin, EjectorPotPin, MagnetSensePin, PowerPotPin,
+};
+
+// Nominal until the servos are swept on the bench
+/// Ejector servo pot, 0-270 degrees across most of the ADC range
+pub const EJECTOR_POT: PotCalibration = PotCalibration {
+    raw_low: 200,
+    deg_low: 0,
+    raw_high: 3900,
+    deg_high: 270,
+};
+
+/// Power servo pot, same part as the ejector servo
+pub const POWER_POT: PotCalibration = EJECTOR_POT;
+
+/// The sweep notches are 50 ms apart, so a stall is only called after several of them
+pub const SERVO_MOTION: MotionLimits = MotionLimits {
+    tolerance_deg: 6,
+    min_step_deg: 3,
+    start_ms: 300,
+    stall_ms: 300,
+};
+
+/// Shunt and amplifier on the bridge's low side, roughly 1 mA per count
+pub const MAGNET_CURRENT: CurrentLimits = CurrentLimits {
+    ua_per_count: 1000,
+    min_on_ma: 300,
+    max_on_ma: 2000,
+    max_off_ma: 50,
+    settle_ms: 20,
+};
+
+type AnalogPin<P> = AdcPin<Pin<P, FunctionNull, PullDown>>;
+
+/// The ADC inputs behind the actuators
+pub struct FeedbackAdc {
+    adc: Adc,
+    ejector_pot: AnalogPin<EjectorPotPin>,
+    power_pot: AnalogPin<PowerPotPin>,
+    magnet_sense: AnalogPin<MagnetSensePin>,
+}
+
+impl FeedbackAdc {
+    pub fn new(
+        adc: Adc,
+        ejector_pot: AnalogPin<EjectorPotPin>,
+        power_pot: AnalogPin<PowerPotPin>,
+        magnet_sense: AnalogPin<MagnetSensePin>,
+    ) -> Self {
+        FeedbackAdc {
+            adc,
+            ejector_pot,
+            power_pot,
+            magnet_sense,
+        }
+    }
+
+    /// Ejector servo pot, in counts
+    pub fn ejector_pot(&mut self) -> Option<u16> {
+        // One shot blocks for the conversion, a couple of us
+        self.adc.read(&mut self.ejector_pot).ok()
+    }
+
+    /// Power servo pot, in counts
+    pub fn power_pot(&mut self) -> Option<u16> {
+        self.adc.read(&mut self.power_pot).ok()
+    }
+
+    /// Magnet current sense, in counts
+    pub fn magnet_sense(&mut self) -> Option<u16> {
+        self.adc.read(&mut self.magnet_sense).ok()
+    }
+}
+
+/// Everything the ejector sequence checks its moves against
+pub struct ActuatorFeedback {
+    /// Pots and current sense
+    pub adc: FeedbackAdc,
+    /// Closed while the deployable is in
+    pub deployment_switch: DeploymentSwitch<DeploymentSwitchPin>,
+}
@@ -3,4 +3,5 @@
 #![warn(missing_docs)]
 
 pub mod electromag;
+pub mod feedback;
 pub mod servo;
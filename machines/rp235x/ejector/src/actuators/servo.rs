@@ -46,8 +46,8 @@ const MIN_DUTY: u16 = (TOP as f64 * (2.5 / 100.)) as u16;
 // 2.4ms is 12% of 20ms; 180 degree in servo
 const MAX_DUTY: u16 = (TOP as f64 * (12.5 / 100.)) as u16;
 
-pub static EJECTION_ANGLE: u16 = 240;
-pub static HOLDING_ANGLE: u16 = 150;
+pub const EJECTION_ANGLE: u16 = 240;
+pub const HOLDING_ANGLE: u16 = 150;
 // pub static LOCKING_SERVO_LOCKED: u16 = 105;
 // pub static LOCKING_SERVO_UNLOCKED: u16 = 20;
 
@@ -103,8 +103,8 @@ pub type PowerServoType = Servo<
     PowerServoMosfet,
 >;
 
-pub static POWER_ANGLE: u16 = 40;
-pub static POWER_HOLDING_ANGLE: u16 = 130;
+pub const POWER_ANGLE: u16 = 40;
+pub const POWER_HOLDING_ANGLE: u16 = 130;
 
 pub struct PowerServo {
     pub servo: PowerServoType
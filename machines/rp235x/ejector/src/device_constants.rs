@@ -43,6 +43,18 @@ pub mod pins {
     /// Ejection detection pin
     pub type EjectionPin = Gpio38;
 
+    // Feedback pins, only taken with the `actuator-feedback` feature. Not confirmed on a board yet.
+
+    /// Limit switch the deployable holds closed
+    pub type DeploymentSwitchPin = Pin<Gpio39, FunctionSio<SioInput>, PullUp>;
+
+    /// Ejector servo potentiometer, ADC3
+    pub type EjectorPotPin = Gpio43;
+    /// Power servo potentiometer, ADC4
+    pub type PowerPotPin = Gpio44;
+    /// Electromagnet current sense, ADC5
+    pub type MagnetSensePin = Gpio45;
+
     /// UART RX
     pub type JupiterRxPin = Pin<Gpio1, FunctionUart, PullDown>;
     /// UART TX
@@ -15,6 +15,9 @@ pub mod sd_card;
 // Guard module
 pub mod guard;
 
+// Actuation sequences
+pub mod sequences;
+
 // RTIC Tasks
 pub mod startup;
 pub mod tasks;
@@ -59,12 +62,15 @@ mod app {
 
     use crate::actuators::electromag::ElectroMagnet;
     use crate::actuators::servo::{EjectorServo, PowerServo};
+    use crate::actuators::feedback::ActuatorFeedback;
     use crate::device_constants::pins::{CamMosfetPin, RBFPin};
     use crate::device_constants::{
         EjectionDetectionPin, JupiterRX, JupiterTX, JupiterUart, OnboardLED, RGBLed, RGBStatus,
         ThermoI2cBus, SAMPLE_COUNT, SensorI2cManager, RGBDriver
     };
     use crate::sd_card::EjectorSdCard;
+    use bin_packets::data::EjectorStatus;
+    use sequencer::{Sequencer, Upload};
 
     use super::*;
     use bin_packets::packets::ApplicationPacket;
@@ -143,7 +149,10 @@ mod app {
         // pub sd_card: EjectorSD,
         pub ejection_enabled: bool,
         pub status_config: RGBStatus,
-        pub temp_store: Deque<ApplicationPacket, 100>
+        pub temp_store: Deque<ApplicationPacket, 100>,
+        pub ejector_sequence: Sequencer,
+        pub camera_sequence: Sequencer,
+        pub ejector_status: EjectorStatus,
     }
 
     #[local]
@@ -166,6 +175,7 @@ mod app {
         pub rgb_driver: RGBDriver,
         pub ejection_trigger_tx: SignalWriter<'static, ()>,
         pub ejection_trigger_rx: SignalReader<'static, ()>,
+        pub feedback: Option<ActuatorFeedback>,
     }
 
     #[init(local = [adc: Option<hal::Adc> = None])]
@@ -176,16 +186,16 @@ mod app {
     extern "Rust" {
         // Sequences the ejection
         // ejection pin
-        #[task(shared = [ejection_enabled], local = [ power_servo, ejector_servo, ejecctor_magnet, ejection_trigger_rx , ejection_pin],  priority = 1)]
+        #[task(shared = [ejection_enabled, ejector_sequence, ejector_status], local = [ power_servo, ejector_servo, ejecctor_magnet, ejection_trigger_rx , ejection_pin, feedback],  priority = 1)]
         async fn ejector_sequencer(mut ctx: ejector_sequencer::Context);
 
         // Sequences cameras activation
-        #[task(local = [camera_mosfet], priority = 1)]
+        #[task(shared = [camera_sequence], local = [camera_mosfet], priority = 1)]
         async fn camera_sequencer(mut ctx: camera_sequencer::Context);
 
         // Heartbeats the main led (and sends packets after arming)
         //  local = [onboard_led],
-        #[task(shared = [downlink_packets],  priority = 2)]
+        #[task(shared = [downlink_packets, ejector_status],  priority = 2)]
         async fn heartbeat(mut ctx: heartbeat::Context);
 
         #[task( shared = [temp_store, downlink_packets], local = [sensor_manager], priority = 1)]
@@ -201,7 +211,8 @@ mod app {
         // async fn write_sd_card(mut ctx: write_sd_card::Context);
         // Commands
         // Status for status LED
-        #[task(shared = [status_config], local = [status_link, ejection_trigger_tx], priority = 2)]
+        // Sequence uploads
+        #[task(shared = [status_config, downlink_packets, ejector_sequence, camera_sequence, ejector_status], local = [status_link, ejection_trigger_tx, upload: Upload = Upload::new()], priority = 2)]
         async fn rx_from_jupiter(mut ctx: rx_from_jupiter::Context);
 
         #[task(shared = [status_config], local = [rgb_driver], priority = 1)]
@@ -0,0 +1,72 @@
+//! The Ejector's compiled-in sequences, and the limits any replacement uploaded from JUPITER is
+//! checked against
+
+use bin_packets::sequence::{Actuator, Guard, Polarity, Step, Target};
+use sequencer::{AngleRange, Limits};
+
+use crate::actuators::servo::{EJECTION_ANGLE, HOLDING_ANGLE, POWER_ANGLE, POWER_HOLDING_ANGLE};
+use crate::tasks::JUPITER_BOOT_LOCKOUT_TIME_SECONDS;
+
+/// What the ejector sequence may drive
+pub const EJECTOR_LIMITS: Limits = Limits {
+    actuators: &[
+        Actuator::EjectorServo,
+        Actuator::PowerServo,
+        Actuator::Magnet,
+    ],
+    ejector_servo: AngleRange::new(HOLDING_ANGLE - 10, EJECTION_ANGLE),
+    power_servo: AngleRange::new(POWER_ANGLE - 10, POWER_HOLDING_ANGLE + 10),
+    max_hold_ms: 60_000,
+    max_magnet_ms: 10_000,
+    holding_angle: Some(HOLDING_ANGLE),
+};
+
+/// What the camera sequence may drive
+pub const CAMERA_LIMITS: Limits = Limits {
+    actuators: &[Actuator::Cameras],
+    ejector_servo: EJECTOR_LIMITS.ejector_servo,
+    power_servo: EJECTOR_LIMITS.power_servo,
+    max_hold_ms: 600_000,
+    max_magnet_ms: 0,
+    holding_angle: None,
+};
+
+// One notch of the release sweep
+const fn sweep(angle: u16) -> Step {
+    Step::new(Actuator::EjectorServo, Target::Angle(angle), 50)
+}
+
+/// Latch, wait for the ejection signal, power the deployable, then release and push it out
+pub const EJECTOR: [Step; 15] = [
+    // Latch closed once the RBF pin is out
+    Step::new(Actuator::EjectorServo, Target::Angle(HOLDING_ANGLE), 0).when(Guard::RbfRemoved),
+    // Lockout to let JUPITER boot up
+    Step::new(
+        Actuator::PowerServo,
+        Target::Angle(POWER_HOLDING_ANGLE),
+        JUPITER_BOOT_LOCKOUT_TIME_SECONDS as u32 * 1000,
+    ),
+    Step::new(Actuator::PowerServo, Target::Angle(POWER_ANGLE), 2000).when(Guard::EjectSignal),
+    // For current servo a graduated angle change has worked for fast ejection, but not just
+    // setting to the final angle
+    sweep(140),
+    sweep(150),
+    sweep(160),
+    sweep(170),
+    sweep(180),
+    sweep(190),
+    sweep(200),
+    sweep(210),
+    sweep(220),
+    // Give seven seconds to retract, then disable to save power
+    Step::new(Actuator::Magnet, Target::Magnet(Polarity::Repel), 7000),
+    Step::new(Actuator::Magnet, Target::Off, 0),
+    // Held long enough for the feedback to judge the last moves
+    Step::new(Actuator::EjectorServo, Target::Angle(HOLDING_ANGLE), 500),
+];
+
+/// Power the cameras at T+70 for three and a half minutes
+pub const CAMERAS: [Step; 2] = [
+    Step::new(Actuator::Cameras, Target::On, 210_000).when(Guard::AfterBoot(250_000)),
+    Step::new(Actuator::Cameras, Target::Off, 0),
+];
@@ -33,6 +33,8 @@ use smart_leds::{SmartLedsWrite, RGB8};
 // use rp235x_hal::timer::monotonic::Monotonic;
 
 use crate::actuators::electromag::{ElectroMagnet, ElectroMagnetPolarity, HBridge};
+#[cfg(feature = "actuator-feedback")]
+use crate::actuators::feedback::{ActuatorFeedback, FeedbackAdc};
 use crate::actuators::servo::{EjectionServoMosfet, EjectorServo, PowerServo, Servo};
 use crate::device_constants::pins::{CamMosfetPin, RBFPin};
 use crate::device_constants::{
@@ -41,6 +43,12 @@ use crate::device_constants::{
 };
 use crate::{app::*, Mono};
 use crate::{hal, sd_card};
+use crate::sequences;
+use bin_packets::data::EjectorStatus;
+use bin_packets::phases::EjectorPhase;
+#[cfg(feature = "actuator-feedback")]
+use sequencer::DeploymentSwitch;
+use sequencer::Sequencer;
 
 // Timestamp for logging
 defmt::timestamp!("{=u64:us}", {
@@ -243,6 +251,20 @@ pub fn startup(mut ctx: init::Context<'_>) -> (Shared, Local) {
     // Functionality currently not enabled
     let gpio_detect: EjectionDetectionPin = bank0_pins.gpio38.into_pull_down_input();
 
+    // Actuator feedback, on boards that have it. Without it the moves are made unchecked.
+    #[cfg(feature = "actuator-feedback")]
+    let feedback = Some(ActuatorFeedback {
+        adc: FeedbackAdc::new(
+            hal::Adc::new(ctx.device.ADC, &mut ctx.device.RESETS),
+            AdcPin::new(bank0_pins.gpio43).unwrap(),
+            AdcPin::new(bank0_pins.gpio44).unwrap(),
+            AdcPin::new(bank0_pins.gpio45).unwrap(),
+        ),
+        deployment_switch: DeploymentSwitch::new(bank0_pins.gpio39.into_pull_up_input(), true),
+    });
+    #[cfg(not(feature = "actuator-feedback"))]
+    let feedback = None;
+
 
     let mut rgb_wake = bank0_pins.gpio25.into_push_pull_output();
 
@@ -308,6 +330,12 @@ pub fn startup(mut ctx: init::Context<'_>) -> (Shared, Local) {
     //     12.MHz(),
     // );
 
+    // The compiled-in tables, until JUPITER uploads replacements
+    let ejector_sequence = Sequencer::new(&sequences::EJECTOR, sequences::EJECTOR_LIMITS)
+        .expect("Compiled-in ejector sequence is out of bounds");
+    let camera_sequence = Sequencer::new(&sequences::CAMERAS, sequences::CAMERA_LIMITS)
+        .expect("Compiled-in camera sequence is out of bounds");
+
     info!("Peripherals initialized, spawning tasks");
 
     let status_config = RGBStatus::default();
@@ -334,6 +362,9 @@ pub fn startup(mut ctx: init::Context<'_>) -> (Shared, Local) {
             // sd_card: sd_card,
             status_config,
             temp_store: Deque::new(),
+            ejector_sequence,
+            camera_sequence,
+            ejector_status: EjectorStatus::new(EjectorPhase::Standby),
         },
         Local {
             camera_mosfet: cam_pin,
@@ -351,6 +382,7 @@ pub fn startup(mut ctx: init::Context<'_>) -> (Shared, Local) {
             rgb_driver,
             ejection_trigger_tx,
             ejection_trigger_rx,
+            feedback,
 
         },
     )
@@ -2,13 +2,24 @@
 
 //! RTIC Task defintions for the Ejector
 
+use crate::actuators::feedback::{
+    ActuatorFeedback, EJECTOR_POT, MAGNET_CURRENT, POWER_POT, SERVO_MOTION,
+};
+use crate::actuators::servo::{EjectorServo, PowerServo, HOLDING_ANGLE};
+use crate::device_constants::pins::CamMosfetPin;
 use crate::sd_card::EJECTOR_GAURD_FILENAME;
 use crate::{app::*, device_constants::SAMPLE_COUNT, sd_card, Mono};
 use bin_packets::{
-    commands::CommandPacket,
+    commands::{CommandPacket, CommandReply, NackReason},
+    data::{ActuationResult, EjectorStatus},
     devices::DeviceIdentifier,
     packets::{status::Status, ApplicationPacket},
     rgbstatus::RGBOptions,
+    sequence::{Actuator, Polarity, SequenceId, Target},
+};
+use common_states::{
+    rbf::RbfState,
+    rbf_monitor::{RbfConfig, RbfLine, RbfMonitor},
 };
 use bincode::{config::standard, decode_from_slice, encode_into_slice, error::DecodeError};
 use defmt::{debug, error, info, warn};
@@ -19,6 +30,9 @@ use heapless::{deque::DequeInner, vec::ViewVecStorage, Deque, Vec};
 use rp235x_pac::hstx_fifo::stat;
 use rtic::Mutex;
 use rtic_monotonics::Monotonic;
+use sequencer::{
+    Actuators, MagnetMonitor, Progress, ReplaceError, Sequencer, ServoMonitor, Signals,
+};
 use tinyframe::frame::Frame;
 
 use bin_packets::phases::EjectorPhase;
@@ -26,7 +40,7 @@ use rtic_sync::signal::Signal;
 
 use ws2812_pio::Ws2812Direct;
 use smart_leds::{SmartLedsWrite, RGB8};
-use rtic_sync::portable_atomic::{AtomicBool, AtomicU8, Ordering};
+use rtic_sync::portable_atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
 
 use crate::device_constants::{
     MagnetState, ServoState, RGBDriver, COLOR_DIM_BLUE, COLOR_DIM_GREEN, 
@@ -38,6 +52,11 @@ static LOCAL_RBF_IN: AtomicBool = AtomicBool::new(true);
 static LOCAL_MAGNET_STATE: AtomicU8 = AtomicU8::new(0);
 static LOCAL_SERVO_STATE: AtomicU8 = AtomicU8::new(0); 
 static LOCAL_RX_ALIVE: AtomicBool = AtomicBool::new(false);
+// Uptime JUPITER was last heard from, in ms
+static LAST_RX_MS: AtomicU32 = AtomicU32::new(0);
+// Set when a packet is dropped for want of queue space, cleared by each status packet
+static QUEUE_OVERFLOW: AtomicBool = AtomicBool::new(false);
+static SENSOR_FAULT: AtomicBool = AtomicBool::new(false);
 
 static STATUS_UPDATE: AtomicBool = AtomicBool::new(false);
 
@@ -50,18 +69,57 @@ static WES_MODE: AtomicBool = AtomicBool::new(true);
 
 
 #[cfg(not(feature = "fast-startup"))]
-const JUPITER_BOOT_LOCKOUT_TIME_SECONDS: u64 = 5;
+pub(crate) const JUPITER_BOOT_LOCKOUT_TIME_SECONDS: u64 = 5;
 /// Constant to prevent ejector from interfering with JUPITER's u-boot sequence
 #[cfg(feature = "fast-startup")]
-const JUPITER_BOOT_LOCKOUT_TIME_SECONDS: u64 = 10;
+pub(crate) const JUPITER_BOOT_LOCKOUT_TIME_SECONDS: u64 = 10;
+
+/// JUPITER is counted as gone after this long without a packet. It sends the status colors every
+/// second.
+const LINK_TIMEOUT_MS: u64 = 5000;
+
+/// Downlink queue, as shared between tasks
+type DownlinkQueue = Deque<ApplicationPacket, 128>;
+
+// Queue a packet for JUPITER, noting it for the status if there's no room
+fn enqueue(q: &mut DownlinkQueue, packet: ApplicationPacket) {
+    if q.push_back(packet).is_err() {
+        QUEUE_OVERFLOW.store(true, Ordering::Relaxed);
+    }
+}
 
-const SHUTDOWN_TIME_CAMERAS: u64 = 210;
+// Fault bits for the status packet, from everything but the queue
+fn active_faults(status: &EjectorStatus, now_ms: u64) -> u16 {
+    let mut faults = 0;
+    if status.actuations().any(|result| !result.ok()) {
+        faults |= EjectorStatus::FAULT_ACTUATION;
+    }
+    // Nothing is expected until the receive lockout is over
+    let last_rx = (LAST_RX_MS.load(Ordering::Relaxed) as u64).max(RX_LOCKOUT_MS);
+    if now_ms.saturating_sub(last_rx) > LINK_TIMEOUT_MS {
+        faults |= EjectorStatus::FAULT_LINK;
+    }
+    if SENSOR_FAULT.load(Ordering::Relaxed) {
+        faults |= EjectorStatus::FAULT_SENSORS;
+    }
+    faults
+}
+
+// Phase reported back in command replies
+fn current_phase() -> EjectorPhase {
+    if EJECT.load(Ordering::Relaxed) {
+        EjectorPhase::Ejection
+    } else {
+        EjectorPhase::Standby
+    }
+}
 
 /// Task for sending heartbeat packets to JUPITER and toggling the onboard LED
 pub async fn heartbeat(mut ctx: heartbeat::Context<'_>) {
     // let onboard_led = ctx.local.onboard_led;
 
     let mut sequence_number = 0;
+    let mut phase_since = 0;
 
     // Still blink, but toggle as it is done
     loop {
@@ -71,9 +129,36 @@ pub async fn heartbeat(mut ctx: heartbeat::Context<'_>) {
         if Mono::now().duration_since_epoch().to_secs() > JUPITER_BOOT_LOCKOUT_TIME_SECONDS {
             let status = Status::new(DeviceIdentifier::Ejector, now_timestamp(), sequence_number);
 
-            ctx.shared
-                .downlink_packets
-                .lock(|q| q.push_back(status.into()).ok());
+            let now_ms = Mono::now().duration_since_epoch().to_millis();
+            let queue_depth = ctx.shared.downlink_packets.lock(|q| q.len());
+            let ejector_status = ctx.shared.ejector_status.lock(|ejector_status| {
+                let phase = current_phase();
+                if ejector_status.phase != phase {
+                    ejector_status.phase = phase;
+                    phase_since = now_ms;
+                }
+                let mut faults = active_faults(ejector_status, now_ms);
+                if QUEUE_OVERFLOW.swap(false, Ordering::Relaxed) {
+                    faults |= EjectorStatus::FAULT_QUEUE_FULL;
+                }
+
+                let health = &mut ejector_status.health;
+                health.uptime_ms = now_ms;
+                health.time_in_phase_ms = now_ms - phase_since;
+                health.packet_number = sequence_number;
+                health.rbf_inserted = Some(LOCAL_RBF_IN.load(Ordering::Relaxed));
+                health.faults = faults;
+                health.record_queue(queue_depth);
+                *ejector_status
+            });
+            if ejector_status.health.faults != 0 {
+                warn!("Ejector faults: {:#06x}", ejector_status.health.faults);
+            }
+
+            ctx.shared.downlink_packets.lock(|q| {
+                enqueue(q, status.into());
+                enqueue(q, ApplicationPacket::EjectorStatus(ejector_status));
+            });
 
             sequence_number = sequence_number.wrapping_add(1);
         }
@@ -108,91 +193,257 @@ pub async fn downlink_jupiter(mut ctx: downlink_jupiter::Context<'_>) {
 
 const SCRATCH: usize = 512;
 
+/// Nothing is read from JUPITER until it's had time to boot, in ms
+const RX_LOCKOUT_MS: u64 = 40_000;
+
 /// Task for camera sequencing
 pub async fn camera_sequencer(mut ctx: camera_sequencer::Context<'_>) {
-    // T+70, drive the cameras high
-    Mono::delay(250.secs()).await;
-    info!("Activating cameras!");
-    ctx.local.camera_mosfet.set_high().ok();
-    Mono::delay(SHUTDOWN_TIME_CAMERAS.secs()).await;
-    info!("Shutting down cameras!");
-    ctx.local.camera_mosfet.set_low().ok();
+    let mut cameras = CameraRig {
+        mosfet: ctx.local.camera_mosfet,
+    };
+    run_sequence(&mut ctx.shared.camera_sequence, &mut cameras, || {
+        signals(!LOCAL_RBF_IN.load(Ordering::Relaxed))
+    })
+    .await;
+    info!("Camera sequencing complete");
 }
 
 /// Task that manages the Ejector sequencing
 ///
-/// NOTE: When the RBF pin is inserted, this task will idle and block ejection until the pin is removed.
+/// NOTE: When the RBF pin is inserted, this task will idle and block ejection until the pin is
+/// removed. The sequence's first step waits on it too, and its limits won't take a table that
+/// doesn't.
 pub async fn ejector_sequencer(mut ctx: ejector_sequencer::Context<'_>) {
     while !ctx.shared.ejection_enabled.lock(|enabled| *enabled) {
         debug!("Ejector sequencer idling while RBF pin is inserted");
         Mono::delay(100_u64.millis()).await;
     }
 
-    let servo = ctx.local.ejector_servo;
-    let power_servo = ctx.local.power_servo;
-    let e_magnet = ctx.local.ejecctor_magnet;
+    let mut rig = EjectorRig {
+        servo: ctx.local.ejector_servo,
+        power_servo: ctx.local.power_servo,
+        magnet: ctx.local.ejecctor_magnet,
+        feedback: ctx.local.feedback.as_mut(),
+        servo_monitor: ServoMonitor::new(EJECTOR_POT, SERVO_MOTION),
+        power_monitor: ServoMonitor::new(POWER_POT, SERVO_MOTION),
+        magnet_monitor: MagnetMonitor::new(MAGNET_CURRENT),
+        status: &mut ctx.shared.ejector_status,
+    };
+    let ejection_enabled = &mut ctx.shared.ejection_enabled;
+    let ejection_pin = ctx.local.ejection_pin;
 
-    // Latch ejector servos closed
-    servo.enable();
-    servo.hold();
-    power_servo.enable();
-    power_servo.hold();
+    run_sequence(&mut ctx.shared.ejector_sequence, &mut rig, || {
+        if ejection_pin.is_high().unwrap_or(false) {
+            // Either way in, command replies report it from here
+            EJECT.store(true, Ordering::Relaxed);
+        }
+        signals(ejection_enabled.lock(|enabled| *enabled))
+    })
+    .await;
 
-    LOCAL_SERVO_STATE.store(ServoState::PowerOn as u8, Ordering::Relaxed);
-    LOCAL_MAGNET_STATE.store(MagnetState::Holding as u8, Ordering::Relaxed);
+    LOCAL_SERVO_STATE.store(ServoState::Off as u8, Ordering::Relaxed);
+    info!("Ejector disabled, servo and magnet disabled. Ejector sequencing complete.");
+}
 
-    let ejection_pin = ctx.local.ejection_pin;
+// What the guards of a sequence see right now
+fn signals(rbf_removed: bool) -> Signals {
+    Signals {
+        uptime_ms: Mono::now().duration_since_epoch().to_millis(),
+        rbf_removed,
+        eject: EJECT.load(Ordering::Relaxed),
+    }
+}
 
-    // Lockout for one minute to let JUPITER boot up
-    warn!("Idling sequencer");
-    Mono::delay(JUPITER_BOOT_LOCKOUT_TIME_SECONDS.secs()).await;
-    // ctx.local.arming_led.set_low().ok();
-    info!("Sequencer unlocked, waiting for ejection signal");
+// How often actuator feedback is checked through a hold
+const MONITOR_PERIOD_MS: u64 = 20;
 
-    // ctx.local.ejection_trigger_rx.wait().await;
-    while !(EJECT.load(Ordering::Relaxed) || ejection_pin.is_high().unwrap_or(false)) {
-        debug!("Ejector idling while waiting for ejection signal");
-        Mono::delay(100_u64.millis()).await;
+// Sequence hardware with feedback to keep an eye on between steps
+trait Monitored: Actuators {
+    fn monitor(&mut self, _uptime_ms: u64) {}
+}
+
+// Polls a sequence until it's done, checking feedback through holds. Uploads can swap the table
+// out until the first step has run.
+async fn run_sequence(
+    sequence: &mut impl Mutex<T = Sequencer>,
+    actuators: &mut impl Monitored,
+    mut signals: impl FnMut() -> Signals,
+) {
+    loop {
+        let now = signals();
+        actuators.monitor(now.uptime_ms);
+        match sequence.lock(|sequence| sequence.poll(now, actuators)) {
+            Progress::Done => return,
+            Progress::Holding { until_ms, .. } => {
+                let remaining = until_ms.saturating_sub(now.uptime_ms);
+                Mono::delay(remaining.min(MONITOR_PERIOD_MS).millis()).await;
+            }
+            Progress::Waiting { step, guard } => {
+                debug!("Step {} waiting on {}", step, guard);
+                Mono::delay(100_u64.millis()).await;
+            }
+        }
     }
+}
 
-    
-    power_servo.power();
+// The servos and magnet, as the ejector sequence drives them, with their feedback on boards that
+// have it
+struct EjectorRig<'a, S> {
+    servo: &'a mut EjectorServo,
+    power_servo: &'a mut PowerServo,
+    magnet: &'a mut EjectorMagnet,
+    feedback: Option<&'a mut ActuatorFeedback>,
+    servo_monitor: ServoMonitor,
+    power_monitor: ServoMonitor,
+    magnet_monitor: MagnetMonitor,
+    status: S,
+}
 
-    Mono::delay(2000_u64.millis()).await;
+// Logs a verdict and records it for the next status packet
+fn report(slot: &mut Option<ActuationResult>, actuator: Actuator, result: ActuationResult) {
+    if result.ok() {
+        info!("{} reached {} in {} ms", actuator, result.target, result.elapsed_ms);
+    } else {
+        warn!(
+            "{} {} short of {}: read {}",
+            actuator, result.outcome, result.target, result.reading
+        );
+    }
+    *slot = Some(result);
+}
 
-    info!("Ejecting!");
-    LOCAL_SERVO_STATE.store(ServoState::Release as u8, Ordering::Relaxed);
-    // For current servo a graduated angle change has worked for fast ejection, but not just setting to the final angle
-    for i in (14..23) {
-        info!("i {}!", i);
+impl<S: Mutex<T = EjectorStatus>> Actuators for EjectorRig<'_, S> {
+    fn apply(&mut self, actuator: Actuator, target: Target) {
+        info!("{} -> {}", actuator, target);
+        let now = Mono::now().duration_since_epoch().to_millis();
+        match (actuator, target) {
+            (Actuator::EjectorServo, Target::Angle(angle)) => {
+                self.servo.servo.set_angle(angle);
+                self.servo.enable();
+                if let Some(raw) = self.feedback.as_mut().and_then(|f| f.adc.ejector_pot()) {
+                    self.servo_monitor.command(angle, now, raw);
+                }
+                let state = if angle == HOLDING_ANGLE {
+                    ServoState::PowerOn
+                } else {
+                    ServoState::Release
+                };
+                LOCAL_SERVO_STATE.store(state as u8, Ordering::Relaxed);
+            }
+            (Actuator::EjectorServo, Target::On) => self.servo.enable(),
+            (Actuator::EjectorServo, Target::Off) => {
+                self.servo.disable();
+                LOCAL_SERVO_STATE.store(ServoState::Off as u8, Ordering::Relaxed);
+            }
+            (Actuator::PowerServo, Target::Angle(angle)) => {
+                self.power_servo.servo.set_angle(angle);
+                self.power_servo.enable();
+                if let Some(raw) = self.feedback.as_mut().and_then(|f| f.adc.power_pot()) {
+                    self.power_monitor.command(angle, now, raw);
+                }
+            }
+            (Actuator::PowerServo, Target::On) => self.power_servo.enable(),
+            (Actuator::PowerServo, Target::Off) => self.power_servo.disable(),
+            (Actuator::Magnet, Target::Magnet(polarity)) => {
+                self.magnet.drive(polarity.into());
+                if self.feedback.is_some() {
+                    self.magnet_monitor.command(target, now);
+                }
+                let state = match polarity {
+                    Polarity::Attract => MagnetState::Holding,
+                    Polarity::Repel => MagnetState::Ejecting,
+                };
+                LOCAL_MAGNET_STATE.store(state as u8, Ordering::Relaxed);
+            }
+            (Actuator::Magnet, Target::Off) => {
+                self.magnet.disable();
+                if self.feedback.is_some() {
+                    self.magnet_monitor.command(target, now);
+                }
+                LOCAL_MAGNET_STATE.store(MagnetState::Off as u8, Ordering::Relaxed);
+            }
+            // EJECTOR_LIMITS keeps anything else out of the table
+            _ => {}
+        }
+    }
+}
 
-        let angle = (i * 10) as u16;
-        info!("Set {}!", angle);
+impl<S: Mutex<T = EjectorStatus>> Monitored for EjectorRig<'_, S> {
+    fn monitor(&mut self, uptime_ms: u64) {
+        // Nothing to judge the moves by, so there's nothing to report either
+        let Some(feedback) = self.feedback.as_mut() else {
+            return;
+        };
+
+        let servo = if self.servo_monitor.busy() {
+            match feedback.adc.ejector_pot() {
+                Some(raw) => self.servo_monitor.update(uptime_ms, raw),
+                None => self.servo_monitor.fault(uptime_ms),
+            }
+        } else {
+            None
+        };
+        let power = if self.power_monitor.busy() {
+            match feedback.adc.power_pot() {
+                Some(raw) => self.power_monitor.update(uptime_ms, raw),
+                None => self.power_monitor.fault(uptime_ms),
+            }
+        } else {
+            None
+        };
+        let magnet = if self.magnet_monitor.busy() {
+            match feedback.adc.magnet_sense() {
+                Some(raw) => self.magnet_monitor.update(uptime_ms, raw),
+                None => self.magnet_monitor.fault(uptime_ms),
+            }
+        } else {
+            None
+        };
+        let deployed = feedback.deployment_switch.deployed().ok();
 
-        servo.servo.set_angle(angle);
-        info!("Set {}!", angle);
-        Mono::delay(50_u64.millis()).await;
+        self.status.lock(|status| {
+            if let Some(result) = servo {
+                report(&mut status.ejector_servo, Actuator::EjectorServo, result);
+            }
+            if let Some(result) = power {
+                report(&mut status.power_servo, Actuator::PowerServo, result);
+            }
+            if let Some(result) = magnet {
+                report(&mut status.magnet, Actuator::Magnet, result);
+            }
+            if deployed == Some(true) && status.deployed != Some(true) {
+                info!("Deployable is clear of the ejector");
+            }
+            status.deployed = deployed;
+        });
     }
+}
 
-    e_magnet.enable();
-    e_magnet.polarity_switch();
-    LOCAL_MAGNET_STATE.store(MagnetState::Ejecting as u8, Ordering::Relaxed);
+// The camera supply, as the camera sequence drives it
+struct CameraRig<'a> {
+    mosfet: &'a mut CamMosfetPin,
+}
 
-    // Right now we don't have a pin read from jupiter, although this may be re-added later
-    // Wait until ejection pin from JUPITER reads high
-    
-    // Give seven seconds to retract, then disable to save power
-    Mono::delay(7000_u64.millis()).await;
-    e_magnet.polarity_switch();
-    // servo.disable();
-    e_magnet.disable();
-    servo.hold();
-
-    LOCAL_MAGNET_STATE.store(MagnetState::Off as u8, Ordering::Relaxed);
-    LOCAL_SERVO_STATE.store(ServoState::Off as u8, Ordering::Relaxed);
-    info!("Ejector disabled, servo and magnet disabled. Ejector sequencing complete.");
+impl Actuators for CameraRig<'_> {
+    fn apply(&mut self, actuator: Actuator, target: Target) {
+        match (actuator, target) {
+            (Actuator::Cameras, Target::On) => {
+                info!("Activating cameras!");
+                self.mosfet.set_high().ok();
+            }
+            (Actuator::Cameras, Target::Off) => {
+                info!("Shutting down cameras!");
+                self.mosfet.set_low().ok();
+            }
+            // CAMERA_LIMITS keeps anything else out of the table
+            _ => {}
+        }
+    }
 }
 
+// Nothing to watch on the camera supply
+impl Monitored for CameraRig<'_> {}
+
 /// Task to measure the temperature for the thermal dissipation layer experiment
 ///
 /// Timing: Every second
@@ -211,11 +462,15 @@ pub async fn poll_temperature(mut ctx: poll_temperature::Context<'_>) {
                 // .downlink_packets
                 // .lock(|q| q.push_back(packet).ok());
                 info!("BME Packet retrieved, {:?}",packet );
+                SENSOR_FAULT.store(false, Ordering::Relaxed);
                 ctx.shared
                 .downlink_packets
-                .lock(|q| q.push_back(packet).ok());
+                .lock(|q| enqueue(q, packet));
+            }
+            None => {
+                error!("Failed to poll bme280");
+                SENSOR_FAULT.store(true, Ordering::Relaxed);
             }
-            None => error!("Failed to poll bme280")
         }
 
 
@@ -227,7 +482,7 @@ pub async fn poll_temperature(mut ctx: poll_temperature::Context<'_>) {
 
             ctx.shared
                 .downlink_packets
-                .lock(|q| q.push_back(packet.clone()).ok());
+                .lock(|q| enqueue(q, packet.clone()));
             
             
             ctx.shared
@@ -239,27 +494,27 @@ pub async fn poll_temperature(mut ctx: poll_temperature::Context<'_>) {
     }
 }
 
-/// Task to poll the RBF pin and block ejection if it is inserted
+/// Task to poll the RBF pin and block ejection unless it is out
 ///
-/// Timing: Every 100 ms
+/// Timing: Every 50 ms, and a level has to hold for the debounce time before it counts, so a
+/// connector bouncing during handling can't arm the sequence
 pub async fn poll_rbf(mut ctx: poll_rbf::Context<'_>) {
+    let mut line = RbfLine::active_low(ctx.local.rbf_pin);
+    let mut monitor = RbfMonitor::new(RbfConfig::DEFAULT);
+
     loop {
-        if ctx
-            .local
-            .rbf_pin
-            .is_low()
-            .expect("Failed to read the RBF pin state")
-        {
-            // info!("RBF pin is low, blocking ejection code...");
-            ctx.shared.ejection_enabled.lock(|blocked| *blocked = false);
-            LOCAL_RBF_IN.store(true, Ordering::Relaxed);
-        } else {
-            // info!("RBF pin is high, ejection code enabled.");
-            ctx.shared.ejection_enabled.lock(|blocked| *blocked = true);
-            LOCAL_RBF_IN.store(false, Ordering::Relaxed);
+        let now_ms = Mono::now().duration_since_epoch().to_millis();
+        if let Some(transition) = monitor.update(now_ms, line.read()) {
+            info!(
+                "RBF {} -> {}, edge at {} ms",
+                transition.from, transition.to, transition.edge_ms
+            );
         }
-        Mono::delay(3000_u64.millis()).await;
 
+        let removed = monitor.inhibition() == RbfState::Uninhibited;
+        ctx.shared.ejection_enabled.lock(|enabled| *enabled = removed);
+        LOCAL_RBF_IN.store(!removed, Ordering::Relaxed);
+        Mono::delay(50_u64.millis()).await;
     }
 }
 
@@ -306,7 +561,7 @@ pub async fn rx_from_jupiter(mut ctx: rx_from_jupiter::Context<'_>) {
 
     let mut rx_buf = [0u8; SCRATCH];
     let mut idx = 0;
-    Mono::delay(40000_u64.millis()).await;
+    Mono::delay(RX_LOCKOUT_MS.millis()).await;
 
     loop {
         let mut data_received = false;
@@ -336,50 +591,108 @@ pub async fn rx_from_jupiter(mut ctx: rx_from_jupiter::Context<'_>) {
         // Decode if bytes read
         while idx > 0 {
             match decode_from_slice::<ApplicationPacket, _>(&rx_buf[..idx], config) {
-                Ok((
-                    ApplicationPacket::Command(CommandPacket::ColorSet(status_options)),
-                    bytes_used,
-                )) => {
-                    // info!("Color command");
-                    ctx.shared.status_config.lock(|status_config| {
-                        status_config.update_from_options(status_options);
-                    });
-
-                    let remaining = idx - bytes_used;
-                    if remaining > 0 {
-                        rx_buf.copy_within(bytes_used..idx, 0);
-                    }
-                    idx = remaining;
-                    STATUS_UPDATE.store(true, Ordering::Relaxed);
-                }
-
-                // This would be way better with just a pin toggle
-                Ok((
-                    ApplicationPacket::Command(CommandPacket::EjectorPhaseSet(
-                        EjectorPhase::Ejection,
-                    )),
-                    bytes_used,
-                )) => {
-                    info!("Ejector phase set");
-                    // ctx.local.ejection_trigger_tx.write(());
-
-                    EJECT.store(true, Ordering::Relaxed);
-
+                Ok((packet, bytes_used)) => {
                     let remaining = idx - bytes_used;
                     if remaining > 0 {
                         rx_buf.copy_within(bytes_used..idx, 0);
                     }
                     idx = remaining;
-                }
 
-                // Successfully decoded a packet, but it's not a ColorSet command
-                Ok((_, bytes_used)) => {
-                    info!("Other");
-                    let remaining = idx - bytes_used;
-                    if remaining > 0 {
-                        rx_buf.copy_within(bytes_used..idx, 0);
-                    }
-                    idx = remaining;
+                    let now_ms = Mono::now().duration_since_epoch().to_millis();
+                    LAST_RX_MS.store(now_ms as u32, Ordering::Relaxed);
+
+                    let ApplicationPacket::Command { seq, command } = packet else {
+                        info!("Other");
+                        continue;
+                    };
+                    ctx.shared
+                        .ejector_status
+                        .lock(|status| status.health.last_command = Some(seq));
+
+                    let reply = match command {
+                        CommandPacket::ColorSet(status_options) => {
+                            // info!("Color command");
+                            ctx.shared.status_config.lock(|status_config| {
+                                status_config.update_from_options(status_options);
+                            });
+                            STATUS_UPDATE.store(true, Ordering::Relaxed);
+                            CommandReply::Ack
+                        }
+
+                        // Jupiter resends this until it hears back, so it has to be fine to get
+                        // more than once
+                        CommandPacket::EjectorPhaseSet(EjectorPhase::Ejection) => {
+                            info!("Ejector phase set");
+                            EJECT.store(true, Ordering::Relaxed);
+                            CommandReply::Ack
+                        }
+                        CommandPacket::EjectorPhaseSet(EjectorPhase::Standby)
+                            if !EJECT.load(Ordering::Relaxed) =>
+                        {
+                            CommandReply::Ack
+                        }
+                        CommandPacket::EjectorPhaseSet(EjectorPhase::Standby) => {
+                            CommandReply::Nack(NackReason::InvalidPhase)
+                        }
+                        CommandPacket::Ping => CommandReply::Ack,
+
+                        // Steps are staged until the whole table is in, then it replaces the
+                        // compiled-in one as long as that hasn't started
+                        CommandPacket::SequenceStep {
+                            sequence,
+                            index,
+                            len,
+                            step,
+                        } => match ctx.local.upload.receive(sequence, index, len, step) {
+                            Ok(None) => CommandReply::Ack,
+                            Ok(Some((sequence, table))) => {
+                                let replaced = match sequence {
+                                    SequenceId::Ejector => ctx
+                                        .shared
+                                        .ejector_sequence
+                                        .lock(|s| s.replace(&table)),
+                                    SequenceId::Cameras => ctx
+                                        .shared
+                                        .camera_sequence
+                                        .lock(|s| s.replace(&table)),
+                                };
+                                match replaced {
+                                    Ok(()) => {
+                                        info!("Loaded {} steps for {}", table.len(), sequence);
+                                        CommandReply::Ack
+                                    }
+                                    Err(ReplaceError::Started) => {
+                                        warn!("{} already running, upload dropped", sequence);
+                                        CommandReply::Nack(NackReason::Busy)
+                                    }
+                                    Err(ReplaceError::Invalid(e)) => {
+                                        warn!("Rejected {} upload: {}", sequence, e);
+                                        CommandReply::Nack(NackReason::InvalidSequence)
+                                    }
+                                }
+                            }
+                            Err(e) => {
+                                warn!("Bad sequence step: {}", e);
+                                CommandReply::Nack(NackReason::InvalidSequence)
+                            }
+                        },
+                        CommandPacket::EjectorPhaseSet(EjectorPhase::Hold)
+                        | CommandPacket::SyncTime(_) => CommandReply::Nack(NackReason::Unsupported),
+                    };
+
+                    let ack = ApplicationPacket::CommandAck {
+                        seq,
+                        command,
+                        reply,
+                        phase: current_phase(),
+                    };
+                    // Jupiter is timing the reply, so it goes ahead of the status packets
+                    ctx.shared.downlink_packets.lock(|q| {
+                        if q.is_full() {
+                            q.pop_back();
+                        }
+                        q.push_front(ack).ok();
+                    });
                 }
 
                 // Incomplete packet: wait for more bytes on the next loop
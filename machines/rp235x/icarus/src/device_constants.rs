@@ -43,13 +43,118 @@ pub mod pins {
     pub type MuxS3Pin = Gpio10;
     /// Mux Disable
     pub type MuxEPin = Gpio12;
-
-    // pub type ADCMux = CD74HC4067<Pin<MuxS0Pin, rp235x_hal::gpio::FunctionSio<rp235x_hal::gpio::SioOutput>, rp235x_hal::gpio::PullDown>, Pin<MuxS1Pin, rp235x_hal::gpio::FunctionSio<rp235x_hal::gpio::SioOutput>, rp235x_hal::gpio::PullDown>, Pin<MuxS2Pin, rp235x_hal::gpio::FunctionSio<rp235x_hal::gpio::SioOutput>, rp235x_hal::gpio::PullDown>, Pin<MuxS3Pin, rp235x_hal::gpio::FunctionSio<rp235x_hal::gpio::SioOutput>, rp235x_hal::gpio::PullDown>, Pin<MuxEPin, rp235x_hal::gpio::FunctionSio<rp235x_hal::gpio::SioOutput>, rp235x_hal::gpio::PullDown>>;
+    /// Second mux disable, the muxes share S0-S3. Unconfirmed: nothing else here uses GPIO15,
+    /// but it hasn't been checked against the schematic. See [`super::sun::SUN_CONFIRMED`].
+    pub type MuxE2Pin = Gpio15;
+    /// Mux common pin, into the ADC
+    pub type PhotodiodeAdcPin = Gpio40;
 
     /// ESC I2C SDA pin
     pub type EscI2CSdaPin = Gpio16;
     /// ESC I2C SCL pin
     pub type EscI2CSclPin = Gpio17;
+
+    /// INA260 ALERT, open collector and shared by all four. Not confirmed against the schematic;
+    /// it's only ever an input and the alerts are polled anyway, so a wrong pin only loses the
+    /// early wake-up.
+    pub type PowerAlertPin = Gpio22;
+
+    // No load enable pins are confirmed yet, so no channel is switched. Add them here and hand
+    // them to the guards in startup.
+}
+
+/// Overcurrent protection on the INA260s
+pub mod power {
+    use ina260_terminus::{guard::LoadGuard, AlertConfig, AlertLimit};
+    use rp235x_hal::gpio::{DynPinId, FunctionSio, Pin, PullDown, PullUp, SioInput, SioOutput};
+    use rtic_sync::arbiter::i2c::ArbiterDevice;
+
+    use super::{pins::PowerAlertPin, MotorI2cBus};
+    use crate::Mono;
+
+    /// Load switch enable, high for on. Dynamic so the guards share a type. Channels without one
+    /// are only monitored.
+    pub type LoadSwitch = Pin<DynPinId, FunctionSio<SioOutput>, PullDown>;
+
+    /// The shared ALERT line, pulled low by whichever INA260 tripped
+    pub type PowerAlert = Pin<PowerAlertPin, FunctionSio<SioInput>, PullUp>;
+
+    /// An INA260 and, once its enable pin is known, the load it switches off
+    pub type PowerGuard = LoadGuard<ArbiterDevice<'static, MotorI2cBus>, Mono, LoadSwitch>;
+
+    /// INA260 addresses, channel order
+    pub const INA260_ADDRESSES: [u8; 4] = [0x40, 0x41, 0x44, 0x45];
+
+    /// Trip points, channel order. Latched so every INA260 on the shared line can be asked which
+    /// one it was.
+    pub const POWER_ALERTS: [AlertConfig; 4] =
+        [AlertConfig::new(AlertLimit::OverCurrent { milliamps: 2000.0 }).latching(); 4];
+}
+
+/// Photodiode sun sensor
+pub mod sun {
+    use cd74hc4067::{Cascade, ScanConfig, Scanner};
+    use rp235x_hal::gpio::{DynPinId, FunctionSio, Pin, PullDown, SioOutput};
+    use sun_sensor::{Diode, SolverConfig, SunSensor};
+
+    use super::pins::{MuxS0Pin, MuxS1Pin, MuxS2Pin, MuxS3Pin};
+    use crate::{sensors::photodiodes::PhotodiodeAdc, Mono};
+
+    /// Mux select line
+    pub type MuxSelect<P> = Pin<P, FunctionSio<SioOutput>, PullDown>;
+    /// Mux disable, high for off. Dynamic so both muxes share a type.
+    pub type MuxDisable = Pin<DynPinId, FunctionSio<SioOutput>, PullDown>;
+
+    /// Both muxes, diodes 1-16 on the first and 17-24 on the second
+    pub type PhotodiodeMux = Cascade<
+        MuxSelect<MuxS0Pin>,
+        MuxSelect<MuxS1Pin>,
+        MuxSelect<MuxS2Pin>,
+        MuxSelect<MuxS3Pin>,
+        MuxDisable,
+        2,
+    >;
+
+    /// The muxes and the ADC behind them
+    pub type PhotodiodeScanner = Scanner<PhotodiodeMux, PhotodiodeAdc, Mono>;
+
+    /// Every diode in order, 20us to settle and four samples each
+    pub const PHOTODIODE_SCAN: ScanConfig<24> = ScanConfig::sequential(0, 20).oversampled(4, 0);
+
+    /// Whether the second mux pin, the face normals and the diode calibration below have been
+    /// checked on the flight unit. Until they are the fix is downlinked for checking but nothing
+    /// navigates by it.
+    pub const SUN_CONFIRMED: bool = false;
+
+    /// Face normals, four diodes to a face in diode order. Unconfirmed, laid out as if each face
+    /// looked straight down a body axis.
+    const FACES: [[f32; 3]; 6] = [
+        [1.0, 0.0, 0.0],
+        [-1.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0],
+        [0.0, -1.0, 0.0],
+        [0.0, 0.0, 1.0],
+        [0.0, 0.0, -1.0],
+    ];
+
+    /// Nominal dark reading and full sun gain, in counts. Unconfirmed until the flight unit is
+    /// calibrated against a lamp.
+    const DARK: f32 = 40.0;
+    const GAIN: f32 = 3000.0;
+
+    /// Calibration, in diode order
+    pub const PHOTODIODES: [Diode; 24] = {
+        let mut diodes = [Diode::new([0.0; 3], DARK, GAIN); 24];
+        let mut i = 0;
+        while i < 24 {
+            diodes[i].normal = FACES[i / 4];
+            i += 1;
+        }
+        diodes
+    };
+
+    /// The diodes and what counts as a fix
+    pub const SUN_SENSOR: SunSensor<24> = SunSensor::new(PHOTODIODES, SolverConfig::DEFAULT);
 }
 
 /// Servo items
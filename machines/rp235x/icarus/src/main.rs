@@ -21,7 +21,7 @@ use core::mem::MaybeUninit;
 use bme280::AsyncBME280;
 use bmi323::{AsyncBmi323, AsyncI2cInterface};
 use bmm350::{AsyncBmm350, AsyncI2cInterface as AsyncBmmI2cInterface};
-use ina260_terminus::AsyncINA260;
+use sun_sensor::VectorReference;
 
 // Busses
 use rtic_sync::arbiter::i2c::ArbiterDevice;
@@ -57,13 +57,15 @@ pub static IMAGE_DEF: rp235x_hal::block::ImageDef = rp235x_hal::block::ImageDef:
 )]
 mod app {
     use crate::device_constants::{
+        power::{PowerAlert, PowerGuard},
         servos::{FlapServo, RelayServo},
+        sun::PhotodiodeScanner,
         AvionicsI2cBus, DownlinkBuffer, IcarusHC12, MotorI2cBus,
     };
 
     use super::*;
 
-    use bin_packets::{phases::IcarusPhase, time::Timestamp};
+    use bin_packets::{data::IcarusStatus, phases::IcarusPhase, time::Timestamp};
 
     use hal::gpio::{self, FunctionSio, PullNone, SioOutput};
     use rp235x_hal::{
@@ -72,7 +74,10 @@ mod app {
     };
     pub const XTAL_FREQ_HZ: u32 = 12_000_000u32;
 
-    use rtic_sync::{arbiter::Arbiter, signal::Signal};
+    use rtic_sync::{
+        arbiter::Arbiter,
+        signal::{Signal, SignalReader, SignalWriter},
+    };
 
     pub type UART0Bus = UartPeripheral<
         rp235x_hal::uart::Enabled,
@@ -87,6 +92,7 @@ mod app {
     #[shared]
     pub struct Shared {
         pub data: DownlinkBuffer,
+        pub status: IcarusStatus,
     }
 
     #[local]
@@ -98,41 +104,14 @@ mod app {
         pub bmm350: AsyncBmm350<AsyncBmmI2cInterface<ArbiterDevice<'static, AvionicsI2cBus>>, Mono>,
         pub bmi323: AsyncBmi323<AsyncI2cInterface<ArbiterDevice<'static, AvionicsI2cBus>>, Mono>,
         pub bme280: AsyncBME280<ArbiterDevice<'static, AvionicsI2cBus>, Mono>,
-        pub ina260_1: AsyncINA260<ArbiterDevice<'static, MotorI2cBus>, Mono>,
-        pub ina260_2: AsyncINA260<ArbiterDevice<'static, MotorI2cBus>, Mono>,
-        pub ina260_3: AsyncINA260<ArbiterDevice<'static, MotorI2cBus>, Mono>,
-        pub ina260_4: AsyncINA260<ArbiterDevice<'static, MotorI2cBus>, Mono>,
+        pub power_guards: [PowerGuard; 4],
+        pub power_alert: PowerAlert,
+        pub power_alert_writer: SignalWriter<'static, ()>,
+        pub power_alert_reader: SignalReader<'static, ()>,
         pub rbf: Pin<Gpio4, FunctionSio<SioInput>, PullDown>,
-        // pub adc: hal::adc::Adc,
-        // pub adc_photoresistors:
-        //     AdcPin<gpio::Pin<gpio::bank0::Gpio40, gpio::FunctionNull, gpio::PullDown>>,
-        // pub mux: CD74HC4067<
-        //     Pin<
-        //         MuxS0Pin,
-        //         rp235x_hal::gpio::FunctionSio<rp235x_hal::gpio::SioOutput>,
-        //         rp235x_hal::gpio::PullDown,
-        //     >,
-        //     Pin<
-        //         MuxS1Pin,
-        //         rp235x_hal::gpio::FunctionSio<rp235x_hal::gpio::SioOutput>,
-        //         rp235x_hal::gpio::PullDown,
-        //     >,
-        //     Pin<
-        //         MuxS2Pin,
-        //         rp235x_hal::gpio::FunctionSio<rp235x_hal::gpio::SioOutput>,
-        //         rp235x_hal::gpio::PullDown,
-        //     >,
-        //     Pin<
-        //         MuxS3Pin,
-        //         rp235x_hal::gpio::FunctionSio<rp235x_hal::gpio::SioOutput>,
-        //         rp235x_hal::gpio::PullDown,
-        //     >,
-        //     Pin<
-        //         MuxEPin,
-        //         rp235x_hal::gpio::FunctionSio<rp235x_hal::gpio::SioOutput>,
-        //         rp235x_hal::gpio::PullDown,
-        //     >,
-        // >,
+        pub photodiodes: PhotodiodeScanner,
+        pub sun_reference_writer: SignalWriter<'static, VectorReference>,
+        pub sun_reference_reader: SignalReader<'static, VectorReference>,
     }
 
     #[init(
@@ -142,6 +121,8 @@ mod app {
             i2c_avionics_bus: MaybeUninit<Arbiter<AvionicsI2cBus>> = MaybeUninit::uninit(),
             i2c_motor_bus: MaybeUninit<Arbiter<MotorI2cBus>> = MaybeUninit::uninit(),
             esc_state_signal: MaybeUninit<Signal<IcarusPhase>> = MaybeUninit::uninit(),
+            power_alert_signal: MaybeUninit<Signal<()>> = MaybeUninit::uninit(),
+            sun_reference_signal: MaybeUninit<Signal<VectorReference>> = MaybeUninit::uninit(),
         ]
     )]
     fn init(ctx: init::Context) -> (Shared, Local) {
@@ -150,27 +131,35 @@ mod app {
 
     extern "Rust" {
         // Heartbeats the main led
-        #[task(local = [led], shared = [data], priority = 1)]
+        #[task(local = [led], shared = [data, status], priority = 1)]
         async fn heartbeat(ctx: heartbeat::Context);
 
         // Takes care of incoming packets
         #[task(shared = [data], local=[radio], priority = 2)]
         async fn radio_send(mut ctx: radio_send::Context);
 
-        #[task(priority = 3, local=[flap_servo, relay_servo, rbf])]
+        #[task(priority = 3, shared = [status], local=[flap_servo, relay_servo, rbf])]
         async fn mode_sequencer(&mut ctx: mode_sequencer::Context);
 
-        // Handles INA sensors
-        #[task(priority = 2, shared = [data], local=[ina260_1, ina260_2, ina260_3, ina260_4])]
+        // Handles INA sensors, and switches loads off when they alert
+        #[task(priority = 2, shared = [data, status], local=[power_guards, power_alert_reader])]
         async fn ina_sample(&mut ctx: ina_sample::Context, i2c: &'static Arbiter<MotorI2cBus>);
 
-        #[task(local = [bme280, bmi323, bmm350], shared = [data], priority = 2)]
+        // INA260 ALERT edge, hands off to ina_sample which owns the bus
+        #[task(binds = IO_IRQ_BANK0, local = [power_alert, power_alert_writer], priority = 4)]
+        fn power_alert_irq(ctx: power_alert_irq::Context);
+
+        #[task(local = [bme280, bmi323, bmm350], shared = [data, status], priority = 2)]
         async fn sample_sensors(
             mut ctx: sample_sensors::Context,
             avionics_i2c: &'static Arbiter<AvionicsI2cBus>,
         );
 
-        #[task(priority = 2)]
+        // Scans the photodiodes for a sun fix
+        #[task(local = [photodiodes, sun_reference_writer], shared = [data], priority = 1)]
+        async fn photodiode_scan(mut ctx: photodiode_scan::Context);
+
+        #[task(local = [sun_reference_reader], priority = 2)]
         async fn inertial_nav(mut ctx: inertial_nav::Context);
     }
 
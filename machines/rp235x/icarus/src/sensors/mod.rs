@@ -1 +1 @@
-
+pub mod photodiodes;
@@ -0,0 +1,30 @@
+use cd74hc4067::AdcSample;
+use embedded_hal_0_2::adc::OneShot;
+use rp235x_hal::{
+    adc::AdcPin,
+    gpio::{FunctionNull, Pin, PullDown},
+    Adc,
+};
+
+use crate::device_constants::pins::PhotodiodeAdcPin;
+
+/// The ADC input behind the photodiode muxes
+pub struct PhotodiodeAdc {
+    adc: Adc,
+    pin: AdcPin<Pin<PhotodiodeAdcPin, FunctionNull, PullDown>>,
+}
+
+impl PhotodiodeAdc {
+    pub fn new(adc: Adc, pin: AdcPin<Pin<PhotodiodeAdcPin, FunctionNull, PullDown>>) -> Self {
+        PhotodiodeAdc { adc, pin }
+    }
+}
+
+impl AdcSample for PhotodiodeAdc {
+    type Error = ();
+
+    fn sample(&mut self) -> Result<u16, ()> {
+        // One shot blocks for the conversion, a couple of us
+        self.adc.read(&mut self.pin).map_err(|_| ())
+    }
+}
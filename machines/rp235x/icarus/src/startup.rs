@@ -1,9 +1,12 @@
 use crate::{
     actuators::servo::Servo,
     device_constants::{
-        pins::{MuxEPin, MuxS0Pin, MuxS1Pin, MuxS2Pin, MuxS3Pin},
+        pins::{MuxE2Pin, MuxEPin, MuxS0Pin, MuxS1Pin, MuxS2Pin, MuxS3Pin},
+        power::{PowerAlert, PowerGuard, INA260_ADDRESSES, POWER_ALERTS},
+        sun::MuxDisable,
         DownlinkBuffer,
     },
+    sensors::photodiodes::PhotodiodeAdc,
 };
 use crate::{
     app::*,
@@ -29,20 +32,23 @@ use hc12_rs::{
 };
 use rp235x_hal::{
     clocks,
-    gpio::{FunctionI2C, FunctionPwm, Pin, PullNone, PullUp},
+    gpio::{FunctionI2C, FunctionPwm, Interrupt, Pin, PinState, PullNone, PullUp},
     pwm::Slices,
     uart::{DataBits, StopBits, UartConfig, UartPeripheral},
     Clock, Sio, Watchdog, I2C,
 };
 use rtic_sync::arbiter::{i2c::ArbiterDevice, Arbiter};
 
+use bin_packets::{data::IcarusStatus, phases::IcarusPhase};
+
 // Sensors
 use crate::device_constants::IcarusHC12;
 use bme280::AsyncBME280;
 use bmi323::AsyncBmi323;
 use bmm350::AsyncBmm350;
-use cd74hc4067::CD74HC4067;
-use ina260_terminus::AsyncINA260;
+use cd74hc4067::{Cascade, Scanner};
+use ina260_terminus::{guard::LoadGuard, AsyncINA260};
+use rtic_sync::signal::Signal;
 
 // Logs our time for demft
 defmt::timestamp!("{=u64:us}", { epoch_ns() });
@@ -253,13 +259,30 @@ pub fn startup(mut ctx: init::Context) -> (Shared, Local) {
     let bmi323 = AsyncBmi323::new_with_i2c(ArbiterDevice::new(avionics_i2c_arbiter), 0x69, Mono);
     let bme280 = AsyncBME280::new(ArbiterDevice::new(avionics_i2c_arbiter), 0x77, Mono);
 
-    let ina260_1 = AsyncINA260::new(ArbiterDevice::new(motor_i2c_arbiter), 0x40, Mono);
-    let ina260_2 = AsyncINA260::new(ArbiterDevice::new(motor_i2c_arbiter), 0x41, Mono);
-    let ina260_3 = AsyncINA260::new(ArbiterDevice::new(motor_i2c_arbiter), 0x44, Mono);
-    let ina260_4 = AsyncINA260::new(ArbiterDevice::new(motor_i2c_arbiter), 0x45, Mono);
+    // None of the load enables are confirmed on the schematic, so every channel is only
+    // monitored for now. To switch one, start its pin in the state the load has without a guard
+    // (on) and pass it to LoadGuard::new; a failed arm then leaves it on.
+    let power_guards: [PowerGuard; 4] = core::array::from_fn(|channel| {
+        LoadGuard::monitor(
+            AsyncINA260::new(
+                ArbiterDevice::new(motor_i2c_arbiter),
+                INA260_ADDRESSES[channel],
+                Mono,
+            ),
+            POWER_ALERTS[channel],
+        )
+    });
+
+    let power_alert: PowerAlert = pins.gpio22.reconfigure();
+    power_alert.set_interrupt_enabled(Interrupt::EdgeLow, true);
+    let (power_alert_writer, power_alert_reader) = ctx
+        .local
+        .power_alert_signal
+        .write(Signal::new())
+        .split();
 
-    // let mut adc = rp235x_hal::Adc::new(ctx.device.ADC, &mut ctx.device.RESETS);
-    // let mut adc_photoresistors: rp235x_hal::adc::AdcPin<Pin<rp235x_hal::gpio::bank0::Gpio40, rp235x_hal::gpio::FunctionNull, rp235x_hal::gpio::PullDown>> = rp235x_hal::adc::AdcPin::new(pins.gpio40).unwrap();
+    let adc = rp235x_hal::Adc::new(ctx.device.ADC, &mut ctx.device.RESETS);
+    let photodiode_pin = rp235x_hal::adc::AdcPin::new(pins.gpio40).unwrap();
 
     let s0: Pin<
         MuxS0Pin,
@@ -285,9 +308,20 @@ pub fn startup(mut ctx: init::Context) -> (Shared, Local) {
         MuxEPin,
         rp235x_hal::gpio::FunctionSio<rp235x_hal::gpio::SioOutput>,
         rp235x_hal::gpio::PullDown,
-    > = pins.gpio12.into_push_pull_output();
-    // TODO: Maybe?
-    let _mux = CD74HC4067::new_enable(s0, s1, s2, s3, e);
+    > = pins.gpio12.into_push_pull_output_in_state(PinState::High);
+    let e2: Pin<
+        MuxE2Pin,
+        rp235x_hal::gpio::FunctionSio<rp235x_hal::gpio::SioOutput>,
+        rp235x_hal::gpio::PullDown,
+    > = pins.gpio15.into_push_pull_output_in_state(PinState::High);
+    let mux_disables: [MuxDisable; 2] = [e.into_dyn_pin(), e2.into_dyn_pin()];
+    let photodiodes = Scanner::new(
+        Cascade::new(s0, s1, s2, s3, mux_disables),
+        PhotodiodeAdc::new(adc, photodiode_pin),
+        Mono,
+    );
+    let (sun_reference_writer, sun_reference_reader) =
+        ctx.local.sun_reference_signal.write(Signal::new()).split();
 
     let data = DownlinkBuffer::new();
     let rbf = pins.gpio4.into_pull_down_input();
@@ -298,9 +332,14 @@ pub fn startup(mut ctx: init::Context) -> (Shared, Local) {
     ina_sample::spawn(motor_i2c_arbiter).ok();
     sample_sensors::spawn(avionics_i2c_arbiter).ok();
     radio_send::spawn().ok();
+    photodiode_scan::spawn().ok();
+    inertial_nav::spawn().ok();
     info!("Tasks spawned!");
     (
-        Shared { data },
+        Shared {
+            data,
+            status: IcarusStatus::new(IcarusPhase::Ejection),
+        },
         Local {
             radio: hc,
             flap_servo,
@@ -309,14 +348,14 @@ pub fn startup(mut ctx: init::Context) -> (Shared, Local) {
             bmm350,
             bmi323,
             bme280,
-            ina260_1,
-            ina260_2,
-            ina260_3,
+            power_guards,
+            power_alert,
+            power_alert_writer,
+            power_alert_reader,
             rbf,
-            ina260_4,
-            // adc,
-            // adc_photoresistors,
-            // mux
+            photodiodes,
+            sun_reference_writer,
+            sun_reference_reader,
         },
     )
 }
@@ -1,5 +1,8 @@
 use bin_packets::devices::DeviceIdentifier;
 use bin_packets::packets::status::Status;
+use bin_packets::data::adcs::SunFixStatus;
+use bin_packets::data::{IcarusStatus, PowerFaultKind};
+use bin_packets::phases::IcarusPhase;
 use bin_packets::packets::ApplicationPacket;
 use bincode::config::standard;
 use bincode::encode_into_slice;
@@ -9,25 +12,75 @@ use bmm350::MagConfig;
 use defmt::{error, info};
 use embedded_hal::digital::{InputPin, StatefulOutputPin};
 
-use crate::device_constants::AvionicsI2cBus;
+use crate::device_constants::power::PowerGuard;
+use crate::device_constants::sun::{PHOTODIODE_SCAN, SUN_CONFIRMED, SUN_SENSOR};
+use crate::device_constants::{AvionicsI2cBus, DownlinkBuffer};
 use crate::phases::{Modes, RelayServoStatus};
 use crate::{app::*, device_constants::MotorI2cBus, Mono};
 use embedded_io::Write;
+use ina260_terminus::AlertLimit;
+use rp235x_hal::gpio::Interrupt;
 use fugit::ExtU64;
 use rtic::Mutex;
 use rtic_monotonics::Monotonic;
 use rtic_sync::arbiter::Arbiter;
+use rtic_sync::portable_atomic::{AtomicBool, Ordering};
+use sun_sensor::{Rejection, SunEstimate, VectorReference};
+
+// Set when a packet is dropped for want of queue space, cleared by each status packet
+static QUEUE_OVERFLOW: AtomicBool = AtomicBool::new(false);
+
+// Queue a packet for the radio, noting it for the status if there's no room
+fn enqueue(data: &mut DownlinkBuffer, packet: ApplicationPacket) {
+    if data.push_back(packet).is_err() {
+        QUEUE_OVERFLOW.store(true, Ordering::Relaxed);
+    }
+}
+
+// Set or clear the fault bits in `fault`
+fn flag(status: &mut impl Mutex<T = IcarusStatus>, fault: u16, active: bool) {
+    status.lock(|status| {
+        if active {
+            status.health.faults |= fault;
+        } else {
+            status.health.faults &= !fault;
+        }
+    });
+}
 
 pub async fn heartbeat(mut ctx: heartbeat::Context<'_>) {
     let mut sequence_number: u16 = 0;
+    let mut phase = IcarusPhase::Ejection;
+    let mut phase_since = 0;
     loop {
         _ = ctx.local.led.toggle();
 
         let status = Status::new(DeviceIdentifier::Icarus, now_timestamp(), sequence_number);
 
-        ctx.shared
-            .data
-            .lock(|data| data.push_back(status.into()).ok());
+        let now_ms = Mono::now().duration_since_epoch().to_millis();
+        let queue_depth = ctx.shared.data.lock(|data| data.len());
+        let overflowed = QUEUE_OVERFLOW.swap(false, Ordering::Relaxed);
+        let icarus_status = ctx.shared.status.lock(|icarus_status| {
+            if icarus_status.phase != phase {
+                phase = icarus_status.phase;
+                phase_since = now_ms;
+            }
+            let health = &mut icarus_status.health;
+            health.uptime_ms = now_ms;
+            health.time_in_phase_ms = now_ms - phase_since;
+            health.packet_number = sequence_number;
+            health.record_queue(queue_depth);
+            let mut snapshot = *icarus_status;
+            if overflowed {
+                snapshot.health.faults |= IcarusStatus::FAULT_QUEUE_FULL;
+            }
+            snapshot
+        });
+
+        ctx.shared.data.lock(|data| {
+            enqueue(data, status.into());
+            enqueue(data, ApplicationPacket::IcarusStatus(icarus_status));
+        });
 
         sequence_number = sequence_number.wrapping_add(1);
 
@@ -95,7 +148,7 @@ unsafe fn I2C0_IRQ() {
 }
 
 use crate::phases::mode::{FLUTTER_COUNT, FLUTTER_START_TIME, SERVO_DISABLE_DELAY};
-pub async fn mode_sequencer(ctx: mode_sequencer::Context<'_>) {
+pub async fn mode_sequencer(mut ctx: mode_sequencer::Context<'_>) {
     let mut mode_start = Mono::now();
     let mut relay_status = false;
     ctx.local.relay_servo.enable();
@@ -108,10 +161,23 @@ pub async fn mode_sequencer(ctx: mode_sequencer::Context<'_>) {
 
     // Wait for RBF removal
     while ctx.local.rbf.is_high().unwrap() {
+        ctx.shared
+            .status
+            .lock(|status| status.health.rbf_inserted = Some(true));
         Mono::delay(100.millis()).await;
     }
+    ctx.shared
+        .status
+        .lock(|status| status.health.rbf_inserted = Some(false));
 
     loop {
+        let phase = match (end_task, relay_status) {
+            (true, _) => IcarusPhase::OrientSolar,
+            (false, true) => IcarusPhase::FlapDeploy,
+            (false, false) => IcarusPhase::Ejection,
+        };
+        ctx.shared.status.lock(|status| status.phase = phase);
+
         if !end_task {
             if !relay_status {
                 // flap_status = Modes::open_flaps_sequence(mode_start, ctx.local.flap_servo).await;
@@ -153,54 +219,55 @@ pub async fn mode_sequencer(ctx: mode_sequencer::Context<'_>) {
 
 pub async fn ina_sample(mut ctx: ina_sample::Context<'_>, _i2c: &'static Arbiter<MotorI2cBus>) {
     info!("INA Sample Task Started");
-    if let Err(e) = ctx.local.ina260_1.init().await {
-        error!("Error initializing INA 1: {:?}", e);
+    for (channel, guard) in ctx.local.power_guards.iter_mut().enumerate() {
+        if let Err(e) = guard.sensor().init().await {
+            error!("Error initializing INA {}: {:?}", channel + 1, e);
+        }
+        Mono::delay(2_u64.millis()).await;
     }
-    Mono::delay(2_u64.millis()).await;
 
-    if let Err(e) = ctx.local.ina260_2.init().await {
-        error!("Error initializing INA 2: {:?}", e);
+    for (channel, guard) in ctx.local.power_guards.iter_mut().enumerate() {
+        guard
+            .sensor()
+            .set_operating_mode(ina260_terminus::OperMode::SCBVC)
+            .await
+            .ok();
+        // A load is only switched on once its alert is armed, so one that can't be armed is left
+        // as it was
+        if let Err(e) = guard.arm().await {
+            error!("Error arming INA {} alert: {:?}", channel + 1, e);
+        }
     }
-    Mono::delay(2_u64.millis()).await;
 
-    if let Err(e) = ctx.local.ina260_3.init().await {
-        error!("Error initializing INA 3: {:?}", e);
-    }
-    Mono::delay(2_u64.millis()).await;
-    if let Err(e) = ctx.local.ina260_4.init().await {
-        error!("Error initializing INA 4: {:?}", e);
-    }
-    Mono::delay(2_u64.millis()).await;
+    loop {
+        // An alert cuts the wait short. The alerts latch, so they're checked every time round
+        // rather than trusting the line.
+        Mono::timeout_after(250.millis(), ctx.local.power_alert_reader.wait())
+            .await
+            .ok();
+        if handle_power_alert(ctx.local.power_guards, &mut ctx.shared.data).await {
+            // Nothing re-arms a tripped channel, so the fault stays
+            flag(&mut ctx.shared.status, IcarusStatus::FAULT_POWER, true);
+        }
 
-    ctx.local
-        .ina260_1
-        .set_operating_mode(ina260_terminus::OperMode::SCBVC)
-        .await
-        .ok();
-    ctx.local
-        .ina260_2
-        .set_operating_mode(ina260_terminus::OperMode::SCBVC)
-        .await
-        .ok();
-    ctx.local
-        .ina260_3
-        .set_operating_mode(ina260_terminus::OperMode::SCBVC)
-        .await
-        .ok();
-    ctx.local
-        .ina260_4
-        .set_operating_mode(ina260_terminus::OperMode::SCBVC)
-        .await
-        .ok();
+        let ina_samples = ina_data_handle(ctx.local.power_guards).await;
+        let (voltage, current, power) = ina_samples.1;
+        let unanswered = voltage
+            .iter()
+            .chain(&current)
+            .chain(&power)
+            .any(|reading| reading.is_nan());
+        flag(
+            &mut ctx.shared.status,
+            IcarusStatus::FAULT_POWER_MONITOR,
+            unanswered,
+        );
+        // Every load hangs off the battery bus, so the first monitor sees it
+        let battery_mv = voltage[0].is_finite().then(|| (voltage[0] * 1000.0) as u16);
+        ctx.shared
+            .status
+            .lock(|status| status.health.battery_mv = battery_mv);
 
-    loop {
-        let ina_samples = ina_data_handle(
-            ctx.local.ina260_1,
-            ctx.local.ina260_2,
-            ctx.local.ina260_3,
-            ctx.local.ina260_4,
-        )
-        .await;
         ctx.shared.data.lock(|data| {
             let voltages_packet = ApplicationPacket::VoltageData {
                 timestamp: ina_samples.0.0,
@@ -217,11 +284,73 @@ pub async fn ina_sample(mut ctx: ina_sample::Context<'_>, _i2c: &'static Arbiter
             info!("Voltage Packet: {}", voltages_packet);
             info!("Current Packet: {}", current_packet);
             info!("Power Packet: {}", power_packet);
-            data.push_back(voltages_packet).ok();
-            data.push_back(current_packet).ok();
-            data.push_back(power_packet).ok();
+            enqueue(data, voltages_packet);
+            enqueue(data, current_packet);
+            enqueue(data, power_packet);
         });
-        Mono::delay(250.millis()).await;
+    }
+}
+
+pub fn power_alert_irq(ctx: power_alert_irq::Context<'_>) {
+    let alert = ctx.local.power_alert;
+    if alert.interrupt_status(Interrupt::EdgeLow) {
+        alert.clear_interrupt(Interrupt::EdgeLow);
+        ctx.local.power_alert_writer.write(());
+    }
+}
+
+// The line is shared, so ask every INA260 whether it was them. Returns whether any channel
+// tripped.
+async fn handle_power_alert(
+    guards: &mut [PowerGuard; 4],
+    data: &mut impl Mutex<T = DownlinkBuffer>,
+) -> bool {
+    let mut tripped = false;
+    for (channel, guard) in guards.iter_mut().enumerate() {
+        match guard.on_alert().await {
+            Ok(Some(trip)) => {
+                tripped = true;
+                error!(
+                    "INA {} tripped at {} (limit {}), load {} {}",
+                    channel + 1,
+                    trip.measured,
+                    trip.limit.threshold(),
+                    channel + 1,
+                    if guard.switched() {
+                        "switched off"
+                    } else {
+                        "has no switch"
+                    }
+                );
+                let fault = ApplicationPacket::PowerFault {
+                    timestamp_ms: now_timestamp().millis(),
+                    channel: channel as u8,
+                    kind: fault_kind(trip.limit),
+                    limit: trip.limit.threshold(),
+                    measured: trip.measured,
+                };
+                // Faults go to the front, they matter more than anything waiting
+                data.lock(|data| {
+                    if data.is_full() {
+                        data.pop_back();
+                    }
+                    data.push_front(fault).ok();
+                });
+            }
+            Ok(None) => {}
+            Err(e) => error!("Error checking INA {} alert: {:?}", channel + 1, e),
+        }
+    }
+    tripped
+}
+
+fn fault_kind(limit: AlertLimit) -> PowerFaultKind {
+    match limit {
+        AlertLimit::OverCurrent { .. } => PowerFaultKind::OverCurrent,
+        AlertLimit::UnderCurrent { .. } => PowerFaultKind::UnderCurrent,
+        AlertLimit::OverVoltage { .. } => PowerFaultKind::OverVoltage,
+        AlertLimit::UnderVoltage { .. } => PowerFaultKind::UnderVoltage,
+        AlertLimit::OverPower { .. } => PowerFaultKind::OverPower,
     }
 }
 
@@ -267,6 +396,7 @@ pub async fn sample_sensors(
         .ok();
 
     loop {
+        let mut unread = false;
         let imu_result = ctx.local.bmi323.read_accel_data_scaled().await;
         match imu_result {
             Ok(acc) => {
@@ -278,11 +408,12 @@ pub async fn sample_sensors(
                     z: acc.z,
                 };
                 ctx.shared.data.lock(|data| {
-                    data.push_back(acceleration_packet).ok();
+                    enqueue(data, acceleration_packet);
                 });
             }
             Err(i2c_error) => {
                 error!("BMI: {}", i2c_error);
+                unread = true;
             }
         }
         let gyro_result = ctx.local.bmi323.read_gyro_data_scaled().await;
@@ -296,11 +427,12 @@ pub async fn sample_sensors(
                     z: gyro.z,
                 };
                 ctx.shared.data.lock(|data| {
-                    data.push_back(gyro_packet).ok();
+                    enqueue(data, gyro_packet);
                 });
             }
             Err(i2c_error) => {
                 error!("BMI: {}", i2c_error);
+                unread = true;
             }
         }
         let mag_result = ctx.local.bmm350.read_mag_data_scaled().await;
@@ -314,11 +446,12 @@ pub async fn sample_sensors(
                     z: mag.z,
                 };
                 ctx.shared.data.lock(|data| {
-                    data.push_back(mag_packet).ok();
+                    enqueue(data, mag_packet);
                 });
             }
             Err(i2c_error) => {
                 error!("BMM: {}", i2c_error);
+                unread = true;
             }
         }
         let env = ctx.local.bme280.sample().await;
@@ -329,246 +462,130 @@ pub async fn sample_sensors(
             humidity: env.3,
         };
         ctx.shared.data.lock(|data| {
-            data.push_back(env_packet).ok();
+            enqueue(data, env_packet);
         });
+        flag(&mut ctx.shared.status, IcarusStatus::FAULT_SENSORS, unread);
         Mono::delay(100.millis()).await;
     }
 }
 
-pub async fn inertial_nav(_ctx: inertial_nav::Context<'_>) {
+pub async fn photodiode_scan(mut ctx: photodiode_scan::Context<'_>) {
     loop {
-        info!("Inertial Navigation");
+        let timestamp_ms = now_timestamp().millis();
+        match ctx.local.photodiodes.scan_async(&PHOTODIODE_SCAN).await {
+            Ok(counts) => {
+                let fix = SUN_SENSOR.solve(&counts);
+                // Downlinked either way, but only steered by once the sensor's been checked
+                if let Ok(estimate) = fix {
+                    if SUN_CONFIRMED {
+                        ctx.local.sun_reference_writer.write(estimate.reference());
+                    }
+                }
+                let packet = sun_vector_packet(timestamp_ms, fix);
+                ctx.shared.data.lock(|data| {
+                    enqueue(data, packet);
+                });
+            }
+            Err(e) => {
+                error!("Photodiode scan: {}", e);
+            }
+        }
+        Mono::delay(200_u64.millis()).await;
+    }
+}
+
+fn sun_vector_packet(timestamp_ms: u64, fix: Result<SunEstimate, Rejection>) -> ApplicationPacket {
+    let (direction, irradiance, residual, albedo, used, status) = match fix {
+        Ok(estimate) => (
+            estimate.direction,
+            estimate.irradiance,
+            estimate.residual,
+            estimate.albedo,
+            estimate.used,
+            SunFixStatus::Valid,
+        ),
+        Err(Rejection::TooFewLit { .. }) => ([0.0; 3], 0.0, 0.0, 0.0, 0, SunFixStatus::TooFewLit),
+        Err(Rejection::Dark { irradiance }) => {
+            ([0.0; 3], irradiance, 0.0, 0.0, 0, SunFixStatus::Dark)
+        }
+        Err(Rejection::Residual { residual }) => {
+            ([0.0; 3], 0.0, residual, 0.0, 0, SunFixStatus::Residual)
+        }
+        Err(Rejection::Albedo { albedo }) => ([0.0; 3], 0.0, 0.0, albedo, 0, SunFixStatus::Albedo),
+    };
+    ApplicationPacket::SunVector {
+        timestamp_ms,
+        direction,
+        irradiance,
+        residual,
+        albedo,
+        used,
+        status,
+    }
+}
+
+pub async fn inertial_nav(ctx: inertial_nav::Context<'_>) {
+    // Latest coarse reference, body frame, until there's something to fuse it with. Stays empty
+    // until the sun sensor is confirmed.
+    let mut sun: Option<VectorReference> = None;
+    loop {
+        if let Some(reference) = ctx.local.sun_reference_reader.try_read() {
+            sun = Some(reference);
+        }
+        info!("Inertial Navigation, sun {}", sun);
         Mono::delay(250_u64.millis()).await;
     }
 }
 
 // Sample Functions
-use crate::peripherals::async_i2c::AsyncI2c;
-use ina260_terminus::AsyncINA260;
-use rp235x_hal::{
-    gpio::{Pin, PullUp},
-    I2C,
-};
-use rtic_sync::arbiter::i2c::ArbiterDevice;
-#[allow(clippy::type_complexity)]
 async fn ina_data_handle(
-    ina260_1: &mut AsyncINA260<
-        ArbiterDevice<
-            '_,
-            AsyncI2c<
-                I2C<
-                    rp235x_pac::I2C0,
-                    (
-                        Pin<rp235x_hal::gpio::bank0::Gpio16, rp235x_hal::gpio::FunctionI2c, PullUp>,
-                        Pin<rp235x_hal::gpio::bank0::Gpio17, rp235x_hal::gpio::FunctionI2c, PullUp>,
-                    ),
-                >,
-            >,
-        >,
-        Mono,
-    >,
-    ina260_2: &mut AsyncINA260<
-        ArbiterDevice<
-            '_,
-            AsyncI2c<
-                I2C<
-                    rp235x_pac::I2C0,
-                    (
-                        Pin<rp235x_hal::gpio::bank0::Gpio16, rp235x_hal::gpio::FunctionI2c, PullUp>,
-                        Pin<rp235x_hal::gpio::bank0::Gpio17, rp235x_hal::gpio::FunctionI2c, PullUp>,
-                    ),
-                >,
-            >,
-        >,
-        Mono,
-    >,
-    ina260_3: &mut AsyncINA260<
-        ArbiterDevice<
-            '_,
-            AsyncI2c<
-                I2C<
-                    rp235x_pac::I2C0,
-                    (
-                        Pin<rp235x_hal::gpio::bank0::Gpio16, rp235x_hal::gpio::FunctionI2c, PullUp>,
-                        Pin<rp235x_hal::gpio::bank0::Gpio17, rp235x_hal::gpio::FunctionI2c, PullUp>,
-                    ),
-                >,
-            >,
-        >,
-        Mono,
-    >,
-    ina260_4: &mut AsyncINA260<
-        ArbiterDevice<
-            '_,
-            AsyncI2c<
-                I2C<
-                    rp235x_pac::I2C0,
-                    (
-                        Pin<rp235x_hal::gpio::bank0::Gpio16, rp235x_hal::gpio::FunctionI2c, PullUp>,
-                        Pin<rp235x_hal::gpio::bank0::Gpio17, rp235x_hal::gpio::FunctionI2c, PullUp>,
-                    ),
-                >,
-            >,
-        >,
-        Mono,
-    >,
+    guards: &mut [PowerGuard; 4],
 ) -> (
     ([u64; 4], [u64; 4], [u64; 4]),
     ([f32; 4], [f32; 4], [f32; 4]),
 ) {
-    let voltage_1 = ina260_1.voltage().await;
-    let v1_ts = now_timestamp().millis();
-
-    let voltage_2 = ina260_2.voltage().await;
-    let v2_ts = now_timestamp().millis();
-
-    let voltage_3 = ina260_3.voltage().await;
-    let v3_ts = now_timestamp().millis();
-
-    let voltage_4 = ina260_4.voltage().await;
-    let v4_ts = now_timestamp().millis();
-
-    let current_1 = ina260_1.current().await;
-    let i1_ts = now_timestamp().millis();
-
-    let current_2 = ina260_2.current().await;
-    let i2_ts = now_timestamp().millis();
-
-    let current_3 = ina260_3.current().await;
-    let i3_ts = now_timestamp().millis();
-
-    let current_4 = ina260_4.current().await;
-    let i4_ts = now_timestamp().millis();
-
-    let power_1 = ina260_1.power().await;
-    let p1_ts = now_timestamp().millis();
-
-    let power_2 = ina260_2.power().await;
-    let p2_ts = now_timestamp().millis();
-
-    let power_3 = ina260_3.power().await;
-    let p3_ts = now_timestamp().millis();
-
-    let power_4 = ina260_4.power().await;
-    let p4_ts = now_timestamp().millis();
-
     let mut voltage_slice = [0.0_f32; 4];
-    let v_ts_slice = [v1_ts, v2_ts, v3_ts, v4_ts];
+    let mut v_ts_slice = [0_u64; 4];
 
     let mut current_slice = [0.0_f32; 4];
-    let i_ts_slice = [i1_ts, i2_ts, i3_ts, i4_ts];
+    let mut i_ts_slice = [0_u64; 4];
 
     let mut power_slice = [0.0_f32; 4];
-    let p_ts_slice = [p1_ts, p2_ts, p3_ts, p4_ts];
+    let mut p_ts_slice = [0_u64; 4];
 
-    match voltage_1 {
-        Ok(voltage) => {
-            voltage_slice[0] = voltage;
-        }
-        Err(i2c_error) => {
-            error!("V1 Err: {}", i2c_error);
-            voltage_slice[0] = f32::NAN;
-        }
-    }
-    match voltage_2 {
-        Ok(voltage) => {
-            voltage_slice[1] = voltage;
-        }
-        Err(i2c_error) => {
-            error!("V2 Err: {}", i2c_error);
-            voltage_slice[1] = f32::NAN;
-        }
-    }
-    match voltage_3 {
-        Ok(voltage) => {
-            voltage_slice[2] = voltage;
-        }
-        Err(i2c_error) => {
-            error!("V3 Err: {}", i2c_error);
-            voltage_slice[2] = f32::NAN;
-        }
-    }
-    match voltage_4 {
-        Ok(voltage) => {
-            voltage_slice[3] = voltage;
-        }
-        Err(i2c_error) => {
-            error!("V4 Err: {}", i2c_error);
-            voltage_slice[3] = f32::NAN;
-        }
-    }
-    match current_1 {
-        Ok(current) => {
-            current_slice[0] = current;
-        }
-        Err(i2c_error) => {
-            error!("I1 Err: {}", i2c_error);
-            current_slice[0] = f32::NAN;
-        }
-    }
-    match current_2 {
-        Ok(current) => {
-            current_slice[1] = current;
-        }
-        Err(i2c_error) => {
-            error!("I2 Err: {}", i2c_error);
-            current_slice[1] = f32::NAN;
-        }
-    }
-    match current_3 {
-        Ok(current) => {
-            current_slice[2] = current;
-        }
-        Err(i2c_error) => {
-            error!("I3 Err: {}", i2c_error);
-            current_slice[2] = f32::NAN;
-        }
-    }
-    match current_4 {
-        Ok(current) => {
-            current_slice[3] = current;
-        }
-        Err(i2c_error) => {
-            error!("I4 Err: {}", i2c_error);
-            current_slice[3] = f32::NAN;
-        }
-    }
-    match power_1 {
-        Ok(power) => {
-            power_slice[0] = power;
-        }
-        Err(i2c_error) => {
-            error!("P1 Err: {}", i2c_error);
-            current_slice[0] = f32::NAN;
-        }
-    }
-    match power_2 {
-        Ok(power) => {
-            power_slice[1] = power;
-        }
-        Err(i2c_error) => {
-            error!("P2 Err: {}", i2c_error);
-            power_slice[1] = f32::NAN;
-        }
+    for (i, guard) in guards.iter_mut().enumerate() {
+        voltage_slice[i] = match guard.sensor().voltage().await {
+            Ok(voltage) => voltage,
+            Err(i2c_error) => {
+                error!("V{} Err: {}", i + 1, i2c_error);
+                f32::NAN
+            }
+        };
+        v_ts_slice[i] = now_timestamp().millis();
     }
-    match power_3 {
-        Ok(power) => {
-            power_slice[2] = power;
-        }
-        Err(i2c_error) => {
-            error!("P3 Err: {}", i2c_error);
-            power_slice[2] = f32::NAN;
-        }
+
+    for (i, guard) in guards.iter_mut().enumerate() {
+        current_slice[i] = match guard.sensor().current().await {
+            Ok(current) => current,
+            Err(i2c_error) => {
+                error!("I{} Err: {}", i + 1, i2c_error);
+                f32::NAN
+            }
+        };
+        i_ts_slice[i] = now_timestamp().millis();
     }
-    match power_4 {
-        Ok(power) => {
-            power_slice[3] = power;
-        }
-        Err(i2c_error) => {
-            error!("P4 Err: {}", i2c_error);
-            power_slice[3] = f32::NAN;
-        }
+
+    for (i, guard) in guards.iter_mut().enumerate() {
+        power_slice[i] = match guard.sensor().power().await {
+            Ok(power) => power,
+            Err(i2c_error) => {
+                error!("P{} Err: {}", i + 1, i2c_error);
+                f32::NAN
+            }
+        };
+        p_ts_slice[i] = now_timestamp().millis();
     }
+
     (
         (v_ts_slice, i_ts_slice, p_ts_slice),
         (voltage_slice, current_slice, power_slice),
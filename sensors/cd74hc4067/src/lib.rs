@@ -2,6 +2,9 @@
 
 use embedded_hal::digital::OutputPin;
 
+mod scan;
+pub use scan::*;
+
 // Types/Constants
 pub enum Channel {
     Disable = 0b00001,
@@ -43,6 +46,18 @@ impl<S0: OutputPin, S1: OutputPin, S2: OutputPin, S3: OutputPin, E: OutputPin>
             enable,
         }
     }
+    /// Connect channel `index`, 0 to 15, to the common pin. Only the low four bits are used.
+    pub fn select(&mut self, index: u8) {
+        self.s0.set_state((index & 0b0001 != 0).into()).ok();
+        self.s1.set_state((index & 0b0010 != 0).into()).ok();
+        self.s2.set_state((index & 0b0100 != 0).into()).ok();
+        self.s3.set_state((index & 0b1000 != 0).into()).ok();
+        self.enable.set_low().ok();
+    }
+    /// Disconnect every channel
+    pub fn disable(&mut self) {
+        self.enable.set_high().ok();
+    }
     pub fn set_pin(&mut self, channel: &Channel) {
         // Set the pins according to the channel
         match channel {
@@ -159,13 +174,12 @@ impl<S0: OutputPin, S1: OutputPin, S2: OutputPin, S3: OutputPin, E: OutputPin>
                 self.enable.set_low().ok();
             }
             Channel::Channel15 => {
-                self.s0.set_low().ok();
+                self.s0.set_high().ok();
                 self.s1.set_high().ok();
                 self.s2.set_high().ok();
                 self.s3.set_high().ok();
                 self.enable.set_low().ok();
             }
-            _ => {}
         }
     }
     #[cfg(feature = "async")]
@@ -285,7 +299,7 @@ impl<S0: OutputPin, S1: OutputPin, S2: OutputPin, S3: OutputPin, E: OutputPin>
                 self.enable.set_low().ok();
             }
             Channel::Channel15 => {
-                self.s0.set_low().ok();
+                self.s0.set_high().ok();
                 self.s1.set_high().ok();
                 self.s2.set_high().ok();
                 self.s3.set_high().ok();
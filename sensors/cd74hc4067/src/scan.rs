@@ -0,0 +1,571 @@
+//! Walking a list of channels through an ADC
+//!
+//! Each channel gets its own settling time after it's selected, for the RC of whatever is behind
+//! it and the mux's on resistance into the ADC's sample capacitor, and is then sampled as many
+//! times as the scan asks and averaged.
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+
+use crate::CD74HC4067;
+
+/// A single-ended ADC input, sampled on demand
+pub trait AdcSample {
+    type Error: core::fmt::Debug;
+
+    /// Take one conversion
+    fn sample(&mut self) -> Result<u16, Self::Error>;
+}
+
+/// Something that connects one of several inputs to a common output
+pub trait MuxSelect {
+    /// How many channels [`MuxSelect::select`] takes
+    const CHANNELS: u8;
+
+    /// Connect `channel`, disconnecting whatever was before
+    fn select(&mut self, channel: u8);
+
+    /// Disconnect every channel
+    fn disable(&mut self);
+}
+
+impl<S0: OutputPin, S1: OutputPin, S2: OutputPin, S3: OutputPin, E: OutputPin> MuxSelect
+    for CD74HC4067<S0, S1, S2, S3, E>
+{
+    const CHANNELS: u8 = 16;
+
+    fn select(&mut self, channel: u8) {
+        CD74HC4067::select(self, channel)
+    }
+
+    fn disable(&mut self) {
+        CD74HC4067::disable(self)
+    }
+}
+
+/// `K` CD74HC4067s sharing select lines, each with its own enable, with their common pins tied
+/// to one ADC input. Channel `n` is channel `n % 16` on mux `n / 16`.
+pub struct Cascade<S0, S1, S2, S3, E, const K: usize> {
+    s0: S0,
+    s1: S1,
+    s2: S2,
+    s3: S3,
+    enables: [E; K], // When high, disables that mux's switches
+}
+
+impl<S0: OutputPin, S1: OutputPin, S2: OutputPin, S3: OutputPin, E: OutputPin, const K: usize>
+    Cascade<S0, S1, S2, S3, E, K>
+{
+    /// Everything starts disabled
+    pub fn new(s0: S0, s1: S1, s2: S2, s3: S3, mut enables: [E; K]) -> Self {
+        // Fails the build for more muxes than a channel number reaches
+        let _ = <Self as MuxSelect>::CHANNELS;
+        for enable in enables.iter_mut() {
+            enable.set_high().ok();
+        }
+        Cascade {
+            s0,
+            s1,
+            s2,
+            s3,
+            enables,
+        }
+    }
+}
+
+impl<S0: OutputPin, S1: OutputPin, S2: OutputPin, S3: OutputPin, E: OutputPin, const K: usize>
+    MuxSelect for Cascade<S0, S1, S2, S3, E, K>
+{
+    const CHANNELS: u8 = {
+        assert!(K <= 15, "a cascade numbers its channels in a u8, at most 15 muxes");
+        (16 * K) as u8
+    };
+
+    fn select(&mut self, channel: u8) {
+        let target = (channel / 16) as usize;
+        // Break before make, two muxes on at once short their inputs together
+        for (i, enable) in self.enables.iter_mut().enumerate() {
+            if i != target {
+                enable.set_high().ok();
+            }
+        }
+        self.s0.set_state((channel & 0b0001 != 0).into()).ok();
+        self.s1.set_state((channel & 0b0010 != 0).into()).ok();
+        self.s2.set_state((channel & 0b0100 != 0).into()).ok();
+        self.s3.set_state((channel & 0b1000 != 0).into()).ok();
+        if let Some(enable) = self.enables.get_mut(target) {
+            enable.set_low().ok();
+        }
+    }
+
+    fn disable(&mut self) {
+        for enable in self.enables.iter_mut() {
+            enable.set_high().ok();
+        }
+    }
+}
+
+/// One entry in a scan
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ScanChannel {
+    /// Channel on the mux
+    pub channel: u8,
+    /// Wait between selecting it and the first sample, in µs
+    pub settle_us: u32,
+}
+
+impl ScanChannel {
+    pub const fn new(channel: u8, settle_us: u32) -> Self {
+        ScanChannel { channel, settle_us }
+    }
+}
+
+/// Channels in the order they're read, and how each is sampled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ScanConfig<const N: usize> {
+    pub channels: [ScanChannel; N],
+    /// Samples averaged per channel, at least one is always taken
+    pub oversample: u16,
+    /// Wait between samples of the same channel, in µs
+    pub sample_interval_us: u32,
+}
+
+impl<const N: usize> ScanConfig<N> {
+    /// Read `channels`, one sample each
+    pub const fn new(channels: [ScanChannel; N]) -> Self {
+        ScanConfig {
+            channels,
+            oversample: 1,
+            sample_interval_us: 0,
+        }
+    }
+
+    /// Channels `first..first + N` in order, each settling for `settle_us`
+    pub const fn sequential(first: u8, settle_us: u32) -> Self {
+        let mut channels = [ScanChannel::new(0, settle_us); N];
+        let mut i = 0;
+        while i < N {
+            channels[i].channel = first + i as u8;
+            i += 1;
+        }
+        Self::new(channels)
+    }
+
+    /// Average `samples` conversions per channel, `interval_us` apart
+    pub const fn oversampled(mut self, samples: u16, interval_us: u32) -> Self {
+        self.oversample = samples;
+        self.sample_interval_us = interval_us;
+        self
+    }
+
+    fn samples(&self) -> u16 {
+        if self.oversample == 0 {
+            1
+        } else {
+            self.oversample
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ScanError<E> {
+    /// The ADC failed
+    Adc(E),
+    /// A channel past the end of the mux
+    InvalidChannel(u8),
+}
+
+/// A mux in front of an ADC input, read a scan at a time
+pub struct Scanner<M, A, D> {
+    mux: M,
+    adc: A,
+    delay: D,
+}
+
+impl<M: MuxSelect, A: AdcSample, D> Scanner<M, A, D> {
+    /// The mux is disabled until the first scan
+    pub fn new(mut mux: M, adc: A, delay: D) -> Self {
+        mux.disable();
+        Scanner { mux, adc, delay }
+    }
+
+    /// Give back the mux, ADC and delay
+    pub fn release(self) -> (M, A, D) {
+        (self.mux, self.adc, self.delay)
+    }
+
+    fn check<const N: usize>(config: &ScanConfig<N>) -> Result<(), ScanError<A::Error>> {
+        match config.channels.iter().find(|c| c.channel >= M::CHANNELS) {
+            Some(bad) => Err(ScanError::InvalidChannel(bad.channel)),
+            None => Ok(()),
+        }
+    }
+
+    fn average(sum: u32, samples: u16) -> u16 {
+        // Rounded. 65535 samples of 65535 still fit a u32.
+        ((sum + samples as u32 / 2) / samples as u32) as u16
+    }
+}
+
+impl<M: MuxSelect, A: AdcSample, D: DelayNs> Scanner<M, A, D> {
+    /// Read every channel in `config`, in order, giving each one's average. The mux is disabled
+    /// afterwards, error or not.
+    pub fn scan<const N: usize>(
+        &mut self,
+        config: &ScanConfig<N>,
+    ) -> Result<[u16; N], ScanError<A::Error>> {
+        Self::check(config)?;
+        let result = self.scan_inner(config);
+        self.mux.disable();
+        result
+    }
+
+    fn scan_inner<const N: usize>(
+        &mut self,
+        config: &ScanConfig<N>,
+    ) -> Result<[u16; N], ScanError<A::Error>> {
+        let samples = config.samples();
+        let mut out = [0; N];
+        for (entry, value) in config.channels.iter().zip(out.iter_mut()) {
+            self.mux.select(entry.channel);
+            self.delay.delay_us(entry.settle_us);
+
+            let mut sum = 0u32;
+            for i in 0..samples {
+                if i > 0 && config.sample_interval_us > 0 {
+                    self.delay.delay_us(config.sample_interval_us);
+                }
+                sum += self.adc.sample().map_err(ScanError::Adc)? as u32;
+            }
+            *value = Self::average(sum, samples);
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<M: MuxSelect, A: AdcSample, D: embedded_hal_async::delay::DelayNs> Scanner<M, A, D> {
+    /// [`Scanner::scan`], yielding through the settling and sample waits. Conversions themselves
+    /// are a few µs and aren't worth yielding for.
+    pub async fn scan_async<const N: usize>(
+        &mut self,
+        config: &ScanConfig<N>,
+    ) -> Result<[u16; N], ScanError<A::Error>> {
+        Self::check(config)?;
+        let result = self.scan_inner_async(config).await;
+        self.mux.disable();
+        result
+    }
+
+    async fn scan_inner_async<const N: usize>(
+        &mut self,
+        config: &ScanConfig<N>,
+    ) -> Result<[u16; N], ScanError<A::Error>> {
+        let samples = config.samples();
+        let mut out = [0; N];
+        for (entry, value) in config.channels.iter().zip(out.iter_mut()) {
+            self.mux.select(entry.channel);
+            self.delay.delay_us(entry.settle_us).await;
+
+            let mut sum = 0u32;
+            for i in 0..samples {
+                if i > 0 && config.sample_interval_us > 0 {
+                    self.delay.delay_us(config.sample_interval_us).await;
+                }
+                sum += self.adc.sample().map_err(ScanError::Adc)? as u32;
+            }
+            *value = Self::average(sum, samples);
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::RefCell;
+    use core::convert::Infallible;
+    use std::rc::Rc;
+    use std::vec::Vec;
+
+    use embedded_hal::digital::ErrorType;
+
+    use super::*;
+
+    // Everything the mux, delay and ADC did, in order
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Event {
+        Pin(&'static str, bool),
+        Delay(u32),
+        Sample(u16),
+    }
+
+    type Log = Rc<RefCell<Vec<Event>>>;
+
+    struct Pin(&'static str, Log);
+
+    impl ErrorType for Pin {
+        type Error = Infallible;
+    }
+
+    impl OutputPin for Pin {
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            self.1.borrow_mut().push(Event::Pin(self.0, false));
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            self.1.borrow_mut().push(Event::Pin(self.0, true));
+            Ok(())
+        }
+    }
+
+    struct Delay(Log);
+
+    impl DelayNs for Delay {
+        fn delay_ns(&mut self, ns: u32) {
+            self.0.borrow_mut().push(Event::Delay(ns / 1000));
+        }
+    }
+
+    // Reads back whichever channel the select lines and enables point at, as 100 * (channel + 1)
+    // plus the sample count on it so averaging shows
+    struct Adc {
+        log: Log,
+        reads: u16,
+        fail_at: Option<usize>,
+    }
+
+    impl Adc {
+        fn selected(&self) -> Option<u16> {
+            let mut state = std::collections::HashMap::new();
+            for event in self.log.borrow().iter() {
+                if let Event::Pin(name, high) = event {
+                    state.insert(*name, *high);
+                }
+            }
+            let bit = |name| state.get(name).copied().unwrap_or(false) as u16;
+            let low = bit("s0") | bit("s1") << 1 | bit("s2") << 2 | bit("s3") << 3;
+            let enabled: Vec<u16> = ["e0", "e1"]
+                .iter()
+                .enumerate()
+                .filter(|(_, e)| state.get(*e) == Some(&false))
+                .map(|(i, _)| i as u16)
+                .collect();
+            match enabled[..] {
+                [mux] => Some(mux * 16 + low),
+                _ => None,
+            }
+        }
+    }
+
+    impl AdcSample for Adc {
+        type Error = ();
+
+        fn sample(&mut self) -> Result<u16, ()> {
+            let count = self
+                .log
+                .borrow()
+                .iter()
+                .filter(|e| matches!(e, Event::Sample(_)))
+                .count();
+            if self.fail_at == Some(count) {
+                return Err(());
+            }
+            let channel = self.selected().expect("sampled with no single mux enabled");
+            let value = 100 * (channel + 1) + self.reads % 2;
+            self.reads += 1;
+            self.log.borrow_mut().push(Event::Sample(value));
+            Ok(value)
+        }
+    }
+
+    fn cascade(log: &Log) -> Cascade<Pin, Pin, Pin, Pin, Pin, 2> {
+        Cascade::new(
+            Pin("s0", log.clone()),
+            Pin("s1", log.clone()),
+            Pin("s2", log.clone()),
+            Pin("s3", log.clone()),
+            [Pin("e0", log.clone()), Pin("e1", log.clone())],
+        )
+    }
+
+    fn adc(log: &Log) -> Adc {
+        Adc {
+            log: log.clone(),
+            reads: 0,
+            fail_at: None,
+        }
+    }
+
+    fn timing(log: &Log) -> Vec<Event> {
+        log.borrow()
+            .iter()
+            .filter(|e| !matches!(e, Event::Pin(..)))
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn test_channel_order_and_settling() {
+        let log = Log::default();
+        let mut scanner = Scanner::new(cascade(&log), adc(&log), Delay(log.clone()));
+        let config = ScanConfig::new([
+            ScanChannel::new(17, 50),
+            ScanChannel::new(3, 10),
+            ScanChannel::new(15, 20),
+        ]);
+        log.borrow_mut().clear();
+
+        assert_eq!(scanner.scan(&config).unwrap(), [1800, 401, 1600]);
+        assert_eq!(
+            timing(&log),
+            [
+                Event::Delay(50),
+                Event::Sample(1800),
+                Event::Delay(10),
+                Event::Sample(401),
+                Event::Delay(20),
+                Event::Sample(1600),
+            ]
+        );
+
+        // Left with both off
+        assert_eq!(adc(&log).selected(), None);
+    }
+
+    #[test]
+    fn test_break_before_make() {
+        let log = Log::default();
+        let mut mux = cascade(&log);
+        mux.select(16 + 5);
+        log.borrow_mut().clear();
+
+        mux.select(2);
+        let events = log.borrow().clone();
+        let e1_off = events.iter().position(|e| *e == Event::Pin("e1", true));
+        let e0_on = events.iter().position(|e| *e == Event::Pin("e0", false));
+        assert!(e1_off.unwrap() < e0_on.unwrap(), "{events:?}");
+    }
+
+    #[test]
+    fn test_oversampling() {
+        let log = Log::default();
+        let mut scanner = Scanner::new(cascade(&log), adc(&log), Delay(log.clone()));
+        let config = ScanConfig::<2>::sequential(0, 30).oversampled(4, 5);
+        log.borrow_mut().clear();
+
+        // Alternating +0/+1 over four samples rounds half up
+        assert_eq!(scanner.scan(&config).unwrap(), [101, 201]);
+        assert_eq!(
+            timing(&log),
+            [
+                Event::Delay(30),
+                Event::Sample(100),
+                Event::Delay(5),
+                Event::Sample(101),
+                Event::Delay(5),
+                Event::Sample(100),
+                Event::Delay(5),
+                Event::Sample(101),
+                Event::Delay(30),
+                Event::Sample(200),
+                Event::Delay(5),
+                Event::Sample(201),
+                Event::Delay(5),
+                Event::Sample(200),
+                Event::Delay(5),
+                Event::Sample(201),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_errors_leave_mux_disabled() {
+        let log = Log::default();
+        let mut scanner = Scanner::new(cascade(&log), adc(&log), Delay(log.clone()));
+
+        assert!(matches!(
+            scanner.scan(&ScanConfig::new([ScanChannel::new(32, 0)])),
+            Err(ScanError::InvalidChannel(32))
+        ));
+        assert_eq!(
+            log.borrow()
+                .iter()
+                .filter(|e| matches!(e, Event::Sample(_)))
+                .count(),
+            0
+        );
+
+        let (mux, mut failing, delay) = scanner.release();
+        failing.fail_at = Some(1);
+        let mut scanner = Scanner::new(mux, failing, delay);
+        assert!(matches!(
+            scanner.scan(&ScanConfig::<3>::sequential(0, 0)),
+            Err(ScanError::Adc(()))
+        ));
+        assert_eq!(adc(&log).selected(), None);
+    }
+
+    #[test]
+    fn test_single_mux() {
+        let log = Log::default();
+        let mux = CD74HC4067::new_enable(
+            Pin("s0", log.clone()),
+            Pin("s1", log.clone()),
+            Pin("s2", log.clone()),
+            Pin("s3", log.clone()),
+            Pin("e0", log.clone()),
+        );
+        let mut scanner = Scanner::new(mux, adc(&log), Delay(log.clone()));
+
+        assert_eq!(
+            scanner.scan(&ScanConfig::<16>::sequential(0, 1)).unwrap(),
+            core::array::from_fn(|i| 100 * (i as u16 + 1) + i as u16 % 2)
+        );
+        assert!(matches!(
+            scanner.scan(&ScanConfig::new([ScanChannel::new(16, 0)])),
+            Err(ScanError::InvalidChannel(16))
+        ));
+    }
+
+    #[cfg(feature = "async")]
+    impl embedded_hal_async::delay::DelayNs for Delay {
+        async fn delay_ns(&mut self, ns: u32) {
+            self.0.borrow_mut().push(Event::Delay(ns / 1000));
+        }
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_scan_async() {
+        use core::pin::pin;
+        use core::task::{Context, Poll, Waker};
+
+        let log = Log::default();
+        let mut scanner = Scanner::new(cascade(&log), adc(&log), Delay(log.clone()));
+        let config = ScanConfig::new([ScanChannel::new(20, 40), ScanChannel::new(1, 15)]);
+        log.borrow_mut().clear();
+
+        // The mock delay never pends, so one poll finishes the scan
+        let mut scan = pin!(scanner.scan_async(&config));
+        let Poll::Ready(result) = scan.as_mut().poll(&mut Context::from_waker(Waker::noop()))
+        else {
+            panic!("mock delay pended");
+        };
+        assert_eq!(result.unwrap(), [2100, 201]);
+        assert_eq!(
+            timing(&log),
+            [
+                Event::Delay(40),
+                Event::Sample(2100),
+                Event::Delay(15),
+                Event::Sample(201),
+            ]
+        );
+    }
+}
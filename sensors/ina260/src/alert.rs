@@ -0,0 +1,189 @@
+use crate::{MaskEnable, Register};
+
+/// What the ALERT pin watches, with the threshold in engineering units
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AlertLimit {
+    /// Current above the limit
+    OverCurrent { milliamps: f32 },
+    /// Current below the limit
+    UnderCurrent { milliamps: f32 },
+    /// Bus voltage above the limit
+    OverVoltage { millivolts: f32 },
+    /// Bus voltage below the limit
+    UnderVoltage { millivolts: f32 },
+    /// Power above the limit
+    OverPower { milliwatts: f32 },
+}
+
+impl AlertLimit {
+    /// The Mask/Enable function bit for this limit
+    #[inline(always)]
+    pub fn function(&self) -> MaskEnable {
+        match self {
+            AlertLimit::OverCurrent { .. } => MaskEnable::OCL,
+            AlertLimit::UnderCurrent { .. } => MaskEnable::UCL,
+            AlertLimit::OverVoltage { .. } => MaskEnable::BOL,
+            AlertLimit::UnderVoltage { .. } => MaskEnable::BUL,
+            AlertLimit::OverPower { .. } => MaskEnable::POL,
+        }
+    }
+
+    /// The threshold, in mA, mV or mW
+    #[inline(always)]
+    pub fn threshold(&self) -> f32 {
+        match *self {
+            AlertLimit::OverCurrent { milliamps } | AlertLimit::UnderCurrent { milliamps } => {
+                milliamps
+            }
+            AlertLimit::OverVoltage { millivolts } | AlertLimit::UnderVoltage { millivolts } => {
+                millivolts
+            }
+            AlertLimit::OverPower { milliwatts } => milliwatts,
+        }
+    }
+
+    /// The register the limit is compared against, which is the one to read back on a trip
+    #[inline(always)]
+    pub fn register(&self) -> Register {
+        match self {
+            AlertLimit::OverCurrent { .. } | AlertLimit::UnderCurrent { .. } => Register::CURRENT,
+            AlertLimit::OverVoltage { .. } | AlertLimit::UnderVoltage { .. } => Register::VOLTAGE,
+            AlertLimit::OverPower { .. } => Register::POWER,
+        }
+    }
+
+    /// The Alert Limit register value, in the format of the register it's compared against.
+    /// Out of range thresholds saturate.
+    pub fn limit_bits(&self) -> u16 {
+        match *self {
+            // Current is signed, 1.25mA per bit
+            AlertLimit::OverCurrent { milliamps } | AlertLimit::UnderCurrent { milliamps } => {
+                (milliamps / 1.25) as i16 as u16
+            }
+            // Voltage is unsigned, 1.25mV per bit
+            AlertLimit::OverVoltage { millivolts } | AlertLimit::UnderVoltage { millivolts } => {
+                (millivolts / 1.25) as u16
+            }
+            // Power is unsigned, 10mW per bit
+            AlertLimit::OverPower { milliwatts } => (milliwatts / 10.0) as u16,
+        }
+    }
+
+    /// A raw reading of [`AlertLimit::register`] in the threshold's units
+    pub fn measured(&self, raw: u16) -> f32 {
+        match self {
+            AlertLimit::OverCurrent { .. } | AlertLimit::UnderCurrent { .. } => {
+                crate::current_from_raw(raw as i16) * 1000.0
+            }
+            AlertLimit::OverVoltage { .. } | AlertLimit::UnderVoltage { .. } => {
+                crate::voltage_from_raw(raw) * 1000.0
+            }
+            AlertLimit::OverPower { .. } => crate::power_from_raw(raw) * 1000.0,
+        }
+    }
+}
+
+/// ALERT pin configuration: one limit, and how the pin behaves when it's crossed
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AlertConfig {
+    pub limit: AlertLimit,
+    /// Hold the pin and flag until the Mask/Enable register is read, rather than clearing them on
+    /// the next conversion in range
+    pub latch: bool,
+    /// Drive the pin high on an alert instead of pulling it low
+    pub active_high: bool,
+}
+
+impl AlertConfig {
+    /// Alert on `limit`, transparent and active low like the part comes up
+    pub const fn new(limit: AlertLimit) -> Self {
+        AlertConfig {
+            limit,
+            latch: false,
+            active_high: false,
+        }
+    }
+
+    /// Latch alerts until they're read
+    pub const fn latching(mut self) -> Self {
+        self.latch = true;
+        self
+    }
+
+    /// Active high ALERT pin
+    pub const fn active_high(mut self) -> Self {
+        self.active_high = true;
+        self
+    }
+
+    /// The Mask/Enable register value
+    pub fn mask_bits(&self) -> u16 {
+        let mut bits = self.limit.function().bits();
+        if self.latch {
+            bits |= MaskEnable::LEN.bits();
+        }
+        if self.active_high {
+            bits |= MaskEnable::APOL.bits();
+        }
+        bits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_bits() {
+        assert_eq!(
+            AlertLimit::OverCurrent { milliamps: 2000.0 }.limit_bits(),
+            1600
+        );
+        assert_eq!(
+            AlertLimit::UnderCurrent { milliamps: -10.0 }.limit_bits(),
+            0xFFF8
+        );
+        assert_eq!(
+            AlertLimit::UnderVoltage { millivolts: 6000.0 }.limit_bits(),
+            4800
+        );
+        assert_eq!(
+            AlertLimit::OverPower { milliwatts: 5000.0 }.limit_bits(),
+            500
+        );
+
+        // Past what the registers hold
+        assert_eq!(
+            AlertLimit::OverCurrent { milliamps: 1e6 }.limit_bits(),
+            0x7FFF
+        );
+        assert_eq!(AlertLimit::OverVoltage { millivolts: -5.0 }.limit_bits(), 0);
+    }
+
+    #[test]
+    fn test_mask_bits() {
+        let limit = AlertLimit::OverCurrent { milliamps: 2000.0 };
+        assert_eq!(AlertConfig::new(limit).mask_bits(), 0x8000);
+        assert_eq!(AlertConfig::new(limit).latching().mask_bits(), 0x8001);
+        assert_eq!(
+            AlertConfig::new(AlertLimit::UnderVoltage { millivolts: 6000.0 })
+                .latching()
+                .active_high()
+                .mask_bits(),
+            0x1003
+        );
+    }
+
+    #[test]
+    fn test_measured_round_trip() {
+        let limit = AlertLimit::OverCurrent { milliamps: 0.0 };
+        assert_eq!(limit.measured(1600), 2000.0);
+        assert_eq!(limit.measured(0xFFF8), -10.0);
+        assert_eq!(
+            AlertLimit::OverPower { milliwatts: 0.0 }.measured(500),
+            5000.0
+        );
+    }
+}
@@ -0,0 +1,296 @@
+// TI INA260 Current Sensor
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::i2c::I2c as AsyncI2c;
+
+use crate::{
+    AlertConfig, AlertLimit, Averaging, BVConvTime, DieId, MaskEnable, OperMode, Register,
+    SCConvTime, Status, current_from_raw, power_from_raw, voltage_from_raw,
+};
+
+pub struct AsyncINA260<I2C, Delay> {
+    i2c: I2C,
+    pub address: u8,
+    _marker: core::marker::PhantomData<I2C>,
+    state: u16,
+    delay: Delay,
+}
+
+impl<I2C: AsyncI2c, D> AsyncINA260<I2C, D>
+where
+    I2C: AsyncI2c,
+    D: DelayNs,
+{
+    /// Create a new INA260 instance
+    ///
+    /// # Arguments
+    ///
+    /// * `i2c` - The I2C peripheral to use
+    /// * `address` - The I2C address of the INA260
+    pub fn new(i2c: I2C, address: u8, delay: D) -> Self {
+        AsyncINA260 {
+            i2c,
+            address,
+            delay,
+            _marker: core::marker::PhantomData,
+            state: OperMode::SCBVC.bits()
+                | Averaging::AVG64.bits()
+                | SCConvTime::MS8_244.bits()
+                | BVConvTime::MS8_244.bits(),
+        }
+    }
+
+    pub async fn init(&mut self) -> Result<(), I2C::Error> {
+        self.write_register(Register::CONFIG, 0x8000).await?;
+        // Let the reset finish before anything else is written
+        self.delay.delay_ms(1).await;
+        Ok(())
+    }
+
+    /// Give back the bus
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    async fn write_register(&mut self, register: Register, data: u16) -> Result<(), I2C::Error> {
+        self.i2c
+            .write(
+                self.address,
+                &[register.addr(), (data >> 8) as u8, (data & 255) as u8],
+            )
+            .await
+    }
+
+    async fn read_reg(&mut self, reg: Register) -> Result<[u8; 2], I2C::Error> {
+        let mut buf = [0; 2];
+        self.i2c
+            .write_read(self.address, &[reg.addr()], &mut buf)
+            .await?;
+        Ok(buf)
+    }
+
+    /// Read a 16 bit unsigned integer from a register
+    async fn read_reg_u16(&mut self, reg: Register) -> Result<u16, I2C::Error> {
+        let buf = self.read_reg(reg).await?;
+        Ok(u16::from_be_bytes(buf))
+    }
+
+    /// Read a 16-bit signed integer from a register
+    async fn read_reg_i16(&mut self, reg: Register) -> Result<i16, I2C::Error> {
+        let buf = self.read_reg(reg).await?;
+        Ok(i16::from_be_bytes(buf))
+    }
+
+    /// Change the Mask/Enable mode of the INA260
+    ///
+    /// The Mask/Enable Register selects the function that is enabled to control the ALERT pin as well as how that pin
+    /// functions. If multiple functions are enabled, the highest significant bit position Alert Function (D15-D11) takes
+    /// priority and responds to the Alert Limit Register.
+    #[inline(always)]
+    pub async fn set_mask_enable(&mut self, m: MaskEnable) -> Result<(), I2C::Error> {
+        self.write_register(Register::MASK_ENABLE, m.bits()).await
+    }
+
+    /// Set the alert limit of the INA260
+    ///
+    /// The Alert Limit Register contains the value used to compare to the register selected in the Mask/Enable Register
+    /// to determine if a limit has been exceeded. The format for this register will match the format of the register that is
+    /// selected for comparison.
+    #[inline(always)]
+    pub async fn set_alert_limit(&mut self, limit: u16) -> Result<(), I2C::Error> {
+        self.write_register(Register::ALERT_LIMIT, limit).await
+    }
+
+    /// Set up the ALERT pin: the limit, then the function watching it
+    pub async fn configure_alert(&mut self, config: AlertConfig) -> Result<(), I2C::Error> {
+        self.set_alert_limit(config.limit.limit_bits()).await?;
+        self.write_register(Register::MASK_ENABLE, config.mask_bits())
+            .await
+    }
+
+    /// Stop the ALERT pin asserting on anything
+    pub async fn disable_alert(&mut self) -> Result<(), I2C::Error> {
+        self.write_register(Register::MASK_ENABLE, 0).await
+    }
+
+    /// Read whatever `limit` is watching, in its units
+    pub async fn measure(&mut self, limit: AlertLimit) -> Result<f32, I2C::Error> {
+        Ok(limit.measured(self.read_reg_u16(limit.register()).await?))
+    }
+
+    /// Change the averaging mode of the INA260
+    #[inline(always)]
+    pub async fn set_averaging_mode(&mut self, a: Averaging) -> Result<(), I2C::Error> {
+        let bits = a.bits();
+        let state = (self.state & !Averaging::AVG1024.bits()) | bits;
+        let result = self.write_register(Register::CONFIG, state).await;
+        self.state = state;
+        result
+    }
+
+    /// Change the operating mode of the INA260. Please note that if you change to Triggered mode,
+    /// you'll have to call this method again each time you would like to get a new sample.
+    #[inline(always)]
+    pub async fn set_operating_mode(&mut self, o: OperMode) -> Result<(), I2C::Error> {
+        let bits = o.bits();
+        let state = (self.state & !OperMode::SCBVC.bits()) | bits;
+        let result = self.write_register(Register::CONFIG, state).await;
+        self.state = state;
+        result
+    }
+
+    /// Change the shut current conversion time
+    #[inline(always)]
+    pub async fn set_scconvtime_mode(&mut self, s: SCConvTime) -> Result<(), I2C::Error> {
+        let bits = s.bits();
+        let state = (self.state & !SCConvTime::MS8_244.bits()) | bits;
+        let result = self.write_register(Register::CONFIG, state).await;
+        self.state = state;
+        result
+    }
+
+    /// Change the bus voltage conversion time
+    #[inline(always)]
+    pub async fn set_bvconvtime_mode(&mut self, b: BVConvTime) -> Result<(), I2C::Error> {
+        let bits = b.bits();
+        let state = (self.state & !BVConvTime::MS8_244.bits()) | bits;
+        let result = self.write_register(Register::CONFIG, state).await;
+        self.state = state;
+        result
+    }
+
+    /// Delivers the manufacturer id, 0x5449 ("TI") on a genuine part
+    #[inline(always)]
+    pub async fn manufacturer_id(&mut self) -> Result<u16, I2C::Error> {
+        self.read_reg_u16(Register::MANUFACTURER_ID).await
+    }
+
+    /// Delivers the device id and die revision
+    #[inline(always)]
+    pub async fn die_id(&mut self) -> Result<DieId, I2C::Error> {
+        Ok(DieId::from_bits(self.read_reg_u16(Register::DIE_ID).await?))
+    }
+
+    /// Delivers the conversion ready, overflow and alert flags. Reading clears conversion ready,
+    /// and a latched alert.
+    #[inline(always)]
+    pub async fn status(&mut self) -> Result<Status, I2C::Error> {
+        Ok(Status::from_bits(
+            self.read_reg_u16(Register::MASK_ENABLE).await?,
+        ))
+    }
+
+    /// Delivers the measured raw current in 1.25mA per bit
+    #[inline(always)]
+    async fn current_raw(&mut self) -> Result<i16, I2C::Error> {
+        self.read_reg_i16(Register::CURRENT).await
+    }
+
+    /// Delivers the measured current in A
+    #[inline(always)]
+    pub async fn current(&mut self) -> Result<f32, I2C::Error> {
+        Ok(current_from_raw(self.current_raw().await?))
+    }
+
+    /// Delivers the measured raw voltage in 1.25mV per bit
+    #[inline(always)]
+    async fn voltage_raw(&mut self) -> Result<u16, I2C::Error> {
+        self.read_reg_u16(Register::VOLTAGE).await
+    }
+
+    /// Delivers the measured current in V
+    #[inline(always)]
+    pub async fn voltage(&mut self) -> Result<f32, I2C::Error> {
+        Ok(voltage_from_raw(self.voltage_raw().await?))
+    }
+
+    /// Delivers the measured raw power in 10mW per bit
+    #[inline(always)]
+    async fn power_raw(&mut self) -> Result<u16, I2C::Error> {
+        self.read_reg_u16(Register::POWER).await
+    }
+
+    /// Delivers the measured power in Watts
+    #[inline(always)]
+    pub async fn power(&mut self) -> Result<f32, I2C::Error> {
+        Ok(power_from_raw(self.power_raw().await?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::pin::pin;
+    use core::task::{Context, Poll, Waker};
+    use std::vec;
+
+    use embedded_hal_mock::eh1::delay::NoopDelay;
+    use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+
+    use super::*;
+
+    const ADDRESS: u8 = 0x40;
+
+    // The mock never pends, so one poll finishes anything
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        match future
+            .as_mut()
+            .poll(&mut Context::from_waker(Waker::noop()))
+        {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("mock bus pended"),
+        }
+    }
+
+    #[test]
+    fn test_measurements() {
+        let i2c = Mock::new(&[
+            Transaction::write_read(ADDRESS, vec![0x01], vec![0xFF, 0x38]),
+            Transaction::write_read(ADDRESS, vec![0x02], vec![0x25, 0x80]),
+            Transaction::write_read(ADDRESS, vec![0x03], vec![0x01, 0x2C]),
+        ]);
+        let mut ina = AsyncINA260::new(i2c, ADDRESS, NoopDelay::new());
+
+        assert_eq!(block_on(ina.current()).unwrap(), -0.25);
+        assert_eq!(block_on(ina.voltage()).unwrap(), 12.0);
+        assert_eq!(block_on(ina.power()).unwrap(), 3.0);
+        ina.release().done();
+    }
+
+    #[test]
+    fn test_configuration_and_ids() {
+        let i2c = Mock::new(&[
+            Transaction::write(ADDRESS, vec![0x00, 0x80, 0x00]),
+            Transaction::write(ADDRESS, vec![0x00, 0x01, 0xFF]),
+            Transaction::write(ADDRESS, vec![0x06, 0x80, 0x00]),
+            Transaction::write(ADDRESS, vec![0x07, 0x03, 0x20]),
+            Transaction::write_read(ADDRESS, vec![0xFE], vec![0x54, 0x49]),
+            Transaction::write_read(ADDRESS, vec![0xFF], vec![0x22, 0x70]),
+            Transaction::write_read(ADDRESS, vec![0x06], vec![0x80, 0x1C]),
+        ]);
+        let mut ina = AsyncINA260::new(i2c, ADDRESS, NoopDelay::new());
+
+        block_on(ina.init()).unwrap();
+        block_on(ina.set_averaging_mode(Averaging::AVG1)).unwrap();
+        block_on(ina.set_mask_enable(MaskEnable::OCL)).unwrap();
+        block_on(ina.set_alert_limit(800)).unwrap();
+        assert_eq!(block_on(ina.manufacturer_id()).unwrap(), 0x5449);
+        assert_eq!(
+            block_on(ina.die_id()).unwrap(),
+            DieId {
+                device: 0x227,
+                revision: 0
+            }
+        );
+        assert_eq!(
+            block_on(ina.status()).unwrap(),
+            Status {
+                alert: true,
+                conversion_ready: true,
+                overflow: true
+            }
+        );
+        ina.release().done();
+    }
+}
@@ -0,0 +1,260 @@
+//! Switching a load off when its INA260 alerts
+//!
+//! ALERT is open collector, so several INA260s can share one interrupt line. On an edge every
+//! guard on the line gets [`LoadGuard::on_alert`], and the ones whose flag is set trip. A guard
+//! without a switch only reports.
+
+use embedded_hal::digital::OutputPin;
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::i2c::I2c as AsyncI2c;
+
+use crate::{AlertConfig, AlertLimit, AsyncINA260};
+
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum GuardError<E, PE> {
+    /// I²C bus error
+    Bus(E),
+    /// The load switch pin failed
+    Load(PE),
+}
+
+/// An alert on a guarded load, which switched it off if the guard has a switch
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Trip {
+    /// The limit that was crossed
+    pub limit: AlertLimit,
+    /// What the INA260 read once the load was off, in the limit's units. What it tripped on is
+    /// gone by then; this is here to show whether the fault went with the load. Without a switch
+    /// it's just the reading after the alert.
+    pub measured: f32,
+}
+
+/// An INA260 watching the supply to one load, with the switch for that load if there is one
+pub struct LoadGuard<I2C, D, P> {
+    ina: AsyncINA260<I2C, D>,
+    load: Option<P>,
+    config: AlertConfig,
+    tripped: Option<Trip>,
+}
+
+impl<I2C, D, P> LoadGuard<I2C, D, P>
+where
+    I2C: AsyncI2c,
+    D: DelayNs,
+    P: OutputPin,
+{
+    /// Guard `load`, which is on while its pin is high. The pin is left as it is until
+    /// [`LoadGuard::arm`] succeeds.
+    pub fn new(ina: AsyncINA260<I2C, D>, load: P, config: AlertConfig) -> Self {
+        LoadGuard {
+            ina,
+            load: Some(load),
+            config,
+            tripped: None,
+        }
+    }
+
+    /// Watch a load there's no switch for. Alerts are reported the same, nothing is switched.
+    pub fn monitor(ina: AsyncINA260<I2C, D>, config: AlertConfig) -> Self {
+        LoadGuard {
+            ina,
+            load: None,
+            config,
+            tripped: None,
+        }
+    }
+
+    /// Program the alert and switch the load on. Also how a tripped guard is reset.
+    ///
+    /// The load is only touched once the INA260 has taken the alert, so if this fails the load
+    /// stays as it was.
+    pub async fn arm(&mut self) -> Result<(), GuardError<I2C::Error, P::Error>> {
+        self.ina
+            .configure_alert(self.config)
+            .await
+            .map_err(GuardError::Bus)?;
+        // Clear anything latched from before
+        self.ina.status().await.map_err(GuardError::Bus)?;
+        if let Some(load) = &mut self.load {
+            load.set_high().map_err(GuardError::Load)?;
+        }
+        self.tripped = None;
+        Ok(())
+    }
+
+    /// Check for an alert on this channel, switching the load off if there's one. Returns the trip
+    /// when this call is the one that tripped it.
+    pub async fn on_alert(&mut self) -> Result<Option<Trip>, GuardError<I2C::Error, P::Error>> {
+        if !self.ina.status().await.map_err(GuardError::Bus)?.alert {
+            return Ok(None);
+        }
+
+        // Off first, everything else can wait
+        if let Some(load) = &mut self.load {
+            load.set_low().map_err(GuardError::Load)?;
+        }
+
+        let measured = self
+            .ina
+            .measure(self.config.limit)
+            .await
+            .map_err(GuardError::Bus)?;
+        let trip = Trip {
+            limit: self.config.limit,
+            measured,
+        };
+        let first = self.tripped.is_none();
+        self.tripped = Some(trip);
+        Ok(first.then_some(trip))
+    }
+
+    /// The trip that switched the load off, if it's off. Without a switch, the last alert since
+    /// [`LoadGuard::arm`].
+    pub fn tripped(&self) -> Option<Trip> {
+        self.tripped
+    }
+
+    /// Whether there's a switch to turn the load off with
+    pub fn switched(&self) -> bool {
+        self.load.is_some()
+    }
+
+    /// The INA260, for sampling between alerts
+    pub fn sensor(&mut self) -> &mut AsyncINA260<I2C, D> {
+        &mut self.ina
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::pin::pin;
+    use core::task::{Context, Poll, Waker};
+    use std::vec;
+
+    use embedded_hal_mock::eh1::delay::NoopDelay;
+    use embedded_hal_mock::eh1::digital::{Mock as PinMock, State, Transaction as PinTransaction};
+    use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+
+    use super::*;
+
+    const ADDRESS: u8 = 0x44;
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        match future
+            .as_mut()
+            .poll(&mut Context::from_waker(Waker::noop()))
+        {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("mock bus pended"),
+        }
+    }
+
+    fn config() -> AlertConfig {
+        AlertConfig::new(AlertLimit::OverCurrent { milliamps: 2000.0 }).latching()
+    }
+
+    #[test]
+    fn test_trips_once_on_its_own_alert() {
+        let i2c = Mock::new(&[
+            // Arm
+            Transaction::write(ADDRESS, vec![0x07, 0x06, 0x40]),
+            Transaction::write(ADDRESS, vec![0x06, 0x80, 0x01]),
+            Transaction::write_read(ADDRESS, vec![0x06], vec![0x80, 0x01]),
+            // Another channel's alert, nothing latched here
+            Transaction::write_read(ADDRESS, vec![0x06], vec![0x80, 0x09]),
+            // Ours, 2.5A tripped it and 10mA is left with the load off
+            Transaction::write_read(ADDRESS, vec![0x06], vec![0x80, 0x19]),
+            Transaction::write_read(ADDRESS, vec![0x01], vec![0x00, 0x08]),
+            // Still latched on the next edge, but it's already off
+            Transaction::write_read(ADDRESS, vec![0x06], vec![0x80, 0x11]),
+            Transaction::write_read(ADDRESS, vec![0x01], vec![0x00, 0x00]),
+        ]);
+        let load = PinMock::new(&[
+            PinTransaction::set(State::High),
+            PinTransaction::set(State::Low),
+            PinTransaction::set(State::Low),
+        ]);
+        let ina = AsyncINA260::new(i2c, ADDRESS, NoopDelay::new());
+        let mut guard = LoadGuard::new(ina, load, config());
+
+        block_on(guard.arm()).unwrap();
+        assert_eq!(block_on(guard.on_alert()).unwrap(), None);
+
+        let trip = block_on(guard.on_alert()).unwrap().unwrap();
+        assert_eq!(trip.limit, AlertLimit::OverCurrent { milliamps: 2000.0 });
+        assert_eq!(trip.measured, 10.0);
+        assert_eq!(guard.tripped(), Some(trip));
+
+        assert_eq!(block_on(guard.on_alert()).unwrap(), None);
+        assert!(guard.tripped().is_some());
+
+        let LoadGuard { ina, load, .. } = guard;
+        ina.release().done();
+        load.unwrap().done();
+    }
+
+    #[test]
+    fn test_bus_error_leaves_load_alone() {
+        let i2c = Mock::new(&[
+            Transaction::write_read(ADDRESS, vec![0x06], vec![0x00, 0x00])
+                .with_error(embedded_hal::i2c::ErrorKind::Other),
+        ]);
+        let load = PinMock::new(&[]);
+        let ina = AsyncINA260::new(i2c, ADDRESS, NoopDelay::new());
+        let mut guard = LoadGuard::new(ina, load, config());
+
+        assert!(matches!(
+            block_on(guard.on_alert()),
+            Err(GuardError::Bus(_))
+        ));
+        assert_eq!(guard.tripped(), None);
+
+        let LoadGuard { ina, load, .. } = guard;
+        ina.release().done();
+        load.unwrap().done();
+    }
+
+    #[test]
+    fn test_failed_arm_leaves_load_alone() {
+        let i2c = Mock::new(&[
+            Transaction::write(ADDRESS, vec![0x07, 0x06, 0x40]),
+            Transaction::write(ADDRESS, vec![0x06, 0x80, 0x01])
+                .with_error(embedded_hal::i2c::ErrorKind::Other),
+        ]);
+        let load = PinMock::new(&[]);
+        let ina = AsyncINA260::new(i2c, ADDRESS, NoopDelay::new());
+        let mut guard = LoadGuard::new(ina, load, config());
+
+        assert!(matches!(block_on(guard.arm()), Err(GuardError::Bus(_))));
+
+        let LoadGuard { ina, load, .. } = guard;
+        ina.release().done();
+        load.unwrap().done();
+    }
+
+    #[test]
+    fn test_monitor_reports_without_switching() {
+        let i2c = Mock::new(&[
+            Transaction::write(ADDRESS, vec![0x07, 0x06, 0x40]),
+            Transaction::write(ADDRESS, vec![0x06, 0x80, 0x01]),
+            Transaction::write_read(ADDRESS, vec![0x06], vec![0x80, 0x01]),
+            Transaction::write_read(ADDRESS, vec![0x06], vec![0x80, 0x19]),
+            Transaction::write_read(ADDRESS, vec![0x01], vec![0x08, 0x00]),
+        ]);
+        let ina = AsyncINA260::new(i2c, ADDRESS, NoopDelay::new());
+        let mut guard: LoadGuard<_, _, PinMock> = LoadGuard::monitor(ina, config());
+        assert!(!guard.switched());
+
+        block_on(guard.arm()).unwrap();
+        let trip = block_on(guard.on_alert()).unwrap().unwrap();
+        assert_eq!(trip.measured, 2560.0);
+        assert_eq!(guard.tripped(), Some(trip));
+
+        guard.ina.release().done();
+    }
+}
@@ -1,216 +1,83 @@
 #![no_std]
 
 // TI INA260 Current Sensor
+#[cfg(feature = "sync")]
+pub mod sync;
+#[cfg(feature = "sync")]
+pub use sync::*;
 #[cfg(feature = "async")]
-use embedded_hal_async::delay::DelayNs;
+pub mod r#async;
 #[cfg(feature = "async")]
-use embedded_hal_async::i2c::I2c as AsyncI2c;
+pub use r#async::*;
 
+mod alert;
+pub use alert::*;
 #[cfg(feature = "async")]
-pub struct AsyncINA260<I2C, Delay> {
-    i2c: I2C,
-    pub address: u8,
-    _marker: core::marker::PhantomData<I2C>,
-    state: u16,
-    delay: Delay,
-}
-
-impl<I2C: AsyncI2c, D> AsyncINA260<I2C, D>
-where
-    I2C: AsyncI2c,
-    D: DelayNs,
-{
-    /// Create a new INA260 instance
-    ///
-    /// # Arguments
-    ///
-    /// * `i2c` - The I2C peripheral to use
-    /// * `address` - The I2C address of the INA260
-    pub fn new(i2c: I2C, address: u8, delay: D) -> Self {
-        AsyncINA260 {
-            i2c,
-            address,
-            delay,
-            _marker: core::marker::PhantomData,
-            state: OperMode::SCBVC.bits()
-                | Averaging::AVG64.bits()
-                | SCConvTime::MS8_244.bits()
-                | BVConvTime::MS8_244.bits(),
-        }
-    }
-
-    pub async fn init(&mut self) -> Result<(), I2C::Error> {
-        self.write_register(Register::CONFIG, 0x8000).await
-    }
-
-    async fn write_register(&mut self, register: Register, data: u16) -> Result<(), I2C::Error> {
-        self.i2c
-            .write(
-                self.address,
-                &[register.addr(), (data >> 8) as u8, (data & 255) as u8],
-            )
-            .await
-    }
-
-    async fn read_reg(&mut self, reg: Register) -> Result<[u8; 2], I2C::Error> {
-        let mut buf = [0; 2];
-        self.i2c
-            .write_read(self.address, &[reg.addr()], &mut buf)
-            .await?;
-        Ok(buf)
-    }
-
-    /// Read a 16 bit unsigned integer from a register
-    async fn read_reg_u16(&mut self, reg: Register) -> Result<u16, I2C::Error> {
-        let buf = self.read_reg(reg).await?;
-        Ok(u16::from_be_bytes(buf))
-    }
-
-    /// Read a 16-bit signed integer from a register
-    async fn read_reg_i16(&mut self, reg: Register) -> Result<i16, I2C::Error> {
-        let buf = self.read_reg(reg).await?;
-        Ok(i16::from_be_bytes(buf))
-    }
-
-    /// Change the Mask/Enable mode of the INA260
-    ///
-    /// The Mask/Enable Register selects the function that is enabled to control the ALERT pin as well as how that pin
-    /// functions. If multiple functions are enabled, the highest significant bit position Alert Function (D15-D11) takes
-    /// priority and responds to the Alert Limit Register.
-    #[inline(always)]
-    pub async fn set_mask_enable(&mut self, m: MaskEnable) -> Result<(), I2C::Error> {
-        self.write_register(Register::MASK_ENABLE, m.bits()).await
-    }
-
-    /// Set the alert limit of the INA260
-    ///
-    /// The Alert Limit Register contains the value used to compare to the register selected in the Mask/Enable Register
-    /// to determine if a limit has been exceeded. The format for this register will match the format of the register that is
-    /// selected for comparison.
-    #[inline(always)]
-    pub async fn set_alert_limit(&mut self, limit: u16) -> Result<(), I2C::Error> {
-        self.write_register(Register::ALERT_LIMIT, limit).await
-    }
-
-    /// Change the averaging mode of the INA260
-    #[inline(always)]
-    pub async fn set_averaging_mode(&mut self, a: Averaging) -> Result<(), I2C::Error> {
-        let bits = a.bits();
-        let state = (self.state & !Averaging::AVG1024.bits()) | bits;
-        let result = self.write_register(Register::CONFIG, state).await;
-        self.state = state;
-        result
-    }
+pub mod guard;
 
-    /// Change the operating mode of the INA260. Please note that if you change to Triggered mode,
-    /// you'll have to call this method again each time you would like to get a new sample.
-    #[inline(always)]
-    pub async fn set_operating_mode(&mut self, o: OperMode) -> Result<(), I2C::Error> {
-        let bits = o.bits();
-        let state = (self.state & !OperMode::SCBVC.bits()) | bits;
-        let result = self.write_register(Register::CONFIG, state).await;
-        self.state = state;
-        result
-    }
-
-    /// Change the shut current conversion time
-    #[inline(always)]
-    pub async fn set_scconvtime_mode(&mut self, s: SCConvTime) -> Result<(), I2C::Error> {
-        let bits = s.bits();
-        let state = (self.state & !SCConvTime::MS8_244.bits()) | bits;
-        let result = self.write_register(Register::CONFIG, state).await;
-        self.state = state;
-        result
-    }
-
-    /// Change the bus voltage conversion time
-    #[inline(always)]
-    pub async fn set_bvconvtime_mode(&mut self, b: BVConvTime) -> Result<(), I2C::Error> {
-        let bits = b.bits();
-        let state = (self.state & !BVConvTime::MS8_244.bits()) | bits;
-        let result = self.write_register(Register::CONFIG, state).await;
-        self.state = state;
-        result
-    }
-
-    // /// Delivers the unique chip id
-    // #[inline(always)]
-    // pub async fn did(&mut self) -> Result<u16, I2C::Error> {
-    //     let buffer = self.read_reg_u16(Register::DIE_ID).await?;
-    //     Ok((u16(buffer[0]) << 8 | u16(buffer[1])) >> 4)
-    // }
-
-    // /// Delivers the die revision id
-    // #[inline(always)]
-    // pub async fn rid(&mut self) -> Result<u16, I2C::Error> {
-    //     let result = self.read_reg_u16(Register::MANUFACTURER_ID).await;
-    //     match result {
-    //         Ok(buffer) => Ok(u16(buffer[1]) & 0b1111),
-    //         Err(e) => {
-    //             error!("Error reading INA260 Die ID");
-    //             Err(e)
-    //         }
-    //     }
-    // }
+/// Current in A from the CURRENT register, 1.25mA per bit
+#[inline(always)]
+pub fn current_from_raw(raw: i16) -> f32 {
+    raw as f32 * 1.25 / 1000.0
+}
 
-    /// Delivers the measured raw current in 1.25mA per bit
-    #[inline(always)]
-    async fn current_raw(&mut self) -> Result<i16, I2C::Error> {
-        self.read_reg_i16(Register::CURRENT).await
-    }
+/// Voltage in V from the VOLTAGE register, 1.25mV per bit
+#[inline(always)]
+pub fn voltage_from_raw(raw: u16) -> f32 {
+    raw as f32 * 1.25 / 1000.0
+}
 
-    /// Delivers the measured current in A
-    #[inline(always)]
-    pub async fn current(&mut self) -> Result<f32, I2C::Error> {
-        Ok(self.current_raw().await? as f32 * 1.25 / 1000.0)
-    }
+/// Power in W from the POWER register, 10mW per bit
+#[inline(always)]
+pub fn power_from_raw(raw: u16) -> f32 {
+    raw as f32 / 100.0
+}
 
-    /// Delivers the measured raw voltage in 1.25mV per bit
-    #[inline(always)]
-    async fn voltage_raw(&mut self) -> Result<u16, I2C::Error> {
-        let mut buffer = [0u8; 2];
-        let result = self
-            .i2c
-            .write_read(self.address, &[Register::VOLTAGE.addr()], &mut buffer)
-            .await;
-        match result {
-            Ok(_) => {
-                let msb = buffer[0];
-                let lsb = buffer[1];
-                Ok(u16::from_be_bytes([msb, lsb]))
-            }
-            Err(e) => Err(e),
-        }
-    }
+/// Contents of the DIE_ID register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DieId {
+    /// Device id, 0x227 for the INA260
+    pub device: u16,
+    /// Die revision
+    pub revision: u8,
+}
 
-    /// Delivers the measured current in V
+impl DieId {
     #[inline(always)]
-    pub async fn voltage(&mut self) -> Result<f32, I2C::Error> {
-        let result = self.voltage_raw().await;
-        match result {
-            Ok(raw) => {
-                Ok((raw as u32) as f32 * 1.25 / 1000.0) // 1.25mV per bit
-            }
-            Err(e) => Err(e),
+    pub fn from_bits(bits: u16) -> Self {
+        DieId {
+            device: bits >> 4,
+            revision: (bits & 0b1111) as u8,
         }
     }
+}
 
-    /// Delivers the measured raw current in 1.25mA per bit
-    #[inline(always)]
-    async fn power_raw(&mut self) -> Result<u16, I2C::Error> {
-        self.read_reg_u16(Register::POWER).await
-    }
+/// Flags from the Mask/Enable register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Status {
+    /// The enabled alert function tripped, see [`MaskEnable::AFF`]
+    pub alert: bool,
+    /// All conversions, averaging and multiplication are done, see [`MaskEnable::CVRF`]
+    pub conversion_ready: bool,
+    /// Power overflowed, see [`MaskEnable::OVF`]
+    pub overflow: bool,
+}
 
-    /// Delivers the measured power in Watts
+impl Status {
     #[inline(always)]
-    pub async fn power(&mut self) -> Result<f32, I2C::Error> {
-        let raw = self.power_raw().await?;
-        Ok(raw as f32 / 100.0) // 10mW per interval
+    pub fn from_bits(bits: u16) -> Self {
+        Status {
+            alert: bits & MaskEnable::AFF.bits() != 0,
+            conversion_ready: bits & MaskEnable::CVRF.bits() != 0,
+            overflow: bits & MaskEnable::OVF.bits() != 0,
+        }
     }
 }
 
-use defmt::Format;
-#[derive(Debug, Format)]
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Error<E> {
     /// Failed to compensate a raw measurement
     CompensationFailed,
@@ -0,0 +1,272 @@
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::I2c;
+
+use crate::{
+    AlertConfig, AlertLimit, Averaging, BVConvTime, DieId, MaskEnable, OperMode, Register,
+    SCConvTime, Status, current_from_raw, power_from_raw, voltage_from_raw,
+};
+
+pub struct INA260<I2C, Delay> {
+    i2c: I2C,
+    pub address: u8,
+    state: u16,
+    delay: Delay,
+}
+
+impl<I2C, D> INA260<I2C, D>
+where
+    I2C: I2c,
+    D: DelayNs,
+{
+    /// Create a new INA260 instance
+    ///
+    /// # Arguments
+    ///
+    /// * `i2c` - The I2C peripheral to use
+    /// * `address` - The I2C address of the INA260
+    pub fn new(i2c: I2C, address: u8, delay: D) -> Self {
+        INA260 {
+            i2c,
+            address,
+            delay,
+            state: OperMode::SCBVC.bits()
+                | Averaging::AVG64.bits()
+                | SCConvTime::MS8_244.bits()
+                | BVConvTime::MS8_244.bits(),
+        }
+    }
+
+    pub fn init(&mut self) -> Result<(), I2C::Error> {
+        self.write_register(Register::CONFIG, 0x8000)?;
+        // Let the reset finish before anything else is written
+        self.delay.delay_ms(1);
+        Ok(())
+    }
+
+    /// Give back the bus
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    fn write_register(&mut self, register: Register, data: u16) -> Result<(), I2C::Error> {
+        self.i2c.write(
+            self.address,
+            &[register.addr(), (data >> 8) as u8, (data & 255) as u8],
+        )
+    }
+
+    fn read_reg(&mut self, reg: Register) -> Result<[u8; 2], I2C::Error> {
+        let mut buf = [0; 2];
+        self.i2c.write_read(self.address, &[reg.addr()], &mut buf)?;
+        Ok(buf)
+    }
+
+    /// Read a 16 bit unsigned integer from a register
+    fn read_reg_u16(&mut self, reg: Register) -> Result<u16, I2C::Error> {
+        let buf = self.read_reg(reg)?;
+        Ok(u16::from_be_bytes(buf))
+    }
+
+    /// Read a 16-bit signed integer from a register
+    fn read_reg_i16(&mut self, reg: Register) -> Result<i16, I2C::Error> {
+        let buf = self.read_reg(reg)?;
+        Ok(i16::from_be_bytes(buf))
+    }
+
+    /// Change the Mask/Enable mode of the INA260
+    ///
+    /// The Mask/Enable Register selects the function that is enabled to control the ALERT pin as well as how that pin
+    /// functions. If multiple functions are enabled, the highest significant bit position Alert Function (D15-D11) takes
+    /// priority and responds to the Alert Limit Register.
+    #[inline(always)]
+    pub fn set_mask_enable(&mut self, m: MaskEnable) -> Result<(), I2C::Error> {
+        self.write_register(Register::MASK_ENABLE, m.bits())
+    }
+
+    /// Set the alert limit of the INA260
+    ///
+    /// The Alert Limit Register contains the value used to compare to the register selected in the Mask/Enable Register
+    /// to determine if a limit has been exceeded. The format for this register will match the format of the register that is
+    /// selected for comparison.
+    #[inline(always)]
+    pub fn set_alert_limit(&mut self, limit: u16) -> Result<(), I2C::Error> {
+        self.write_register(Register::ALERT_LIMIT, limit)
+    }
+
+    /// Set up the ALERT pin: the limit, then the function watching it
+    pub fn configure_alert(&mut self, config: AlertConfig) -> Result<(), I2C::Error> {
+        self.set_alert_limit(config.limit.limit_bits())?;
+        self.write_register(Register::MASK_ENABLE, config.mask_bits())
+    }
+
+    /// Stop the ALERT pin asserting on anything
+    pub fn disable_alert(&mut self) -> Result<(), I2C::Error> {
+        self.write_register(Register::MASK_ENABLE, 0)
+    }
+
+    /// Read whatever `limit` is watching, in its units
+    pub fn measure(&mut self, limit: AlertLimit) -> Result<f32, I2C::Error> {
+        Ok(limit.measured(self.read_reg_u16(limit.register())?))
+    }
+
+    /// Change the averaging mode of the INA260
+    #[inline(always)]
+    pub fn set_averaging_mode(&mut self, a: Averaging) -> Result<(), I2C::Error> {
+        let bits = a.bits();
+        let state = (self.state & !Averaging::AVG1024.bits()) | bits;
+        let result = self.write_register(Register::CONFIG, state);
+        self.state = state;
+        result
+    }
+
+    /// Change the operating mode of the INA260. Please note that if you change to Triggered mode,
+    /// you'll have to call this method again each time you would like to get a new sample.
+    #[inline(always)]
+    pub fn set_operating_mode(&mut self, o: OperMode) -> Result<(), I2C::Error> {
+        let bits = o.bits();
+        let state = (self.state & !OperMode::SCBVC.bits()) | bits;
+        let result = self.write_register(Register::CONFIG, state);
+        self.state = state;
+        result
+    }
+
+    /// Change the shut current conversion time
+    #[inline(always)]
+    pub fn set_scconvtime_mode(&mut self, s: SCConvTime) -> Result<(), I2C::Error> {
+        let bits = s.bits();
+        let state = (self.state & !SCConvTime::MS8_244.bits()) | bits;
+        let result = self.write_register(Register::CONFIG, state);
+        self.state = state;
+        result
+    }
+
+    /// Change the bus voltage conversion time
+    #[inline(always)]
+    pub fn set_bvconvtime_mode(&mut self, b: BVConvTime) -> Result<(), I2C::Error> {
+        let bits = b.bits();
+        let state = (self.state & !BVConvTime::MS8_244.bits()) | bits;
+        let result = self.write_register(Register::CONFIG, state);
+        self.state = state;
+        result
+    }
+
+    /// Delivers the manufacturer id, 0x5449 ("TI") on a genuine part
+    #[inline(always)]
+    pub fn manufacturer_id(&mut self) -> Result<u16, I2C::Error> {
+        self.read_reg_u16(Register::MANUFACTURER_ID)
+    }
+
+    /// Delivers the device id and die revision
+    #[inline(always)]
+    pub fn die_id(&mut self) -> Result<DieId, I2C::Error> {
+        Ok(DieId::from_bits(self.read_reg_u16(Register::DIE_ID)?))
+    }
+
+    /// Delivers the conversion ready, overflow and alert flags. Reading clears conversion ready,
+    /// and a latched alert.
+    #[inline(always)]
+    pub fn status(&mut self) -> Result<Status, I2C::Error> {
+        Ok(Status::from_bits(self.read_reg_u16(Register::MASK_ENABLE)?))
+    }
+
+    /// Delivers the measured raw current in 1.25mA per bit
+    #[inline(always)]
+    fn current_raw(&mut self) -> Result<i16, I2C::Error> {
+        self.read_reg_i16(Register::CURRENT)
+    }
+
+    /// Delivers the measured current in A
+    #[inline(always)]
+    pub fn current(&mut self) -> Result<f32, I2C::Error> {
+        Ok(current_from_raw(self.current_raw()?))
+    }
+
+    /// Delivers the measured raw voltage in 1.25mV per bit
+    #[inline(always)]
+    fn voltage_raw(&mut self) -> Result<u16, I2C::Error> {
+        self.read_reg_u16(Register::VOLTAGE)
+    }
+
+    /// Delivers the measured current in V
+    #[inline(always)]
+    pub fn voltage(&mut self) -> Result<f32, I2C::Error> {
+        Ok(voltage_from_raw(self.voltage_raw()?))
+    }
+
+    /// Delivers the measured raw power in 10mW per bit
+    #[inline(always)]
+    fn power_raw(&mut self) -> Result<u16, I2C::Error> {
+        self.read_reg_u16(Register::POWER)
+    }
+
+    /// Delivers the measured power in Watts
+    #[inline(always)]
+    pub fn power(&mut self) -> Result<f32, I2C::Error> {
+        Ok(power_from_raw(self.power_raw()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec;
+
+    use embedded_hal_mock::eh1::delay::NoopDelay;
+    use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+
+    use super::*;
+
+    const ADDRESS: u8 = 0x40;
+
+    #[test]
+    fn test_measurements() {
+        let i2c = Mock::new(&[
+            Transaction::write_read(ADDRESS, vec![0x01], vec![0xFF, 0x38]),
+            Transaction::write_read(ADDRESS, vec![0x02], vec![0x25, 0x80]),
+            Transaction::write_read(ADDRESS, vec![0x03], vec![0x01, 0x2C]),
+        ]);
+        let mut ina = INA260::new(i2c, ADDRESS, NoopDelay::new());
+
+        assert_eq!(ina.current().unwrap(), -0.25);
+        assert_eq!(ina.voltage().unwrap(), 12.0);
+        assert_eq!(ina.power().unwrap(), 3.0);
+        ina.release().done();
+    }
+
+    #[test]
+    fn test_configuration_and_ids() {
+        let i2c = Mock::new(&[
+            Transaction::write(ADDRESS, vec![0x00, 0x80, 0x00]),
+            Transaction::write(ADDRESS, vec![0x00, 0x01, 0xFF]),
+            Transaction::write(ADDRESS, vec![0x06, 0x80, 0x00]),
+            Transaction::write(ADDRESS, vec![0x07, 0x03, 0x20]),
+            Transaction::write_read(ADDRESS, vec![0xFE], vec![0x54, 0x49]),
+            Transaction::write_read(ADDRESS, vec![0xFF], vec![0x22, 0x70]),
+            Transaction::write_read(ADDRESS, vec![0x06], vec![0x80, 0x1C]),
+        ]);
+        let mut ina = INA260::new(i2c, ADDRESS, NoopDelay::new());
+
+        ina.init().unwrap();
+        ina.set_averaging_mode(Averaging::AVG1).unwrap();
+        ina.set_mask_enable(MaskEnable::OCL).unwrap();
+        ina.set_alert_limit(800).unwrap();
+        assert_eq!(ina.manufacturer_id().unwrap(), 0x5449);
+        assert_eq!(
+            ina.die_id().unwrap(),
+            DieId {
+                device: 0x227,
+                revision: 0
+            }
+        );
+        assert_eq!(
+            ina.status().unwrap(),
+            Status {
+                alert: true,
+                conversion_ready: true,
+                overflow: true
+            }
+        );
+        ina.release().done();
+    }
+}
@@ -0,0 +1,76 @@
+use crate::dot;
+
+/// Calibration for one photodiode
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Diode {
+    /// Outward normal in the body frame, unit length
+    pub normal: [f32; 3],
+    /// Reading with no light, in ADC counts
+    pub dark: f32,
+    /// Counts above dark with full sun straight down the normal
+    pub gain: f32,
+    /// Response goes as (irradiance · cos θ)^exponent. 1 is an ideal cosine detector; the
+    /// package and window usually fall off a little faster off axis.
+    pub exponent: f32,
+}
+
+impl Diode {
+    /// An ideal cosine detector
+    pub const fn new(normal: [f32; 3], dark: f32, gain: f32) -> Self {
+        Diode {
+            normal,
+            dark,
+            gain,
+            exponent: 1.0,
+        }
+    }
+
+    /// Correct for a response that isn't quite cosine
+    pub const fn with_exponent(mut self, exponent: f32) -> Self {
+        self.exponent = exponent;
+        self
+    }
+
+    /// Irradiance along the normal implied by a reading, as a fraction of full sun. Readings at
+    /// or below dark give zero.
+    pub fn signal(&self, counts: u16) -> f32 {
+        let above = (counts as f32 - self.dark) / self.gain;
+        if above <= 0.0 || self.gain <= 0.0 {
+            0.0
+        } else if self.exponent == 1.0 {
+            above
+        } else {
+            libm::powf(above, 1.0 / self.exponent)
+        }
+    }
+
+    /// What the diode reads with the sun along unit vector `sun` at `irradiance`, the inverse of
+    /// [`Diode::signal`]
+    pub fn expected(&self, sun: [f32; 3], irradiance: f32) -> f32 {
+        let along = dot(self.normal, sun) * irradiance;
+        if along <= 0.0 {
+            self.dark
+        } else {
+            self.dark + self.gain * libm::powf(along, self.exponent)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signal_inverts_expected() {
+        let diode = Diode::new([0.0, 0.0, 1.0], 40.0, 3000.0).with_exponent(1.2);
+        let sun = [0.6, 0.0, 0.8];
+
+        let counts = diode.expected(sun, 0.9);
+        assert!((diode.signal(counts as u16) - 0.72).abs() < 1e-3);
+
+        // Facing away, and below dark
+        assert_eq!(diode.expected([0.0, 0.0, -1.0], 1.0), 40.0);
+        assert_eq!(diode.signal(12), 0.0);
+    }
+}
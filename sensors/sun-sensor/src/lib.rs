@@ -0,0 +1,21 @@
+#![no_std]
+
+//! Sun direction from an array of photodiodes on the body
+//!
+//! Each diode reads its dark offset plus a gain times the irradiance along its normal. With the
+//! diodes calibrated, the lit ones give one linear equation each in the sun vector scaled by
+//! irradiance, which is solved in the least squares sense.
+
+mod diode;
+mod solver;
+
+pub use diode::*;
+pub use solver::*;
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn norm(v: [f32; 3]) -> f32 {
+    libm::sqrtf(dot(v, v))
+}
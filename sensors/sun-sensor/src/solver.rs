@@ -0,0 +1,459 @@
+use crate::{Diode, dot, norm};
+
+/// Limits on what the solver will call a sun fix
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SolverConfig {
+    /// Signal, as a fraction of full sun, a diode needs to be used in the fit
+    pub min_signal: f32,
+    /// Lit diodes needed to attempt a fit
+    pub min_diodes: u8,
+    /// Irradiance below which it's too dark to trust, as a fraction of full sun. Eclipse, or the
+    /// diodes are covered.
+    pub min_irradiance: f32,
+    /// RMS fit residual, relative to irradiance, past which the fit is rejected
+    pub max_residual: f32,
+    /// Mean signal on diodes facing away from the fit, less the brightest, relative to
+    /// irradiance, past which too much light is coming from elsewhere. Usually Earth albedo.
+    pub max_albedo: f32,
+    /// Uncertainty a good fit is still given, in radians. Calibration is never better than this.
+    pub min_uncertainty: f32,
+}
+
+impl SolverConfig {
+    pub const DEFAULT: SolverConfig = SolverConfig {
+        min_signal: 0.05,
+        min_diodes: 3,
+        min_irradiance: 0.3,
+        max_residual: 0.1,
+        max_albedo: 0.1,
+        min_uncertainty: 0.05,
+    };
+}
+
+impl Default for SolverConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// A sun fix
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SunEstimate {
+    /// Unit vector toward the sun, body frame
+    pub direction: [f32; 3],
+    /// Fraction of full sun
+    pub irradiance: f32,
+    /// RMS fit residual relative to irradiance
+    pub residual: f32,
+    /// Mean signal on the diodes facing away, less the brightest, relative to irradiance
+    pub albedo: f32,
+    /// Bit per diode used in the fit
+    pub used: u32,
+    /// Independent directions among the used normals. Under 3, the direction along the missing
+    /// ones is only known to be dark.
+    pub rank: u8,
+    /// Rough one sigma error in the direction, in radians
+    pub uncertainty: f32,
+}
+
+/// A body frame direction for the attitude estimator to match against its inertial
+/// counterpart
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct VectorReference {
+    /// Unit vector, body frame
+    pub body: [f32; 3],
+    /// One sigma error, radians
+    pub sigma: f32,
+}
+
+impl SunEstimate {
+    /// The fix as a coarse attitude reference
+    pub fn reference(&self) -> VectorReference {
+        VectorReference {
+            body: self.direction,
+            sigma: self.uncertainty,
+        }
+    }
+}
+
+/// Why there's no fix
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Rejection {
+    /// Not enough diodes above [`SolverConfig::min_signal`]
+    TooFewLit { lit: u8 },
+    /// Fitted irradiance under [`SolverConfig::min_irradiance`]
+    Dark { irradiance: f32 },
+    /// The diodes don't agree on a direction
+    Residual { residual: f32 },
+    /// Too much light on the side facing away from the sun
+    Albedo { albedo: f32 },
+}
+
+/// Calibrated photodiodes and the limits on a fix. Holds up to 32.
+pub struct SunSensor<const N: usize> {
+    diodes: [Diode; N],
+    config: SolverConfig,
+}
+
+struct Fit {
+    vector: [f32; 3],
+    rank: u8,
+    residual: f32,
+}
+
+impl<const N: usize> SunSensor<N> {
+    pub const fn new(diodes: [Diode; N], config: SolverConfig) -> Self {
+        SunSensor { diodes, config }
+    }
+
+    pub fn diodes(&self) -> &[Diode; N] {
+        &self.diodes
+    }
+
+    /// Find the sun in a set of raw readings, in diode order
+    pub fn solve(&self, counts: &[u16; N]) -> Result<SunEstimate, Rejection> {
+        let mut signals = [0.0; N];
+        let mut lit = 0u32;
+        for (i, (diode, &count)) in self.diodes.iter().zip(counts).enumerate().take(32) {
+            signals[i] = diode.signal(count);
+            if signals[i] >= self.config.min_signal {
+                lit |= 1 << i;
+            }
+        }
+        if lit.count_ones() < self.config.min_diodes as u32 {
+            return Err(Rejection::TooFewLit {
+                lit: lit.count_ones() as u8,
+            });
+        }
+
+        let mut used = lit;
+        let mut fit = self.fit(&signals, used);
+
+        // A lit diode facing away from the answer is seeing something other than the sun. Fit
+        // again without it.
+        let facing_away = self.facing_away(fit.vector) & used;
+        if facing_away != 0 && (used & !facing_away).count_ones() >= self.config.min_diodes as u32 {
+            used &= !facing_away;
+            fit = self.fit(&signals, used);
+        }
+
+        let irradiance = norm(fit.vector);
+        if irradiance < self.config.min_irradiance {
+            return Err(Rejection::Dark { irradiance });
+        }
+        let direction = fit.vector.map(|x| x / irradiance);
+        let residual = fit.residual / irradiance;
+        if residual > self.config.max_residual {
+            return Err(Rejection::Residual { residual });
+        }
+
+        // Leaving out the brightest, so one stuck diode isn't taken for albedo
+        let away = self.facing_away(direction);
+        let albedo = if away.count_ones() < 2 {
+            0.0
+        } else {
+            let (total, brightest) = (0..N)
+                .filter(|i| away & (1 << i) != 0)
+                .map(|i| signals[i])
+                .fold((0.0f32, 0.0f32), |(total, brightest), s| {
+                    (total + s, brightest.max(s))
+                });
+            (total - brightest) / (away.count_ones() - 1) as f32 / irradiance
+        };
+        if albedo > self.config.max_albedo {
+            return Err(Rejection::Albedo { albedo });
+        }
+
+        let mut uncertainty = libm::asinf(residual.min(1.0));
+        if fit.rank < 3 {
+            // Whatever's along the missing direction stayed under the lit threshold
+            uncertainty += libm::asinf((self.config.min_signal / irradiance).min(1.0));
+        }
+
+        Ok(SunEstimate {
+            direction,
+            irradiance,
+            residual,
+            albedo,
+            used,
+            rank: fit.rank,
+            uncertainty: uncertainty.max(self.config.min_uncertainty),
+        })
+    }
+
+    // Diodes at or past 90 degrees from `direction`
+    fn facing_away(&self, direction: [f32; 3]) -> u32 {
+        self.diodes
+            .iter()
+            .take(32)
+            .enumerate()
+            .filter(|(_, d)| dot(d.normal, direction) <= 0.0)
+            .fold(0, |mask, (i, _)| mask | 1 << i)
+    }
+
+    // Least squares for v in normal · v = signal over the diodes in `used`. Where the normals
+    // don't span all three axes this gives the smallest v that fits, which puts nothing along
+    // the directions no diode can see.
+    fn fit(&self, signals: &[f32; N], used: u32) -> Fit {
+        let mut ata = [[0.0f32; 3]; 3];
+        let mut atb = [0.0f32; 3];
+        for (i, diode) in self.diodes.iter().enumerate().take(32) {
+            if used & (1 << i) == 0 {
+                continue;
+            }
+            let n = diode.normal;
+            for r in 0..3 {
+                atb[r] += n[r] * signals[i];
+                for c in 0..3 {
+                    ata[r][c] += n[r] * n[c];
+                }
+            }
+        }
+
+        let (values, vectors) = symmetric_eigen(ata);
+        let largest = values.iter().fold(0.0f32, |a, &b| a.max(b));
+        let mut vector = [0.0; 3];
+        let mut rank = 0;
+        for k in 0..3 {
+            if values[k] <= largest * 1e-3 {
+                continue;
+            }
+            rank += 1;
+            let e = [vectors[0][k], vectors[1][k], vectors[2][k]];
+            let scale = dot(e, atb) / values[k];
+            for r in 0..3 {
+                vector[r] += scale * e[r];
+            }
+        }
+
+        let mut squares = 0.0;
+        for (i, diode) in self.diodes.iter().enumerate().take(32) {
+            if used & (1 << i) != 0 {
+                let error = dot(diode.normal, vector) - signals[i];
+                squares += error * error;
+            }
+        }
+        Fit {
+            vector,
+            rank,
+            residual: libm::sqrtf(squares / used.count_ones().max(1) as f32),
+        }
+    }
+}
+
+// Eigenvalues and eigenvectors, as columns, of a symmetric 3x3 by Jacobi rotations
+fn symmetric_eigen(mut a: [[f32; 3]; 3]) -> ([f32; 3], [[f32; 3]; 3]) {
+    let mut v = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    for _ in 0..16 {
+        let off = a[0][1] * a[0][1] + a[0][2] * a[0][2] + a[1][2] * a[1][2];
+        if off < 1e-12 {
+            break;
+        }
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q].abs() < 1e-9 {
+                continue;
+            }
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+            let t = theta.signum() / (theta.abs() + libm::sqrtf(theta * theta + 1.0));
+            let c = 1.0 / libm::sqrtf(t * t + 1.0);
+            let s = t * c;
+            for row in a.iter_mut().chain(v.iter_mut()) {
+                let (kp, kq) = (row[p], row[q]);
+                row[p] = c * kp - s * kq;
+                row[q] = s * kp + c * kq;
+            }
+            let (pp, qq) = (a[p], a[q]);
+            for k in 0..3 {
+                a[p][k] = c * pp[k] - s * qq[k];
+                a[q][k] = s * pp[k] + c * qq[k];
+            }
+        }
+    }
+    ([a[0][0], a[1][1], a[2][2]], v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Four diodes on each face of a cube, +X, -X, +Y, -Y, +Z, -Z, with a spread of darks and
+    // gains and a slightly steep response
+    fn cube() -> [Diode; 24] {
+        const FACES: [[f32; 3]; 6] = [
+            [1.0, 0.0, 0.0],
+            [-1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, -1.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [0.0, 0.0, -1.0],
+        ];
+        core::array::from_fn(|i| {
+            Diode::new(
+                FACES[i / 4],
+                30.0 + (i % 5) as f32 * 8.0,
+                2800.0 + (i % 7) as f32 * 60.0,
+            )
+            .with_exponent(1.1)
+        })
+    }
+
+    fn unit(v: [f32; 3]) -> [f32; 3] {
+        let n = norm(v);
+        v.map(|x| x / n)
+    }
+
+    // Readings for the sun along `sun` at `irradiance`, plus a diffuse source along `glow`
+    // adding `glow_irradiance`
+    fn illuminate(
+        diodes: &[Diode; 24],
+        sun: [f32; 3],
+        irradiance: f32,
+        glow: [f32; 3],
+        glow_irradiance: f32,
+    ) -> [u16; 24] {
+        core::array::from_fn(|i| {
+            let diode = diodes[i];
+            let along = (dot(diode.normal, sun) * irradiance).max(0.0)
+                + (dot(diode.normal, glow) * glow_irradiance).max(0.0);
+            (diode.dark + diode.gain * libm::powf(along, diode.exponent)) as u16
+        })
+    }
+
+    fn angle(a: [f32; 3], b: [f32; 3]) -> f32 {
+        libm::acosf(dot(unit(a), unit(b)).clamp(-1.0, 1.0)).to_degrees()
+    }
+
+    #[test]
+    fn test_synthetic_directions() {
+        let sensor = SunSensor::new(cube(), SolverConfig::default());
+        for sun in [
+            [1.0, 0.0, 0.0],
+            [0.3, -0.5, 0.8],
+            [-0.7, 0.7, 0.0],
+            [-0.2, -0.9, -0.4],
+            [0.0, 0.0, -1.0],
+        ] {
+            let counts = illuminate(sensor.diodes(), unit(sun), 1.0, [0.0; 3], 0.0);
+            let fix = sensor.solve(&counts).unwrap();
+            assert!(angle(fix.direction, sun) < 0.5, "{sun:?}: {fix:?}");
+            assert!((fix.irradiance - 1.0).abs() < 0.01, "{sun:?}: {fix:?}");
+            assert!(fix.residual < 0.01, "{sun:?}: {fix:?}");
+            assert_eq!(fix.albedo, 0.0);
+        }
+    }
+
+    #[test]
+    fn test_face_on_and_edge_on() {
+        let sensor = SunSensor::new(cube(), SolverConfig::default());
+
+        // Only +Y sees it, the other two axes are only known from the dark faces
+        let fix = sensor
+            .solve(&illuminate(
+                sensor.diodes(),
+                [0.0, 1.0, 0.0],
+                1.0,
+                [0.0; 3],
+                0.0,
+            ))
+            .unwrap();
+        assert_eq!(fix.rank, 1);
+        assert_eq!(fix.used, 0b1111 << 8);
+        assert!(angle(fix.direction, [0.0, 1.0, 0.0]) < 0.5);
+        assert!(fix.uncertainty > 0.05);
+
+        let sun = unit([1.0, 0.0, 1.0]);
+        let fix = sensor
+            .solve(&illuminate(sensor.diodes(), sun, 0.8, [0.0; 3], 0.0))
+            .unwrap();
+        assert_eq!(fix.rank, 2);
+        assert!(angle(fix.direction, sun) < 0.5);
+        assert!((fix.irradiance - 0.8).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_dark() {
+        let sensor = SunSensor::new(cube(), SolverConfig::default());
+        let diodes = sensor.diodes();
+
+        // Eclipse
+        let counts = illuminate(diodes, [0.0, 0.0, 1.0], 0.0, [0.0; 3], 0.0);
+        assert_eq!(sensor.solve(&counts), Err(Rejection::TooFewLit { lit: 0 }));
+
+        // Something's lit, but it isn't the sun
+        let counts = illuminate(diodes, unit([1.0, 1.0, 1.0]), 0.15, [0.0; 3], 0.0);
+        assert!(matches!(sensor.solve(&counts), Err(Rejection::Dark { .. })));
+    }
+
+    #[test]
+    fn test_albedo() {
+        let sensor = SunSensor::new(cube(), SolverConfig::default());
+        let sun = unit([0.5, 0.2, 0.8]);
+
+        // A little Earth shine from below is tolerated, and seen
+        let counts = illuminate(sensor.diodes(), sun, 1.0, [0.0, 0.0, -1.0], 0.05);
+        let fix = sensor.solve(&counts).unwrap();
+        assert!(fix.albedo > 0.0 && fix.albedo < 0.1, "{fix:?}");
+        assert!(angle(fix.direction, sun) < 2.0, "{fix:?}");
+
+        // A lot isn't
+        let counts = illuminate(sensor.diodes(), sun, 1.0, unit([-1.0, -0.3, -1.0]), 0.6);
+        assert!(
+            matches!(sensor.solve(&counts), Err(Rejection::Albedo { .. })),
+            "{:?}",
+            sensor.solve(&counts)
+        );
+    }
+
+    #[test]
+    fn test_bad_diode() {
+        let sensor = SunSensor::new(cube(), SolverConfig::default());
+        let sun = unit([0.4, 0.6, 0.7]);
+
+        // One diode on the far side stuck at full scale is dropped from the fit
+        let mut counts = illuminate(sensor.diodes(), sun, 1.0, [0.0; 3], 0.0);
+        counts[5] = 4095;
+        let fix = sensor.solve(&counts).unwrap();
+        assert_eq!(fix.used & (1 << 5), 0);
+        assert!(angle(fix.direction, sun) < 0.5, "{fix:?}");
+
+        // Half of a lit face reading double doesn't fit anything. All of it would, on a cube
+        // three faces give exactly three equations.
+        let mut counts = illuminate(sensor.diodes(), sun, 1.0, [0.0; 3], 0.0);
+        for count in &mut counts[8..10] {
+            *count = (*count - 40) * 2;
+        }
+        assert!(
+            matches!(sensor.solve(&counts), Err(Rejection::Residual { .. })),
+            "{:?}",
+            sensor.solve(&counts)
+        );
+    }
+
+    #[test]
+    fn test_reference() {
+        let sensor = SunSensor::new(cube(), SolverConfig::default());
+        let sun = unit([-0.3, 0.4, 0.5]);
+        let fix = sensor
+            .solve(&illuminate(sensor.diodes(), sun, 1.0, [0.0; 3], 0.0))
+            .unwrap();
+        let reference = fix.reference();
+        assert_eq!(reference.body, fix.direction);
+        assert_eq!(reference.sigma, 0.05);
+    }
+
+    #[test]
+    fn test_eigen() {
+        let m = [[4.0, 1.0, 0.5], [1.0, 3.0, 0.2], [0.5, 0.2, 1.0]];
+        let (values, vectors) = symmetric_eigen(m);
+        for k in 0..3 {
+            let e = [vectors[0][k], vectors[1][k], vectors[2][k]];
+            for r in 0..3 {
+                assert!((dot(m[r], e) - values[k] * e[r]).abs() < 1e-4);
+            }
+        }
+    }
+}